@@ -0,0 +1,20 @@
+//! An example blob-dl post-processing plugin: it prints the name of every downloaded file
+//!
+//! Build it with `cargo build --example plugin`, then choose
+//! target/debug/examples/libplugin.so (.dylib on macOS, plugin.dll on Windows)
+//! when blob-dl asks "Run a post-processing plugin?" in the advanced options
+use std::ffi::{c_char, c_int, CStr};
+
+/// Called by blob-dl once for every downloaded file, returning anything other than 0 reports an error
+///
+/// # Safety
+/// blob-dl always passes valid, null-terminated UTF-8 strings
+#[no_mangle]
+pub unsafe extern "C" fn blob_dl_post_process(downloaded_file: *const c_char, url: *const c_char) -> c_int {
+    let downloaded_file = CStr::from_ptr(downloaded_file).to_string_lossy();
+    let url = CStr::from_ptr(url).to_string_lossy();
+
+    println!("[example plugin] {} was downloaded from {}", downloaded_file, url);
+
+    0
+}
@@ -1,24 +1,182 @@
+use std::collections::HashMap;
+use std::time::Duration;
 use url::Url;
 use dialoguer::console::Term;
 use dialoguer::{theme::ColorfulTheme, Select};
+use serde::{Deserialize, Serialize};
 
 use crate::error::{BlobdlError, BlobResult};
+use crate::parser;
 
-#[derive(Debug, PartialOrd, PartialEq, Clone)]
+#[derive(Debug, PartialOrd, PartialEq, Clone, Serialize, Deserialize)]
 pub enum DownloadOption {
     /// If the url refers to a video in a playlist and the user only wants to download the single video, YtVideo's value is the video's index in the playlist
     YtVideo(usize),
     YtPlaylist,
+    /// If the url refers to a video in a playlist and the user wants to download it and every video after it,
+    /// YtPlaylistFrom's value is the video's index in the playlist
+    YtPlaylistFrom(usize),
+    /// youtube.com/shorts/<ID>, a short vertical video
+    YtShort,
+}
+
+/// Removes what commonly ends up around a pasted url: whitespace, quotes, trailing backslashes (line continuations),
+/// backslashes used to escape `&`, `?` and `=`, and `&amp;` copied from html
+///
+/// blob-dl never passes urls through a shell, so none of these characters are needed
+pub fn clean_url(raw: &str) -> String {
+    let mut url = raw.trim();
+
+    // Quotes can be unbalanced if only part of the url was selected
+    url = url.trim_start_matches(['"', '\'', '`']);
+    url = url.trim_end_matches(['"', '\'', '`', '\\']);
+    url = url.trim();
+
+    url.replace("\\&", "&")
+        .replace("\\?", "?")
+        .replace("\\=", "=")
+        .replace("&amp;", "&")
+}
+
+/// Returns an error if the shell most likely cut the url at an unquoted `&`
+///
+/// The shell then runs blob-dl in the background, where it can't ask anything,
+/// and whatever came after the `&` (like list=...) never reaches blob-dl
+pub fn check_url_truncation(url: &str) -> BlobResult<()> {
+    if is_bare_video_url(url) && is_background_job() {
+        Err(BlobdlError::UrlTruncatedByShell)
+    } else {
+        Ok(())
+    }
+}
+
+/// Whether the url has no list, which is what's left of a playlist url cut at the `&` before list=...
+fn is_bare_video_url(url: &str) -> bool {
+    match Url::parse(url) {
+        Ok(parsed) => parsed.query_pairs().all(|(key, _)| key != "list"),
+        Err(_) => false,
+    }
+}
+
+/// Whether blob-dl was started in the background (`blob-dl url &`), which means the terminal isn't ours
+#[cfg(unix)]
+fn is_background_job() -> bool {
+    // Safety: both functions only read the process' state
+    unsafe {
+        let foreground = libc::tcgetpgrp(libc::STDIN_FILENO);
+        // tcgetpgrp fails when stdin isn't a terminal, blob-dl is being used by a script in that case
+        foreground != -1 && foreground != libc::getpgrp()
+    }
+}
+
+#[cfg(not(unix))]
+fn is_background_job() -> bool {
+    false
+}
+
+/// How many redirects a shortened url is followed through before blob-dl stops
+const MAX_REDIRECTS: usize = 5;
+
+/// How long every redirect can take to answer
+const REDIRECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The host of `url` if it's one of youtube's: youtube.com and its subdomains, youtu.be and youtube-nocookie.com
+pub(crate) fn youtube_host(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let domain = url.domain()?;
+
+    let is_youtube = domain == "youtube.com" || domain.ends_with(".youtube.com")
+        || domain == "youtu.be" || domain == "youtube-nocookie.com" || domain.ends_with(".youtube-nocookie.com");
+    is_youtube.then(|| domain.to_string())
+}
+
+/// Follows the redirects of shortened urls (bit.ly/<code>, ...) until they reach youtube, at most MAX_REDIRECTS times.
+/// Urls already on youtube are returned as they are, and so is anything which isn't an http(s) url: analyze_url
+/// then says it's not supported
+///
+/// The requests use --socket-timeout and the proxy of the environment (HTTPS_PROXY, ...) like yt-dlp does.
+/// They can't be bound to --source-address, so with it the url is returned as it is instead of leaving from
+/// another network interface.
+/// Only the Location headers are followed, pages which redirect with javascript are returned as they are
+pub fn resolve_url(url: &str, cli_config: &parser::CliConfig) -> BlobResult<String> {
+    let is_web_url = Url::parse(url).is_ok_and(|parsed| parsed.scheme() == "http" || parsed.scheme() == "https");
+    if !is_web_url || cli_config.source_address().is_some() {
+        return Ok(url.to_string());
+    }
+
+    let timeout = cli_config.socket_timeout().map(Duration::from_secs).unwrap_or(REDIRECT_TIMEOUT);
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(timeout))
+        // Every redirect is checked, following them all would end up on youtube's cookie consent page in some countries
+        .max_redirects(0)
+        .http_status_as_error(false)
+        .build()
+        .into();
+
+    let mut current = url.to_string();
+    for _ in 0..MAX_REDIRECTS {
+        if youtube_host(&current).is_some() {
+            break;
+        }
+
+        let response = agent.head(&current).call()?;
+        if !response.status().is_redirection() {
+            break;
+        }
+
+        let location = match response.headers().get("location").and_then(|location| location.to_str().ok()) {
+            Some(location) => location,
+            None => break,
+        };
+        // Locations can be relative to the url which redirected
+        current = Url::parse(&current)
+            .and_then(|base| base.join(location))
+            .map_err(|_| BlobdlError::UrlParsingError)?
+            .to_string();
+    }
+
+    Ok(current)
+}
+
+/// Remembers the urls resolved during this run, so that a url passed more than once is only resolved once
+#[derive(Debug, Default)]
+pub struct UrlResolver {
+    resolved: HashMap<String, String>,
+}
+
+impl UrlResolver {
+    pub fn resolve(&mut self, url: &str, cli_config: &parser::CliConfig) -> BlobResult<String> {
+        if let Some(resolved) = self.resolved.get(url) {
+            return Ok(resolved.clone());
+        }
+
+        let resolved = resolve_url(url, cli_config)?;
+        self.resolved.insert(url.to_string(), resolved.clone());
+        Ok(resolved)
+    }
 }
 
 /// Analyzes the url provided by the user and deduces whether it
 /// refers to a youtube video or playlist
+///
+/// The user is asked what to download when the url refers to a video in a playlist
 pub fn analyze_url(command_line_url: &str) -> BlobResult<DownloadOption> {
+    analyze(command_line_url, ask_video_or_playlist)
+}
+
+/// Like analyze_url, but a video in a playlist is downloaded with the whole playlist instead of asking,
+/// for what runs without a wizard
+pub(crate) fn analyze_url_without_asking(command_line_url: &str) -> BlobResult<DownloadOption> {
+    analyze(command_line_url, |_| Ok(DownloadOption::YtPlaylist))
+}
+
+/// `video_in_playlist` chooses what to download when the url refers to a video in a playlist, it gets the url's query
+fn analyze(command_line_url: &str, video_in_playlist: impl FnOnce(&str) -> BlobResult<DownloadOption>) -> BlobResult<DownloadOption> {
     return if let Ok(url) = Url::parse(command_line_url) {
         if let Some(domain_name) = url.domain() {
             // All youtube-related urls have "youtu" in them
             if domain_name.contains("youtu") {
-                inspect_yt_url(url)
+                inspect_yt_url(url, video_in_playlist)
             } else {
                 // The url isn't from youtube
                 Err(BlobdlError::UnsupportedWebsite)
@@ -32,51 +190,204 @@ pub fn analyze_url(command_line_url: &str) -> BlobResult<DownloadOption> {
 }
 
 /// Given a youtube url determines whether it refers to a video/playlist
-fn inspect_yt_url(yt_url: Url) -> BlobResult<DownloadOption> {
+fn inspect_yt_url(yt_url: Url, video_in_playlist: impl FnOnce(&str) -> BlobResult<DownloadOption>) -> BlobResult<DownloadOption> {
+    // Shorts don't have a query, the id is in the path
+    if yt_url.path().starts_with("/shorts/") {
+        return Ok(DownloadOption::YtShort);
+    }
+
+    // youtu.be/<ID> only needs a query for playlists
+    if yt_url.domain() == Some("youtu.be") && yt_url.path().len() > 1
+        && !yt_url.query_pairs().any(|(key, _)| key == "list") {
+        return Ok(DownloadOption::YtVideo(0));
+    }
+
     if let Some(query) = yt_url.query() {
         if query.contains("&index=") {
             // This video is part of a youtube playlist
-            let term = Term::buffered_stderr();
-
-            // Ask the user whether they want to download the whole playlist or just the video
-            let user_selection = Select::with_theme(&ColorfulTheme::default())
-                .with_prompt("The url refers to a video in a playlist, which do you want to download?")
-                .default(0)
-                .items(&["Only the video", "The whole playlist"])
-                .interact_on(&term)?;
-
-            return match user_selection {
-                0 => {
-                    let index = if let Some(index_location) = query.find("&index=") {
-                        let slice = &query[index_location + "&index=".len() ..];
-
-                        if let Some(second_ampersand_location) = slice.find('&') {
-                            // There are url parameters after &index=..
-                             &slice[..second_ampersand_location]
-                        } else {
-                            slice
-                        }
-                    } else {
-                        panic!("url has &index= but doesn't provide a numerical index")
-                    };
-
-                    if let Ok(parsed) = index.parse() {
-                        Ok(DownloadOption::YtVideo(parsed))
-                    } else {
-                        Err(BlobdlError::UrlIndexParsingError)
-                    }
-                }
-
-                _ => Ok(DownloadOption::YtPlaylist),
-            };
+            return video_in_playlist(query);
         }
         if yt_url.path().contains("playlist") || query.contains("list"){
             return Ok(DownloadOption::YtPlaylist);
         }
 
-        // This url is probably referring to a video or a short
+        // This url is probably referring to a video
         return Ok(DownloadOption::YtVideo(0));
     }
 
     Err(BlobdlError::QueryCouldNotBeParsed)
-}
\ No newline at end of file
+}
+
+/// Asks the user whether they want to download the whole playlist or just the video, `query` is the url's
+fn ask_video_or_playlist(query: &str) -> BlobResult<DownloadOption> {
+    let term = Term::buffered_stderr();
+
+    let user_selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("The url refers to a video in a playlist, which do you want to download?")
+        .default(0)
+        .items(&["Only the video", "The whole playlist", "This video and everything after it in the playlist"])
+        .interact_on(&term)?;
+
+    match user_selection {
+        0 => Ok(DownloadOption::YtVideo(parse_playlist_index(query)?)),
+
+        1 => Ok(DownloadOption::YtPlaylist),
+
+        _ => Ok(DownloadOption::YtPlaylistFrom(parse_playlist_index(query)?)),
+    }
+}
+
+/// Reads the value of &index= in the query of a url which refers to a video in a playlist
+fn parse_playlist_index(query: &str) -> BlobResult<usize> {
+    let index = if let Some(index_location) = query.find("&index=") {
+        let slice = &query[index_location + "&index=".len() ..];
+
+        if let Some(second_ampersand_location) = slice.find('&') {
+            // There are url parameters after &index=..
+            &slice[..second_ampersand_location]
+        } else {
+            slice
+        }
+    } else {
+        panic!("url has &index= but doesn't provide a numerical index")
+    };
+
+    index.parse().map_err(|_| BlobdlError::UrlIndexParsingError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tests::config;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn pasted_urls_lose_quotes_and_escapes() {
+        let cases = [
+            ("  https://www.youtube.com/watch?v=abc  ", "https://www.youtube.com/watch?v=abc"),
+            ("\"https://www.youtube.com/watch?v=abc&list=PL123\"", "https://www.youtube.com/watch?v=abc&list=PL123"),
+            // Only part of the url was selected
+            ("'https://www.youtube.com/watch?v=abc", "https://www.youtube.com/watch?v=abc"),
+            ("`https://www.youtube.com/watch?v=abc`", "https://www.youtube.com/watch?v=abc"),
+            ("https://www.youtube.com/watch?v=abc \\", "https://www.youtube.com/watch?v=abc"),
+            ("https://www.youtube.com/watch\\?v\\=abc\\&list\\=PL123", "https://www.youtube.com/watch?v=abc&list=PL123"),
+            ("https://www.youtube.com/watch?v=abc&amp;list=PL123", "https://www.youtube.com/watch?v=abc&list=PL123"),
+        ];
+
+        for (raw, expected) in cases {
+            assert_eq!(clean_url(raw), expected, "{}", raw);
+        }
+    }
+
+    #[test]
+    fn only_urls_without_a_list_can_have_been_cut_by_the_shell() {
+        assert!(is_bare_video_url("https://www.youtube.com/watch?v=abc"));
+        assert!(is_bare_video_url("https://www.youtube.com/watch?v=abc&t=42"));
+        assert!(!is_bare_video_url("https://www.youtube.com/watch?v=abc&list=PL123"));
+        assert!(!is_bare_video_url("https://www.youtube.com/playlist?list=PL123"));
+        assert!(!is_bare_video_url("not a url"));
+    }
+
+    #[test]
+    fn youtube_hosts_include_short_links() {
+        assert_eq!(youtube_host("https://youtu.be/abc"), Some(String::from("youtu.be")));
+        assert_eq!(youtube_host("https://music.youtube.com/watch?v=abc"), Some(String::from("music.youtube.com")));
+        assert_eq!(youtube_host("https://www.youtube-nocookie.com/embed/abc"), Some(String::from("www.youtube-nocookie.com")));
+        assert_eq!(youtube_host("https://notyoutube.com/watch?v=abc"), None);
+        assert_eq!(youtube_host("youtube.com/watch?v=abc"), None);
+    }
+
+    #[test]
+    fn urls_which_need_no_network_are_returned_as_they_are() {
+        let cli_config = config(&["https://youtu.be/abc"]);
+        for url in ["not a url", "ftp://example.com/video", "https://youtu.be/abc", "https://www.youtube.com/watch?v=abc"] {
+            assert_eq!(resolve_url(url, &cli_config).unwrap(), url);
+        }
+    }
+
+    #[test]
+    fn short_links_are_videos_unless_they_have_a_list() {
+        assert_eq!(analyze_url("https://youtu.be/abc").unwrap(), DownloadOption::YtVideo(0));
+        assert_eq!(analyze_url("https://youtu.be/abc?si=xyz").unwrap(), DownloadOption::YtVideo(0));
+        assert_eq!(analyze_url("https://youtu.be/abc?list=PL123").unwrap(), DownloadOption::YtPlaylist);
+        assert!(matches!(analyze_url("not a url"), Err(BlobdlError::UrlParsingError)));
+    }
+
+    #[test]
+    fn videos_in_playlists_are_playlists_without_asking() {
+        assert_eq!(analyze_url_without_asking("https://www.youtube.com/watch?v=abc&list=PL123&index=4").unwrap(), DownloadOption::YtPlaylist);
+        assert_eq!(analyze_url_without_asking("https://www.youtube.com/watch?v=abc").unwrap(), DownloadOption::YtVideo(0));
+        assert_eq!(analyze_url_without_asking("https://www.youtube.com/shorts/abc").unwrap(), DownloadOption::YtShort);
+    }
+
+    /// A web server on localhost which answers a request for a path in `redirects` with its (status, Location),
+    /// and any other request with 200. Returns its address and how many requests it got
+    fn redirect_stub(redirects: &'static [(&'static str, u16, &'static str)]) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let counter = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                counter.fetch_add(1, Ordering::SeqCst);
+
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                let _ = reader.read_line(&mut request_line);
+                // The headers end with an empty line
+                let mut header = String::new();
+                while reader.read_line(&mut header).is_ok_and(|read| read > 2) {
+                    header.clear();
+                }
+
+                let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+                let response = match redirects.iter().find(|(from, _, _)| *from == path) {
+                    Some((_, status, location)) => format!("HTTP/1.1 {} Redirect\r\nLocation: {}\r\n", status, location),
+                    None => String::from("HTTP/1.1 200 OK\r\n"),
+                };
+                let _ = write!(stream, "{}Content-Length: 0\r\nConnection: close\r\n\r\n", response);
+            }
+        });
+
+        (address, requests)
+    }
+
+    #[test]
+    fn redirect_chains_are_followed_until_youtube() {
+        let (stub, requests) = redirect_stub(&[
+            ("/short", 301, "/hop"),
+            ("/hop", 302, "https://www.youtube.com/watch?v=dQw4w9WgXcQ"),
+        ]);
+
+        let resolved = resolve_url(&format!("{}/short", stub), &config(&["https://youtu.be/a"])).unwrap();
+        assert_eq!(resolved, "https://www.youtube.com/watch?v=dQw4w9WgXcQ");
+        // youtube itself isn't asked
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn redirects_stop_after_the_maximum_depth() {
+        let (stub, requests) = redirect_stub(&[("/loop", 302, "/loop")]);
+
+        let resolved = resolve_url(&format!("{}/loop", stub), &config(&["https://youtu.be/a"])).unwrap();
+        assert_eq!(resolved, format!("{}/loop", stub));
+        assert_eq!(requests.load(Ordering::SeqCst), MAX_REDIRECTS);
+    }
+
+    #[test]
+    fn pages_which_dont_redirect_are_kept_and_resolved_once() {
+        let (stub, requests) = redirect_stub(&[]);
+        let url = format!("{}/watch", stub);
+        let mut resolver = UrlResolver::default();
+
+        assert_eq!(resolver.resolve(&url, &config(&["https://youtu.be/a"])).unwrap(), url);
+        assert_eq!(resolver.resolve(&url, &config(&["https://youtu.be/a"])).unwrap(), url);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+}
@@ -1,24 +1,47 @@
 pub mod youtube;
 
 use crate::analyzer;
+use crate::parser;
 use crate::error::BlobResult;
+use crate::secrets;
+use crate::timings::Timings;
 
 /// Asks the user for specific download preferences (output path, download format, ...) and builds
 /// a yt-dlp command according to them
 ///
+/// Options passed as command line arguments take precedence over the answers given to the wizard
+///
 /// Returns the command along with a DownloadConfig object, which contains all the user-specified preferences
-pub(crate) fn generate_command(url: &str, download_option: &analyzer::DownloadOption) -> BlobResult<(std::process::Command, youtube::config::DownloadConfig)> {
+///
+/// The time spent waiting for yt-dlp is recorded in `timings`
+///
+/// The answers of `existing_config` are reused for a video instead of asking again, see yt_video::assemble_data
+pub(crate) fn generate_command(url: &str, download_option: &analyzer::DownloadOption, existing_config: Option<&youtube::config::DownloadConfig>,
+                               cli_config: &parser::CliConfig, timings: &Timings)
+    -> BlobResult<(std::process::Command, youtube::config::DownloadConfig)>
+{
     // Get preferences from the user, various errors may occur
-    let unchecked_config = match download_option {
-        analyzer::DownloadOption::YtPlaylist => youtube::yt_playlist::assemble_data(url),
+    let unchecked_config = if let Some(audio_format) = cli_config.music() {
+        // The preset answers every question
+        youtube::music::assemble_data(url, download_option, audio_format, cli_config, timings)
+    } else {
+        match download_option {
+            analyzer::DownloadOption::YtPlaylist => youtube::yt_playlist::assemble_data(url, None, cli_config, timings),
 
-        analyzer::DownloadOption::YtVideo(id) => youtube::yt_video::assemble_data(url, *id)
+            analyzer::DownloadOption::YtPlaylistFrom(index) => youtube::yt_playlist::assemble_data(url, Some(*index), cli_config, timings),
+
+            analyzer::DownloadOption::YtVideo(_) => youtube::yt_video::assemble_data(url, existing_config, cli_config, timings),
+
+            analyzer::DownloadOption::YtShort => youtube::yt_short::assemble_data(url, cli_config, timings),
+        }
     };
 
     match unchecked_config {
-        Ok(safe) => {
+        Ok(mut safe) => {
+            safe.apply_cli_config(cli_config);
             // Everything went smoothly, now generate a yt-dlp command
             let (command, local_config) = safe.build_command();
+            cli_config.verbosity().debug(&secrets::scrub(&format!("Built the command {:?} from {:?}", command, local_config)));
             Ok((command, local_config))
         }
         // Propagate the errors
@@ -1,101 +1,744 @@
 pub mod yt_playlist;
 pub mod yt_video;
+pub mod yt_short;
 pub mod config;
+pub(crate) mod file_names;
+pub(crate) mod storage;
+pub(crate) mod music;
+pub(crate) mod remembered;
 
+use crate::analyzer;
 use crate::error::{BlobdlError, BlobResult};
+use crate::ui_prompts::*;
+use crate::parser;
+use crate::timings::{Phase, Timings};
+use crate::units::Bytes;
 use dialoguer::console::Term;
-use dialoguer::{theme::ColorfulTheme, Select, Input};
+use dialoguer::{theme::ColorfulTheme, Select, Input, MultiSelect};
 use serde::{Deserialize, Serialize};
 use serde_json;
 use std::{env, fmt};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::net::IpAddr;
 use colored::Colorize;
 
 // Functions used both in yt_video.rs and yt_playlist.rs
-/// Asks the user whether they want to download video files or audio-only
-fn get_media_selection(term: &Term) -> Result<MediaSelection, std::io::Error> {
-    let download_formats = &[
-        "Normal Video",
-        "Audio-only",
-        "Video-only"
-    ];
+/// Asks the user whether they want to download video files or audio-only, unless `preset` already answers it
+///
+/// `previous` is pre-selected. Video-only files are often mistaken for normal videos, so choosing them has to be
+/// confirmed unless `assume_yes` is set (--yes), the question is asked again otherwise
+fn get_media_selection(term: &Term, preset: Option<&MediaSelection>, previous: Option<&MediaSelection>, assume_yes: bool) -> BlobResult<MediaSelection> {
+    if let Some(media) = preset {
+        return Ok(media.clone());
+    }
+
+    // The answer given the last time the same source was downloaded from is pre-selected
+    let default = MEDIA_OPTIONS.iter().position(|(_, media)| Some(media) == previous).unwrap_or(0);
+    let labels: Vec<&str> = MEDIA_OPTIONS.iter().map(|(label, _)| *label).collect();
+
+    loop {
+        // Ask the user which format they want the downloaded files to be in
+        let user_selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("What kind of file(s) do you want to download?")
+            .default(default)
+            .items(&labels)
+            .interact_on(term)?;
+
+        let media = MEDIA_OPTIONS[user_selection].1.clone();
+        if confirm_media_selection(term, &media, assume_yes)? {
+            return Ok(media);
+        }
+    }
+}
+
+/// Whether the user really wants `media`: video-only files are often mistaken for normal videos,
+/// so choosing them has to be confirmed unless `assume_yes` is set (--yes)
+fn confirm_media_selection(term: &Term, media: &MediaSelection, assume_yes: bool) -> BlobResult<bool> {
+    if *media != MediaSelection::VideoOnly || assume_yes {
+        return Ok(true);
+    }
+    get_yes_or_no(term, VIDEO_ONLY_CONFIRM_PROMPT, false)
+}
+
+/// The answers of get_media_selection, in the order they are shown
+const MEDIA_OPTIONS: [(&str, MediaSelection); 3] = [
+    ("Normal video (with audio)", MediaSelection::FullVideo),
+    ("Audio-only", MediaSelection::AudioOnly),
+    ("Video-only (no audio track)", MediaSelection::VideoOnly),
+];
+
+/// Offers to answer the format questions like the last download from the same channel or playlist, with one keypress
+///
+/// Returns the answers to reuse, None if the questions are asked again. Nothing is offered when --media or --quality
+/// already answer them
+fn get_previous_answers(term: &Term, previous: Option<&remembered::RememberedAnswers>, cli_config: &parser::CliConfig)
+                        -> BlobResult<Option<remembered::RememberedAnswers>> {
+    let previous = match previous {
+        Some(previous) if cli_config.media().is_none() && cli_config.quality().is_none() => previous,
+        _ => return Ok(None),
+    };
 
-    // Ask the user which format they want the downloaded files to be in
-    let media_selection = Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("What kind of file(s) do you want to download?")
+    let options = [format!("{} ({})", USE_PREVIOUS_ANSWERS_PROMPT, previous), String::from(CHOOSE_AGAIN_PROMPT)];
+
+    let user_selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(PREVIOUS_ANSWERS_PROMPT)
         .default(0)
-        .items(download_formats)
+        .items(&options)
         .interact_on(term)?;
 
-    match media_selection {
-        0 => Ok(MediaSelection::FullVideo),
-        1 => Ok(MediaSelection::AudioOnly),
-        _ => Ok(MediaSelection::VideoOnly),
-    }
+    Ok((user_selection == 0).then(|| previous.clone()))
 }
 
 /// Asks for an directory to store downloaded file(s) in
 ///
 /// The current directory can be selected or one can be typed in
-fn get_output_path(term: &Term) -> BlobResult<String> {
-    let output_path_options = &[
-        "Current directory",
-        "Other [specify]",
+///
+/// System directories and running as root require confirmation, unless `allow_system_paths` is true.
+/// Cloud-synced folders and network filesystems are only warned about, the user can pick another directory
+///
+/// The question is skipped if `preset` (--output-path) is a directory the checks are fine with
+fn get_output_path(term: &Term, preset: Option<&String>, allow_system_paths: bool) -> BlobResult<String> {
+    if let Some(output_path) = preset {
+        check_output_path_safety(term, output_path, allow_system_paths)?;
+
+        if confirm_slow_storage(term, output_path)? {
+            return Ok(output_path.clone());
+        }
+    }
+
+    // Not every platform has a downloads folder
+    let downloads_folder = dirs::download_dir();
+
+    let mut output_path_options = vec![CURRENT_DIRECTORY_PROMPT];
+    if downloads_folder.is_some() {
+        output_path_options.push(DOWNLOADS_FOLDER_PROMPT);
+    }
+    output_path_options.push(CUSTOM_PATH_PROMPT);
+
+    loop {
+        let user_selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Where do you want the downloaded file(s) to be saved?")
+            .default(0)
+            .items(&output_path_options)
+            .interact_on(term)?;
+
+        let output_path = match (output_path_options[user_selection], &downloads_folder) {
+            // Return the current directory
+            (CURRENT_DIRECTORY_PROMPT, _) => env::current_dir()?
+                .as_path()
+                .display()
+                .to_string(),
+
+            (DOWNLOADS_FOLDER_PROMPT, Some(folder)) => folder.display().to_string(),
+
+            // Return a directory typed in by the user
+            _ => Input::with_theme(&ColorfulTheme::default())
+                .with_prompt("Output path:")
+                .interact_text()?,
+        };
+
+        check_output_path_safety(term, &output_path, allow_system_paths)?;
+
+        if confirm_slow_storage(term, &output_path)? {
+            return Ok(output_path);
+        }
+    }
+}
+
+/// Warns about downloading into a cloud-synced folder or a network filesystem, which can make syncing
+/// or the download itself crawl, and asks whether to continue
+///
+/// Without a terminal the warning is only printed
+fn confirm_slow_storage(term: &Term, output_path: &str) -> BlobResult<bool> {
+    let filesystem_type = storage::network_filesystem_type(std::path::Path::new(output_path));
+
+    let warning = match storage::classify_output_path(output_path, filesystem_type) {
+        Some(storage::SlowStorage::CloudSync(service)) => format!("{} {}. {}", CLOUD_FOLDER_WARNING, service, SLOW_STORAGE_ADVICE),
+        Some(storage::SlowStorage::NetworkFilesystem(filesystem)) => format!("{} ({}). {}", NETWORK_FOLDER_WARNING, filesystem, SLOW_STORAGE_ADVICE),
+        None => return Ok(true),
+    };
+
+    eprintln!("{}: {}", "WARNING".yellow(), warning);
+    if !term.is_term() {
+        return Ok(true);
+    }
+
+    get_yes_or_no(term, "Do you want to download here anyway?", true)
+}
+
+/// Makes sure the user really wants to write to a system directory or as root
+///
+/// If blob-dl isn't running in a terminal nobody can confirm, so the check fails
+fn check_output_path_safety(term: &Term, output_path: &str, allow_system_paths: bool) -> BlobResult<()> {
+    if allow_system_paths {
+        return Ok(());
+    }
+
+    let warning = if is_system_path(output_path) {
+        SYSTEM_PATH_WARNING
+    } else if is_root() {
+        RUNNING_AS_ROOT_WARNING
+    } else {
+        return Ok(());
+    };
+
+    if !term.is_term() {
+        return Err(BlobdlError::SystemPathRefused);
+    }
+
+    eprintln!("{}: {}", "WARNING".yellow(), warning);
+    if get_yes_or_no(term, "Do you want to continue anyway?", false)? {
+        Ok(())
+    } else {
+        Err(BlobdlError::SystemPathRefused)
+    }
+}
+
+/// Whether `path` is (or is inside) a directory which belongs to the operating system
+fn is_system_path(path: &str) -> bool {
+    // Trailing separators don't change the directory, except for the root itself
+    let trimmed = path.trim();
+    let normalized = match trimmed.trim_end_matches(['/', '\\']) {
+        "" if !trimmed.is_empty() => "/",
+        other => other,
+    }.to_lowercase();
+
+    // The whole directory is off-limits
+    const UNIX_ROOTS: &[&str] = &["/usr", "/etc", "/bin", "/sbin", "/boot", "/dev", "/proc", "/sys", "/lib", "/lib64", "/var/lib"];
+    const WINDOWS_ROOTS: &[&str] = &["c:\\windows", "c:\\program files", "c:\\program files (x86)", "c:\\programdata"];
+
+    // Only the directory itself is off-limits, its subdirectories are fine (/home/user, /tmp/...)
+    const TOP_LEVEL: &[&str] = &["/", "c:", "/var", "/home", "/opt", "c:\\users"];
+
+    let inside = |root: &&str| normalized == *root
+        || normalized.starts_with(&format!("{}/", root))
+        || normalized.starts_with(&format!("{}\\", root));
+
+    TOP_LEVEL.contains(&normalized.as_str())
+        || UNIX_ROOTS.iter().any(inside)
+        || WINDOWS_ROOTS.iter().any(inside)
+}
+
+/// Whether blob-dl is running with root privileges
+#[cfg(unix)]
+fn is_root() -> bool {
+    // Safety: geteuid can't fail and doesn't touch memory
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    false
+}
+
+/// Asks a question which can be answered with yes or no
+fn get_yes_or_no(term: &Term, prompt: &str, default_yes: bool) -> BlobResult<bool> {
+    let answers = &[
+        "Yes",
+        "No",
     ];
 
-    let output_path = Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("Where do you want the downloaded file(s) to be saved?")
+    let answer = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .default(if default_yes { 0 } else { 1 })
+        .items(answers)
+        .interact_on(term)?;
+
+    Ok(answer == 0)
+}
+
+/// Asks whether yt-dlp should prefer free containers and codecs (WebM, VP9, Opus) over non-free ones (MP4, AVC, AAC)
+///
+/// An exact format leaves yt-dlp nothing to choose, so the question is skipped
+fn get_free_formats_preference(term: &Term, chosen_format: &VideoQualityAndFormatPreferences) -> BlobResult<bool> {
+    if let VideoQualityAndFormatPreferences::UniqueFormat(_) = chosen_format {
+        return Ok(false);
+    }
+
+    get_yes_or_no(term, FREE_FORMATS_PROMPT, false)
+}
+
+/// Asks for settings most users don't need, they are only shown if the user asks for them
+fn get_advanced_options(term: &Term, download_config: &mut config::DownloadConfig) -> BlobResult<()> {
+    if !get_yes_or_no(term, ADVANCED_OPTIONS_PROMPT, false)? {
+        return Ok(());
+    }
+
+    download_config.plugin = get_plugin_path(term)?;
+
+    download_config.source_address = get_source_address(term)?;
+
+    download_config.ascii_filenames = get_yes_or_no(term, ASCII_NAMES_PROMPT, false)?;
+
+    // yt-dlp already does it on Windows, the answer matters for shares mounted on other systems
+    download_config.windows_safe_filenames = get_yes_or_no(term, WINDOWS_FILENAMES_PROMPT, cfg!(windows))?;
+
+    if get_yes_or_no(term, SHORT_TITLES_PROMPT, false)? {
+        download_config.max_title_bytes = Some(config::DEFAULT_MAX_TITLE_BYTES);
+    }
+
+    download_config.write_description = get_write_description(term, &download_config.download_target)?;
+
+    download_config.embed_info_json = get_embed_info_json(term, download_config.new_extension())?;
+
+    download_config.custom_id3_tags = get_custom_id3_tags(term, &download_config.audio_extraction)?;
+
+    download_config.subtitle_languages = get_subtitle_languages(term)?;
+    if !download_config.subtitle_languages.is_empty() {
+        download_config.subtitle_convert_format = get_subtitle_format(term)?;
+    }
+
+    download_config.ffmpeg_postprocessor_args = get_ffmpeg_postprocessor_args(term)?;
+
+    download_config.no_part_files = get_yes_or_no(term, NO_PART_FILES_PROMPT, false)?;
+
+    download_config.compat_options = get_compat_options(term)?;
+
+    Ok(())
+}
+
+/// Asks whether descriptions should be saved, playlists get a .description file for every video
+fn get_write_description(term: &Term, download_target: &analyzer::DownloadOption) -> BlobResult<bool> {
+    let prompt = match download_target {
+        analyzer::DownloadOption::YtPlaylist | analyzer::DownloadOption::YtPlaylistFrom(_) => PLAYLIST_WRITE_DESCRIPTION_PROMPT,
+        analyzer::DownloadOption::YtVideo(_) | analyzer::DownloadOption::YtShort => WRITE_DESCRIPTION_PROMPT,
+    };
+
+    get_yes_or_no(term, prompt, false)
+}
+
+/// Asks whether the full metadata should be embedded in the files, yt-dlp needs ffmpeg to do it
+///
+/// `extension` is the one the files will have, see DownloadConfig::new_extension
+fn get_embed_info_json(term: &Term, extension: Option<&str>) -> BlobResult<bool> {
+    if which::which("ffmpeg").is_err() || !get_yes_or_no(term, EMBED_INFO_JSON_PROMPT, false)? {
+        return Ok(false);
+    }
+
+    warn_info_json_container(extension);
+    Ok(true)
+}
+
+/// Warns that the info json can only be embedded in mkv (and mka) files, unless `extension` is one of them
+pub(crate) fn warn_info_json_container(extension: Option<&str>) {
+    if let Some(warning) = info_json_container_warning(extension) {
+        eprintln!("{}: {}", "WARNING".yellow(), warning);
+    }
+}
+
+fn info_json_container_warning(extension: Option<&str>) -> Option<String> {
+    match extension {
+        Some("mkv") | Some("mka") => None,
+        Some(extension) => Some(format!("{} {} files", INFO_JSON_CONTAINER_WARNING, extension)),
+        // youtube mostly provides mp4 and webm
+        None => Some(format!("{} {}", INFO_JSON_CONTAINER_WARNING, INFO_JSON_UNKNOWN_CONTAINER)),
+    }
+}
+
+/// Asks for ID3 tags to add to the mp3 files, only if they are converted to mp3 and a tool which can write them is installed
+fn get_custom_id3_tags(term: &Term, audio_extraction: &AudioExtractionMode) -> BlobResult<Vec<(String, String)>> {
+    let is_mp3 = matches!(audio_extraction, AudioExtractionMode::FfmpegConvert(format) if format == "mp3");
+    if !is_mp3 || crate::postprocess::id3_tool().is_none() || !get_yes_or_no(term, ID3_TAGS_PROMPT, false)? {
+        return Ok(vec![]);
+    }
+
+    let mut tags = vec![];
+    loop {
+        let tag: String = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt(ID3_TAG_INPUT_PROMPT)
+            .allow_empty(true)
+            .validate_with(|input: &String| -> Result<(), &str> {
+                match input.split_once('=') {
+                    _ if input.trim().is_empty() => Ok(()),
+                    Some((name, _)) if !name.trim().is_empty() && !name.contains(':') => Ok(()),
+                    _ => Err("Write the tag as NAME=VALUE, the name can't contain ':'"),
+                }
+            })
+            .interact_text_on(term)?;
+
+        // The input was validated, so only an empty one has no '='
+        match tag.split_once('=') {
+            Some((name, value)) => tags.push((name.trim().to_string(), value.trim().to_string())),
+            None => break,
+        }
+    }
+
+    Ok(tags)
+}
+
+/// Asks whether subtitles should be downloaded and in which languages, like yt-dlp's --sub-langs (en, it, all, ...)
+///
+/// Returns no languages if the user doesn't want subtitles
+fn get_subtitle_languages(term: &Term) -> BlobResult<Vec<String>> {
+    if !get_yes_or_no(term, SUBTITLES_PROMPT, false)? {
+        return Ok(vec![]);
+    }
+
+    let languages: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt(SUBTITLE_LANGUAGES_PROMPT)
+        .default(String::from("en"))
+        .interact_text_on(term)?;
+
+    Ok(languages.split(',').map(str::trim).filter(|language| !language.is_empty()).map(String::from).collect())
+}
+
+/// Asks which format the subtitles should be converted to, None keeps the one youtube provides
+fn get_subtitle_format(term: &Term) -> BlobResult<Option<SubtitleFormat>> {
+    let mut options = vec![String::from(KEEP_SUBTITLE_FORMAT_PROMPT)];
+    options.extend(SubtitleFormat::ALL.iter().map(|format| format.to_string()));
+
+    let user_selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(SUBTITLE_FORMAT_PROMPT)
         .default(0)
-        .items(output_path_options)
+        .items(&options)
+        .interact_on(term)?;
+
+    // The first option keeps youtube's format
+    Ok(user_selection.checked_sub(1).map(|i| SubtitleFormat::ALL[i]))
+}
+
+/// Every option yt-dlp accepts in --compat-options, it changes often so unknown ones are only warned about
+pub(crate) const KNOWN_COMPAT_OPTIONS: &[&str] = &[
+    "filename", "filename-sanitization", "format-sort", "format-spec", "multistreams", "no-live-chat",
+    "no-clean-infojson", "no-config-locations", "no-attach-info-json", "embed-metadata", "embed-thumbnail-atomicparsley",
+    "abort-on-error", "no-playlist-metafiles", "no-youtube-channel-redirect", "no-youtube-unavailable-videos",
+    "no-youtube-prefer-utc-upload-date", "no-external-downloader-progress", "no-certifi", "no-direct-merge",
+    "manifest-filesize-approx", "allow-unsafe-ext", "prefer-vp9-sort", "playlist-match-filter",
+    "no-keep-subs", "playlist-index", "2021", "2022", "2023", "youtube-dl", "youtube-dlc",
+];
+
+/// The compat options offered in the wizard, the ones old youtube-dl scripts usually depend on
+const COMMON_COMPAT_OPTIONS: [&str; 3] = ["no-youtube-channel-redirect", "no-live-chat", "manifest-filesize-approx"];
+
+/// Lets the user revert some of yt-dlp's behaviours to youtube-dl's
+fn get_compat_options(term: &Term) -> BlobResult<Vec<String>> {
+    if !get_yes_or_no(term, COMPAT_OPTIONS_PROMPT, false)? {
+        return Ok(vec![]);
+    }
+
+    let user_selection = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt(COMPAT_OPTIONS_SELECTION_PROMPT)
+        .items(&COMMON_COMPAT_OPTIONS)
         .interact_on(term)?;
 
-    match output_path {
-        // Return the current directory
-        0 => Ok(env::current_dir()?
-            .as_path()
-            .display()
-            .to_string()),
+    Ok(user_selection.into_iter().map(|i| COMMON_COMPAT_OPTIONS[i].to_string()).collect())
+}
 
-        // Return a directory typed in by the user
-        _ => Ok(Input::with_theme(&ColorfulTheme::default())
-            .with_prompt("Output path:")
-            .interact_text()?),
+/// Warns about the compat options yt-dlp probably won't recognize, they are still passed on in case they are newer than this list
+pub(crate) fn warn_unknown_compat_options(options: &[String]) {
+    for option in unknown_compat_options(options) {
+        eprintln!("{}: {} {}", "WARNING".yellow(), UNKNOWN_COMPAT_OPTION, option);
     }
 }
 
+/// The options which aren't in KNOWN_COMPAT_OPTIONS
+fn unknown_compat_options(options: &[String]) -> Vec<&str> {
+    options.iter()
+        .map(String::as_str)
+        // Options can be negated with a leading '-'
+        .filter(|option| !KNOWN_COMPAT_OPTIONS.contains(&option.trim_start_matches('-')))
+        .collect()
+}
+
+/// Asks whether only the videos with a certain number of views should be downloaded, and which bounds
+fn get_view_count_filter(term: &Term) -> BlobResult<Option<ViewCountFilter>> {
+    if !get_yes_or_no(term, VIEW_COUNT_FILTER_PROMPT, false)? {
+        return Ok(None);
+    }
+
+    let filter = ViewCountFilter { min: get_view_count(term, MIN_VIEWS_PROMPT)?, max: get_view_count(term, MAX_VIEWS_PROMPT)? };
+
+    Ok(filter.match_filter().map(|_| filter))
+}
+
+/// Asks for a number of views, None if the user leaves it empty
+fn get_view_count(term: &Term, prompt: &str) -> BlobResult<Option<u64>> {
+    let views: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .allow_empty(true)
+        .validate_with(|input: &String| -> Result<(), &str> {
+            if input.trim().is_empty() || input.trim().parse::<u64>().is_ok() {
+                Ok(())
+            } else {
+                Err("This isn't a whole number")
+            }
+        })
+        .interact_text_on(term)?;
+
+    // The input was validated, so parsing only fails when it's empty
+    Ok(views.trim().parse().ok())
+}
+
+/// Asks for the IP address of the network interface yt-dlp should use
+fn get_source_address(term: &Term) -> BlobResult<Option<IpAddr>> {
+    if !get_yes_or_no(term, SOURCE_ADDRESS_PROMPT, false)? {
+        return Ok(None);
+    }
+
+    let address: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("IP address:")
+        .validate_with(|input: &String| -> Result<(), &str> {
+            match input.trim().parse::<IpAddr>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err("This isn't a valid IPv4 or IPv6 address"),
+            }
+        })
+        .interact_text_on(term)?;
+
+    // The input was validated, so parsing can't fail
+    Ok(address.trim().parse().ok())
+}
+
+/// Asks for arguments to pass to ffmpeg every time yt-dlp runs it (merging, converting, ...), only if ffmpeg is installed
+///
+/// They aren't checked, so the user is warned that invalid ones make the downloads fail
+fn get_ffmpeg_postprocessor_args(term: &Term) -> BlobResult<Option<String>> {
+    if which::which("ffmpeg").is_err() || !get_yes_or_no(term, FFMPEG_ARGS_PROMPT, false)? {
+        return Ok(None);
+    }
+
+    eprintln!("{}: {}", "WARNING".yellow(), FFMPEG_ARGS_WARNING);
+
+    let args: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt(FFMPEG_ARGS_INPUT_PROMPT)
+        .allow_empty(true)
+        .interact_text_on(term)?;
+
+    Ok(Some(args.trim().to_string()).filter(|args| !args.is_empty()))
+}
+
+/// Asks for the shared library which should be run on every downloaded file (see plugin.rs)
+fn get_plugin_path(term: &Term) -> BlobResult<Option<PathBuf>> {
+    if !get_yes_or_no(term, PLUGIN_PROMPT, false)? {
+        return Ok(None);
+    }
+
+    let path: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt(PLUGIN_PATH_PROMPT)
+        .validate_with(|input: &String| -> Result<(), &str> {
+            if PathBuf::from(input).is_file() {
+                Ok(())
+            } else {
+                Err("This file doesn't exist")
+            }
+        })
+        .interact_text_on(term)?;
+
+    Ok(Some(PathBuf::from(path)))
+}
+
+/// Lets the user pick some of a video's chapters, only those parts of the video are downloaded
+///
+/// Cutting the video requires ffmpeg. An empty list means the whole video
+fn get_chapter_selection(term: &Term, url: &str, cli_config: &parser::CliConfig, timings: &Timings, cache: &mut FormatCache) -> BlobResult<Vec<ChapterRange>> {
+    if which::which("ffmpeg").is_err() || !get_yes_or_no(term, CHAPTERS_PROMPT, false)? {
+        return Ok(vec![]);
+    }
+
+    let video_specs = get_video_specs(url, cli_config, timings, cache)?;
+
+    let chapters = video_specs.chapters.unwrap_or_default();
+    if chapters.is_empty() {
+        eprintln!("{}", NO_CHAPTERS);
+        return Ok(vec![]);
+    }
+
+    let chapter_options: Vec<String> = chapters.iter()
+        .map(|chapter| format!("[{} - {}] {}", format_timestamp(chapter.start_time), format_timestamp(chapter.end_time), chapter.title))
+        .collect();
+
+    let user_selection = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt(CHAPTER_SELECTION_PROMPT)
+        .items(&chapter_options)
+        .interact_on(term)?;
+
+    let selected: Vec<&Chapter> = user_selection.into_iter().map(|i| &chapters[i]).collect();
+
+    Ok(merge_chapters(&selected))
+}
+
+/// Turns a list of chapters into as few ranges as possible, chapters which follow each other end up in the same range
+fn merge_chapters(chapters: &[&Chapter]) -> Vec<ChapterRange> {
+    // Chapter boundaries aren't always exact, gaps smaller than this still count as adjacent
+    const MAX_GAP_SECONDS: f64 = 0.5;
+
+    let mut sorted = chapters.to_vec();
+    sorted.sort_by(|a, b| a.start_time.total_cmp(&b.start_time));
+
+    let mut ranges: Vec<ChapterRange> = vec![];
+    for chapter in sorted {
+        match ranges.last_mut() {
+            Some(range) if chapter.start_time <= range.end + MAX_GAP_SECONDS => {
+                range.end = range.end.max(chapter.end_time);
+                range.titles.push(chapter.title.clone());
+            }
+            _ => ranges.push(ChapterRange {
+                start: chapter.start_time,
+                end: chapter.end_time,
+                titles: vec![chapter.title.clone()],
+            }),
+        }
+    }
+
+    ranges
+}
+
+/// Formats a number of seconds as [h:]mm:ss
+pub(crate) fn format_timestamp(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds % 3600 / 60, seconds % 60)
+    } else {
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    }
+}
 
 use spinoff;
 use std::process;
-// Running yt-dlp -j <...>
-use execute::Execute;
 
 /// Returns the output of <yt-dlp -j url>: a JSON dump of all the available format information for a video
-fn get_ytdlp_formats(url: &str) -> Result<process::Output, std::io::Error> {
+///
+/// If `single_video` is true only the video's information is fetched, even if the url also refers to a playlist.
+///
+/// yt-dlp is stopped if it doesn't print anything for `timeout`. The timeout restarts every time a video's information
+/// arrives, so big playlists can take longer
+fn fetch_ytdlp_formats(url: &str, single_video: bool, cli_config: &parser::CliConfig) -> BlobResult<process::Output> {
     // Neat animation to entertain the user while the information is being downloaded
-    let mut sp = spinoff::Spinner::new(spinoff::spinners::Dots10, "Fetching available formats...", spinoff::Color::Cyan);
+    let mut sp = spinoff::Spinner::new_with_stream(spinoff::spinners::Dots10, "Fetching available formats...", spinoff::Color::Cyan, spinoff::Streams::Stderr);
 
-    let mut command = process::Command::new("yt-dlp");
+    let mut command = crate::ytdlp_command();
     // Get a JSON dump of all the available formats related to this url
     command.arg("-j");
     // Continue even if you get errors
     command.arg("-i");
+    if single_video && url_has_playlist_param(url) {
+        // Without this yt-dlp would dump the information of every video in the playlist
+        command.arg("--no-playlist");
+    }
+    command.args(cli_config.client_override().args());
+    if let Some(country) = cli_config.geo_bypass_country() {
+        // Which formats are available can depend on the country
+        command.arg("--geo-bypass-country").arg(country);
+    }
     command.arg(url);
 
-    // Redirect the output to a variable instead of to the screen
-    command.stdout(process::Stdio::piped());
-    // Don't show errors and warnings
-    command.stderr(process::Stdio::piped());
-    let output = command.execute_output();
+    // Every line is the JSON dump of one video
+    let output = match crate::ytdlp_output(&mut command, cli_config.fetch_timeout())? {
+        Some(output) => output,
+        None => {
+            sp.fail("yt-dlp took too long to answer");
+            return Err(BlobdlError::FormatFetchTimeout);
+        }
+    };
 
     // Stop the ui spinner
     sp.success("Formats downloaded successfully".bold().to_string().as_str());
 
-    output
+    // Errors and warnings aren't shown
+    Ok(process::Output { stderr: vec![], ..output })
+}
+
+/// Runs fetch_ytdlp_formats, the time it takes is recorded in `timings`
+fn get_ytdlp_formats(url: &str, single_video: bool, cli_config: &parser::CliConfig, timings: &Timings) -> BlobResult<process::Output> {
+    timings.count_process();
+    timings.time(Phase::FormatFetching, || fetch_ytdlp_formats(url, single_video, cli_config))
+}
+
+/// Returns every video of a playlist, in the playlist's order, without fetching their formats (yt-dlp --flat-playlist -j)
+fn get_playlist_entries(url: &str, cli_config: &parser::CliConfig, timings: &Timings) -> BlobResult<Vec<PlaylistEntry>> {
+    timings.count_process();
+
+    let mut sp = spinoff::Spinner::new_with_stream(spinoff::spinners::Dots10, "Fetching the list of videos...", spinoff::Color::Cyan, spinoff::Streams::Stderr);
+
+    let mut command = crate::ytdlp_command();
+    command.arg("--flat-playlist");
+    command.arg("-j");
+    command.arg("--yes-playlist");
+    command.args(cli_config.client_override().args());
+    if let Some(country) = cli_config.geo_bypass_country() {
+        // Which formats are available can depend on the country
+        command.arg("--geo-bypass-country").arg(country);
+    }
+    command.arg(url);
+
+    let output = match timings.time(Phase::FormatFetching, || crate::ytdlp_output(&mut command, cli_config.fetch_timeout()))? {
+        Some(output) => output,
+        None => {
+            sp.fail("yt-dlp took too long to answer");
+            return Err(BlobdlError::ListFetchTimeout);
+        }
+    };
+    if !output.status.success() {
+        sp.fail("yt-dlp couldn't list the videos");
+        crate::check_ytdlp_status(&output)?;
+    }
+
+    sp.success("List of videos downloaded successfully".bold().to_string().as_str());
+
+    // Every line describes one video, lines which can't be parsed are skipped
+    Ok(std::str::from_utf8(&output.stdout)?
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Whether the url's query contains a `list` parameter, which makes yt-dlp treat it as a playlist
+fn url_has_playlist_param(url: &str) -> bool {
+    match url::Url::parse(url) {
+        Ok(parsed) => parsed.query_pairs().any(|(key, _)| key == "list"),
+        Err(_) => false,
+    }
+}
+
+/// Asks whether audio-only downloads should keep the audio stream youtube provides or be re-encoded by ffmpeg
+///
+/// Without ffmpeg, or when the user doesn't want audio-only files, the audio stream is always kept as it is
+fn get_audio_extraction_mode(term: &Term, media_selected: &MediaSelection) -> BlobResult<AudioExtractionMode> {
+    if *media_selected != MediaSelection::AudioOnly || which::which("ffmpeg").is_err() {
+        return Ok(AudioExtractionMode::NativeExtraction);
+    }
+
+    let mode_options = &[
+        NATIVE_EXTRACTION_PROMPT,
+        FFMPEG_CONVERT_PROMPT,
+    ];
+
+    let user_selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("How do you want the audio to be extracted?")
+        .default(0)
+        .items(mode_options)
+        .interact_on(term)?;
+
+    match user_selection {
+        0 => Ok(AudioExtractionMode::NativeExtraction),
+        _ => Ok(AudioExtractionMode::FfmpegConvert(choose_container(term, media_selected)?)),
+    }
 }
 
 /// Ask the user what format they want the downloaded file to be recoded to (yt-dlp postprocessor) REQUIRES FFMPEG
 fn convert_to_format(term: &Term, media_selected: &MediaSelection)
                      -> BlobResult<VideoQualityAndFormatPreferences>
 {
+    Ok(VideoQualityAndFormatPreferences::ConvertTo(choose_container(term, media_selected)?))
+}
+
+/// Ask the user which container the downloaded file should be moved to, without re-encoding it REQUIRES FFMPEG
+fn remux_to_format(term: &Term) -> BlobResult<VideoQualityAndFormatPreferences> {
+    // Containers which can hold the streams youtube provides without re-encoding them
+    let format_options = ["mp4", "mkv", "mov", "webm", "avi", "flv"];
+
+    let user_selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Which container do you want the final file to be in?")
+        .default(0)
+        .items(&format_options)
+        .interact_on(term)?;
+
+    Ok(VideoQualityAndFormatPreferences::RemuxTo(format_options[user_selection].to_string()))
+}
+
+/// Lists the containers ffmpeg can produce for media_selected and returns the one the user picked
+fn choose_container(term: &Term, media_selected: &MediaSelection) -> BlobResult<String> {
     // Available formats for recoding
     let format_options = match *media_selected {
         // Only show audio-only formats
@@ -113,59 +756,251 @@ fn convert_to_format(term: &Term, media_selected: &MediaSelection)
         .items(&format_options)
         .interact_on(term)?;
 
-    Ok(VideoQualityAndFormatPreferences::ConvertTo(format_options[user_selection].to_string()))
+    Ok(format_options[user_selection].to_string())
+}
+
+/// The formats of the videos fetched during the wizard, the same video's formats are needed by more questions
+/// (formats table, format selection, chapters) and yt-dlp is slow to fetch them
+#[derive(Debug, Default)]
+pub(crate) struct FormatCache {
+    specs: HashMap<String, VideoSpecs>,
+}
+
+impl FormatCache {
+    pub(crate) fn new() -> FormatCache {
+        FormatCache::default()
+    }
+
+    fn get(&self, url: &str) -> Option<VideoSpecs> {
+        self.specs.get(url).cloned()
+    }
+
+    fn set(&mut self, url: &str, specs: VideoSpecs) {
+        self.specs.insert(url.to_string(), specs);
+    }
+}
+
+/// The formats and chapters of the single video `url` refers to, yt-dlp is only asked the first time
+fn get_video_specs(url: &str, cli_config: &parser::CliConfig, timings: &Timings, cache: &mut FormatCache) -> BlobResult<VideoSpecs> {
+    if let Some(specs) = cache.get(url) {
+        return Ok(specs);
+    }
+
+    let ytdl_formats = get_ytdlp_formats(url, true, cli_config, timings)?;
+    let specs = serialize_formats(
+        std::str::from_utf8(&ytdl_formats.stdout[..])?
+            // Only the requested video's information was fetched, so there is only one JSON root
+            .lines()
+            .next()
+            .ok_or(BlobdlError::JsonSerializationError)?
+    )?;
+
+    cache.set(url, specs.clone());
+    Ok(specs)
+}
+
+/// Fetches the formats of the video `video_id` again and finds the closest equivalent of the exact format
+/// `download_config` was configured with, for when youtube changed its format ids after the wizard fetched them
+///
+/// None if the format wasn't picked from a list of formats (then what it stood for is unknown) or nothing is close to it
+pub(crate) fn refresh_stale_format(download_config: &config::DownloadConfig, video_id: &str, cli_config: &parser::CliConfig)
+                                   -> BlobResult<Option<VideoFormat>>
+{
+    let stale = match &download_config.exact_format {
+        Some(stale) => stale,
+        None => return Ok(None),
+    };
+
+    let url = format!("https://www.youtube.com/watch?v={}", video_id);
+    let ytdl_formats = fetch_ytdlp_formats(&url, true, cli_config)?;
+    let specs = serialize_formats(
+        std::str::from_utf8(&ytdl_formats.stdout[..])?
+            .lines()
+            .next()
+            .ok_or(BlobdlError::JsonSerializationError)?
+    )?;
+
+    Ok(nearest_format(stale, specs.formats()).cloned())
 }
 
 /// Serializes the information about all the formats available for 1 video
+///
+/// Some dumps (like the ones of live streams) have no list of formats, only the fields of the format yt-dlp
+/// would download at the top level: that format is the only one available. Without those either the video
+/// has no formats, which the callers handle
 fn serialize_formats(json_dump: &str) -> BlobResult<VideoSpecs> {
-    let result = serde_json::from_str(json_dump);
-    match result {
-        Ok(cool) => Ok(cool),
-        Err(err) => Err(BlobdlError::SerdeError(err))
+    let mut specs: VideoSpecs = serde_json::from_str(json_dump)?;
+
+    if specs.formats.is_empty() {
+        if let Ok(format) = serde_json::from_str(json_dump) {
+            specs.formats.push(format);
+        }
     }
+
+    Ok(specs)
 }
 
-/// Checks if format has conflicts with media_selected (like a video only format and an audio-only media_selection
-///
-/// Returns true format and media_selected are compatible
-fn check_format(format: &VideoFormat, media_selected: &MediaSelection) -> bool {
-    // Skip image and weird formats (examples of strange formats ids: 233, 234, sb2, sb1, sb0)
+/// Whether a format can be offered for a media selection, and why not
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FormatCompatibility {
+    Compatible,
+    /// Like a video-only format and an audio-only media selection
+    IncompatibleMediaType,
+    /// A grid of thumbnails, not a video
+    IsStoryboard,
+    /// yt-dlp doesn't know its size, because it streams it from a HLS/DASH manifest (examples of ids: 233, 234)
+    IsManifest,
+}
+
+/// Checks if format has conflicts with media_selected (like a video only format and an audio-only media_selection)
+fn check_format(format: &VideoFormat, media_selected: &MediaSelection) -> FormatCompatibility {
+    if is_storyboard_format(format) {
+        return FormatCompatibility::IsStoryboard;
+    }
     if format.filesize.is_none() {
-        return false;
+        return FormatCompatibility::IsManifest;
     }
     // Skip audio-only files if the user wants full video
-    if *media_selected == MediaSelection::FullVideo && format.resolution == "audio only" {
-        return false;
+    if *media_selected == MediaSelection::FullVideo && format.resolution == Resolution::AudioOnly {
+        return FormatCompatibility::IncompatibleMediaType;
     }
     // Skip video files if the user wants audio-only
-    if *media_selected == MediaSelection::AudioOnly && format.resolution != "audio only" {
-        return false;
+    if *media_selected == MediaSelection::AudioOnly && format.resolution != Resolution::AudioOnly {
+        return FormatCompatibility::IncompatibleMediaType;
     }
     if let Some(acodec) = &format.acodec {
         // Skip video-only files if the user doesn't want video-only
         if *media_selected == MediaSelection::FullVideo && acodec == "none" {
-            return false;
+            return FormatCompatibility::IncompatibleMediaType;
         }
         //Skip normal video if the user wants video-only
         if *media_selected == MediaSelection::VideoOnly && acodec != "none" {
-            return false;
+            return FormatCompatibility::IncompatibleMediaType;
         }
     }
-    true
+    FormatCompatibility::Compatible
+}
+
+/// Explains that none of `formats` fit `media_selected` and lets the user switch to a media selection which has some,
+/// the formats were already fetched so they are filtered again without asking yt-dlp
+///
+/// Returns the new media selection, None if the user would rather download the best quality yt-dlp finds.
+/// Switching to video-only has to be confirmed like in get_media_selection, the options are shown again otherwise
+fn triage_empty_formats(term: &Term, formats: &[&VideoFormat], media_selected: &MediaSelection, assume_yes: bool)
+                        -> BlobResult<Option<MediaSelection>> {
+    eprintln!("{} {}", NO_MATCHING_FORMATS.yellow(), media_selected);
+
+    let alternatives = alternative_selections(formats, media_selected);
+    let mut options: Vec<String> = alternatives.iter()
+        .map(|(selection, count)| format!("Switch to {} ({} formats)", selection, count))
+        .collect();
+    options.push(String::from(BEST_QUALITY_FALLBACK_PROMPT));
+
+    loop {
+        let user_selection = Select::with_theme(&ColorfulTheme::default())
+            .with_prompt(EMPTY_FORMATS_PROMPT)
+            .default(0)
+            .items(&options)
+            .interact_on(term)?;
+
+        match alternatives.get(user_selection) {
+            Some((selection, _)) if !confirm_media_selection(term, selection, assume_yes)? => continue,
+            alternative => return Ok(alternative.map(|(selection, _)| selection.clone())),
+        }
+    }
+}
+
+/// The media selections other than `media_selected` which some of `formats` fit, with how many formats fit each one
+fn alternative_selections(formats: &[&VideoFormat], media_selected: &MediaSelection) -> Vec<(MediaSelection, usize)> {
+    [MediaSelection::FullVideo, MediaSelection::VideoOnly, MediaSelection::AudioOnly]
+        .into_iter()
+        .filter(|selection| selection != media_selected)
+        .map(|selection| {
+            let count = formats.iter().filter(|format| check_format(format, &selection) == FormatCompatibility::Compatible).count();
+            (selection, count)
+        })
+        .filter(|(_, count)| *count > 0)
+        .collect()
+}
+
+/// Whether the format is a storyboard (ids sb0, sb1, ...), which can't be downloaded as a video
+fn is_storyboard_format(format: &VideoFormat) -> bool {
+    format.format_id.starts_with("sb")
+        || format.format_note.as_deref().is_some_and(|note| note.contains("storyboard"))
 }
 
 // Common enums and structs
 /// Whether the user wants to download video files or audio-only
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
 pub(crate) enum MediaSelection {
     FullVideo,
     VideoOnly,
     AudioOnly,
 }
 
+impl fmt::Display for MediaSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MediaSelection::FullVideo => write!(f, "normal video"),
+            MediaSelection::VideoOnly => write!(f, "video-only"),
+            MediaSelection::AudioOnly => write!(f, "audio-only"),
+        }
+    }
+}
+
+/// What is downloaded for every video
+#[derive(Debug, Default, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub(crate) enum DownloadMode {
+    /// The video (or its audio), in the chosen format
+    #[default]
+    Full,
+    /// Only the thumbnail, converted to jpg
+    ThumbnailOnly,
+}
+
+/// How the file of an audio-only download is produced
+#[derive(Debug, Default, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub(crate) enum AudioExtractionMode {
+    /// yt-dlp downloads the best audio stream as it is: fastest, but the format depends on the source
+    #[default]
+    NativeExtraction,
+    /// ffmpeg re-encodes the audio to this format
+    FfmpegConvert(String),
+}
+
+/// The formats yt-dlp can convert subtitles to
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub(crate) enum SubtitleFormat {
+    Srt,
+    Vtt,
+    Ass,
+    Lrc,
+}
+
+impl SubtitleFormat {
+    pub(crate) const ALL: [SubtitleFormat; 4] = [SubtitleFormat::Srt, SubtitleFormat::Vtt, SubtitleFormat::Ass, SubtitleFormat::Lrc];
+
+    /// The format called `name` by yt-dlp (srt, vtt, ...)
+    pub(crate) fn from_name(name: &str) -> Option<SubtitleFormat> {
+        SubtitleFormat::ALL.into_iter().find(|format| format.to_string() == name)
+    }
+}
+
+/// The name yt-dlp gives the format, which is also its extension
+impl fmt::Display for SubtitleFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubtitleFormat::Srt => write!(f, "srt"),
+            SubtitleFormat::Vtt => write!(f, "vtt"),
+            SubtitleFormat::Ass => write!(f, "ass"),
+            SubtitleFormat::Lrc => write!(f, "lrc"),
+        }
+    }
+}
+
 /// All the information about a particular video format
-#[derive(Deserialize, Serialize, Debug, PartialOrd, PartialEq)]
-struct VideoFormat {
+#[derive(Deserialize, Serialize, Debug, Clone, PartialOrd, PartialEq)]
+pub(crate) struct VideoFormat {
     format_id: String,
     // File extension
     ext: String,
@@ -173,10 +1008,10 @@ struct VideoFormat {
     fps: Option<f64>,
     // How many audio channels are available, is null for video-only formats. Unavailable on weird sb* formats
     audio_channels: Option<u64>,
-    // Video resolution, is "audio only" for audio-only formats
-    resolution: String,
-    // Measured in MB. Unavailable on sb* formats
-    filesize: Option<u64>,
+    // Video resolution, AudioOnly for audio-only formats
+    resolution: Resolution,
+    // Unavailable on sb* formats
+    filesize: Option<Bytes>,
     // Video codec, can be "none"
     vcodec: String,
     // Audio codec, can be "none" or straight up not exist (like in mp4 audio-only formats)
@@ -186,22 +1021,273 @@ struct VideoFormat {
     // Total average bitrate
     tbr: Option<f64>,
     // When filesize is null, this may be available
-    filesize_approx: Option<u64>,
+    filesize_approx: Option<Bytes>,
+    // Human readable description, "storyboard" for storyboard formats
+    format_note: Option<String>,
+}
+
+/// The resolution of a format, yt-dlp describes it as "audio only" or "WIDTHxHEIGHT"
+#[derive(Deserialize, Serialize, Debug, Clone, PartialOrd, PartialEq)]
+#[serde(from = "String", into = "String")]
+enum Resolution {
+    AudioOnly,
+    Video { width: u32, height: u32 },
+    /// What yt-dlp writes when it only knows part of the resolution, like "720p" or "unknown"
+    Unknown(String),
+}
+
+impl Resolution {
+    /// HD starts at 720p
+    fn is_hd(&self) -> bool {
+        match self {
+            Resolution::Video { height, .. } => *height >= 720,
+            _ => false,
+        }
+    }
+
+    /// Width divided by height, None if the format has no known frame
+    fn aspect_ratio(&self) -> Option<f64> {
+        match self {
+            Resolution::Video { width, height } if *height > 0 => Some(*width as f64 / *height as f64),
+            _ => None,
+        }
+    }
+}
+
+impl From<String> for Resolution {
+    /// yt-dlp can add a note after the resolution, like "1920x1080 (default)"
+    fn from(text: String) -> Self {
+        if text == "audio only" {
+            return Resolution::AudioOnly;
+        }
+
+        let size = text.split_whitespace().next().and_then(|size| size.split_once('x'));
+        match size.and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?))) {
+            Some((width, height)) => Resolution::Video { width, height },
+            None => Resolution::Unknown(text),
+        }
+    }
+}
+
+impl From<Resolution> for String {
+    fn from(resolution: Resolution) -> Self {
+        resolution.to_string()
+    }
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // pad() keeps width and alignment working, like in "{:<13}"
+        match self {
+            Resolution::AudioOnly => f.pad("audio only"),
+            Resolution::Video { width, height } => f.pad(&format!("{}x{}", width, height)),
+            Resolution::Unknown(text) => f.pad(text),
+        }
+    }
 }
 
 // A list of all the formats available for a single video
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 struct VideoSpecs {
+    // Missing for some live streams, see serialize_formats
+    #[serde(default)]
     formats: Vec<VideoFormat>,
+    // Null or missing when the uploader didn't split the video in chapters
+    #[serde(default)]
+    chapters: Option<Vec<Chapter>>,
+    // The id of the uploader's channel, the source the wizard's answers are remembered for
+    #[serde(default)]
+    channel_id: Option<String>,
+}
+
+/// A video in a playlist, as listed by yt-dlp --flat-playlist
+#[derive(Deserialize, Serialize, Debug)]
+struct PlaylistEntry {
+    id: String,
+    // Can be missing for deleted or private videos
+    title: Option<String>,
+    // The playlist's title, used by yt-dlp to name the output folder
+    #[serde(default)]
+    playlist: Option<String>,
+    // The entry's position in the playlist, counting hidden videos like yt-dlp does
+    #[serde(default)]
+    playlist_index: Option<usize>,
+    // "public", "needs_auth", "subscriber_only", "premium_only", ... when youtube says
+    #[serde(default)]
+    availability: Option<String>,
+    // 18 for age-restricted videos, youtube doesn't always say
+    #[serde(default)]
+    age_limit: Option<u32>,
+}
+
+impl PlaylistEntry {
+    /// Whether the video is private or deleted: youtube hides it and yt-dlp can't download it, but it still takes a position
+    ///
+    /// A missing title alone doesn't mean that, flat playlists don't always list one
+    fn is_hidden(&self) -> bool {
+        matches!(self.title.as_deref(), Some("[Private video]") | Some("[Deleted video]"))
+            || self.availability.as_deref() == Some("private")
+    }
+
+    /// Why yt-dlp will most likely fail to download the video, according to its metadata
+    fn predicted_failure(&self) -> Option<&'static str> {
+        match self.availability.as_deref() {
+            Some("premium_only") => Some(PREMIUM_ONLY_REASON),
+            Some("subscriber_only") => Some(MEMBERS_ONLY_REASON),
+            Some("needs_auth") => Some(NEEDS_AUTH_REASON),
+            _ if self.age_limit.is_some_and(|age_limit| age_limit >= 18) => Some(AGE_RESTRICTED_REASON),
+            _ => None,
+        }
+    }
+
+    /// Whether the browser's cookies can fix the predicted failure: it only needs a signed-in user, not a membership
+    fn needs_sign_in(&self) -> bool {
+        matches!(self.predicted_failure(), Some(NEEDS_AUTH_REASON) | Some(AGE_RESTRICTED_REASON))
+    }
+}
+
+/// Pairs every entry with its position in the playlist as yt-dlp numbers it (counting from 1, hidden videos included)
+///
+/// The playlist_index in the dump is preferred, so that an entry which couldn't be parsed doesn't shift the ones after it
+fn positioned_entries(entries: &[PlaylistEntry]) -> Vec<(usize, &PlaylistEntry)> {
+    let mut positioned = vec![];
+    let mut previous = 0;
+    for entry in entries {
+        let position = entry.playlist_index.unwrap_or(previous + 1);
+        positioned.push((position, entry));
+        previous = position;
+    }
+    positioned
+}
+
+/// Pairs every line of yt-dlp -j with the position in the playlist of the video it describes, like positioned_entries
+///
+/// Videos yt-dlp couldn't fetch have no line, so the line number isn't the position. A line which can't be read
+/// is taken to come right after the previous one
+fn positioned_dumps(json_dumps: &str) -> Vec<(usize, &str)> {
+    #[derive(Deserialize)]
+    struct Position {
+        #[serde(default)]
+        playlist_index: Option<usize>,
+    }
+
+    let mut positioned = vec![];
+    let mut previous = 0;
+    for json_dump in json_dumps.lines() {
+        let position = serde_json::from_str::<Position>(json_dump).ok()
+            .and_then(|dump| dump.playlist_index)
+            .unwrap_or(previous + 1);
+        positioned.push((position, json_dump));
+        previous = position;
+    }
+    positioned
+}
+
+/// A chapter of a video, times are in seconds
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct Chapter {
+    start_time: f64,
+    end_time: f64,
+    title: String,
+}
+
+/// A part of a video made of one or more adjacent chapters, only this part gets downloaded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ChapterRange {
+    start: f64,
+    end: f64,
+    titles: Vec<String>,
+}
+
+impl ChapterRange {
+    /// The value for yt-dlp's --download-sections
+    ///
+    /// A single chapter is selected by its title, so that yt-dlp can name the file after it
+    pub(crate) fn to_section_arg(&self) -> String {
+        match &self.titles[..] {
+            [title] => format!("^{}$", escape_regex(title)),
+            _ => format!("*{}-{}", self.start, self.end),
+        }
+    }
+}
+
+/// Escapes the characters which have a special meaning in python's regular expressions
+fn escape_regex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.^$*+?{}[]|()".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The player client yt-dlp should get videos with and the user agent it should send, yt-dlp's defaults are used for
+/// what isn't set
+///
+/// Youtube sometimes blocks the formats of a client, another one can still download them
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ClientOverride {
+    pub(crate) player_client: Option<String>,
+    pub(crate) user_agent: Option<String>,
+}
+
+/// The player client videos are retried with when youtube blocks the default one
+pub(crate) const FALLBACK_PLAYER_CLIENT: &str = "android";
+
+impl ClientOverride {
+    /// The youtube extractor argument which picks the player client, like `player_client=android`
+    pub(crate) fn extractor_arg(&self) -> Option<String> {
+        self.player_client.as_ref().map(|client| format!("player_client={}", client))
+    }
+
+    /// The yt-dlp arguments which apply the override, like `--extractor-args youtube:player_client=android`
+    pub(crate) fn args(&self) -> Vec<String> {
+        let mut args = vec![];
+        if let Some(extractor_arg) = self.extractor_arg() {
+            args.push(String::from("--extractor-args"));
+            args.push(format!("youtube:{}", extractor_arg));
+        }
+        if let Some(user_agent) = &self.user_agent {
+            args.push(String::from("--user-agent"));
+            args.push(user_agent.clone());
+        }
+        args
+    }
+}
+
+/// The view counts the videos of a playlist have to be within to be downloaded, either bound can be left out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ViewCountFilter {
+    pub(crate) min: Option<u64>,
+    pub(crate) max: Option<u64>,
+}
+
+impl ViewCountFilter {
+    /// The condition for yt-dlp's --match-filters, None if neither bound is set
+    pub(crate) fn match_filter(&self) -> Option<String> {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => Some(format!("view_count > {} & view_count < {}", min, max)),
+            (Some(min), None) => Some(format!("view_count > {}", min)),
+            (None, Some(max)) => Some(format!("view_count < {}", max)),
+            (None, None) => None,
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 /// What quality and format the user wants a specific video to be downloaded in
+///
+/// Serialized as {"kind": ..., "format": ...} so that the format ids of UniqueFormat can't be mistaken for the other variants
+#[serde(tag = "kind", content = "format", rename_all = "snake_case")]
 pub(crate) enum VideoQualityAndFormatPreferences {
     // Code of the selected format
     UniqueFormat(String),
     // Recode the downloaded file to this format (post-processor)
     ConvertTo(String),
+    // Move the downloaded streams to this container without re-encoding them (post-processor)
+    RemuxTo(String),
     BestQuality,
     SmallestSize,
 }
@@ -215,15 +1301,16 @@ impl fmt::Display for VideoFormat {
             // Add container
             result = format!("{:<6} ", self.ext);
 
-            if self.resolution != "audio only" {
+            if self.resolution.is_hd() {
+                result = format!("{}| {:<13} ", result, format!("{} HD", self.resolution));
+            } else if self.resolution != Resolution::AudioOnly {
                 result = format!("{}| {:<13} ", result, self.resolution);
             }
 
-            // This isn't a picture format so unwrap() is safe
-            let filesize = self.filesize.unwrap_or(0);
+            // Picture formats were skipped, so the size is almost always there
+            let filesize = self.filesize.unwrap_or_default();
 
-            // filesize is converted from bytes to MB
-            let filesize_section = format!("| filesize: {:<.2}MB", filesize as f32 * 0.000001);
+            let filesize_section = format!("| filesize: {}", filesize);
             result = format!("{}{:<24}", result, filesize_section);
 
             // If available, add audio channels
@@ -243,6 +1330,10 @@ impl fmt::Display for VideoFormat {
                 }
             }
 
+            if let Some(hint) = codec_hint(&self.vcodec, self.acodec.as_deref(), &self.ext) {
+                result = format!("{}| {}", result, hint);
+            }
+
             #[cfg(debug_assertions)]
             return {
                 result = format!("[[DEBUG code: {:<3}]] {} ", self.format_id, result);
@@ -257,8 +1348,494 @@ impl fmt::Display for VideoFormat {
     }
 }
 
+/// Fetches every format of the video and prints them as a table, so the user can see what they are choosing from
+fn print_formats_table(term: &Term, url: &str, cli_config: &parser::CliConfig, timings: &Timings, cache: &mut FormatCache) -> BlobResult<()> {
+    let video_specs = get_video_specs(url, cli_config, timings, cache)?;
+
+    // Rows longer than the terminal would wrap and break the columns
+    let (_, width) = term.size();
+    for row in formats_table(&video_specs.formats, width as usize) {
+        eprintln!("{}", row);
+    }
+
+    Ok(())
+}
+
+/// Lays out the formats as an aligned table: one row per format, preceded by a header. Rows are cut to `width` characters
+///
+/// Missing information is shown as "-"
+fn formats_table(formats: &[VideoFormat], width: usize) -> Vec<String> {
+    let header = ["ID", "EXT", "RESOLUTION", "RATIO", "FPS", "VCODEC", "ACODEC", "SIZE", "NOTE"];
+
+    let mut cells: Vec<Vec<String>> = vec![header.iter().map(|title| title.to_string()).collect()];
+    for format in formats {
+        let size = match (format.filesize, format.filesize_approx) {
+            (Some(bytes), _) => bytes.to_string(),
+            // yt-dlp's estimate
+            (None, Some(bytes)) => format!("~{}", bytes),
+            (None, None) => String::from("-"),
+        };
+        let or_dash = |value: Option<&str>| match value {
+            Some(value) if !value.is_empty() && value != "none" => value.to_string(),
+            _ => String::from("-"),
+        };
+
+        cells.push(vec![
+            format.format_id.clone(),
+            format.ext.clone(),
+            format.resolution.to_string(),
+            format.resolution.aspect_ratio().map(|ratio| format!("{:.2}", ratio)).unwrap_or_else(|| String::from("-")),
+            format.fps.map(|fps| format!("{}", fps)).unwrap_or_else(|| String::from("-")),
+            or_dash(Some(&format.vcodec)),
+            or_dash(format.acodec.as_deref()),
+            size,
+            or_dash(format.format_note.as_deref()),
+        ]);
+    }
+
+    // Every column is as wide as its longest cell
+    let column_widths: Vec<usize> = (0..header.len())
+        .map(|column| cells.iter().map(|row| row[column].chars().count()).max().unwrap_or(0))
+        .collect();
+
+    cells.iter()
+        .map(|row| {
+            let line = row.iter()
+                .zip(&column_widths)
+                .map(|(cell, column_width)| format!("{:<1$}", cell, column_width))
+                .collect::<Vec<_>>()
+                .join("  ");
+            line.trim_end().chars().take(width).collect()
+        })
+        .collect()
+}
+
+/// A short explanation of where a format can be played, shared by every format picker
+fn codec_hint(vcodec: &str, acodec: Option<&str>, ext: &str) -> Option<&'static str> {
+    let acodec = acodec.unwrap_or("none");
+
+    // Codec strings look like avc1.64001F or mp4a.40.2, only their family matters
+    let is_modern = |codec: &str| ["vp9", "vp09", "av01", "opus"].iter().any(|family| codec.starts_with(family));
+    let is_compatible = |codec: &str| ["avc1", "mp4a"].iter().any(|family| codec.starts_with(family));
+
+    if is_modern(vcodec) || is_modern(acodec) || ext == "webm" {
+        Some(MODERN_CODEC_HINT)
+    } else if (vcodec == "none" || is_compatible(vcodec))
+        && (acodec == "none" || is_compatible(acodec))
+        && (ext == "mp4" || ext == "m4a") {
+        Some(COMPATIBLE_CODEC_HINT)
+    } else {
+        None
+    }
+}
+
+/// The format of `current` closest to `stale`, a format whose id youtube changed since it was fetched
+///
+/// Only formats with the same streams (video, audio or both) are considered. The closest one has the nearest height,
+/// then the same codec families, extension and frame rate, then the nearest bitrate. None if no format has the same streams
+fn nearest_format<'a>(stale: &VideoFormat, current: &'a [VideoFormat]) -> Option<&'a VideoFormat> {
+    let streams = |format: &VideoFormat| (
+        format.vcodec != "none" && format.resolution != Resolution::AudioOnly,
+        format.acodec.as_deref() != Some("none"),
+    );
+    let height = |format: &VideoFormat| match format.resolution {
+        Resolution::Video { height, .. } => height,
+        _ => 0,
+    };
+    // Codec strings look like avc1.64001F or mp4a.40.2, only their family matters
+    let family = |codec: Option<&str>| codec.unwrap_or("none").split('.').next().unwrap_or_default().to_string();
+
+    current.iter()
+        .filter(|format| !is_storyboard_format(format) && streams(format) == streams(stale))
+        .min_by_key(|format| (
+            height(format).abs_diff(height(stale)),
+            u8::from(family(Some(&format.vcodec)) != family(Some(&stale.vcodec)))
+                + u8::from(family(format.acodec.as_deref()) != family(stale.acodec.as_deref())),
+            format.ext != stale.ext,
+            (format.fps.unwrap_or_default() - stale.fps.unwrap_or_default()).abs().round() as u64,
+            (format.tbr.unwrap_or_default() - stale.tbr.unwrap_or_default()).abs().round() as u64,
+        ))
+}
+
+impl VideoFormat {
+    pub(crate) fn format_id(&self) -> &str {
+        &self.format_id
+    }
+}
+
 impl VideoSpecs {
     fn formats(&self) -> &Vec<VideoFormat> {
         &self.formats
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(json: &str) -> PlaylistEntry {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn client_overrides_become_ytdlp_args() {
+        assert!(ClientOverride::default().args().is_empty());
+
+        let fallback = ClientOverride { player_client: Some(FALLBACK_PLAYER_CLIENT.to_string()), user_agent: None };
+        assert_eq!(fallback.args(), ["--extractor-args", "youtube:player_client=android"]);
+
+        let both = ClientOverride { player_client: Some(String::from("ios")), user_agent: Some(String::from("Mozilla/5.0")) };
+        assert_eq!(both.args(), ["--extractor-args", "youtube:player_client=ios", "--user-agent", "Mozilla/5.0"]);
+    }
+
+    #[test]
+    fn storyboards_are_never_offered() {
+        let with = |id: &str, note: Option<&str>| -> VideoFormat {
+            serde_json::from_value(serde_json::json!({
+                "format_id": id, "ext": "mhtml", "resolution": "320x180", "vcodec": "none", "acodec": "none",
+                "format_note": note, "filesize": 1000,
+            })).unwrap()
+        };
+
+        for id in ["sb0", "sb1", "sb2", "sb3"] {
+            let storyboard = with(id, Some("storyboard"));
+            assert!(is_storyboard_format(&storyboard), "{}", id);
+            for selection in [MediaSelection::FullVideo, MediaSelection::VideoOnly, MediaSelection::AudioOnly] {
+                assert_eq!(check_format(&storyboard, &selection), FormatCompatibility::IsStoryboard);
+            }
+        }
+        // The note is enough
+        assert!(is_storyboard_format(&with("thumbs", Some("storyboard"))));
+
+        assert!(!is_storyboard_format(&format("18", "mp4", "640x360", "avc1.42001E", "mp4a.40.2")));
+        assert!(!is_storyboard_format(&with("137", Some("1080p"))));
+        assert!(!is_storyboard_format(&with("137", None)));
+    }
+
+    #[test]
+    fn embedding_the_info_json_warns_unless_the_files_are_mkv() {
+        assert_eq!(info_json_container_warning(Some("mkv")), None);
+        assert_eq!(info_json_container_warning(Some("mka")), None);
+
+        let warning = info_json_container_warning(Some("mp4")).unwrap();
+        assert!(warning.starts_with(INFO_JSON_CONTAINER_WARNING));
+        assert!(warning.ends_with("mp4 files"));
+        // When the extension isn't known it might still be mp4 or webm
+        assert!(info_json_container_warning(None).unwrap().ends_with(INFO_JSON_UNKNOWN_CONTAINER));
+    }
+
+    #[test]
+    fn formats_are_offered_only_for_the_media_they_contain() {
+        let sized = |id: &str, resolution: &str, vcodec: &str, acodec: &str| -> VideoFormat {
+            serde_json::from_value(serde_json::json!({
+                "format_id": id, "ext": "mp4", "resolution": resolution, "vcodec": vcodec, "acodec": acodec, "filesize": 1000,
+            })).unwrap()
+        };
+        let full = sized("18", "640x360", "avc1.42001E", "mp4a.40.2");
+        let video_only = sized("137", "1920x1080", "avc1.640028", "none");
+        let audio_only = sized("140", "audio only", "none", "mp4a.40.2");
+
+        use FormatCompatibility::*;
+        let expected = [
+            (MediaSelection::FullVideo, [Compatible, IncompatibleMediaType, IncompatibleMediaType]),
+            (MediaSelection::VideoOnly, [IncompatibleMediaType, Compatible, IncompatibleMediaType]),
+            (MediaSelection::AudioOnly, [IncompatibleMediaType, IncompatibleMediaType, Compatible]),
+        ];
+        for (selection, compatibility) in expected {
+            for (format, expected) in [&full, &video_only, &audio_only].into_iter().zip(compatibility) {
+                assert_eq!(check_format(format, &selection), expected, "{} for {:?}", format.format_id, selection);
+            }
+        }
+
+        // Formats streamed from a manifest have no known size
+        let manifest = format("234", "mp4", "audio only", "none", "mp4a.40.2");
+        assert_eq!(check_format(&manifest, &MediaSelection::AudioOnly), IsManifest);
+        // Storyboards are left out before anything else
+        assert_eq!(check_format(&sized("sb0", "320x180", "none", "none"), &MediaSelection::AudioOnly), IsStoryboard);
+    }
+
+    #[test]
+    fn system_directories_are_recognized() {
+        for path in ["/", "/usr", "/usr/local/share", "/etc/", "/var", "/var/lib/videos", "/home", "C:\\", "C:\\Windows\\System32", "c:\\program files (x86)", "C:\\Users"] {
+            assert!(is_system_path(path), "{}", path);
+        }
+        // Only the top level directories themselves are off-limits
+        for path in ["/home/user/Videos", "/var/tmp/downloads", "/tmp", "/opt/media", "C:\\Users\\user\\Videos", "D:\\", "/usrdata", "videos", ""] {
+            assert!(!is_system_path(path), "{}", path);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn system_paths_are_refused_without_a_terminal() {
+        let unattended = Term::read_write_pair(std::fs::File::open("/dev/null").unwrap(),
+                                               std::fs::OpenOptions::new().write(true).open("/dev/null").unwrap());
+
+        assert!(matches!(check_output_path_safety(&unattended, "/usr", false), Err(BlobdlError::SystemPathRefused)));
+        assert!(check_output_path_safety(&unattended, "/usr", true).is_ok());
+        // Running as root is refused the same way
+        assert_eq!(check_output_path_safety(&unattended, "/home/user/Videos", false).is_ok(), !is_root());
+    }
+
+    #[test]
+    fn codec_hints_follow_the_codec_families() {
+        let cases = [
+            (("avc1.640028", Some("mp4a.40.2"), "mp4"), Some(COMPATIBLE_CODEC_HINT)),
+            (("avc1.4d401f", Some("none"), "mp4"), Some(COMPATIBLE_CODEC_HINT)),
+            (("none", Some("mp4a.40.2"), "m4a"), Some(COMPATIBLE_CODEC_HINT)),
+            (("vp9", Some("none"), "webm"), Some(MODERN_CODEC_HINT)),
+            (("vp09.00.40.08", Some("none"), "mp4"), Some(MODERN_CODEC_HINT)),
+            (("av01.0.08M.08", Some("none"), "mp4"), Some(MODERN_CODEC_HINT)),
+            (("none", Some("opus"), "webm"), Some(MODERN_CODEC_HINT)),
+            // A modern stream makes the whole format modern
+            (("avc1.640028", Some("opus"), "mp4"), Some(MODERN_CODEC_HINT)),
+            (("none", Some("mp4a.40.2"), "webm"), Some(MODERN_CODEC_HINT)),
+            // Nothing is known about these
+            (("avc1.640028", Some("mp4a.40.2"), "3gp"), None),
+            (("none", None, "mhtml"), None),
+        ];
+        for ((vcodec, acodec, ext), hint) in cases {
+            assert_eq!(codec_hint(vcodec, acodec, ext), hint, "{} {:?} {}", vcodec, acodec, ext);
+        }
+    }
+
+    #[test]
+    fn list_parameters_make_urls_playlists() {
+        assert!(url_has_playlist_param("https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=PLx0sYbCqOb8TBPRdmBHs5Iftvv9TPboYG"));
+        assert!(url_has_playlist_param("https://www.youtube.com/watch?list=PL1&v=dQw4w9WgXcQ&index=3"));
+        assert!(url_has_playlist_param("https://youtu.be/dQw4w9WgXcQ?list=PL1"));
+
+        assert!(!url_has_playlist_param("https://www.youtube.com/watch?v=dQw4w9WgXcQ"));
+        assert!(!url_has_playlist_param("https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=42"));
+        // Only the parameter's name counts
+        assert!(!url_has_playlist_param("https://www.youtube.com/watch?v=list&playlist=PL1"));
+        assert!(!url_has_playlist_param("https://www.youtube.com/list/watch?v=dQw4w9WgXcQ"));
+        assert!(!url_has_playlist_param("not a url&list=PL1"));
+    }
+
+    #[test]
+    fn only_private_and_deleted_videos_are_hidden() {
+        assert!(entry(r#"{"id": "a", "title": "[Private video]"}"#).is_hidden());
+        assert!(entry(r#"{"id": "a", "title": "[Deleted video]"}"#).is_hidden());
+        assert!(entry(r#"{"id": "a", "title": null, "availability": "private"}"#).is_hidden());
+        // Flat playlists can leave the title out
+        assert!(!entry(r#"{"id": "a"}"#).is_hidden());
+        assert!(!entry(r#"{"id": "a", "title": "A video", "availability": "public"}"#).is_hidden());
+    }
+
+    fn format(id: &str, ext: &str, resolution: &str, vcodec: &str, acodec: &str) -> VideoFormat {
+        serde_json::from_str(&format!(r#"{{"format_id": "{}", "ext": "{}", "resolution": "{}", "vcodec": "{}", "acodec": "{}"}}"#,
+                                      id, ext, resolution, vcodec, acodec)).unwrap()
+    }
+
+    #[test]
+    fn resolutions_are_parsed_from_ytdlp_descriptions() {
+        let resolution = |text: &str| format("22", "mp4", text, "avc1", "mp4a").resolution;
+
+        assert_eq!(resolution("audio only"), Resolution::AudioOnly);
+        assert_eq!(resolution("1280x720"), Resolution::Video { width: 1280, height: 720 });
+        assert_eq!(resolution("3840x2160"), Resolution::Video { width: 3840, height: 2160 });
+        // The note after the size is left out
+        assert_eq!(resolution("1920x1080 (default)"), Resolution::Video { width: 1920, height: 1080 });
+        assert_eq!(resolution("720p"), Resolution::Unknown(String::from("720p")));
+
+        // They are shown the way yt-dlp describes them
+        assert_eq!(resolution("1920x1080 (default)").to_string(), "1920x1080");
+        assert_eq!(format!("{:<12}|", resolution("audio only")), "audio only  |");
+    }
+
+    #[test]
+    fn hd_and_aspect_ratio_need_a_known_frame() {
+        let hd = Resolution::from(String::from("1280x720"));
+        assert!(hd.is_hd());
+        assert_eq!(hd.aspect_ratio(), Some(16.0 / 9.0));
+
+        let vertical = Resolution::from(String::from("1080x1920"));
+        assert!(vertical.is_hd());
+        assert_eq!(vertical.aspect_ratio(), Some(0.5625));
+
+        let sd = Resolution::from(String::from("854x480"));
+        assert!(!sd.is_hd());
+
+        for unknown in [Resolution::AudioOnly, Resolution::from(String::from("0x0")), Resolution::from(String::from("unknown"))] {
+            assert!(!unknown.is_hd());
+            assert_eq!(unknown.aspect_ratio(), None);
+        }
+    }
+
+    #[test]
+    fn empty_format_lists_offer_the_selections_which_have_formats() {
+        let formats: Vec<VideoFormat> = serde_json::from_str(r#"[
+            {"format_id": "140", "ext": "m4a", "resolution": "audio only", "vcodec": "none", "acodec": "mp4a.40.2", "filesize": 1000},
+            {"format_id": "251", "ext": "webm", "resolution": "audio only", "vcodec": "none", "acodec": "opus", "filesize": 1000},
+            {"format_id": "137", "ext": "mp4", "resolution": "1920x1080", "vcodec": "avc1.640028", "acodec": "none", "filesize": 1000},
+            {"format_id": "sb0", "ext": "mhtml", "resolution": "320x180", "vcodec": "none", "acodec": "none", "filesize": 1000},
+            {"format_id": "234", "ext": "mp4", "resolution": "audio only", "vcodec": "none", "acodec": "mp4a.40.2"}
+        ]"#).unwrap();
+        let formats: Vec<&VideoFormat> = formats.iter().collect();
+
+        // No format has both streams
+        assert_eq!(alternative_selections(&formats, &MediaSelection::FullVideo),
+                   [(MediaSelection::VideoOnly, 1), (MediaSelection::AudioOnly, 2)]);
+        assert_eq!(alternative_selections(&formats, &MediaSelection::AudioOnly), [(MediaSelection::VideoOnly, 1)]);
+        assert!(alternative_selections(&[], &MediaSelection::FullVideo).is_empty());
+    }
+
+    #[test]
+    fn only_unknown_compat_options_are_warned_about() {
+        let options = ["no-live-chat", "-multistreams", "2022", "no-such-option", "-also-unknown"].map(String::from);
+
+        assert_eq!(unknown_compat_options(&options), ["no-such-option", "-also-unknown"]);
+        assert!(unknown_compat_options(&COMMON_COMPAT_OPTIONS.map(String::from)).is_empty());
+    }
+
+    #[test]
+    fn compat_options_are_split_at_commas() {
+        let cli_config = crate::parser::tests::config(&["--compat-options", "no-live-chat,,-multistreams", "https://youtu.be/abc"]);
+        assert_eq!(cli_config.compat_options(), &["no-live-chat", "-multistreams"]);
+    }
+
+    #[test]
+    fn formats_table_aligns_the_columns_and_fills_the_gaps() {
+        let formats: Vec<VideoFormat> = serde_json::from_str(r#"[
+            {"format_id": "18", "ext": "mp4", "resolution": "640x360", "fps": 25, "vcodec": "avc1.42001E", "acodec": "mp4a.40.2", "filesize": 512},
+            {"format_id": "140", "ext": "m4a", "resolution": "audio only", "vcodec": "none", "acodec": "mp4a.40.2", "filesize_approx": 100, "format_note": "medium"}
+        ]"#).unwrap();
+
+        assert_eq!(formats_table(&formats, 200), [
+            "ID   EXT  RESOLUTION  RATIO  FPS  VCODEC       ACODEC     SIZE    NOTE",
+            "18   mp4  640x360     1.78   25   avc1.42001E  mp4a.40.2  512 B   -",
+            "140  m4a  audio only  -      -    -            mp4a.40.2  ~100 B  medium",
+        ]);
+        // Rows never wrap in a narrow terminal
+        assert_eq!(formats_table(&formats, 20)[0], "ID   EXT  RESOLUTION");
+        assert_eq!(formats_table(&[], 200).len(), 1);
+    }
+
+    #[test]
+    fn nearest_format_keeps_the_streams_then_the_height() {
+        let stale = format("137", "mp4", "1920x1080", "avc1.640028", "none");
+        let current = [
+            format("sb0", "mhtml", "320x180", "none", "none"),
+            format("18", "mp4", "640x360", "avc1.42001E", "mp4a.40.2"),
+            format("140", "m4a", "audio only", "none", "mp4a.40.2"),
+            format("136", "mp4", "1280x720", "avc1.4d401f", "none"),
+            format("248", "webm", "1920x1080", "vp9", "none"),
+        ];
+        // The same height matters more than the same codec
+        assert_eq!(nearest_format(&stale, &current).map(VideoFormat::format_id), Some("248"));
+
+        // Among formats of the same height, the same codec family and extension win
+        let mut with_avc = current.to_vec();
+        with_avc.push(format("270", "mp4", "1920x1080", "avc1.640028", "none"));
+        assert_eq!(nearest_format(&stale, &with_avc).map(VideoFormat::format_id), Some("270"));
+
+        let stale_audio = format("251", "webm", "audio only", "none", "opus");
+        assert_eq!(nearest_format(&stale_audio, &current).map(VideoFormat::format_id), Some("140"));
+    }
+
+    #[test]
+    fn nearest_format_needs_the_same_streams() {
+        let stale = format("22", "mp4", "1280x720", "avc1.64001F", "mp4a.40.2");
+        let current = [format("136", "mp4", "1280x720", "avc1.4d401f", "none"), format("140", "m4a", "audio only", "none", "mp4a.40.2")];
+        assert!(nearest_format(&stale, &current).is_none());
+    }
+
+    #[test]
+    fn only_json_dumps_are_read_as_formats() {
+        // The header and separator of yt-dlp's -F table, blob-dl never asks for it
+        for line in ["ID  EXT   RESOLUTION FPS CH |   FILESIZE   TBR PROTO | VCODEC", "-----------------------------------------------",
+                     "140 m4a   audio only      2 |    3.28MiB  129k https | audio only"] {
+            assert!(serialize_formats(line).is_err(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn dumps_without_formats_have_none() {
+        let specs = serialize_formats(r#"{"id": "a", "formats": []}"#).unwrap();
+        assert!(specs.formats().is_empty());
+
+        // A live stream only describes the format yt-dlp would download
+        let live = r#"{"id": "a", "formats": [], "format_id": "95", "ext": "mp4", "resolution": "1280x720", "vcodec": "avc1"}"#;
+        let specs = serialize_formats(live).unwrap();
+        assert_eq!(specs.formats().len(), 1);
+        assert_eq!(specs.formats()[0].format_id, "95");
+    }
+
+    #[test]
+    fn adjacent_chapters_are_merged_into_one_section() {
+        let concert = serialize_formats(r#"{"id": "a", "formats": [], "chapters": [
+            {"start_time": 0.0, "end_time": 312.0, "title": "Intro"},
+            {"start_time": 312.0, "end_time": 640.5, "title": "Song (Live)"},
+            {"start_time": 640.8, "end_time": 900.0, "title": "Encore"},
+            {"start_time": 900.0, "end_time": 1200.0, "title": "Outro"}
+        ]}"#).unwrap();
+        let chapters = concert.chapters.unwrap();
+
+        // Selected out of order, the 0.3 seconds between the 2nd and 3rd chapter don't count as a gap
+        let selected = [&chapters[2], &chapters[0], &chapters[1]];
+        assert_eq!(merge_chapters(&selected), [ChapterRange {
+            start: 0.0, end: 900.0, titles: vec![String::from("Intro"), String::from("Song (Live)"), String::from("Encore")],
+        }]);
+
+        let ranges = merge_chapters(&[&chapters[0], &chapters[3]]);
+        assert_eq!(ranges.len(), 2);
+        assert_eq!(merge_chapters(&[]), []);
+
+        // Single chapters are selected by title, so that the file is named after it
+        let arguments: Vec<String> = merge_chapters(&[&chapters[1], &chapters[3]]).iter().map(ChapterRange::to_section_arg).collect();
+        assert_eq!(arguments, ["^Song \\(Live\\)$", "^Outro$"]);
+        assert_eq!(merge_chapters(&selected)[0].to_section_arg(), "*0-900");
+    }
+
+    #[test]
+    fn videos_without_chapters_have_none() {
+        assert!(serialize_formats(r#"{"id": "a", "formats": [], "chapters": null}"#).unwrap().chapters.is_none());
+        assert!(serialize_formats(r#"{"id": "a", "formats": []}"#).unwrap().chapters.is_none());
+    }
+
+    #[test]
+    fn dumps_are_positioned_by_their_playlist_index() {
+        // The video at position 2 couldn't be fetched, the one at 5 has a broken line
+        let dumps = "{\"playlist_index\": 1}\n{\"playlist_index\": 3, \"formats\": []}\n{\"playlist_index\": 4}\n{\"formats\": [\n{}";
+        let positions: Vec<usize> = positioned_dumps(dumps).into_iter().map(|(position, _)| position).collect();
+        assert_eq!(positions, [1, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn media_options_cover_every_selection_once() {
+        let selections: Vec<&MediaSelection> = MEDIA_OPTIONS.iter().map(|(_, media)| media).collect();
+        assert_eq!(selections, [&MediaSelection::FullVideo, &MediaSelection::AudioOnly, &MediaSelection::VideoOnly]);
+
+        // The labels have to tell which files come with a sound track
+        for (label, media) in MEDIA_OPTIONS {
+            match media {
+                MediaSelection::FullVideo => assert!(label.contains("with audio"), "{}", label),
+                MediaSelection::AudioOnly => assert!(label.contains("Audio-only"), "{}", label),
+                MediaSelection::VideoOnly => assert!(label.contains("no audio"), "{}", label),
+            }
+        }
+    }
+
+    #[test]
+    fn only_sign_in_failures_can_be_fixed_with_cookies() {
+        let age_limited = entry(r#"{"id": "a", "title": "A video", "availability": "public", "age_limit": 18}"#);
+        assert_eq!(age_limited.predicted_failure(), Some(AGE_RESTRICTED_REASON));
+        assert!(age_limited.needs_sign_in());
+
+        let needs_auth = entry(r#"{"id": "a", "title": "A video", "availability": "needs_auth"}"#);
+        assert_eq!(needs_auth.predicted_failure(), Some(NEEDS_AUTH_REASON));
+        assert!(needs_auth.needs_sign_in());
+
+        // A membership is more than signing in, even for age-restricted videos
+        let members_only = entry(r#"{"id": "a", "title": "A video", "availability": "subscriber_only", "age_limit": 18}"#);
+        assert_eq!(members_only.predicted_failure(), Some(MEMBERS_ONLY_REASON));
+        assert!(!members_only.needs_sign_in());
+
+        let premium_only = entry(r#"{"id": "a", "title": "A video", "availability": "premium_only"}"#);
+        assert_eq!(premium_only.predicted_failure(), Some(PREMIUM_ONLY_REASON));
+        assert!(!premium_only.needs_sign_in());
+
+        assert_eq!(entry(r#"{"id": "a", "title": "A video", "age_limit": 13}"#).predicted_failure(), None);
+    }
+}
@@ -0,0 +1,242 @@
+//! Predicts the names yt-dlp gives to downloaded files, so that blob-dl can look at the output folder before downloading
+use std::path::{Path, PathBuf};
+
+/// Extensions of yt-dlp's temporary files and of the files blob-dl writes next to downloads, they are never conflicts
+const IGNORED_EXTENSIONS: [&str; 10] = ["part", "ytdl", "temp", "sha256", "json", "description", "srt", "vtt", "ass", "lrc"];
+
+/// Cuts `title` to at most `max_bytes` bytes of UTF-8 like yt-dlp's `%(title).NB`, a character which doesn't fit is left out whole
+pub(crate) fn truncate_to_bytes(title: &str, max_bytes: usize) -> &str {
+    if title.len() <= max_bytes {
+        return title;
+    }
+
+    let mut end = max_bytes;
+    while !title.is_char_boundary(end) {
+        end -= 1;
+    }
+    &title[..end]
+}
+
+/// The characters Windows doesn't allow in file names, yt-dlp replaces them in every title (and in every folder name
+/// with --windows-filenames)
+pub(crate) const WINDOWS_RESERVED_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// A character produced by sanitize_filename: substitutes replace characters that can't be in a file name,
+/// they are treated differently at the edges of the name and when they repeat
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Piece {
+    Kept(char),
+    Substitute(char),
+}
+
+/// Turns a title into the file name yt-dlp would give it, mirroring yt-dlp's `sanitize_filename`
+///
+/// `restricted` is --restrict-filenames (blob-dl's ASCII-safe names). Unlike yt-dlp, accented letters
+/// are replaced with '_' in restricted mode instead of being transliterated
+pub(crate) fn sanitize_filename(title: &str, restricted: bool) -> String {
+    let chars: Vec<char> = title.chars().collect();
+    let mut pieces = vec![];
+
+    for (i, &c) in chars.iter().enumerate() {
+        // Timestamps like 1:23:45 become 1_23_45
+        let in_timestamp = c == ':'
+            && i > 0 && chars[i - 1].is_ascii_digit()
+            && chars.get(i + 1).is_some_and(|next| next.is_ascii_digit());
+
+        if in_timestamp {
+            pieces.push(Piece::Kept('_'));
+        } else {
+            pieces.extend(replace_insane(c, restricted));
+        }
+    }
+
+    // Repeated substitutes collapse into one
+    pieces.dedup_by(|a, b| matches!(a, Piece::Substitute(_)) && a == b);
+
+    // Substitutes at the edges are removed, along with the separators next to them
+    let is_separator = |piece: &Piece| matches!(piece, Piece::Substitute(_) | Piece::Kept(' ' | '_' | '-'));
+    if matches!(pieces.first(), Some(Piece::Substitute(_))) {
+        let start = pieces.iter().position(|piece| !is_separator(piece)).unwrap_or(pieces.len());
+        pieces.drain(..start);
+    }
+    if matches!(pieces.last(), Some(Piece::Substitute(_))) {
+        let end = pieces.iter().rposition(|piece| !is_separator(piece)).map_or(0, |i| i + 1);
+        pieces.truncate(end);
+    }
+
+    let mut result: String = pieces.into_iter().map(|piece| match piece {
+        Piece::Kept(c) | Piece::Substitute(c) => c,
+    }).collect();
+
+    while result.contains("__") {
+        result = result.replace("__", "_");
+    }
+    let mut result = result.trim_matches('_').to_string();
+
+    if restricted && result.starts_with("-_") {
+        result.replace_range(..2, "");
+    }
+    if result.starts_with('-') {
+        result.replace_range(..1, "_");
+    }
+    let result = result.trim_start_matches('.');
+
+    if result.is_empty() {
+        String::from("_")
+    } else {
+        result.to_string()
+    }
+}
+
+/// What a single character of a title becomes in a file name
+fn replace_insane(c: char, restricted: bool) -> Vec<Piece> {
+    if !restricted {
+        return match c {
+            '\n' => vec![Piece::Substitute(' ')],
+            // Replaced with their full-width counterparts, which look the same but are allowed in file names
+            '/' => vec![Piece::Kept('\u{29F8}')],
+            '\\' => vec![Piece::Kept('\u{29F9}')],
+            c if WINDOWS_RESERVED_CHARS.contains(&c) => vec![Piece::Kept(char::from_u32(c as u32 + 0xFEE0).unwrap_or('_'))],
+            c if c.is_ascii_control() => vec![],
+            c => vec![Piece::Kept(c)],
+        };
+    }
+
+    match c {
+        '?' | '"' => vec![],
+        c if c.is_ascii_control() => vec![],
+        ':' => vec![Piece::Substitute('_'), Piece::Substitute('-')],
+        '\\' | '/' | '|' | '*' | '<' | '>' => vec![Piece::Substitute('_')],
+        '!' | '&' | '\'' | '(' | ')' | '[' | ']' | '{' | '}' | '$' | ';' | '`' | '^' | ',' | '#' => vec![Piece::Substitute('_')],
+        c if c.is_whitespace() || !c.is_ascii() => vec![Piece::Substitute('_')],
+        c => vec![Piece::Kept(c)],
+    }
+}
+
+/// The name yt-dlp gives to a folder with --windows-filenames: Windows doesn't allow names ending with a dot or a space,
+/// so the last character becomes '#', like the reserved characters which made it through `name`
+pub(crate) fn windows_safe_folder_name(name: &str) -> String {
+    let mut safe: String = name.chars()
+        .map(|c| if WINDOWS_RESERVED_CHARS.contains(&c) { '#' } else { c })
+        .collect();
+
+    if safe.ends_with(|c: char| c == '.' || c.is_whitespace()) {
+        safe.pop();
+        safe.push('#');
+    }
+    safe
+}
+
+/// A file in the output folder named like a video which is about to be downloaded, but with a different extension
+/// (like song.mp4 when song.mp3 is going to be downloaded)
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ExtensionConflict {
+    /// The video's position in the playlist, counting from 1
+    pub(crate) position: usize,
+    pub(crate) existing_file: PathBuf,
+}
+
+/// Looks in `folder` for files named like one of `expected_names` ((position, file name without extension) pairs)
+///
+/// Files with `new_extension` aren't conflicts: yt-dlp recognizes them as already downloaded.
+/// When the extension of the new files can't be known in advance every extension counts
+pub(crate) fn find_extension_conflicts(folder: &Path, expected_names: &[(usize, String)], new_extension: Option<&str>) -> Vec<ExtensionConflict> {
+    let entries = match std::fs::read_dir(folder) {
+        Ok(entries) => entries,
+        // Nothing was downloaded here yet
+        Err(_) => return vec![],
+    };
+
+    let mut conflicts = vec![];
+    for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+        let (stem, extension) = match (path.file_stem(), path.extension()) {
+            (Some(stem), Some(extension)) => (stem.to_string_lossy(), extension.to_string_lossy()),
+            _ => continue,
+        };
+
+        if IGNORED_EXTENSIONS.contains(&extension.as_ref()) || new_extension == Some(extension.as_ref()) {
+            continue;
+        }
+
+        if let Some((position, _)) = expected_names.iter().find(|(_, name)| *name == stem) {
+            conflicts.push(ExtensionConflict { position: *position, existing_file: path.clone() });
+        }
+    }
+
+    conflicts.sort_by_key(|conflict| conflict.position);
+    conflicts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_safe_names_replace_everything_else() {
+        let cases = [
+            ("Never Gonna Give You Up 🎵", "Never_Gonna_Give_You_Up"),
+            ("東京 Tokyo", "Tokyo"),
+            ("東京", "_"),
+            // Combining diacritics and accented letters alike
+            ("Cafe\u{301} Paris", "Cafe_Paris"),
+            ("Café Paris", "Caf_Paris"),
+            ("Artist: Song", "Artist_-_Song"),
+            ("Live at 1:23:45", "Live_at_1_23_45"),
+            ("What's up?", "What_s_up"),
+        ];
+        for (title, name) in cases {
+            assert_eq!(sanitize_filename(title, true), name, "{}", title);
+        }
+    }
+
+    #[test]
+    fn regular_names_keep_unicode_and_widen_reserved_characters() {
+        assert_eq!(sanitize_filename("東京 Tokyo 🎵", false), "東京 Tokyo 🎵");
+        assert_eq!(sanitize_filename("AC/DC: Live?", false), "AC\u{29F8}DC\u{FF1A} Live\u{FF1F}");
+        assert_eq!(sanitize_filename("Live at 1:23:45", false), "Live at 1_23_45");
+    }
+
+    #[test]
+    fn conflicts_are_files_of_the_same_name_with_another_extension() {
+        let folder = std::env::temp_dir().join(format!("blob-dl-conflicts-{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        for file in ["2_Song.mp4", "2_Song.mp3", "1_Intro.webm", "1_Intro.part", "1_Intro.info.json", "3_Outro.mkv", "Other.mp4"] {
+            std::fs::write(folder.join(file), b"").unwrap();
+        }
+        let expected_names = [(1, String::from("1_Intro")), (2, String::from("2_Song")), (3, String::from("3_Outro"))];
+
+        let conflicts = find_extension_conflicts(&folder, &expected_names, Some("mp3"));
+        let positions: Vec<_> = conflicts.iter().map(|conflict| (conflict.position, conflict.existing_file.file_name().unwrap().to_owned())).collect();
+        // Sorted by position, the mp3 already counts as downloaded and the partial downloads are yt-dlp's business
+        assert_eq!(positions, [(1, "1_Intro.webm".into()), (2, "2_Song.mp4".into()), (3, "3_Outro.mkv".into())]);
+
+        // Without a known extension even the mp3 is a conflict
+        assert_eq!(find_extension_conflicts(&folder, &expected_names, None).len(), 4);
+        assert!(find_extension_conflicts(&folder.join("missing"), &expected_names, None).is_empty());
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn truncation_never_splits_a_character() {
+        // '東' and '京' are 3 bytes each
+        assert_eq!(truncate_to_bytes("東京", 5), "東");
+        assert_eq!(truncate_to_bytes("東京", 6), "東京");
+        assert_eq!(truncate_to_bytes("🎵a", 3), "");
+        assert_eq!(truncate_to_bytes("abc", 10), "abc");
+    }
+
+    #[test]
+    fn windows_reserved_characters_never_reach_a_name() {
+        for c in WINDOWS_RESERVED_CHARS {
+            let title = format!("a{}b", c);
+            for restricted in [false, true] {
+                let name = sanitize_filename(&title, restricted);
+                assert!(!name.contains(WINDOWS_RESERVED_CHARS), "{:?} became {:?}", title, name);
+            }
+            assert_eq!(windows_safe_folder_name(&title), "a#b");
+        }
+        // Windows doesn't allow a trailing dot or space either
+        assert_eq!(windows_safe_folder_name("Vol. 1."), "Vol. 1#");
+    }
+}
@@ -0,0 +1,106 @@
+//! `blob-dl --music`: downloads songs without asking anything, as tagged audio files with their cover art
+//! and a .m3u playlist next to them
+use std::env;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use which::which;
+
+use crate::analyzer;
+use crate::assembling::youtube::*;
+use crate::error::{BlobdlError, BlobResult};
+use crate::parser;
+use crate::timings::Timings;
+
+/// Most music videos are titled "Artist - Song", yt-dlp only fills the tags when the title matches
+pub(crate) const ARTIST_AND_TRACK_PATTERN: &str = "title:%(artist)s - %(track)s";
+
+/// Configures the download of `url` as music: the best audio converted to `audio_format`, saved in --output-path
+/// or in the current directory
+///
+/// The directory goes through the same checks as the wizard's answer, which only ask something if it looks wrong.
+/// Converting, tagging and embedding the cover art all need ffmpeg, so it fails right away without it
+pub(crate) fn assemble_data(url: &str, download_option: &analyzer::DownloadOption, audio_format: &str, cli_config: &parser::CliConfig,
+                            timings: &Timings) -> BlobResult<config::DownloadConfig>
+{
+    if which("ffmpeg").is_err() {
+        return Err(BlobdlError::MusicNeedsFfmpeg);
+    }
+
+    let output_path = match cli_config.output_path() {
+        Some(output_path) => output_path.clone(),
+        None => env::current_dir()?.display().to_string(),
+    };
+    let output_path = get_output_path(&Term::buffered_stderr(), Some(&output_path), cli_config.allow_system_paths())?;
+
+    let mut download_config = match download_option {
+        analyzer::DownloadOption::YtPlaylist | analyzer::DownloadOption::YtPlaylistFrom(_) => config::DownloadConfig::new_playlist(
+            url,
+            output_path,
+            // Songs are sorted by the .m3u file
+            false,
+            VideoQualityAndFormatPreferences::BestQuality,
+            MediaSelection::AudioOnly,
+        ),
+        analyzer::DownloadOption::YtVideo(_) | analyzer::DownloadOption::YtShort => config::DownloadConfig::new_video(
+            url,
+            VideoQualityAndFormatPreferences::BestQuality,
+            output_path,
+            MediaSelection::AudioOnly,
+        ),
+    };
+
+    if let analyzer::DownloadOption::YtPlaylistFrom(index) = download_option {
+        // The url's index can be stale, like in the wizard
+        let entries = get_playlist_entries(url, cli_config, timings)?;
+        download_config.playlist_start = Some(yt_playlist::get_start_position(url, *index, &entries));
+    }
+
+    download_config.audio_extraction = AudioExtractionMode::FfmpegConvert(audio_format.to_string());
+    download_config.music = true;
+
+    Ok(download_config)
+}
+
+/// Writes a .m3u playlist listing `downloaded_files` in the order they were downloaded, named after the folder they are in
+///
+/// Returns the playlist's path, None if nothing was downloaded
+pub(crate) fn write_m3u(downloaded_files: &[String]) -> BlobResult<Option<PathBuf>> {
+    let folder = match downloaded_files.first().and_then(|file| Path::new(file).parent()) {
+        Some(folder) => folder,
+        None => return Ok(None),
+    };
+    let name = folder.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_else(|| String::from("playlist"));
+    let playlist_path = folder.join(format!("{}.m3u", name));
+
+    let mut playlist = std::fs::File::create(&playlist_path)?;
+    writeln!(playlist, "#EXTM3U")?;
+    for file in downloaded_files {
+        // Paths are relative to the playlist, so that the folder can be moved around
+        let path = Path::new(file);
+        let relative = path.strip_prefix(folder).unwrap_or(path);
+        writeln!(playlist, "{}", relative.display())?;
+    }
+
+    Ok(Some(playlist_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn m3u_playlists_list_the_songs_relative_to_their_folder() {
+        let folder = env::temp_dir().join(format!("blob-dl-m3u-{}", std::process::id())).join("Road Trip");
+        std::fs::create_dir_all(&folder).unwrap();
+        let songs: Vec<String> = ["02 Second.mp3", "01 First.mp3"].iter().map(|song| folder.join(song).display().to_string()).collect();
+
+        let playlist = write_m3u(&songs).unwrap().unwrap();
+
+        assert_eq!(playlist, folder.join("Road Trip.m3u"));
+        // In the order they were downloaded
+        assert_eq!(std::fs::read_to_string(&playlist).unwrap(), "#EXTM3U\n02 Second.mp3\n01 First.mp3\n");
+        assert_eq!(write_m3u(&[]).unwrap(), None);
+
+        std::fs::remove_dir_all(folder.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,180 @@
+//! Remembers what the wizard was answered for every channel and playlist, so that downloading from the same source
+//! again can reuse the answers with one keypress
+//!
+//! The answers are kept in blob-dl's state directory, the ones not used for MAX_AGE are forgotten
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::assembling::youtube::{AudioExtractionMode, DownloadMode, MediaSelection, VideoQualityAndFormatPreferences};
+use crate::state;
+
+/// Where the answers for every source are kept, in blob-dl's state directory
+const ANSWERS_FILE: &str = "remembered-answers.json";
+
+/// Answers older than this are pruned, the source was most likely not downloaded from anymore
+const MAX_AGE: Duration = Duration::from_secs(180 * 24 * 60 * 60);
+
+/// The answers to the wizard's format questions given the last time a source was downloaded from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct RememberedAnswers {
+    pub(crate) media_selection: MediaSelection,
+    pub(crate) audio_extraction: AudioExtractionMode,
+    pub(crate) download_mode: DownloadMode,
+    pub(crate) format: VideoQualityAndFormatPreferences,
+    /// Seconds since the unix epoch
+    saved_at: u64,
+}
+
+impl RememberedAnswers {
+    pub(crate) fn new(media_selection: MediaSelection, audio_extraction: AudioExtractionMode, download_mode: DownloadMode,
+                      format: VideoQualityAndFormatPreferences) -> RememberedAnswers {
+        RememberedAnswers { media_selection, audio_extraction, download_mode, format, saved_at: now() }
+    }
+
+    /// The exact format which was picked, it only exists for the videos which were listed with it
+    pub(crate) fn format_id(&self) -> Option<&str> {
+        match &self.format {
+            VideoQualityAndFormatPreferences::UniqueFormat(id) => Some(id),
+            _ => None,
+        }
+    }
+}
+
+/// What the user picked, like "audio-only, converted to mp3"
+impl fmt::Display for RememberedAnswers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.download_mode == DownloadMode::ThumbnailOnly {
+            return write!(f, "thumbnails only");
+        }
+
+        write!(f, "{}, ", self.media_selection)?;
+
+        if let AudioExtractionMode::FfmpegConvert(audio_format) = &self.audio_extraction {
+            return write!(f, "converted to {}", audio_format);
+        }
+
+        match &self.format {
+            VideoQualityAndFormatPreferences::BestQuality => write!(f, "best quality"),
+            VideoQualityAndFormatPreferences::SmallestSize => write!(f, "smallest size"),
+            VideoQualityAndFormatPreferences::UniqueFormat(id) => write!(f, "format {}", id),
+            VideoQualityAndFormatPreferences::ConvertTo(format) => write!(f, "converted to {}", format),
+            VideoQualityAndFormatPreferences::RemuxTo(format) => write!(f, "remuxed to {}", format),
+        }
+    }
+}
+
+/// The source `url` downloads from: "playlist:<id>" when it has a list, "channel:<id or name>" when it points to a channel
+///
+/// None for the urls of single videos, their channel is only known from their metadata (see channel_key)
+pub(crate) fn source_key(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+
+    if let Some((_, list)) = url.query_pairs().find(|(key, value)| key == "list" && !value.is_empty()) {
+        return Some(format!("playlist:{}", list));
+    }
+
+    let mut segments = url.path_segments()?;
+    match segments.next()? {
+        // youtube.com/channel/UC..., youtube.com/c/name and youtube.com/user/name
+        "channel" | "c" | "user" => segments.next().filter(|name| !name.is_empty()).map(channel_key),
+        // youtube.com/@handle
+        handle if handle.len() > 1 && handle.starts_with('@') => Some(channel_key(handle)),
+        _ => None,
+    }
+}
+
+/// The key of the channel with id `channel_id`, the same as the one of its youtube.com/channel/ url
+pub(crate) fn channel_key(channel_id: &str) -> String {
+    format!("channel:{}", channel_id)
+}
+
+/// Whether answers are remembered for any channel, looking up the channel of a video is only worth it then
+pub(crate) fn has_channel_answers() -> bool {
+    load_answers().keys().any(|key| key.starts_with("channel:"))
+}
+
+/// The answers given the last time `key` was downloaded from, if they aren't too old
+pub(crate) fn recall(key: &str) -> Option<RememberedAnswers> {
+    load_answers().remove(key)
+}
+
+/// Saves `answers` as the ones to offer the next time `key` is downloaded from
+///
+/// Failures are shown to the user, the wizard asks everything again next time in the worst case
+pub(crate) fn remember(key: &str, answers: RememberedAnswers) {
+    let mut all_answers = load_answers();
+    all_answers.insert(key.to_string(), answers);
+
+    if let Err(err) = save_answers(&all_answers) {
+        eprintln!("{}: {} ({})", "ERROR".red(), err, ANSWERS_FILE);
+    }
+}
+
+/// Every source's answers, keyed by source
+///
+/// Entries which can't be read (written by another version of blob-dl, edited by hand, ...) and old ones are left out
+fn load_answers() -> HashMap<String, RememberedAnswers> {
+    let entries: HashMap<String, serde_json::Value> = state::state_dir()
+        .and_then(|dir| std::fs::read_to_string(dir.join(ANSWERS_FILE)).ok())
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default();
+
+    let oldest = now().saturating_sub(MAX_AGE.as_secs());
+
+    entries.into_iter()
+        .filter_map(|(key, entry)| Some((key, serde_json::from_value::<RememberedAnswers>(entry).ok()?)))
+        .filter(|(_, answers)| answers.saved_at >= oldest)
+        .collect()
+}
+
+fn save_answers(answers: &HashMap<String, RememberedAnswers>) -> std::io::Result<()> {
+    let dir = state::state_dir().ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no state directory"))?;
+    std::fs::write(dir.join(ANSWERS_FILE), serde_json::to_string_pretty(answers)?)
+}
+
+/// Seconds since the unix epoch, 0 if the clock is before it
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn playlists_are_keyed_by_their_list() {
+        assert_eq!(source_key("https://www.youtube.com/playlist?list=PL123"), Some(String::from("playlist:PL123")));
+        assert_eq!(source_key("https://www.youtube.com/watch?v=abc&list=PL123&index=2"), Some(String::from("playlist:PL123")));
+        assert_eq!(source_key("https://www.youtube.com/watch?v=abc&list="), None);
+    }
+
+    #[test]
+    fn channels_are_keyed_by_id_name_or_handle() {
+        assert_eq!(source_key("https://www.youtube.com/channel/UC123/videos"), Some(String::from("channel:UC123")));
+        assert_eq!(source_key("https://www.youtube.com/c/name"), Some(String::from("channel:name")));
+        assert_eq!(source_key("https://www.youtube.com/user/name"), Some(String::from("channel:name")));
+        assert_eq!(source_key("https://www.youtube.com/@handle"), Some(String::from("channel:@handle")));
+        assert_eq!(source_key("https://www.youtube.com/channel/"), None);
+        assert_eq!(channel_key("UC123"), String::from("channel:UC123"));
+    }
+
+    #[test]
+    fn single_videos_have_no_key_of_their_own() {
+        assert_eq!(source_key("https://www.youtube.com/watch?v=abc"), None);
+        assert_eq!(source_key("https://youtu.be/abc"), None);
+        assert_eq!(source_key("https://www.youtube.com/@"), None);
+        assert_eq!(source_key("not a url"), None);
+    }
+
+    #[test]
+    fn only_exact_formats_have_an_id() {
+        let answers = |format| RememberedAnswers::new(MediaSelection::FullVideo, AudioExtractionMode::NativeExtraction, DownloadMode::Full, format);
+
+        assert_eq!(answers(VideoQualityAndFormatPreferences::UniqueFormat(String::from("137"))).format_id(), Some("137"));
+        assert_eq!(answers(VideoQualityAndFormatPreferences::BestQuality).format_id(), None);
+    }
+}
@@ -0,0 +1,136 @@
+//! Recognizes output directories which are slow or costly to download into: cloud-synced folders and network filesystems
+use std::path::Path;
+
+/// Why downloading into a directory could be a bad idea
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum SlowStorage {
+    /// A folder synced by a cloud service, named after the service
+    CloudSync(&'static str),
+    /// A network filesystem (NFS, SMB, ...), named after its type
+    NetworkFilesystem(String),
+}
+
+/// The folders cloud services create by default, matched against every component of a path (lowercase)
+const CLOUD_FOLDERS: &[(&str, &str)] = &[
+    ("dropbox", "Dropbox"),
+    // OneDrive for business folders are called "OneDrive - <company>"
+    ("onedrive", "OneDrive"),
+    ("google drive", "Google Drive"),
+    ("googledrive", "Google Drive"),
+    ("my drive", "Google Drive"),
+    ("icloud drive", "iCloud Drive"),
+    // Where macOS keeps iCloud Drive's files
+    ("mobile documents", "iCloud Drive"),
+];
+
+/// Decides whether `path` is slow storage, `filesystem_type` is the type of the filesystem it is on (see network_filesystem_type)
+///
+/// Kept separate from the platform-specific detection so that any path and filesystem can be checked
+pub(crate) fn classify_output_path(path: &str, filesystem_type: Option<&str>) -> Option<SlowStorage> {
+    // UNC paths (\\server\share) always point to another machine
+    if path.starts_with("\\\\") {
+        return Some(SlowStorage::NetworkFilesystem(String::from("SMB")));
+    }
+
+    if let Some(filesystem) = filesystem_type {
+        return Some(SlowStorage::NetworkFilesystem(filesystem.to_string()));
+    }
+
+    // Windows paths are split on both separators, whatever the current platform is
+    path.split(['/', '\\'])
+        .map(|component| component.trim().to_lowercase())
+        .find_map(|component| CLOUD_FOLDERS.iter()
+            .find(|(folder, _)| component.starts_with(folder))
+            .map(|(_, service)| SlowStorage::CloudSync(service)))
+}
+
+/// The type of the network filesystem `path` is on, None if it is local or it can't be found out
+///
+/// The directory doesn't have to exist yet: its closest existing parent is checked
+#[cfg(target_os = "linux")]
+pub(crate) fn network_filesystem_type(path: &Path) -> Option<&'static str> {
+    use std::os::unix::ffi::OsStrExt;
+
+    // Magic numbers from statfs(2)
+    const NETWORK_FILESYSTEMS: &[(u32, &str)] = &[
+        (0x6969, "NFS"),
+        (0x517B, "SMB"),
+        (0xFF53_4D42, "CIFS"),
+        (0xFE53_4D42, "SMB2"),
+        (0x5346_414F, "AFS"),
+        (0x7375_7245, "Coda"),
+        (0x0102_1997, "9P"),
+    ];
+
+    let existing = path.ancestors().find(|ancestor| ancestor.exists())?;
+    let c_path = std::ffi::CString::new(existing.as_os_str().as_bytes()).ok()?;
+
+    // Safety: statfs only writes to the struct it is given, c_path is null-terminated
+    let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stats) } != 0 {
+        return None;
+    }
+
+    // f_type's size depends on the architecture, the magic numbers all fit in 32 bits
+    let magic = stats.f_type as u32;
+    NETWORK_FILESYSTEMS.iter().find(|(number, _)| *number == magic).map(|(_, name)| *name)
+}
+
+#[cfg(target_os = "macos")]
+pub(crate) fn network_filesystem_type(path: &Path) -> Option<&'static str> {
+    use std::os::unix::ffi::OsStrExt;
+
+    const NETWORK_FILESYSTEMS: &[(&str, &str)] = &[
+        ("nfs", "NFS"),
+        ("smbfs", "SMB"),
+        ("afpfs", "AFP"),
+        ("webdav", "WebDAV"),
+    ];
+
+    let existing = path.ancestors().find(|ancestor| ancestor.exists())?;
+    let c_path = std::ffi::CString::new(existing.as_os_str().as_bytes()).ok()?;
+
+    // Safety: statfs only writes to the struct it is given, c_path is null-terminated
+    let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stats) } != 0 {
+        return None;
+    }
+
+    // Safety: f_fstypename is a null-terminated C string
+    let type_name = unsafe { std::ffi::CStr::from_ptr(stats.f_fstypename.as_ptr()) }.to_string_lossy();
+    NETWORK_FILESYSTEMS.iter().find(|(name, _)| *name == type_name).map(|(_, name)| *name)
+}
+
+/// On other platforms only UNC paths are recognized, see classify_output_path
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub(crate) fn network_filesystem_type(_path: &Path) -> Option<&'static str> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cloud_folders_are_recognized_in_any_component() {
+        let cases = [
+            ("/home/me/Dropbox/videos", Some(SlowStorage::CloudSync("Dropbox"))),
+            ("C:\\Users\\me\\OneDrive - Contoso\\Videos", Some(SlowStorage::CloudSync("OneDrive"))),
+            ("/Users/me/Library/Mobile Documents/com~apple~CloudDocs", Some(SlowStorage::CloudSync("iCloud Drive"))),
+            ("G:\\My Drive\\music", Some(SlowStorage::CloudSync("Google Drive"))),
+            ("/home/me/Videos", None),
+            // Only the beginning of a component counts
+            ("/home/me/not-dropbox", None),
+        ];
+
+        for (path, expected) in cases {
+            assert_eq!(classify_output_path(path, None), expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn network_filesystems_come_before_cloud_folders() {
+        assert_eq!(classify_output_path("\\\\server\\share\\videos", None), Some(SlowStorage::NetworkFilesystem(String::from("SMB"))));
+        assert_eq!(classify_output_path("/mnt/Dropbox", Some("NFS")), Some(SlowStorage::NetworkFilesystem(String::from("NFS"))));
+    }
+}
@@ -1,39 +1,344 @@
 use dialoguer::console::Term;
 use dialoguer::{theme::ColorfulTheme, Select};
 use which::which;
+use std::path::Path;
+use colored::Colorize;
 
 use crate::assembling::youtube::*;
-use crate::error::BlobResult;
+use crate::error::{BlobdlError, BlobResult};
+use crate::only_new::OnlyNew;
 use crate::ui_prompts::*;
+use crate::parser;
+use crate::timings::Timings;
 
 /// This is a wizard for downloading a youtube playlist
 ///
 /// It asks for:
+/// - Whether to reuse the answers given the last time the playlist was downloaded (if it was)
 /// - Video or Audio
 /// - Quality/Format
 /// - Output path
 /// - Index inclusion
+/// - Whether to continue after a video fails
+/// - View count filter (optional)
+/// - Advanced options (optional)
 ///
-/// Returns a fully configured YtPlaylistConfig, build_command() can be called
-pub fn assemble_data(url: &str) -> BlobResult<config::DownloadConfig> {
+/// `start_index` is the playlist index of the url's video when the user wants it and every video after it
+///
+/// Returns a fully configured DownloadConfig, build_command() can be called
+pub fn assemble_data(url: &str, start_index: Option<usize>, cli_config: &parser::CliConfig, timings: &Timings) -> BlobResult<config::DownloadConfig> {
     let term = Term::buffered_stderr();
 
-    // Whether the user wants to download video files or audio-only
-    let media_selected = get_media_selection(&term)?;
+    // The answers given the last time this playlist was downloaded
+    let source = remembered::source_key(url);
+    let previous = source.as_deref().and_then(remembered::recall);
+    // Checking that a format id is still common to every video would mean fetching all of their formats first
+    let offered = previous.as_ref().filter(|answers| answers.format_id().is_none());
+
+    // What an exact format stands for, only known when it's picked from the list of formats
+    let mut exact_format = None;
+
+    let (media_selected, audio_extraction, download_mode, chosen_format) = match get_previous_answers(&term, offered, cli_config)? {
+        Some(answers) => (answers.media_selection, answers.audio_extraction, answers.download_mode, answers.format),
+        None => {
+            // Whether the user wants to download video files or audio-only
+            let mut media_selected = get_media_selection(&term, cli_config.media(), previous.as_ref().map(|answers| &answers.media_selection),
+                                                     cli_config.assume_yes())?;
+
+            let audio_extraction = get_audio_extraction_mode(&term, &media_selected)?;
+
+            let mut download_mode = DownloadMode::Full;
 
-    let chosen_format = format::get_format(&term, url, &media_selected)?;
+            let chosen_format = match audio_extraction {
+                // ffmpeg re-encodes the best audio stream, so there is no quality to choose
+                AudioExtractionMode::FfmpegConvert(_) => VideoQualityAndFormatPreferences::BestQuality,
+                AudioExtractionMode::NativeExtraction => match cli_config.quality() {
+                    Some(quality) => quality.clone(),
+                    None => format::get_format(&term, url, &mut media_selected, &mut download_mode, &mut exact_format, cli_config, timings)?,
+                },
+            };
 
-    let output_path = get_output_path(&term)?;
+            (media_selected, audio_extraction, download_mode, chosen_format)
+        }
+    };
+
+    let answers = remembered::RememberedAnswers::new(media_selected.clone(), audio_extraction.clone(), download_mode, chosen_format.clone());
+
+    // It doesn't matter when only the thumbnails are downloaded
+    let prefer_free_formats = download_mode == DownloadMode::Full && get_free_formats_preference(&term, &chosen_format)?;
+
+    let output_path = get_output_path(&term, cli_config.output_path(), cli_config.allow_system_paths())?;
 
     let include_indexes = get_index_preference(&term)?;
 
-    Ok(config::DownloadConfig::new_playlist(
+    // --ignore-errors answers in advance
+    let ignore_errors = cli_config.ignore_errors() || get_yes_or_no(&term, IGNORE_ERRORS_PROMPT, true)?;
+
+    // --min-views and --max-views answer in advance
+    let view_count_filter = match cli_config.view_count_filter() {
+        Some(filter) => Some(filter),
+        None => get_view_count_filter(&term)?,
+    };
+
+    let entries = get_playlist_entries(url, cli_config, timings)?;
+
+    let mut excluded_positions = if cli_config.keep_duplicates() {
+        vec![]
+    } else {
+        get_duplicate_positions(&entries)
+    };
+
+    let hidden_positions = get_hidden_positions(&entries);
+    // yt-dlp would fail on every one of them
+    excluded_positions.extend_from_slice(&hidden_positions);
+    excluded_positions.extend(get_predicted_failures(&term, &entries, cli_config)?);
+    excluded_positions.sort_unstable();
+    excluded_positions.dedup();
+
+    let mut download_config = config::DownloadConfig::new_playlist(
         url,
         output_path,
         include_indexes,
         chosen_format,
         media_selected,
-    ))
+    );
+
+    download_config.exact_format = exact_format;
+    download_config.audio_extraction = audio_extraction;
+    download_config.download_mode = download_mode;
+    download_config.prefer_free_formats = prefer_free_formats;
+    download_config.view_count_filter = view_count_filter;
+    download_config.ignore_errors = ignore_errors;
+    download_config.excluded_positions = excluded_positions;
+    download_config.hidden_positions = hidden_positions;
+    download_config.index_counts_hidden = cli_config.index_counts_hidden();
+    download_config.playlist_length = positioned_entries(&entries).last().map(|(position, _)| *position);
+    download_config.playlist_start = start_index.map(|index| get_start_position(url, index, &entries));
+
+    if cli_config.only_new() {
+        download_config.only_new = Some(get_only_new(&term, url)?);
+    }
+
+    get_advanced_options(&term, &mut download_config)?;
+
+    // File names depend on the advanced options, so they are predicted last
+    handle_extension_conflicts(&term, &entries, &mut download_config, cli_config)?;
+
+    if let Some(source) = source {
+        remembered::remember(&source, answers);
+    }
+
+    Ok(download_config)
+}
+
+/// Looks for old copies of the videos about to be downloaded which have a different extension (like song.mp4 when
+/// downloading song.mp3) and asks whether to skip those videos, keep both copies or replace the old ones
+///
+/// --on-extension-conflict answers in advance, without a terminal both copies are kept like yt-dlp would
+fn handle_extension_conflicts(term: &Term, entries: &[PlaylistEntry], download_config: &mut config::DownloadConfig,
+                              cli_config: &parser::CliConfig) -> BlobResult<()>
+{
+    let playlist_title = match entries.first().and_then(|entry| entry.playlist.as_deref()) {
+        Some(title) => title,
+        None => return Ok(()),
+    };
+
+    let restricted = download_config.ascii_filenames || cli_config.ascii_names();
+    let mut folder_name = file_names::sanitize_filename(playlist_title, restricted);
+    if download_config.windows_safe_filenames {
+        folder_name = file_names::windows_safe_folder_name(&folder_name);
+    }
+    let folder = Path::new(download_config.output_path()).join(folder_name);
+
+    let playlist_length = download_config.playlist_length.unwrap_or(entries.len());
+    let first = cli_config.start_at().unwrap_or(1);
+    let last = cli_config.stop_at().unwrap_or(playlist_length);
+
+    let expected_names: Vec<(usize, String)> = positioned_entries(entries).into_iter()
+        .filter(|(position, _)| (first..=last).contains(position) && !download_config.excluded_positions.contains(position))
+        .filter_map(|(position, entry)| {
+            let mut title = entry.title.as_deref()?;
+            // yt-dlp shortens the title before sanitizing it
+            if let Some(max_bytes) = download_config.max_title_bytes {
+                title = file_names::truncate_to_bytes(title, max_bytes);
+            }
+            let title = file_names::sanitize_filename(title, restricted);
+            if download_config.include_indexes() {
+                Some((position, format!("{}_{}", download_config.shown_index(position), title)))
+            } else {
+                Some((position, title))
+            }
+        })
+        .collect();
+
+    let conflicts = file_names::find_extension_conflicts(&folder, &expected_names, download_config.new_extension());
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+
+    eprintln!("{}", EXTENSION_CONFLICTS_FOUND.yellow());
+    for conflict in &conflicts {
+        eprintln!("   {} (video {})", conflict.existing_file.display(), conflict.position);
+    }
+
+    let policy = match cli_config.on_extension_conflict() {
+        Some(policy) => policy,
+        None if !term.is_term() => parser::ExtensionConflictPolicy::KeepBoth,
+        None => {
+            let options = &[SKIP_CONFLICTS_PROMPT, KEEP_BOTH_CONFLICTS_PROMPT, REPLACE_CONFLICTS_PROMPT];
+            let choice = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt(EXTENSION_CONFLICTS_PROMPT)
+                .default(0)
+                .items(options)
+                .interact_on(term)?;
+
+            match options[choice] {
+                SKIP_CONFLICTS_PROMPT => parser::ExtensionConflictPolicy::Skip,
+                REPLACE_CONFLICTS_PROMPT => parser::ExtensionConflictPolicy::Replace,
+                _ => parser::ExtensionConflictPolicy::KeepBoth,
+            }
+        }
+    };
+
+    match policy {
+        parser::ExtensionConflictPolicy::Skip => {
+            download_config.excluded_positions.extend(conflicts.iter().map(|conflict| conflict.position));
+            download_config.excluded_positions.sort_unstable();
+            download_config.excluded_positions.dedup();
+        }
+        parser::ExtensionConflictPolicy::Replace => {
+            download_config.replaced_files = conflicts.into_iter().map(|conflict| conflict.existing_file).collect();
+        }
+        parser::ExtensionConflictPolicy::KeepBoth => {}
+    }
+
+    Ok(())
+}
+
+/// Lists the videos whose metadata says they will fail (members-only, age-restricted, ...) and asks whether to leave them out
+///
+/// Returns the positions to leave out. --exclude-unavailable answers in advance,
+/// without a terminal they are kept and their errors are reported after the download like any other.
+/// Videos which only need signing in can be fixed with cookies, so if there are some they are kept unless the user says otherwise
+fn get_predicted_failures(term: &Term, entries: &[PlaylistEntry], cli_config: &parser::CliConfig) -> BlobResult<Vec<usize>> {
+    let failures: Vec<(usize, &PlaylistEntry, &str)> = positioned_entries(entries).into_iter()
+        .filter(|(_, entry)| !entry.is_hidden())
+        .filter_map(|(position, entry)| Some((position, entry, entry.predicted_failure()?)))
+        .collect();
+
+    if failures.is_empty() {
+        return Ok(vec![]);
+    }
+
+    eprintln!("{}", PREDICTED_FAILURES_FOUND.yellow());
+    for (position, entry, reason) in &failures {
+        eprintln!("   {}: \"{}\" ({}), {}", position, entry.title.as_deref().unwrap_or(&entry.id), entry.id, reason);
+    }
+
+    let needs_sign_in = failures.iter().any(|(_, entry, _)| entry.needs_sign_in());
+    if needs_sign_in {
+        eprintln!("{}", SIGN_IN_FIXES_PREDICTED_FAILURES);
+    }
+
+    let exclude = cli_config.exclude_unavailable()
+        || (term.is_term() && get_yes_or_no(term, EXCLUDE_PREDICTED_FAILURES_PROMPT, !needs_sign_in)?);
+
+    Ok(if exclude { failures.iter().map(|(position, _, _)| *position).collect() } else { vec![] })
+}
+
+/// A video which appears more than once in a playlist
+#[derive(Debug, PartialEq)]
+struct Duplicate {
+    id: String,
+    /// Every position the video appears at, counting from 1
+    positions: Vec<usize>,
+}
+
+/// Finds the videos which appear more than once, in the order they first appear in
+fn find_duplicates(entries: &[PlaylistEntry]) -> Vec<Duplicate> {
+    let mut duplicates: Vec<Duplicate> = vec![];
+
+    for (position, entry) in positioned_entries(entries) {
+        match duplicates.iter_mut().find(|duplicate| duplicate.id == entry.id) {
+            Some(duplicate) => duplicate.positions.push(position),
+            None => duplicates.push(Duplicate { id: entry.id.clone(), positions: vec![position] }),
+        }
+    }
+
+    duplicates.retain(|duplicate| duplicate.positions.len() > 1);
+    duplicates
+}
+
+/// Loads what the previous --only-new runs downloaded from the playlist, the first time the user has to confirm
+/// that the whole playlist is going to be downloaded
+fn get_only_new(term: &Term, url: &str) -> BlobResult<OnlyNew> {
+    let only_new = OnlyNew::load(url);
+
+    if only_new.previous.is_none() && !get_yes_or_no(term, ONLY_NEW_FIRST_RUN_PROMPT, true)? {
+        return Err(BlobdlError::DownloadCancelled);
+    }
+
+    Ok(only_new)
+}
+
+/// The position (counting from 1) of the url's video in the playlist, which is where a "this video and everything
+/// after it" download starts
+///
+/// The url's index can be stale if videos were added or removed since the link was copied, so the video is looked up
+/// in the playlist: `index` is only used when it can't be found there
+pub(super) fn get_start_position(url: &str, index: usize, entries: &[PlaylistEntry]) -> usize {
+    let video_id = url::Url::parse(url).ok()
+        .and_then(|url| url.query_pairs().find(|(key, _)| key == "v").map(|(_, id)| id.to_string()));
+
+    let position = video_id.and_then(|id| {
+        let positions: Vec<usize> = positioned_entries(entries).into_iter()
+            .filter(|(_, entry)| entry.id == id)
+            .map(|(position, _)| position)
+            .collect();
+        // A video which appears more than once starts from the occurrence closest to the url's index
+        positions.into_iter().min_by_key(|position| position.abs_diff(index))
+    });
+
+    position.unwrap_or(index)
+}
+
+/// Tells the user how many videos of the playlist are private or deleted and returns their positions, they are not going to be downloaded
+fn get_hidden_positions(entries: &[PlaylistEntry]) -> Vec<usize> {
+    let hidden: Vec<usize> = positioned_entries(entries).into_iter()
+        .filter(|(_, entry)| entry.is_hidden())
+        .map(|(position, _)| position)
+        .collect();
+
+    if !hidden.is_empty() {
+        eprintln!("{} {}", hidden.len(), HIDDEN_VIDEOS_SKIPPED);
+    }
+
+    hidden
+}
+
+/// Tells the user which videos appear more than once in the playlist and returns the positions of
+/// every occurrence except the first one, which are not going to be downloaded
+///
+/// The positions aren't renumbered: with index prefixes enabled the skipped positions leave gaps in the file names
+fn get_duplicate_positions(entries: &[PlaylistEntry]) -> Vec<usize> {
+    let duplicates = find_duplicates(entries);
+
+    let mut excluded = vec![];
+    for duplicate in duplicates {
+        let positions: Vec<String> = duplicate.positions.iter().map(|position| position.to_string()).collect();
+        let title = entries.iter().find(|entry| entry.id == duplicate.id).and_then(|entry| entry.title.as_deref()).unwrap_or(&duplicate.id);
+        eprintln!("{} \"{}\" ({}) appears {} times at positions {}", "Duplicate:".yellow(), title, duplicate.id,
+                 duplicate.positions.len(), positions.join(", "));
+
+        excluded.extend_from_slice(&duplicate.positions[1..]);
+    }
+
+    if !excluded.is_empty() {
+        eprintln!("{}", DUPLICATES_SKIPPED);
+    }
+
+    excluded.sort_unstable();
+    excluded
 }
 
 mod format {
@@ -49,22 +354,37 @@ mod format {
         pub fn new() -> Self {
             FormatsLibrary { videos: vec![] }
         }
-        pub fn add_video(&mut self, video_formats: VideoSpecs) {
-            self.videos.push(video_formats)
+        /// Adds the formats of the video at `position` in the playlist, if they could be read and there is at least one
+        ///
+        /// Otherwise the video is left out with a warning, it is still downloaded but doesn't restrict the common formats
+        pub fn add_video(&mut self, position: usize, video_formats: BlobResult<VideoSpecs>) {
+            match video_formats {
+                Ok(video_formats) if !video_formats.formats().is_empty() => {
+                    self.videos.push(video_formats);
+                }
+                Ok(_) => {
+                    eprintln!("{}: {} {} (no formats listed)", "WARNING".yellow(), FORMATS_UNREADABLE, position);
+                }
+                Err(err) => {
+                    eprintln!("{}: {} {} ({})", "WARNING".yellow(), FORMATS_UNREADABLE, position, err);
+                }
+            }
         }
         pub fn videos(&self) -> &Vec<VideoSpecs> {
             &self.videos
         }
     }
 
-    use crate::assembling::youtube::VideoSpecs;
+    use crate::assembling::youtube::{VideoFormat, VideoSpecs};
     use super::*;
 
     /// Asks the user to choose a download format and quality
     ///
-    /// The chosen format will be applied to the entire playlist
-    pub(super) fn get_format(term: &Term, url: &str, media_selected: &MediaSelection)
-                             -> BlobResult<VideoQualityAndFormatPreferences>
+    /// The chosen format will be applied to the entire playlist.
+    /// `media_selected` changes if the user switches to a media selection the playlist has formats for,
+    /// `download_mode` if they only want the thumbnails and `exact_format` is set to the format picked from the list, if one is
+    pub(super) fn get_format(term: &Term, url: &str, media_selected: &mut MediaSelection, download_mode: &mut DownloadMode,
+                             exact_format: &mut Option<VideoFormat>, cli_config: &parser::CliConfig, timings: &Timings) -> BlobResult<VideoQualityAndFormatPreferences>
     {
 
         // A list of all the format options that can be picked
@@ -73,15 +393,15 @@ mod format {
         format_options.push(BEST_QUALITY_PROMPT_PLAYLIST);
         format_options.push(SMALLEST_QUALITY_PROMPT_PLAYLIST);
 
-        if which("ffmpeg").is_ok() {
+        // Audio-only downloads are re-encoded in get_audio_extraction_mode, so they skip the conversion option
+        if which("ffmpeg").is_ok() && *media_selected != MediaSelection::AudioOnly {
             // If ffmpeg is installed in the system
             // Some features are only available with ffmpeg
-            match media_selected {
-                MediaSelection::AudioOnly => format_options.push(CONVERT_FORMAT_PROMPT_AUDIO),
-                _ => format_options.push(CONVERT_FORMAT_PROMPT_VIDEO_PLAYLIST)
-            }
+            format_options.push(REMUX_FORMAT_PROMPT);
+            format_options.push(CONVERT_FORMAT_PROMPT_VIDEO_PLAYLIST);
 
             format_options.push(YT_FORMAT_PROMPT_PLAYLIST);
+            format_options.push(THUMBNAIL_ONLY_PROMPT);
 
             // Set up a prompt for the user
             let user_selection = Select::with_theme(&ColorfulTheme::default())
@@ -92,13 +412,21 @@ mod format {
             match user_selection {
                 0 => Ok(VideoQualityAndFormatPreferences::BestQuality),
                 1 => Ok(VideoQualityAndFormatPreferences::SmallestSize),
-                2 => convert_to_format(term, media_selected),
-                _ => get_format_from_yt(term, url, media_selected),
+                2 => remux_to_format(term),
+                5 => {
+                    *download_mode = DownloadMode::ThumbnailOnly;
+                    Ok(VideoQualityAndFormatPreferences::BestQuality)
+                }
+                3 => convert_to_format(term, media_selected),
+                _ => get_format_from_yt(term, url, media_selected, exact_format, cli_config, timings),
             }
         } else {
-            println!("{}", FFMPEG_UNAVAILABLE_WARNING);
+            if which("ffmpeg").is_err() {
+                eprintln!("{}", FFMPEG_UNAVAILABLE_WARNING);
+            }
             // ffmpeg isn't installed, so ffmpeg-exclusive features are unavailable (video remuxing)
             format_options.push(YT_FORMAT_PROMPT_PLAYLIST);
+            format_options.push(THUMBNAIL_ONLY_PROMPT);
 
             // Set up a prompt for the user
             let user_selection = Select::with_theme(&ColorfulTheme::default())
@@ -109,17 +437,22 @@ mod format {
             match user_selection {
                 0 => Ok(VideoQualityAndFormatPreferences::BestQuality),
                 1 => Ok(VideoQualityAndFormatPreferences::SmallestSize),
-                _ => get_format_from_yt(term, url, media_selected),
+                3 => {
+                    *download_mode = DownloadMode::ThumbnailOnly;
+                    Ok(VideoQualityAndFormatPreferences::BestQuality)
+                }
+                _ => get_format_from_yt(term, url, media_selected, exact_format, cli_config, timings),
             }
         }
     }
 
     // Show the user a list of formats common across the whole playlist, picked from those available directly from yt.
-    fn get_format_from_yt(term: &Term, url: &str, media_selected: &MediaSelection)
-                          -> BlobResult<VideoQualityAndFormatPreferences>
+    // If none of them fits media_selected the user can switch to another media selection (see triage_empty_formats)
+    fn get_format_from_yt(term: &Term, url: &str, media_selected: &mut MediaSelection, exact_format: &mut Option<VideoFormat>,
+                          cli_config: &parser::CliConfig, timings: &Timings) -> BlobResult<VideoQualityAndFormatPreferences>
     {
         // Get a list of all the formats available for the playlist
-        let ytdl_formats = get_ytdlp_formats(url)?;
+        let ytdl_formats = get_ytdlp_formats(url, false, cli_config, timings)?;
 
         // Filter out formats not available for all the videos
         let (intersections, all_available_formats) = get_common_formats(ytdl_formats)?;
@@ -128,20 +461,59 @@ mod format {
         let mut correct_ids = vec![];
         // Format options that will be shown to the user
         let mut ui_format_options = vec![];
-
-        // Only look at ids common across the whole playlist
-        for id in intersections.iter() {
-            // Since we are looking for ids common to all videos just checking the first one is fine
-            if let Some(first_video_formats) = all_available_formats.videos().first() {
-                for format in first_video_formats.formats() {
-                    // If format and media_selected are compatible and this is the correct id
-                    if check_format(format, media_selected) && format.format_id == *id {
-                        // Add to the list of available formats the current one formatted in a nice way
-                        ui_format_options.push(format.to_string());
-                        correct_ids.push(id);
+        // The formats behind ui_format_options
+        let mut compatible_formats = vec![];
+
+        // Since we are looking for ids common to all videos just checking the first one is fine
+        let common_formats: Vec<&VideoFormat> = all_available_formats.videos()
+            .first()
+            .map(|first_video| first_video.formats().iter().filter(|format| intersections.contains(&format.format_id)).collect())
+            .unwrap_or_default();
+
+        loop {
+            // Only look at ids common across the whole playlist
+            for id in intersections.iter() {
+                for format in common_formats.iter().filter(|format| format.format_id == *id) {
+                    match check_format(format, media_selected) {
+                        FormatCompatibility::Compatible => {
+                            // Add to the list of available formats the current one formatted in a nice way
+                            ui_format_options.push(format.to_string());
+                            correct_ids.push(id);
+                            compatible_formats.push(*format);
+                        }
+                        compatibility => cli_config.verbosity().debug(&format!("Left out format {}: {:?}", id, compatibility)),
                     }
                 }
             }
+
+            if !ui_format_options.is_empty() {
+                break;
+            }
+
+            match triage_empty_formats(term, &common_formats, media_selected, cli_config.assume_yes())? {
+                Some(new_selection) => *media_selected = new_selection,
+                None => return Ok(VideoQualityAndFormatPreferences::BestQuality),
+            }
+        }
+
+        if *media_selected == MediaSelection::AudioOnly {
+            // Raw format ids don't mean much for audio, offer quality tiers first
+            let tiers = audio_quality_tiers(&compatible_formats);
+
+            if !tiers.is_empty() {
+                let mut tier_options: Vec<&str> = tiers.iter().map(|tier| tier.label.as_str()).collect();
+                tier_options.push(EXACT_FORMAT_PROMPT);
+
+                let user_selection = Select::with_theme(&ColorfulTheme::default())
+                    .with_prompt("Which audio quality do you want to apply to all videos?")
+                    .default(0)
+                    .items(&tier_options)
+                    .interact_on(term)?;
+
+                if let Some(tier) = tiers.get(user_selection) {
+                    return Ok(VideoQualityAndFormatPreferences::UniqueFormat(tier.format.clone()));
+                }
+            }
         }
 
         let user_selection = Select::with_theme(&ColorfulTheme::default())
@@ -150,56 +522,89 @@ mod format {
             .items(&ui_format_options)
             .interact_on(term)?;
 
+        *exact_format = Some(compatible_formats[user_selection].clone());
         Ok(VideoQualityAndFormatPreferences::UniqueFormat(correct_ids[user_selection].clone()))
     }
 
+    /// An audio quality which can be applied to a whole playlist
+    pub(super) struct AudioTier {
+        /// What the user sees, for example "High (~160 kbps opus)"
+        pub(super) label: String,
+        /// A yt-dlp format selector: the tier's ids from best to worst, then a generic fallback
+        pub(super) format: String,
+    }
+
+    /// Groups audio-only formats in quality tiers according to their bitrate (tbr)
+    ///
+    /// Tiers without any format are left out
+    pub(super) fn audio_quality_tiers(formats: &[&VideoFormat]) -> Vec<AudioTier> {
+        // (name, lowest tbr included, fallback if none of the tier's ids are available)
+        let buckets = [
+            ("High", 140.0, "bestaudio"),
+            ("Medium", 100.0, "bestaudio"),
+            ("Small", 0.0, "worstaudio"),
+        ];
+
+        let mut tiers = vec![];
+        // The tbr of the previous bucket's lower bound, formats above it were already placed
+        let mut upper_bound = f64::INFINITY;
+
+        for (name, lower_bound, fallback) in buckets {
+            let mut bucket: Vec<&&VideoFormat> = formats.iter()
+                .filter(|format| format.tbr.is_some_and(|tbr| tbr >= lower_bound && tbr < upper_bound))
+                .collect();
+            upper_bound = lower_bound;
+
+            // The best format in the tier comes first
+            bucket.sort_by(|a, b| b.tbr.partial_cmp(&a.tbr).unwrap_or(std::cmp::Ordering::Equal));
+
+            if let Some(best) = bucket.first() {
+                let codec = best.acodec.as_deref().unwrap_or(best.ext.as_str());
+                // tbr is always present in bucketed formats
+                let label = format!("{} (~{:.0} kbps {})", name, best.tbr.unwrap_or(0.0), codec);
+
+                let mut format: Vec<&str> = bucket.iter().map(|f| f.format_id.as_str()).collect();
+                format.push(fallback);
+
+                tiers.push(AudioTier { label, format: format.join("/") });
+            }
+        }
+
+        tiers
+    }
+
     // Finds the formats available for all videos in the playlist and the list of all the available formats
     fn get_common_formats(json_formats: process::Output) -> BlobResult<(Vec<String>, FormatsLibrary)> {
         // A list of videos, which are Vec of formats
         let mut all_available_formats = FormatsLibrary::new();
 
-        // Compute which formats are common across the entire playlist
-        let mut intersections: Vec<String> = vec![];
-        let mut current_ids: Vec<String> = vec![];
-
         // Each line in ytdl_formats contains all the format information for 1 video
-        for (i, video_formats_json) in std::str::from_utf8(&json_formats.stdout)?
-            .lines()
-            .enumerate() {
-            let serialized_video = serialize_formats(video_formats_json)?;
-            // Add the current video's formats to the list of all formats
-            all_available_formats.add_video(serialized_video);
-
-            if i == 0 {
-                // In the first iteration the intersection is all the ids
-                for format in all_available_formats.videos()[i].formats().iter() {
-                    intersections.push(format.format_id.clone());
-                }
-            } else {
-                for format in all_available_formats.videos()[i].formats().iter() {
-                    current_ids.push(format.format_id.clone());
-                }
-                // Actually compute the intersection
-                intersections = intersection(&intersections, &current_ids);
-            }
+        for (position, video_formats_json) in positioned_dumps(std::str::from_utf8(&json_formats.stdout)?) {
+            // Add the current video's formats to the list of all formats, videos whose formats can't be read are skipped
+            all_available_formats.add_video(position, serialize_formats(video_formats_json));
         }
 
-        Ok((intersections, all_available_formats))
+        // Compute which formats are common across the entire playlist
+        let all_ids: Vec<Vec<String>> = all_available_formats.videos().iter()
+            .map(|video| video.formats().iter().map(|format| format.format_id.clone()).collect())
+            .collect();
+
+        Ok((compute_common_ids(&all_ids), all_available_formats))
     }
 }
 
-/// Returns an owned intersection Vec
-fn intersection<'a, T: Eq + Clone>(vec1: &'a [T], vec2: &'a [T]) -> Vec<T> {
-    let mut intersections = vec![];
-
-    for (element_1, element_2) in vec1.iter().zip(vec2.iter()) {
-        if element_1 == element_2 {
-            // Intersection element found!
-            intersections.push(element_1.clone());
-        }
+/// The format ids every video has, in the order of the first video's formats
+///
+/// No videos have no common ids, a single video's ids are all common
+fn compute_common_ids(all_ids: &[Vec<String>]) -> Vec<String> {
+    match all_ids {
+        [] => vec![],
+        [only] => only.clone(),
+        [first, rest @ ..] => first.iter()
+            .filter(|id| rest.iter().all(|ids| ids.contains(id)))
+            .cloned()
+            .collect(),
     }
-
-    intersections
 }
 
 /// Whether the downloaded files should include their index in the playlist as a part of their name
@@ -221,3 +626,117 @@ fn get_index_preference(term: &Term) -> BlobResult<bool> {
         _ => Ok(false),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(ids: &[&str]) -> Vec<PlaylistEntry> {
+        ids.iter().map(|id| serde_json::from_str(&format!(r#"{{"id": "{}", "title": "Video"}}"#, id)).unwrap()).collect()
+    }
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    fn audio_format(id: &str, ext: &str, acodec: &str, tbr: Option<f64>) -> VideoFormat {
+        serde_json::from_value(serde_json::json!({
+            "format_id": id, "ext": ext, "resolution": "audio only", "vcodec": "none", "acodec": acodec, "tbr": tbr,
+        })).unwrap()
+    }
+
+    /// (label, format selector) of every tier
+    fn tiers(formats: &[VideoFormat]) -> Vec<(String, String)> {
+        let formats: Vec<&VideoFormat> = formats.iter().collect();
+        format::audio_quality_tiers(&formats).into_iter().map(|tier| (tier.label, tier.format)).collect()
+    }
+
+    #[test]
+    fn youtube_audio_formats_fill_every_tier() {
+        let formats = [
+            audio_format("249", "webm", "opus", Some(51.2)),
+            audio_format("250", "webm", "opus", Some(68.9)),
+            audio_format("140", "m4a", "mp4a.40.2", Some(129.5)),
+            audio_format("251", "webm", "opus", Some(158.7)),
+        ];
+
+        assert_eq!(tiers(&formats), [
+            (String::from("High (~159 kbps opus)"), String::from("251/bestaudio")),
+            (String::from("Medium (~130 kbps mp4a.40.2)"), String::from("140/bestaudio")),
+            // The best format of the tier comes first
+            (String::from("Small (~69 kbps opus)"), String::from("250/249/worstaudio")),
+        ]);
+    }
+
+    #[test]
+    fn empty_tiers_are_left_out() {
+        // Bounds belong to the tier above them
+        let formats = [audio_format("251", "webm", "opus", Some(140.0)), audio_format("140", "m4a", "mp4a.40.2", Some(100.0))];
+        assert_eq!(tiers(&formats), [
+            (String::from("High (~140 kbps opus)"), String::from("251/bestaudio")),
+            (String::from("Medium (~100 kbps mp4a.40.2)"), String::from("140/bestaudio")),
+        ]);
+
+        // Without a bitrate a format can't be placed
+        assert!(tiers(&[audio_format("251", "webm", "opus", None)]).is_empty());
+        assert!(tiers(&[]).is_empty());
+    }
+
+    #[test]
+    fn duplicates_keep_every_position_in_order() {
+        let playlist = entries(&["a", "b", "a", "c", "b", "a"]);
+
+        assert_eq!(find_duplicates(&playlist), [
+            Duplicate { id: String::from("a"), positions: vec![1, 3, 6] },
+            Duplicate { id: String::from("b"), positions: vec![2, 5] },
+        ]);
+        // Only the first occurrence is downloaded
+        assert_eq!(get_duplicate_positions(&playlist), [3, 5, 6]);
+
+        assert!(find_duplicates(&entries(&["a", "b", "c"])).is_empty());
+        assert!(get_duplicate_positions(&[]).is_empty());
+    }
+
+    #[test]
+    fn skipped_duplicates_leave_gaps_in_the_indexes() {
+        let mut download_config = config::DownloadConfig::new_playlist("https://www.youtube.com/playlist?list=PL", String::from("/downloads"), true,
+                                                                       VideoQualityAndFormatPreferences::BestQuality, MediaSelection::FullVideo);
+        download_config.excluded_positions = get_duplicate_positions(&entries(&["a", "b", "a", "c", "b", "a", "d"]));
+
+        let args: Vec<String> = download_config.build_command().0.get_args().map(|arg| arg.to_string_lossy().into_owned()).collect();
+        assert!(args.windows(2).any(|pair| pair == ["--playlist-items", "1:2,4:4,7:"]), "{:?}", args);
+        // Files keep their position in the playlist: 1, 2, 4 and 7
+        assert!(args.iter().any(|arg| arg.contains("%(playlist_index)s_")), "{:?}", args);
+    }
+
+    #[test]
+    fn common_ids_keep_the_first_videos_order() {
+        let all_ids = [ids(&["18", "137", "140", "248"]), ids(&["248", "140", "18"]), ids(&["140", "18", "22", "248"])];
+        assert_eq!(compute_common_ids(&all_ids), ["18", "140", "248"]);
+    }
+
+    #[test]
+    fn common_ids_of_one_or_no_videos() {
+        assert!(compute_common_ids(&[]).is_empty());
+        assert_eq!(compute_common_ids(&[ids(&["22", "18"])]), ["22", "18"]);
+        // A video with no formats leaves nothing in common
+        assert!(compute_common_ids(&[ids(&["22", "18"]), vec![]]).is_empty());
+    }
+
+    #[test]
+    fn start_position_follows_the_video_when_the_index_is_stale() {
+        let playlist = entries(&["a", "b", "c", "d"]);
+
+        // The video moved from the 2nd to the 3rd position since the link was copied
+        assert_eq!(get_start_position("https://www.youtube.com/watch?v=c&list=PL&index=2", 2, &playlist), 3);
+        // Removed from the playlist
+        assert_eq!(get_start_position("https://www.youtube.com/watch?v=z&list=PL&index=2", 2, &playlist), 2);
+    }
+
+    #[test]
+    fn start_position_of_repeated_videos_is_the_closest_to_the_index() {
+        let playlist = entries(&["a", "b", "a", "c", "a"]);
+        assert_eq!(get_start_position("https://www.youtube.com/watch?v=a&list=PL&index=4", 4, &playlist), 3);
+        assert_eq!(get_start_position("https://www.youtube.com/watch?v=a&list=PL&index=5", 5, &playlist), 5);
+    }
+}
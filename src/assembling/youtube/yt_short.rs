@@ -0,0 +1,52 @@
+use dialoguer::console::Term;
+use which::which;
+
+use crate::assembling::youtube::*;
+use crate::analyzer;
+use crate::error::BlobResult;
+use crate::ui_prompts::*;
+use crate::parser;
+use crate::timings::Timings;
+
+/// This is a wizard for downloading a youtube short
+///
+/// Shorts are short, so the best quality is always downloaded. It asks for:
+/// - Video or Audio
+/// - Output path
+/// - Cropping to a vertical frame (only with ffmpeg)
+/// - Advanced options (optional)
+///
+/// Nothing is fetched before downloading a short, so `_timings` is left untouched
+pub(crate) fn assemble_data(url: &str, cli_config: &parser::CliConfig, _timings: &Timings) -> BlobResult<config::DownloadConfig> {
+    let term = Term::buffered_stderr();
+
+    // Whether the user wants to download video files or audio-only
+    let media_selected = get_media_selection(&term, cli_config.media(), None, cli_config.assume_yes())?;
+
+    let output_path = get_output_path(&term, cli_config.output_path(), cli_config.allow_system_paths())?;
+
+    let crop_to_vertical = get_crop_preference(&term, &media_selected)?;
+
+    let mut download_config = config::DownloadConfig::new_video(
+        url,
+        VideoQualityAndFormatPreferences::BestQuality,
+        output_path,
+        media_selected,
+    );
+
+    download_config.download_target = analyzer::DownloadOption::YtShort;
+    download_config.crop_to_vertical = crop_to_vertical;
+
+    get_advanced_options(&term, &mut download_config)?;
+
+    Ok(download_config)
+}
+
+/// Asks whether the short should be cropped to 9:16, which requires ffmpeg
+fn get_crop_preference(term: &Term, media_selected: &MediaSelection) -> BlobResult<bool> {
+    if *media_selected == MediaSelection::AudioOnly || which("ffmpeg").is_err() {
+        return Ok(false);
+    }
+
+    get_yes_or_no(term, CROP_SHORT_PROMPT, false)
+}
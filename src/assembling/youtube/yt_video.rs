@@ -5,27 +5,111 @@ use which::which;
 use crate::assembling::youtube::*;
 use crate::error::BlobResult;
 use crate::ui_prompts::*;
+use crate::parser;
+use crate::timings::Timings;
 
 /// Returns a ConfigYtVideo object with all the necessary data
 /// to start downloading a youtube video
 ///
 /// Takes in the command line arguments list
-pub(crate) fn assemble_data(url: &str, playlist_id: usize) -> BlobResult<config::DownloadConfig> {
+///
+/// With an `existing_config` (the one of the first video of a --url-file) nothing is asked, it is reused for `url`
+pub(crate) fn assemble_data(url: &str, existing_config: Option<&config::DownloadConfig>, cli_config: &parser::CliConfig, timings: &Timings)
+                            -> BlobResult<config::DownloadConfig>
+{
+    if let Some(existing_config) = existing_config {
+        return Ok(existing_config.with_url(url));
+    }
+
     let term = Term::buffered_stderr();
 
-    // Whether the user wants to download video files or audio-only
-    let media_selected = get_media_selection(&term)?;
+    // The video's formats are fetched once, whichever question needs them first
+    let mut cache = FormatCache::new();
+
+    // The answers given the last time something was downloaded from the video's playlist or channel
+    let source = get_source_key(url, cli_config, timings, &mut cache);
+    let previous = source.as_deref().and_then(remembered::recall);
+
+    // A format id picked for another video of the channel isn't always available in this one
+    let offered = previous.as_ref().filter(|answers| match answers.format_id() {
+        Some(id) => get_video_specs(url, cli_config, timings, &mut cache)
+            .is_ok_and(|specs| specs.formats().iter().any(|format| format.format_id == id)),
+        None => true,
+    });
+
+    let (media_selected, audio_extraction, download_mode, chosen_format) = match get_previous_answers(&term, offered, cli_config)? {
+        Some(answers) => (answers.media_selection, answers.audio_extraction, answers.download_mode, answers.format),
+        None => {
+            // Whether the user wants to download video files or audio-only
+            let mut media_selected = get_media_selection(&term, cli_config.media(), previous.as_ref().map(|answers| &answers.media_selection),
+                                                     cli_config.assume_yes())?;
+
+            let audio_extraction = get_audio_extraction_mode(&term, &media_selected)?;
+
+            let mut download_mode = DownloadMode::Full;
+
+            let chosen_format = match audio_extraction {
+                // ffmpeg re-encodes the best audio stream, so there is no quality to choose
+                AudioExtractionMode::FfmpegConvert(_) => VideoQualityAndFormatPreferences::BestQuality,
+                AudioExtractionMode::NativeExtraction => match cli_config.quality() {
+                    Some(quality) => quality.clone(),
+                    None => format::get_format(&term, url, &mut media_selected, &mut download_mode, cli_config, timings, &mut cache)?,
+                },
+            };
+
+            (media_selected, audio_extraction, download_mode, chosen_format)
+        }
+    };
+
+    let answers = remembered::RememberedAnswers::new(media_selected.clone(), audio_extraction.clone(), download_mode, chosen_format.clone());
 
-    let chosen_format = format::get_format(&term, url, &media_selected, playlist_id)?;
+    // Neither matters when only the thumbnail is downloaded
+    let (prefer_free_formats, sections) = if download_mode == DownloadMode::ThumbnailOnly {
+        (false, vec![])
+    } else {
+        (get_free_formats_preference(&term, &chosen_format)?, get_chapter_selection(&term, url, cli_config, timings, &mut cache)?)
+    };
 
-    let output_path = get_output_path(&term)?;
+    let output_path = get_output_path(&term, cli_config.output_path(), cli_config.allow_system_paths())?;
 
-    Ok(config::DownloadConfig::new_video(
+    let mut download_config = config::DownloadConfig::new_video(
         url,
         chosen_format,
         output_path,
         media_selected,
-    ))
+    );
+
+    download_config.audio_extraction = audio_extraction;
+    download_config.download_mode = download_mode;
+    download_config.prefer_free_formats = prefer_free_formats;
+    download_config.sections = sections;
+
+    get_advanced_options(&term, &mut download_config)?;
+
+    // The channel is only known if the questions fetched the video's metadata, it isn't fetched just for this
+    let source = source.or_else(|| cache.get(url).and_then(|specs| specs.channel_id).map(|channel_id| remembered::channel_key(&channel_id)));
+    if let Some(source) = source {
+        remembered::remember(&source, answers);
+    }
+
+    Ok(download_config)
+}
+
+/// The source whose remembered answers are offered: the playlist in the url, otherwise the video's channel
+///
+/// Finding the channel needs the video's metadata, it is only fetched if there are answers for some channel to offer
+/// (the format and chapter questions reuse it). None if it can't be fetched, the questions will report why
+fn get_source_key(url: &str, cli_config: &parser::CliConfig, timings: &Timings, cache: &mut FormatCache) -> Option<String> {
+    if let Some(key) = remembered::source_key(url) {
+        return Some(key);
+    }
+    if (cli_config.media().is_some() && cli_config.quality().is_some()) || !remembered::has_channel_answers() {
+        return None;
+    }
+
+    get_video_specs(url, cli_config, timings, cache).ok()?
+        .channel_id
+        .map(|channel_id| remembered::channel_key(&channel_id))
 }
 
 mod format {
@@ -34,10 +118,17 @@ mod format {
     /// Asks the user to choose a download format and quality between the ones
     /// available for the current video.
     ///
-    /// The options are filtered between video, audio-only and video-only
-    pub(super) fn get_format(term: &Term, url: &str, media_selected: &MediaSelection, playlist_id: usize)
-                             -> BlobResult<VideoQualityAndFormatPreferences>
+    /// The options are filtered between video, audio-only and video-only.
+    /// With --show-formats-table every format is listed before the question.
+    /// `media_selected` changes if the user switches to a media selection the video has formats for,
+    /// `download_mode` if they only want the thumbnail
+    pub(super) fn get_format(term: &Term, url: &str, media_selected: &mut MediaSelection, download_mode: &mut DownloadMode, cli_config: &parser::CliConfig,
+                             timings: &Timings, cache: &mut FormatCache) -> BlobResult<VideoQualityAndFormatPreferences>
     {
+        if cli_config.show_formats_table() {
+            print_formats_table(term, url, cli_config, timings, cache)?;
+        }
+
         // A list of all the format options that can be picked
         let mut format_options: Vec<&str> = vec![];
 
@@ -45,29 +136,21 @@ mod format {
         format_options.push(BEST_QUALITY_PROMPT_SINGLE_VIDEO);
         format_options.push(SMALLEST_QUALITY_PROMPT_SINGLE_VIDEO);
 
-        if which("ffmpeg").is_ok() {
+        if which("ffmpeg").is_err() {
+            eprintln!("{}", FFMPEG_UNAVAILABLE_WARNING);
+        } else if *media_selected != MediaSelection::AudioOnly {
             // If ffmpeg is installed in the system
-            // Some features are only available with ffmpeg
+            // Some features are only available with ffmpeg.
+            // Audio-only downloads are re-encoded in get_audio_extraction_mode, so they skip the conversion option
+            format_options.push(REMUX_FORMAT_PROMPT);
             format_options.push(CONVERT_FORMAT_PROMPT_VIDEO_SINGLE_VIDEO);
-            format_options.push(YT_FORMAT_PROMPT_SINGLE_VIDEO);
-
-            // Set up a prompt for the user
-            let user_selection = Select::with_theme(&ColorfulTheme::default())
-                .with_prompt("Which quality or format do you want to apply to the video?")
-                .default(0)
-                .items(&format_options)
-                .interact_on(term)?;
-            match user_selection {
-                0 => Ok(VideoQualityAndFormatPreferences::BestQuality),
-                1 => Ok(VideoQualityAndFormatPreferences::SmallestSize),
-                2 => convert_to_format(term, media_selected),
-                _ => get_format_from_yt(term, url, media_selected, playlist_id),
-            }
-        } else {
-            println!("{}", FFMPEG_UNAVAILABLE_WARNING);
+        }
 
-            format_options.push(YT_FORMAT_PROMPT_SINGLE_VIDEO);
+        format_options.push(YT_FORMAT_PROMPT_SINGLE_VIDEO);
+        format_options.push(THUMBNAIL_ONLY_PROMPT);
+        format_options.push(SHOW_FORMATS_TABLE_PROMPT);
 
+        loop {
             // Set up a prompt for the user
             let user_selection = Select::with_theme(&ColorfulTheme::default())
                 .with_prompt("Which quality or format do you want to apply to the video?")
@@ -76,48 +159,61 @@ mod format {
                 .interact_on(term)?;
 
             // See individual function documentations for more context
-            match user_selection {
-                0 => Ok(VideoQualityAndFormatPreferences::BestQuality),
-                1 => Ok(VideoQualityAndFormatPreferences::SmallestSize),
-                _ => get_format_from_yt(term, url, media_selected, playlist_id),
-            }
+            return match format_options[user_selection] {
+                BEST_QUALITY_PROMPT_SINGLE_VIDEO => Ok(VideoQualityAndFormatPreferences::BestQuality),
+                SMALLEST_QUALITY_PROMPT_SINGLE_VIDEO => Ok(VideoQualityAndFormatPreferences::SmallestSize),
+                REMUX_FORMAT_PROMPT => remux_to_format(term),
+                CONVERT_FORMAT_PROMPT_VIDEO_SINGLE_VIDEO => convert_to_format(term, media_selected),
+                THUMBNAIL_ONLY_PROMPT => {
+                    *download_mode = DownloadMode::ThumbnailOnly;
+                    Ok(VideoQualityAndFormatPreferences::BestQuality)
+                }
+                SHOW_FORMATS_TABLE_PROMPT => {
+                    // Ask again once the user has seen the formats
+                    print_formats_table(term, url, cli_config, timings, cache)?;
+                    continue;
+                }
+                _ => get_format_from_yt(term, url, media_selected, cli_config, timings, cache),
+            };
         }
     }
 
     /// Presents the user with the formats youtube provides directly for download, without the need for ffmpeg
-    fn get_format_from_yt(term: &Term, url: &str, media_selected: &MediaSelection, playlist_id: usize)
-                          -> BlobResult<VideoQualityAndFormatPreferences>
+    ///
+    /// If no format fits `media_selected` the user can switch to another media selection (see triage_empty_formats)
+    fn get_format_from_yt(term: &Term, url: &str, media_selected: &mut MediaSelection, cli_config: &parser::CliConfig, timings: &Timings,
+                          cache: &mut FormatCache) -> BlobResult<VideoQualityAndFormatPreferences>
     {
-        // Serialize all available formats from the youtube API (through yt-dlp -F)
-        let serialized_formats = {
-            // Get a JSON dump of all the available formats for the current url
-            let ytdl_formats = get_ytdlp_formats(url)?;
-
-            // Serialize the JSON which contains the format information for the current video
-            serialize_formats (
-                std::str::from_utf8(&ytdl_formats.stdout[..])?
-                    // If `url` refers to a playlist the JSON has multiple roots, only parse one
-                    .lines()
-                    // If the requested video isn't the first in a playlist, only parse its information
-                    .nth(playlist_id-1)
-                    // Unwrap is safe because playlist_id is non-0 only when there are multiple lines in the json
-                    .unwrap()
-            )?
-        };
+        // All available formats from the youtube API, even if the url also refers to a playlist
+        let serialized_formats = get_video_specs(url, cli_config, timings, cache)?;
 
         // Ids which the user can pick according to the current media selection
         let mut correct_ids = vec![];
         // Every format which conforms to media_selected will be pushed here
         let mut format_options = vec![];
 
-        // Choose which formats to show to the user
-        for format in serialized_formats.formats() {
-            // If format and media_selected are compatible
-            if check_format(format, media_selected) {
-                // Add to the list of available formats the current one formatted in a nice way
-                format_options.push(format.to_string());
-                // Update the list of ids which match what the user wants
-                correct_ids.push(format.format_id.clone());
+        loop {
+            // Choose which formats to show to the user
+            for format in serialized_formats.formats() {
+                match check_format(format, media_selected) {
+                    FormatCompatibility::Compatible => {
+                        // Add to the list of available formats the current one formatted in a nice way
+                        format_options.push(format.to_string());
+                        // Update the list of ids which match what the user wants
+                        correct_ids.push(format.format_id.clone());
+                    }
+                    compatibility => cli_config.verbosity().debug(&format!("Left out format {}: {:?}", format.format_id, compatibility)),
+                }
+            }
+
+            if !format_options.is_empty() {
+                break;
+            }
+
+            let all_formats: Vec<&VideoFormat> = serialized_formats.formats().iter().collect();
+            match triage_empty_formats(term, &all_formats, media_selected, cli_config.assume_yes())? {
+                Some(new_selection) => *media_selected = new_selection,
+                None => return Ok(VideoQualityAndFormatPreferences::BestQuality),
             }
         }
 
@@ -131,4 +227,21 @@ mod format {
         // Return the format corresponding to what the user selected, the choices are limited so there shouldn't be out-of-bounds problems
         Ok(VideoQualityAndFormatPreferences::UniqueFormat(correct_ids[user_selection].clone()))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn existing_config_is_reused_without_asking() {
+        let existing = config::DownloadConfig::new_video("https://youtu.be/a", VideoQualityAndFormatPreferences::BestQuality,
+                                                         String::from("/downloads"), MediaSelection::AudioOnly);
+        let cli_config = parser::tests::config(&["https://youtu.be/b"]);
+
+        // Tests don't run in a terminal, any question (or fetching the formats with yt-dlp) would fail
+        let reused = assemble_data("https://youtu.be/b", Some(&existing), &cli_config, &Timings::new()).unwrap();
+
+        assert_eq!(reused, existing.with_url("https://youtu.be/b"));
+    }
+}
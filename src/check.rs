@@ -0,0 +1,262 @@
+//! `blob-dl check`: tells what a url refers to without downloading anything, for scripts and CI pipelines which
+//! validate urls before passing them to blob-dl
+//!
+//! Urls are classified like analyze_url_without_asking does for the downloads: a video in a playlist is classified
+//! by the playlist. Channels and mixes, which the downloads don't tell apart, are recognized on top of that
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::analyzer;
+use crate::analyzer::DownloadOption;
+use crate::parser;
+use crate::error::BlobResult;
+use crate::secrets;
+
+/// The exit code when --probe can't reach what the url refers to
+const UNREACHABLE_EXIT_CODE: i32 = 6;
+
+/// What a url refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum UrlCategory {
+    Video,
+    Short,
+    Playlist,
+    /// A playlist youtube generates from a video or an artist (ids starting with RD), it has no fixed end
+    Mix,
+    Channel,
+    /// Not on youtube, or not something blob-dl recognizes
+    Unsupported,
+}
+
+impl UrlCategory {
+    /// 0 for what blob-dl downloads as it is, 2 is left to clap's usage errors
+    fn exit_code(&self) -> i32 {
+        match self {
+            UrlCategory::Video | UrlCategory::Short | UrlCategory::Playlist => 0,
+            UrlCategory::Mix => 3,
+            UrlCategory::Channel => 4,
+            UrlCategory::Unsupported => 5,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            UrlCategory::Video => "video",
+            UrlCategory::Short => "short",
+            UrlCategory::Playlist => "playlist",
+            UrlCategory::Mix => "mix",
+            UrlCategory::Channel => "channel",
+            UrlCategory::Unsupported => "unsupported",
+        }
+    }
+}
+
+/// What `blob-dl check` prints, as JSON with --json
+#[derive(Debug, Serialize)]
+struct CheckReport {
+    /// The url after cleaning and resolving redirects
+    url: String,
+    category: UrlCategory,
+    /// The video, playlist or channel id (or handle), when the url contains it
+    id: Option<String>,
+    /// Only with --probe
+    probe: Option<ProbeReport>,
+}
+
+/// What yt-dlp found at the url
+#[derive(Debug, Serialize)]
+struct ProbeReport {
+    reachable: bool,
+    title: Option<String>,
+    /// How many entries a playlist or channel has, None for videos
+    count: Option<usize>,
+    /// "public", "unlisted", "needs_auth", ... when youtube says
+    availability: Option<String>,
+    /// yt-dlp's error when the url is unreachable
+    error: Option<String>,
+}
+
+/// The fields of yt-dlp's -J output which the probe reports
+#[derive(Debug, Deserialize)]
+struct ProbedMetadata {
+    title: Option<String>,
+    #[serde(default)]
+    availability: Option<String>,
+    #[serde(default)]
+    playlist_count: Option<usize>,
+    #[serde(default)]
+    entries: Option<Vec<serde_json::Value>>,
+}
+
+/// Classifies `url`, probes it with yt-dlp if `probe` is set and prints the result on stdout, as JSON if `json` is set
+///
+/// The process exits with the category's exit code (see UrlCategory::exit_code), or UNREACHABLE_EXIT_CODE
+/// if the probe failed
+pub(crate) fn check(url: &str, probe: bool, json: bool, cli_config: &parser::CliConfig) -> BlobResult<()> {
+    let url = normalize(url, cli_config);
+    let (category, id) = classify(&url);
+
+    let probe = match category {
+        UrlCategory::Unsupported => None,
+        _ if probe => Some(probe_url(&url, category, cli_config)?),
+        _ => None,
+    };
+
+    let exit_code = match &probe {
+        Some(probe) if !probe.reachable => UNREACHABLE_EXIT_CODE,
+        _ => category.exit_code(),
+    };
+
+    // The url is printed, a proxy or a shortener can have put credentials in it
+    let report = CheckReport { url: secrets::scrub(&url), category, id, probe };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for line in report_lines(&report) {
+            println!("{}", line);
+        }
+    }
+
+    if exit_code != 0 {
+        std::process::exit(exit_code);
+    }
+    Ok(())
+}
+
+/// Cleans the url like the downloads do, urls outside of youtube are followed in case they are shortened
+///
+/// A url which can't be resolved is kept as it is, it is then classified as unsupported
+fn normalize(url: &str, cli_config: &parser::CliConfig) -> String {
+    let url = analyzer::clean_url(url);
+    analyzer::resolve_url(&url, cli_config).unwrap_or(url)
+}
+
+/// What `url` refers to, and the id it contains
+fn classify(url: &str) -> (UrlCategory, Option<String>) {
+    let (host, parsed) = match (analyzer::youtube_host(url), Url::parse(url)) {
+        (Some(host), Ok(parsed)) => (host, parsed),
+        _ => return (UrlCategory::Unsupported, None),
+    };
+
+    let query = |name: &str| parsed.query_pairs()
+        .find(|(key, value)| key == name && !value.is_empty())
+        .map(|(_, value)| value.to_string());
+    let segments: Vec<&str> = parsed.path_segments()
+        .map(|segments| segments.filter(|segment| !segment.is_empty()).collect())
+        .unwrap_or_default();
+
+    // The downloads don't know about channels, they are told apart by their path
+    match segments.as_slice() {
+        ["channel", id, ..] | ["c", id, ..] | ["user", id, ..] => return (UrlCategory::Channel, Some(id.to_string())),
+        [handle, ..] if handle.len() > 1 && handle.starts_with('@') => return (UrlCategory::Channel, Some(handle.to_string())),
+        _ => {}
+    }
+
+    match analyzer::analyze_url_without_asking(url) {
+        Ok(DownloadOption::YtShort) => (UrlCategory::Short, segments.get(1).map(|id| id.to_string())),
+        Ok(DownloadOption::YtPlaylist) | Ok(DownloadOption::YtPlaylistFrom(_)) => {
+            let list = query("list");
+            let category = if list.as_deref().is_some_and(|list| list.starts_with("RD")) { UrlCategory::Mix } else { UrlCategory::Playlist };
+            (category, list)
+        }
+        Ok(DownloadOption::YtVideo(_)) => {
+            let id = match segments.as_slice() {
+                // youtu.be/<ID>
+                [id, ..] if host == "youtu.be" => Some(id.to_string()),
+                ["live", id, ..] | ["embed", id, ..] | ["v", id, ..] => Some(id.to_string()),
+                _ => query("v"),
+            };
+            (UrlCategory::Video, id)
+        }
+        Err(_) => (UrlCategory::Unsupported, None),
+    }
+}
+
+/// Asks yt-dlp for the url's metadata, without the formats and without listing the videos of playlists one by one
+///
+/// yt-dlp is given --fetch-timeout to answer, the url is reported as unreachable otherwise
+fn probe_url(url: &str, category: UrlCategory, cli_config: &parser::CliConfig) -> BlobResult<ProbeReport> {
+    let mut command = crate::ytdlp_command();
+    command.arg("--flat-playlist").arg("-J");
+    if matches!(category, UrlCategory::Video | UrlCategory::Short) {
+        command.arg("--no-playlist");
+    }
+    command.arg(url);
+
+    let output = match crate::ytdlp_output(&mut command, cli_config.fetch_timeout())? {
+        Some(output) => output,
+        None => {
+            let error = format!("yt-dlp didn't answer within {} seconds", cli_config.fetch_timeout().as_secs());
+            return Ok(ProbeReport { reachable: false, title: None, count: None, availability: None, error: Some(error) });
+        }
+    };
+
+    if !output.status.success() {
+        // yt-dlp's last error explains why
+        let error = crate::last_ytdlp_error(&output.stderr)
+            .or_else(|| output.status.code().map(|code| format!("yt-dlp exited with code {}", code)));
+
+        return Ok(ProbeReport { reachable: false, title: None, count: None, availability: None, error });
+    }
+
+    let metadata: ProbedMetadata = serde_json::from_slice(&output.stdout)?;
+    let count = metadata.playlist_count.or_else(|| metadata.entries.as_ref().map(Vec::len));
+
+    Ok(ProbeReport { reachable: true, title: metadata.title, count, availability: metadata.availability, error: None })
+}
+
+/// The human readable report, one "field: value" per line
+fn report_lines(report: &CheckReport) -> Vec<String> {
+    let mut lines = vec![format!("url: {}", report.url), format!("category: {}", report.category.name())];
+
+    if let Some(id) = &report.id {
+        lines.push(format!("id: {}", id));
+    }
+
+    if let Some(probe) = &report.probe {
+        if !probe.reachable {
+            lines.push(format!("unreachable: {}", probe.error.as_deref().unwrap_or("unknown error")));
+        }
+        if let Some(title) = &probe.title {
+            lines.push(format!("title: {}", title));
+        }
+        if let Some(count) = probe.count {
+            lines.push(format!("entries: {}", count));
+        }
+        if let Some(availability) = &probe.availability {
+            lines.push(format!("availability: {}", availability));
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_are_classified_like_the_downloads_see_them() {
+        let cases = [
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ", UrlCategory::Video, Some("dQw4w9WgXcQ")),
+            ("https://youtu.be/dQw4w9WgXcQ?si=abc", UrlCategory::Video, Some("dQw4w9WgXcQ")),
+            ("https://www.youtube.com/live/dQw4w9WgXcQ?si=abc", UrlCategory::Video, Some("dQw4w9WgXcQ")),
+            ("https://www.youtube.com/shorts/dQw4w9WgXcQ", UrlCategory::Short, Some("dQw4w9WgXcQ")),
+            ("https://www.youtube.com/playlist?list=PL123", UrlCategory::Playlist, Some("PL123")),
+            // Downloaded with its playlist
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=PL123&index=4", UrlCategory::Playlist, Some("PL123")),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ&list=RDdQw4w9WgXcQ", UrlCategory::Mix, Some("RDdQw4w9WgXcQ")),
+            ("https://www.youtube.com/@handle", UrlCategory::Channel, Some("@handle")),
+            ("https://www.youtube.com/channel/UC123/videos", UrlCategory::Channel, Some("UC123")),
+            // Nothing the downloads could make sense of
+            ("https://www.youtube.com/", UrlCategory::Unsupported, None),
+            ("https://vimeo.com/123", UrlCategory::Unsupported, None),
+            ("not a url", UrlCategory::Unsupported, None),
+        ];
+
+        for (url, category, id) in cases {
+            assert_eq!(classify(url), (category, id.map(String::from)), "{}", url);
+        }
+    }
+}
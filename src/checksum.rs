@@ -0,0 +1,61 @@
+//! SHA-256 checksums of the downloaded files, written next to them as `<file>.sha256`
+//!
+//! The files use `sha256sum`'s format, so a download can be checked later by running `sha256sum -c <file>.sha256`
+//! from the folder it is in
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use colored::Colorize;
+use sha2::{Digest, Sha256};
+
+use crate::error::BlobResult;
+
+/// Computes the SHA-256 of `downloaded_path` and saves it to `<downloaded_path>.sha256`
+///
+/// Only the file's name is written in the checksum file, so that the folder can be moved around
+pub(crate) fn write_checksum_file(downloaded_path: &Path) -> BlobResult<()> {
+    let digest = sha256(downloaded_path)?;
+
+    let file_name = downloaded_path.file_name().unwrap_or(downloaded_path.as_os_str()).to_string_lossy();
+
+    let mut checksum_file = File::create(checksum_path(downloaded_path))?;
+    // Two spaces mean that the file was read in text mode, which is what sha256sum itself writes on unix
+    writeln!(checksum_file, "{}  {}", digest, file_name)?;
+
+    Ok(())
+}
+
+/// Writes a checksum file for every downloaded file
+///
+/// Failures are shown to the user but they don't stop the other files from being processed
+pub(crate) fn write_all(downloaded_files: &[String]) {
+    for file in downloaded_files {
+        if let Err(err) = write_checksum_file(Path::new(file)) {
+            eprintln!("{}: {} ({})", "ERROR".red(), err, file);
+        }
+    }
+}
+
+/// Where the checksum of `downloaded_path` is saved: the same path with .sha256 appended
+fn checksum_path(downloaded_path: &Path) -> PathBuf {
+    let mut path = downloaded_path.as_os_str().to_owned();
+    path.push(".sha256");
+    PathBuf::from(path)
+}
+
+/// Hashes a file in chunks, videos can be too large to be read all at once
+fn sha256(path: &Path) -> BlobResult<String> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
@@ -0,0 +1,453 @@
+//! `--dashboard`: a full-screen view of the running downloads, with a progress bar for every yt-dlp process,
+//! the log and the totals
+//!
+//! The yt-dlp processes report what they do as RunEvents, DashboardState turns them into what is shown.
+//! Drawing it needs the `dashboard` feature, without it (or in a terminal too small for it) the normal output is shown
+// Without the feature the events are still sent, nothing reads them
+#![cfg_attr(not(feature = "dashboard"), allow(dead_code))]
+use std::collections::VecDeque;
+use std::io::IsTerminal;
+use std::sync::{Arc, Mutex};
+use crossbeam_channel::{Receiver, Sender};
+use dialoguer::console::Term;
+
+use crate::pause::PauseControl;
+use crate::ui_prompts::*;
+use crate::units::Bytes;
+
+/// The dashboard doesn't fit in a smaller terminal
+const MIN_COLUMNS: u16 = 60;
+const MIN_ROWS: u16 = 12;
+
+/// How many of yt-dlp's lines the log keeps
+const LOG_LINES: usize = 500;
+
+/// What a yt-dlp process reports while it runs, `worker` is the pid of the process
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum RunEvent {
+    /// A line which isn't a progress update, without secrets
+    Line { worker: u32, line: String },
+    /// yt-dlp moved to the `item`th video of a playlist of `total`
+    Item { worker: u32, item: usize, total: usize },
+    /// yt-dlp started working on a video
+    Video { worker: u32, video_id: String },
+    /// How much of the current file is downloaded, in percent, and how fast in bytes per second
+    Progress { worker: u32, percent: f64, speed: Option<f64> },
+    /// A video was downloaded into `file`
+    Finished { worker: u32, file: String },
+    /// A video failed
+    Failed { worker: u32 },
+    /// The process exited
+    Exited { worker: u32 },
+}
+
+/// Where the yt-dlp processes send their RunEvents, they are dropped while nothing listens
+#[derive(Default)]
+pub(crate) struct EventStream {
+    listener: Mutex<Option<Sender<RunEvent>>>,
+}
+
+impl EventStream {
+    pub(crate) fn send(&self, event: RunEvent) {
+        if let Some(listener) = &*self.listener.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) {
+            // The listener may be gone already
+            let _ = listener.send(event);
+        }
+    }
+
+    pub(crate) fn is_listened(&self) -> bool {
+        self.listener.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).is_some()
+    }
+
+    /// The events sent from now on, until stop_listening is called
+    fn listen(&self) -> Receiver<RunEvent> {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        *self.listener.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(sender);
+        receiver
+    }
+
+    fn stop_listening(&self) {
+        *self.listener.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+    }
+}
+
+/// A yt-dlp process as the dashboard shows it
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct WorkerRow {
+    worker: u32,
+    /// For playlists, the video it's on and how many it has to download: (item, total)
+    item: Option<(usize, usize)>,
+    video_id: Option<String>,
+    /// How much of the current file is downloaded, from 0 to 100
+    percent: f64,
+    /// In bytes per second, None when yt-dlp doesn't know it
+    speed: Option<f64>,
+    exited: bool,
+}
+
+impl WorkerRow {
+    fn new(worker: u32) -> WorkerRow {
+        WorkerRow { worker, item: None, video_id: None, percent: 0.0, speed: None, exited: false }
+    }
+
+    /// What its progress bar says, like `3/10 dQw4w9WgXcQ 42.0% 1.5 MiB/s`
+    pub(crate) fn label(&self) -> String {
+        if self.exited {
+            return String::from(DASHBOARD_WORKER_DONE);
+        }
+
+        let mut label = String::new();
+        if let Some((item, total)) = self.item {
+            label.push_str(&format!("{}/{} ", item, total));
+        }
+        label.push_str(self.video_id.as_deref().unwrap_or(DASHBOARD_WORKER_STARTING));
+        label.push_str(&format!(" {:.1}%", self.percent));
+        if let Some(speed) = self.speed {
+            label.push_str(&format!(" {}/s", Bytes(speed as u64)));
+        }
+        label
+    }
+
+    /// How much of its progress bar is filled, from 0 to 1
+    pub(crate) fn ratio(&self) -> f64 {
+        if self.exited { 1.0 } else { self.percent / 100.0 }
+    }
+}
+
+/// Everything the dashboard shows, built from the RunEvents
+#[derive(Debug, Default)]
+pub(crate) struct DashboardState {
+    /// In the order the processes started
+    workers: Vec<WorkerRow>,
+    /// The last LOG_LINES lines, oldest first
+    log: VecDeque<String>,
+    finished: usize,
+    failed: usize,
+}
+
+impl DashboardState {
+    pub(crate) fn apply(&mut self, event: RunEvent) {
+        match event {
+            RunEvent::Line { line, .. } => {
+                if self.log.len() == LOG_LINES {
+                    self.log.pop_front();
+                }
+                self.log.push_back(line);
+            }
+            RunEvent::Item { worker, item, total } => {
+                let row = self.worker(worker);
+                row.item = Some((item, total));
+                row.percent = 0.0;
+            }
+            RunEvent::Video { worker, video_id } => {
+                let row = self.worker(worker);
+                row.video_id = Some(video_id);
+                row.percent = 0.0;
+            }
+            RunEvent::Progress { worker, percent, speed } => {
+                let row = self.worker(worker);
+                row.percent = percent.clamp(0.0, 100.0);
+                row.speed = speed;
+            }
+            RunEvent::Finished { worker, .. } => {
+                self.finished += 1;
+                let row = self.worker(worker);
+                row.percent = 100.0;
+                row.speed = None;
+            }
+            RunEvent::Failed { .. } => self.failed += 1,
+            RunEvent::Exited { worker } => {
+                let row = self.worker(worker);
+                row.exited = true;
+                row.speed = None;
+            }
+        }
+    }
+
+    /// The row of `worker`, which is added the first time it's seen
+    fn worker(&mut self, worker: u32) -> &mut WorkerRow {
+        let position = match self.workers.iter().position(|row| row.worker == worker) {
+            Some(position) => position,
+            None => {
+                self.workers.push(WorkerRow::new(worker));
+                self.workers.len() - 1
+            }
+        };
+        &mut self.workers[position]
+    }
+
+    pub(crate) fn workers(&self) -> &[WorkerRow] {
+        &self.workers
+    }
+
+    /// The last `count` lines of the log, oldest first
+    pub(crate) fn last_lines(&self, count: usize) -> impl Iterator<Item = &String> {
+        self.log.iter().skip(self.log.len().saturating_sub(count))
+    }
+
+    /// The totals line, like `4 downloaded · 1 failed · 3.2 MiB/s`
+    pub(crate) fn totals(&self) -> String {
+        let speed: f64 = self.workers.iter().filter_map(|row| row.speed).sum();
+        format!("{} downloaded · {} failed · {}/s", self.finished, self.failed, Bytes(speed as u64))
+    }
+}
+
+/// Why the dashboard can't be shown, None if it can
+pub(crate) fn unavailable_reason() -> Option<&'static str> {
+    if !cfg!(feature = "dashboard") {
+        return Some(DASHBOARD_NOT_BUILT);
+    }
+    if !std::io::stdout().is_terminal() {
+        return Some(DASHBOARD_NO_TERMINAL);
+    }
+    let (rows, columns) = Term::stdout().size();
+    if columns < MIN_COLUMNS || rows < MIN_ROWS {
+        return Some(DASHBOARD_TOO_SMALL);
+    }
+    None
+}
+
+/// The dashboard while it's on screen, dropping it gives the terminal back
+pub(crate) struct Dashboard {
+    events: Arc<EventStream>,
+    stop: Arc<std::sync::atomic::AtomicBool>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Dashboard {
+    /// Shows what is sent to `events` until the dashboard is dropped, check unavailable_reason first
+    ///
+    /// p pauses after the current video like without the dashboard, q and CTRL+C call `quit`
+    #[cfg(feature = "dashboard")]
+    pub(crate) fn start(events: &Arc<EventStream>, pause: &Arc<PauseControl>, quit: impl Fn() + Send + 'static) -> Dashboard {
+        use std::sync::atomic::AtomicBool;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let receiver = events.listen();
+        // The dashboard shows the pause instead
+        pause.set_quiet(true);
+
+        let thread = {
+            let stop = Arc::clone(&stop);
+            let pause = Arc::clone(pause);
+            std::thread::spawn(move || {
+                if let Err(err) = view::show(&receiver, &pause, quit, &stop) {
+                    eprintln!("{}: {}", colored::Colorize::red("ERROR"), err);
+                }
+                pause.set_quiet(false);
+            })
+        };
+
+        Dashboard { events: Arc::clone(events), stop, thread: Some(thread) }
+    }
+
+    #[cfg(not(feature = "dashboard"))]
+    pub(crate) fn start(events: &Arc<EventStream>, _pause: &Arc<PauseControl>, _quit: impl Fn() + Send + 'static) -> Dashboard {
+        Dashboard { events: Arc::clone(events), stop: Arc::default(), thread: None }
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        self.events.stop_listening();
+        self.stop.store(true, std::sync::atomic::Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(feature = "dashboard")]
+mod view {
+    use std::io;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+    use crossbeam_channel::Receiver;
+    use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+    use ratatui::layout::{Constraint, Layout};
+    use ratatui::style::{Color, Style, Stylize};
+    use ratatui::text::Line;
+    use ratatui::widgets::{Block, Gauge, Paragraph};
+    use ratatui::Frame;
+
+    use super::{DashboardState, RunEvent};
+    use crate::pause::{PauseControl, PauseState};
+    use crate::ui_prompts::*;
+
+    /// How often the screen is redrawn and the keyboard checked
+    const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+    /// Takes over the terminal until `stop` is set, then gives it back
+    pub(super) fn show(receiver: &Receiver<RunEvent>, pause: &PauseControl, quit: impl Fn(), stop: &AtomicBool) -> io::Result<()> {
+        let mut terminal = ratatui::try_init()?;
+        let mut state = DashboardState::default();
+
+        let result: io::Result<()> = (|| {
+            while !stop.load(Ordering::SeqCst) {
+                for event in receiver.try_iter() {
+                    state.apply(event);
+                }
+                terminal.draw(|frame| draw(frame, &state, pause.state()))?;
+
+                if !event::poll(REFRESH_INTERVAL)? {
+                    continue;
+                }
+                // The terminal is in raw mode, so CTRL+C arrives as a key
+                if let Event::Key(key) = event::read()? {
+                    match key.code {
+                        _ if key.kind != KeyEventKind::Press => {}
+                        KeyCode::Char('p' | 'P') => pause.press_key(),
+                        KeyCode::Char('q' | 'Q') => quit(),
+                        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => quit(),
+                        _ => {}
+                    }
+                }
+            }
+            Ok(())
+        })();
+
+        ratatui::restore();
+        result
+    }
+
+    /// The totals on top, a progress bar for every yt-dlp process, the log and the keys at the bottom
+    pub(super) fn draw(frame: &mut Frame, state: &DashboardState, pause: PauseState) {
+        let workers = state.workers();
+        let [header, bars, log, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(workers.len().max(1) as u16),
+            Constraint::Min(3),
+            Constraint::Length(1),
+        ]).areas(frame.area());
+
+        let pause = match pause {
+            PauseState::Running => "",
+            PauseState::Requested => DASHBOARD_PAUSE_REQUESTED,
+            PauseState::Paused => DASHBOARD_PAUSED,
+        };
+        frame.render_widget(Line::from(vec![" blob-dl ".bold().cyan(), state.totals().into(), "  ".into(), pause.bold().yellow()]), header);
+
+        let rows = Layout::vertical(vec![Constraint::Length(1); workers.len()]).split(bars);
+        for (row, area) in workers.iter().zip(rows.iter()) {
+            let gauge = Gauge::default().gauge_style(Style::new().fg(Color::Cyan)).ratio(row.ratio()).label(row.label());
+            frame.render_widget(gauge, *area);
+        }
+
+        // Borders take a line above and below
+        let lines: Vec<Line> = state.last_lines(usize::from(log.height.saturating_sub(2))).map(|line| Line::raw(line.as_str())).collect();
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" yt-dlp ")), log);
+
+        frame.render_widget(Line::from(DASHBOARD_KEYS).dark_gray(), footer);
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use ratatui::backend::TestBackend;
+        use ratatui::Terminal;
+
+        #[test]
+        fn the_dashboard_shows_the_totals_the_processes_and_the_log() {
+            let mut state = DashboardState::default();
+            state.apply(RunEvent::Item { worker: 7, item: 2, total: 10 });
+            state.apply(RunEvent::Video { worker: 7, video_id: String::from("dQw4w9WgXcQ") });
+            state.apply(RunEvent::Progress { worker: 7, percent: 50.0, speed: None });
+            state.apply(RunEvent::Line { worker: 7, line: String::from("[download] Destination: Never gonna.webm") });
+
+            let mut terminal = Terminal::new(TestBackend::new(80, 14)).unwrap();
+            terminal.draw(|frame| draw(frame, &state, PauseState::Requested)).unwrap();
+
+            let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+            assert!(screen.contains("0 downloaded · 0 failed"));
+            assert!(screen.contains(DASHBOARD_PAUSE_REQUESTED));
+            assert!(screen.contains("2/10 dQw4w9WgXcQ 50.0%"));
+            assert!(screen.contains("[download] Destination: Never gonna.webm"));
+            assert!(screen.contains(DASHBOARD_KEYS));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(events: Vec<RunEvent>) -> DashboardState {
+        let mut state = DashboardState::default();
+        for event in events {
+            state.apply(event);
+        }
+        state
+    }
+
+    #[test]
+    fn every_process_gets_a_row_in_the_order_it_started() {
+        let state = apply(vec![
+            RunEvent::Item { worker: 20, item: 6, total: 10 },
+            RunEvent::Item { worker: 10, item: 1, total: 5 },
+            RunEvent::Video { worker: 20, video_id: String::from("bbbbbbbbbbb") },
+            RunEvent::Progress { worker: 20, percent: 42.04, speed: Some(1.5 * 1024.0 * 1024.0) },
+        ]);
+
+        let labels: Vec<String> = state.workers().iter().map(WorkerRow::label).collect();
+        assert_eq!(labels, [String::from("6/10 bbbbbbbbbbb 42.0% 1.5 MiB/s"), format!("1/5 {} 0.0%", DASHBOARD_WORKER_STARTING)]);
+        assert_eq!(state.workers()[0].ratio(), 0.4204);
+    }
+
+    #[test]
+    fn a_new_video_starts_its_bar_over() {
+        let state = apply(vec![
+            RunEvent::Video { worker: 1, video_id: String::from("aaaaaaaaaaa") },
+            RunEvent::Progress { worker: 1, percent: 250.0, speed: None },
+        ]);
+        // yt-dlp's percentages are kept within the bar
+        assert_eq!(state.workers()[0].ratio(), 1.0);
+
+        let state = apply(vec![
+            RunEvent::Progress { worker: 1, percent: 80.0, speed: None },
+            RunEvent::Video { worker: 1, video_id: String::from("bbbbbbbbbbb") },
+        ]);
+        assert_eq!(state.workers()[0].ratio(), 0.0);
+    }
+
+    #[test]
+    fn totals_count_the_videos_and_add_up_the_speeds() {
+        let state = apply(vec![
+            RunEvent::Progress { worker: 1, percent: 10.0, speed: Some(1024.0) },
+            RunEvent::Progress { worker: 2, percent: 10.0, speed: Some(2048.0) },
+            RunEvent::Finished { worker: 1, file: String::from("a.mp4") },
+            RunEvent::Finished { worker: 2, file: String::from("b.mp4") },
+            RunEvent::Failed { worker: 2 },
+            RunEvent::Progress { worker: 2, percent: 10.0, speed: Some(4096.0) },
+        ]);
+        // The speed of a finished video doesn't count anymore
+        assert_eq!(state.totals(), "2 downloaded · 1 failed · 4.0 KiB/s");
+
+        let state = apply(vec![RunEvent::Progress { worker: 1, percent: 10.0, speed: Some(1024.0) }, RunEvent::Exited { worker: 1 }]);
+        assert_eq!(state.totals(), "0 downloaded · 0 failed · 0 B/s");
+        assert_eq!(state.workers()[0].label(), DASHBOARD_WORKER_DONE);
+        assert_eq!(state.workers()[0].ratio(), 1.0);
+    }
+
+    #[test]
+    fn the_log_keeps_the_last_lines() {
+        let state = apply((0..LOG_LINES + 2).map(|i| RunEvent::Line { worker: 1, line: i.to_string() }).collect());
+
+        assert_eq!(state.last_lines(3).collect::<Vec<_>>(), ["499", "500", "501"]);
+        assert_eq!(state.last_lines(LOG_LINES * 2).count(), LOG_LINES);
+        assert_eq!(state.last_lines(LOG_LINES).next().unwrap(), "2");
+    }
+
+    #[test]
+    fn events_are_dropped_while_nothing_listens() {
+        let events = EventStream::default();
+        events.send(RunEvent::Exited { worker: 1 });
+        assert!(!events.is_listened());
+
+        let receiver = events.listen();
+        events.send(RunEvent::Failed { worker: 1 });
+        events.stop_listening();
+        events.send(RunEvent::Exited { worker: 1 });
+
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [RunEvent::Failed { worker: 1 }]);
+    }
+}
@@ -0,0 +1,108 @@
+//! `blob-dl urls`: prints the direct stream urls of videos instead of downloading them, so that they can be piped
+//! to other downloaders (wget, aria2c, ...)
+use std::process::Stdio;
+
+use crate::analyzer;
+use crate::parser;
+use crate::assembling::youtube::VideoQualityAndFormatPreferences;
+use crate::error::{BlobdlError, BlobResult};
+
+/// What yt-dlp prints for every video, urls never contain tabs. Videos made of separate video and audio streams
+/// have a url on each line
+const PRINT_TEMPLATE: &str = "%(title)s\t%(urls)s";
+
+/// A stream of a video, as yt-dlp resolved it
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DirectUrl {
+    pub(crate) title: String,
+    pub(crate) url: String,
+}
+
+/// Prints the direct urls of every video `urls` refer to on stdout, one per line as `<title>\t<url>`.
+/// With `quiet` only the urls are printed
+///
+/// `format` is "best", "smallest" or one of yt-dlp's format ids
+pub(crate) fn print_direct_urls(urls: &[String], format: &str, quiet: bool, cli_config: &parser::CliConfig) -> BlobResult<()> {
+    let format = format_preference(format);
+    let mut resolver = analyzer::UrlResolver::default();
+
+    for url in urls {
+        let url = resolver.resolve(&analyzer::clean_url(url), cli_config)?;
+
+        for direct_url in get_direct_urls(&url, &format)? {
+            if quiet {
+                println!("{}", direct_url.url);
+            } else {
+                println!("{}\t{}", direct_url.title, direct_url.url);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The preference `format` stands for, anything other than "best" and "smallest" is a format id
+fn format_preference(format: &str) -> VideoQualityAndFormatPreferences {
+    match format {
+        "best" => VideoQualityAndFormatPreferences::BestQuality,
+        "smallest" => VideoQualityAndFormatPreferences::SmallestSize,
+        id => VideoQualityAndFormatPreferences::UniqueFormat(id.to_string()),
+    }
+}
+
+/// Asks yt-dlp for the direct urls of the video, or of every video of the playlist, `url` refers to
+///
+/// The urls expire after a few hours, youtube ties them to the IP address which asked for them
+pub(crate) fn get_direct_urls(url: &str, format: &VideoQualityAndFormatPreferences) -> BlobResult<Vec<DirectUrl>> {
+    let mut command = crate::ytdlp_command();
+
+    // An unavailable video mustn't stop the rest of the playlist
+    command.arg("-i");
+
+    // Nobody is asked anything, the output is meant for a pipe
+    match analyzer::analyze_url_without_asking(url)? {
+        analyzer::DownloadOption::YtPlaylist | analyzer::DownloadOption::YtPlaylistFrom(_) => command.arg("--yes-playlist"),
+        analyzer::DownloadOption::YtVideo(_) | analyzer::DownloadOption::YtShort => command.arg("--no-playlist"),
+    };
+
+    match format {
+        VideoQualityAndFormatPreferences::SmallestSize => {
+            command.arg("-S").arg("+size,+br");
+        }
+        VideoQualityAndFormatPreferences::UniqueFormat(id) => {
+            command.arg("-f").arg(id);
+        }
+        // Conversions happen after downloading, the streams are the best ones
+        _ => {}
+    }
+
+    command.arg("--print").arg(PRINT_TEMPLATE).arg(url);
+
+    // yt-dlp's errors are still shown, on stderr so that they don't end up in the pipe
+    let output = command.stdout(Stdio::piped()).stderr(Stdio::inherit()).output()?;
+    if !output.status.success() && output.stdout.is_empty() {
+        return Err(BlobdlError::YtdlpFailed(output.status.code()));
+    }
+
+    Ok(parse_print_output(std::str::from_utf8(&output.stdout)?))
+}
+
+/// Parses what yt-dlp printed with PRINT_TEMPLATE: a line without a title is another stream of the video above it
+fn parse_print_output(output: &str) -> Vec<DirectUrl> {
+    let mut direct_urls: Vec<DirectUrl> = vec![];
+
+    for line in output.lines().filter(|line| !line.trim().is_empty()) {
+        // Titles can contain tabs, urls can't
+        let (title, url) = match line.rsplit_once('\t') {
+            Some((title, url)) => (title.to_string(), url),
+            None => match direct_urls.last() {
+                Some(previous) => (previous.title.clone(), line),
+                None => continue,
+            },
+        };
+
+        direct_urls.push(DirectUrl { title, url: url.trim().to_string() });
+    }
+
+    direct_urls
+}
@@ -1,24 +1,141 @@
 use crate::analyzer;
 use crate::parser;
 use crate::assembling;
-use crate::error::BlobResult;
+use crate::error::{BlobdlError, BlobResult};
+use crate::ui_prompts::{COMMENTS_SLOW_WARNING, GENTLE_MODE_NOTE, YTDLP_CACHE_CLEARED};
 use crate::run;
+use crate::search;
+use crate::direct_urls;
+use crate::check;
+use crate::stats;
+use crate::secrets;
+use crate::simulate;
+use crate::update;
+use crate::lockfile;
+use crate::timings::{Phase, Timings};
 
-/// Calls the builder function according to what the url refers to (video/playlist), then it runs the ytdl-command and handles errors
+use colored::Colorize;
+use std::path::Path;
+use std::time::Instant;
+
+/// Calls the builder function according to what each url refers to (video/playlist), then it runs the ytdl-commands and handles errors
+///
+/// Every url gets its own configuration, all the questions are asked before anything is downloaded
+///
+/// Only data goes to stdout (the paths of --print-paths, the command of --show-command, the urls of `blob-dl urls`,
+/// the report of `blob-dl check`, the totals of `blob-dl stats`, ...),
+/// prompts, progress, warnings and errors go to stderr so that stdout can be piped
 pub fn dispatch(config: &parser::CliConfig) -> BlobResult<()> {
-    // Parse what the url refers to
-    let download_option = analyzer::analyze_url(config.url());
+    if let Some(subcommand) = config.subcommand() {
+        return match subcommand {
+            parser::BlobSubcommand::SelfCheck => update::self_check(),
+            parser::BlobSubcommand::ClearYtdlpCache => clear_ytdlp_cache(),
+            parser::BlobSubcommand::Search(query) => match search::search(query, config)? {
+                Some(url) => download(&[url], config),
+                // There is nothing to download
+                None => Ok(()),
+            },
+            parser::BlobSubcommand::Urls { urls, format, quiet } => direct_urls::print_direct_urls(urls, format, *quiet, config),
+            parser::BlobSubcommand::Check { url, probe, json } => check::check(url, *probe, *json, config),
+            parser::BlobSubcommand::Stats => stats::print_stats(),
+        };
+    }
+
+    download(config.urls(), config)
+}
+
+/// Configures the download of every url, then downloads them one after the other
+fn download(urls: &[String], config: &parser::CliConfig) -> BlobResult<()> {
+    // Checks for updates at most once a week, the result is shown at the end
+    let update_notice = update::spawn_background_check();
+
+    if config.gentle() {
+        eprintln!("{}", GENTLE_MODE_NOTE);
+    }
 
-    // Generate a command according to the user's preferences
-    let mut command_and_config = assembling::generate_command(config.url(), &download_option?)? ;
+    if config.write_comments() {
+        eprintln!("{}: {}", "WARNING".yellow(), COMMENTS_SLOW_WARNING);
+    }
 
-    if config.show_command() {
-        println!("Command generated by blob-dl: {:?}", command_and_config.0);
+    // Released when download returns, whichever way it does
+    let mut locks = lockfile::OutputLocks::new(config.force());
+    // Locked before the wizard so that its questions aren't asked for nothing,
+    // a folder which doesn't exist yet is only created once the wizard checked it's safe
+    if let Some(output_path) = config.output_path().map(Path::new).filter(|output_path| output_path.is_dir()) {
+        locks.lock(output_path)?;
     }
 
-    // Run the command
-    run::run_and_observe(&mut command_and_config.0, &command_and_config.1, config.verbosity());
+    // Every command generated by blob-dl along with the preferences it was built from
+    let mut downloads = Vec::new();
+
+    let timings = Timings::new();
+
+    // Shortened urls are resolved before they are analyzed
+    let mut resolver = analyzer::UrlResolver::default();
+
+    // The videos of a --url-file (often the --error-file of a previous run) are downloaded like the first one
+    let mut url_file_answers = None;
+
+    for (i, url) in urls.iter().enumerate() {
+        // The flags along with the answers of the url's preset
+        let url_config = config.for_url(i);
+        let reused_answers = url_file_answers.as_ref().filter(|_| config.from_url_file(i));
+        if urls.len() > 1 {
+            eprintln!("\nConfiguring the download for {}", url.bold());
+        }
+
+        // Pasted urls often come with quotes and escapes
+        let url = analyzer::clean_url(url);
+        analyzer::check_url_truncation(&url)?;
+        let url = resolver.resolve(&url, &url_config)?;
+
+        // Parse what the url refers to
+        let download_option = analyzer::analyze_url(&url);
+
+        // Generate a command according to the user's preferences
+        let fetching_before = timings.total(Phase::FormatFetching);
+        let started = Instant::now();
+        let command_and_config = assembling::generate_command(&url, &download_option?, reused_answers, &url_config, &timings)?;
+        // Time spent waiting for yt-dlp isn't time spent answering questions
+        let fetching = timings.total(Phase::FormatFetching).saturating_sub(fetching_before);
+        timings.record(Phase::Wizard, started.elapsed().saturating_sub(fetching));
+
+        if config.show_command() {
+            println!("Command generated by blob-dl: {}", secrets::scrub(&format!("{:?}", command_and_config.0)));
+        }
+
+        let is_video = matches!(command_and_config.1.download_target, analyzer::DownloadOption::YtVideo(_));
+        if is_video && config.from_url_file(i) && url_file_answers.is_none() {
+            url_file_answers = Some(command_and_config.1.clone());
+        }
+
+        downloads.push(command_and_config);
+    }
+
+    if config.simulate() {
+        // Nothing is downloaded, yt-dlp only says what it would do
+        return simulate::simulate(downloads);
+    }
+
+    // The folders chosen in the wizard
+    locks.lock_all(&downloads)?;
+
+    // Run the commands one after the other
+    run::run_and_observe(downloads, config, &timings)?;
+
+    update::print_notice(update_notice);
 
     Ok(())
 }
 
+/// `blob-dl clear-ytdlp-cache`: deletes everything yt-dlp stored in its cache directory
+fn clear_ytdlp_cache() -> BlobResult<()> {
+    let status = crate::ytdlp_command().arg("--rm-cache-dir").status()?;
+
+    if status.success() {
+        eprintln!("{}", YTDLP_CACHE_CLEARED);
+        Ok(())
+    } else {
+        Err(BlobdlError::YtdlpFailed(status.code()))
+    }
+}
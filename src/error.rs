@@ -1,6 +1,8 @@
-use std::io::Write;
 // Import error messages
 use crate::blobdl_error_message::*;
+use crate::youtube_error_message::{POSTPROCESSING, FFMPEG_EXITED, YTDLP_STALLED, FILE_NAME_TOO_LONG, WINDOWS_FILE_NAME_TOO_LONG,
+                                    PERMISSION_DENIED, READ_ONLY_FILESYSTEM, NO_SPACE_LEFT, MUSIC_PREMIUM_ONLY, MUSIC_PREMIUM_ONLY_PLAYABILITY,
+                                    WEB_CLIENT_FORMATS_SKIPPED, NSIG_EXTRACTION_FAILED};
 use crate::ui_prompts::*;
 
 use colored::Colorize;
@@ -9,7 +11,7 @@ pub type BlobResult<T> = Result<T, BlobdlError>;
 
 /// ### The all-encompassing error type used in this project
 /// ## Implements From
-/// For the Errors std::io::Error and ParseIntError
+/// For the Errors std::io::Error, Utf8Error, serde_json::Error, libloading::Error and ureq::Error
 /// ## Contains
 /// Errors for everything that can go wrong in the project
 ///
@@ -29,53 +31,108 @@ pub enum BlobdlError {
     SerdeError(serde_json::Error),
     IoError(std::io::Error),
     QueryCouldNotBeParsed,
+    PluginLoadingError(libloading::Error),
+    PluginFailed(i32),
+    SystemPathRefused,
+    HttpError(Box<ureq::Error>),
+    YtdlpFailed(Option<i32>),
+    InvalidPlaylistRange,
+    UrlTruncatedByShell,
+    FormatFetchTimeout,
+    ListFetchTimeout,
+    DownloadCancelled,
+    MusicNeedsFfmpeg,
+    Id3TaggingFailed(Option<i32>),
+    OutputDirectoryLocked(u32),
+    UnknownPreset(String),
+    DuplicatePreset(String),
+    InvalidPresetFile(serde_json::Error),
+    InvalidConfigFile(serde_json::Error),
+    InvalidBatchFile(usize),
 }
 
 impl BlobdlError {
     // Output an error message according to the error at hand
     pub fn report(&self) {
         eprintln!("\n{}\n", USAGE_MSG);
-        print!("{}: ", "ERROR".red());
+        eprint!("{}: ", "ERROR".red());
+        eprintln!("{}", self);
+        eprintln!("{}", SEE_HELP_PAGE);
+    }
+}
 
-        let _ = std::io::stdout().flush();
 
+// Implementing conversions and boilerplate
+impl std::fmt::Display for BlobdlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            BlobdlError::QueryNotFound => eprintln!("{}", BROKEN_URL_ERR),
+            BlobdlError::QueryNotFound => write!(f, "{}", BROKEN_URL_ERR),
 
-            BlobdlError::UnknownUrl=> eprintln!("{}", BROKEN_URL_ERR),
+            BlobdlError::UnknownUrl=> write!(f, "{}", BROKEN_URL_ERR),
 
-            BlobdlError::UnsupportedWebsite=> eprintln!("{}", UNSUPPORTED_WEBSITE_ERR),
+            BlobdlError::UnsupportedWebsite=> write!(f, "{}", UNSUPPORTED_WEBSITE_ERR),
 
-            BlobdlError::DomainNotFound=> eprintln!("{}", BROKEN_URL_ERR),
+            BlobdlError::DomainNotFound=> write!(f, "{}", BROKEN_URL_ERR),
 
             // The link appears to be completely broken
-            BlobdlError::UrlParsingError=> eprintln!("{}", BROKEN_URL_ERR),
+            BlobdlError::UrlParsingError=> write!(f, "{}", BROKEN_URL_ERR),
 
-            BlobdlError::UnknownIssue=> eprintln!("{}", UNKNOWN_ISSUE_ERR),
+            BlobdlError::UnknownIssue=> write!(f, "{}", UNKNOWN_ISSUE_ERR),
 
-            BlobdlError::MissingArgument=> eprintln!("{}", MISSING_ARGUMENT_ERR),
+            BlobdlError::MissingArgument=> write!(f, "{}", MISSING_ARGUMENT_ERR),
 
-            BlobdlError::JsonSerializationError=> eprintln!("{}", JSON_SERIALIZATION_ERR),
+            BlobdlError::JsonSerializationError=> write!(f, "{}", JSON_SERIALIZATION_ERR),
 
-            BlobdlError::Utf8Error=> eprintln!("{}", UTF8_ERR),
+            BlobdlError::Utf8Error=> write!(f, "{}", UTF8_ERR),
 
-            BlobdlError::SerdeError(err)=> eprintln!("{} {}", SERDE_ERR, err),
+            BlobdlError::SerdeError(err)=> write!(f, "{} {}", SERDE_ERR, err),
 
-            BlobdlError::IoError(err)=> eprintln!("{} {}", IO_ERR, err),
+            BlobdlError::IoError(err)=> write!(f, "{} {}", IO_ERR, err),
 
-            BlobdlError::QueryCouldNotBeParsed => eprintln!("{}", URL_QUERY_COULD_NOT_BE_PARSED),
+            BlobdlError::QueryCouldNotBeParsed => write!(f, "{}", URL_QUERY_COULD_NOT_BE_PARSED),
 
-            BlobdlError::UrlIndexParsingError => eprintln!("{}", URL_INDEX_PARSING_ERR),
-        }
-        eprintln!("{}", SEE_HELP_PAGE);
-    }
-}
+            BlobdlError::UrlIndexParsingError => write!(f, "{}", URL_INDEX_PARSING_ERR),
 
+            BlobdlError::PluginLoadingError(err) => write!(f, "{} {}", PLUGIN_LOADING_ERR, err),
 
-// Implementing conversions and boilerplate
-impl std::fmt::Display for BlobdlError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Hi :) I am the BlobdlError default message, I shouldn't show up, if you see me please report me to the github page")
+            BlobdlError::PluginFailed(code) => write!(f, "{} {}", PLUGIN_FAILED_ERR, code),
+
+            BlobdlError::SystemPathRefused => write!(f, "{}", SYSTEM_PATH_REFUSED_ERR),
+
+            BlobdlError::HttpError(err) => write!(f, "{} {}", HTTP_ERR, err),
+
+            BlobdlError::YtdlpFailed(Some(code)) => write!(f, "{} {}", YTDLP_FAILED_ERR, code),
+
+            BlobdlError::InvalidPlaylistRange => write!(f, "{}", INVALID_PLAYLIST_RANGE_ERR),
+
+            BlobdlError::UrlTruncatedByShell => write!(f, "{}", URL_TRUNCATED_ERR),
+
+            BlobdlError::DownloadCancelled => write!(f, "{}", DOWNLOAD_CANCELLED_ERR),
+
+            BlobdlError::MusicNeedsFfmpeg => write!(f, "{}", MUSIC_NEEDS_FFMPEG_ERR),
+
+            BlobdlError::FormatFetchTimeout => write!(f, "{}", FORMAT_FETCH_TIMEOUT_ERR),
+
+            BlobdlError::ListFetchTimeout => write!(f, "{}", LIST_FETCH_TIMEOUT_ERR),
+
+            BlobdlError::YtdlpFailed(None) => write!(f, "{} unknown", YTDLP_FAILED_ERR),
+
+            BlobdlError::Id3TaggingFailed(Some(code)) => write!(f, "{} {}", ID3_TAGGING_FAILED_ERR, code),
+
+            BlobdlError::Id3TaggingFailed(None) => write!(f, "{} unknown", ID3_TAGGING_FAILED_ERR),
+
+            BlobdlError::OutputDirectoryLocked(pid) => write!(f, "{} {}", OUTPUT_DIRECTORY_LOCKED_ERR, pid),
+
+            BlobdlError::UnknownPreset(name) => write!(f, "{} {}", UNKNOWN_PRESET_ERR, name),
+
+            BlobdlError::DuplicatePreset(url) => write!(f, "{} {}", DUPLICATE_PRESET_ERR, url),
+
+            BlobdlError::InvalidPresetFile(err) => write!(f, "{} {}", INVALID_PRESET_FILE_ERR, err),
+
+            BlobdlError::InvalidConfigFile(err) => write!(f, "{} {}", INVALID_CONFIG_FILE_ERR, err),
+
+            BlobdlError::InvalidBatchFile(line) => write!(f, "{} {}", INVALID_BATCH_FILE_ERR, line),
+        }
     }
 }
 impl std::error::Error for BlobdlError {}
@@ -86,35 +143,90 @@ impl From<std::io::Error> for BlobdlError {
     }
 }
 
+impl From<libloading::Error> for BlobdlError {
+    fn from(err: libloading::Error) -> Self {
+        BlobdlError::PluginLoadingError(err)
+    }
+}
+
+impl From<serde_json::Error> for BlobdlError {
+    fn from(err: serde_json::Error) -> Self {
+        BlobdlError::SerdeError(err)
+    }
+}
+
+impl From<ureq::Error> for BlobdlError {
+    fn from(err: ureq::Error) -> Self {
+        BlobdlError::HttpError(Box::new(err))
+    }
+}
+
 impl From<std::str::Utf8Error> for BlobdlError {
     fn from(_: std::str::Utf8Error) -> Self {
         BlobdlError::Utf8Error
     }
 }
 
+/// The errors the OS gives when yt-dlp can't write to the output folder
+const FILESYSTEM_ERRORS: [&str; 3] = [PERMISSION_DENIED, READ_ONLY_FILESYSTEM, NO_SPACE_LEFT];
+
+/// What is shown in place of the id of a video yt-dlp's error doesn't mention
+pub(crate) const UNKNOWN_VIDEO_ID: &str = "unavailable";
+
 // Used in run.rs
 /// Stores the information found in yt-dlp's error-lines output
 #[derive(Debug)]
 pub(crate) struct YtdlpError {
-    video_id: String,
+    /// None if the error doesn't say which video it refers to
+    video_id: Option<String>,
     error_msg: String,
+    kind: YtdlpErrorKind,
+    /// Whether yt-dlp warned that youtube is blocking its player client before the error
+    client_blocked: bool,
+}
+
+/// What yt-dlp was doing when the error happened
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum YtdlpErrorKind {
+    /// The video couldn't be downloaded
+    Download,
+    /// The video was downloaded but a post-processor (merging, audio extraction, ...) failed
+    PostProcessing {
+        /// The post-processor's name, if yt-dlp mentioned it
+        stage: Option<String>,
+        /// The files yt-dlp downloaded before the post-processor ran (video and audio streams)
+        streams: Vec<String>,
+    },
+    /// yt-dlp stopped writing anything and was killed
+    Timeout,
 }
 
 impl YtdlpError {
-    pub fn video_id(&self) -> &String {
-        &self.video_id
+    pub fn video_id(&self) -> Option<&str> {
+        self.video_id.as_deref()
     }
 
-    pub fn error_msg(&self) -> &String {
+    pub fn error_msg(&self) -> &str {
         &self.error_msg
     }
+
+    pub fn kind(&self) -> &YtdlpErrorKind {
+        &self.kind
+    }
+
+    pub fn client_blocked(&self) -> bool {
+        self.client_blocked
+    }
 }
 
 impl std::fmt::Display for YtdlpError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut result ;
-        result = format!("{} {}", "yt-video id:", self.video_id);
+        result = format!("{} {}", "yt-video id:", self.video_id.as_deref().unwrap_or(UNKNOWN_VIDEO_ID));
         result = format!("{}\n   {} {}\n", result, "Reason:", self.error_msg);
+        if let YtdlpErrorKind::PostProcessing { stage, .. } = &self.kind {
+            result = format!("{}   {} post-processing ({})\n", result, "Failed during:", stage.as_deref().unwrap_or("unknown step"));
+        }
 
         write!(f, "{}", result)
     }
@@ -129,7 +241,7 @@ impl YtdlpError {
         // Skip ERROR:
         section.next().unwrap();
 
-        let mut video_id;
+        let mut video_id = None;
 
         //  for normal errors this should be [youtube]
         let mut youtube = section.next().unwrap();
@@ -140,13 +252,12 @@ impl YtdlpError {
 
         if is_normal_error {
             // This is a usual error, so the video is is in the next section
-            video_id = section.next().unwrap();
+            let id = section.next().unwrap();
             // Delete the trailing ':'
-            video_id = &video_id[..video_id.len() - 1];
+            video_id = Some(&id[..id.len() - 1]);
         } else {
             // The video doesn't exist, this happens in errors such as NONEXISTENT_VIDEO (see lib.rs)
             strange_err_msg_beginning = youtube;
-            video_id = "unavailable";
         }
 
         // Concatenate together the error message and restore whitespace
@@ -163,6 +274,109 @@ impl YtdlpError {
             tmp
         };
 
-        YtdlpError { video_id: video_id.to_string(), error_msg }
+        YtdlpError { video_id: video_id.map(str::to_string), error_msg, kind: YtdlpErrorKind::Download, client_blocked: false }
+    }
+
+    /// Which of FILESYSTEM_ERRORS the OS gave yt-dlp while writing the video's files, if any
+    pub fn filesystem_error(&self) -> Option<&'static str> {
+        FILESYSTEM_ERRORS.into_iter().find(|error| self.error_msg.contains(error))
+    }
+
+    /// Filesystem errors like FILE_NAME_TOO_LONG don't mention their video, `video_id` is the one yt-dlp was working on
+    pub fn or_video_id(mut self, video_id: Option<&str>) -> YtdlpError {
+        if self.video_id.is_none() {
+            self.video_id = video_id.map(str::to_string);
+        }
+        self
+    }
+
+    /// Marks the error as caused by youtube blocking yt-dlp's player client, see is_blocked_client_output
+    pub fn with_blocked_client(mut self) -> YtdlpError {
+        self.client_blocked = true;
+        self
+    }
+
+    /// Whether yt-dlp's line says youtube is blocking its player client: formats are skipped or their signatures can't be solved
+    pub fn is_blocked_client_output(line: &str) -> bool {
+        line.contains(WEB_CLIENT_FORMATS_SKIPPED) || line.contains(NSIG_EXTRACTION_FAILED)
+    }
+
+    /// Whether the filesystem refused the name of the file yt-dlp was writing
+    pub fn is_name_too_long(&self) -> bool {
+        self.error_msg.contains(FILE_NAME_TOO_LONG) || self.error_msg.contains(WINDOWS_FILE_NAME_TOO_LONG)
+    }
+
+    /// Whether the video is a track only YouTube Music Premium subscribers can play
+    pub fn is_premium_only(&self) -> bool {
+        self.error_msg.contains(MUSIC_PREMIUM_ONLY) || self.error_msg.contains(MUSIC_PREMIUM_ONLY_PLAYABILITY)
+    }
+
+    /// yt-dlp got stuck while working on `video_id` (if it's known) and was stopped
+    pub fn timeout(video_id: Option<&str>) -> YtdlpError {
+        YtdlpError {
+            video_id: video_id.map(str::to_string),
+            error_msg: String::from(YTDLP_STALLED),
+            kind: YtdlpErrorKind::Timeout,
+            client_blocked: false,
+        }
+    }
+
+    /// Whether the error line comes from a post-processor rather than from the download itself
+    pub fn is_post_processing_output(error_line: &str) -> bool {
+        error_line.contains(POSTPROCESSING) || error_line.contains(FFMPEG_EXITED)
+    }
+
+    /// Parses an error printed by a post-processor, these lines don't mention the video they refer to
+    ///
+    /// `video_id` is the video yt-dlp was working on and `stage` the post-processor which was running, if they are known.
+    /// `streams` are the files downloaded for the video so far
+    pub fn from_post_processing_output(error_line: &str, video_id: Option<&str>, stage: Option<&str>, streams: &[String]) -> YtdlpError {
+        let error_msg = error_line.split_once("ERROR:")
+            .map(|(_, reason)| reason)
+            .unwrap_or(error_line)
+            .to_string();
+
+        YtdlpError {
+            video_id: video_id.map(str::to_string),
+            error_msg,
+            kind: YtdlpErrorKind::PostProcessing { stage: stage.map(str::to_string), streams: streams.to_vec() },
+            client_blocked: false,
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn youtube_errors_name_their_video() {
+        let error = YtdlpError::from_error_output("ERROR: [youtube] dQw4w9WgXcQ: Private video.  Sign in if you've been granted access");
+
+        assert_eq!(error.video_id(), Some("dQw4w9WgXcQ"));
+        // Runs of whitespace become a single space, the message keeps a leading one
+        assert_eq!(error.error_msg(), " Private video. Sign in if you've been granted access");
+        assert_eq!(error.kind(), &YtdlpErrorKind::Download);
+    }
+
+    #[test]
+    fn other_errors_have_no_video_until_one_is_given() {
+        let error = YtdlpError::from_error_output("ERROR: unable to open for writing: [Errno 36] File name too long");
+        assert_eq!(error.video_id(), None);
+        assert_eq!(error.error_msg(), "unable to open for writing: [Errno 36] File name too long");
+
+        let error = error.or_video_id(Some("dQw4w9WgXcQ"));
+        assert_eq!(error.video_id(), Some("dQw4w9WgXcQ"));
+        // The id of the error wins over the one yt-dlp was working on
+        assert_eq!(error.or_video_id(Some("aaaaaaaaaaa")).video_id(), Some("dQw4w9WgXcQ"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn errors_without_a_video_are_shown_as_unavailable() {
+        let error = YtdlpError::from_post_processing_output("ERROR: Postprocessing: Conversion failed!", None, Some("Merger"), &[]);
+
+        assert_eq!(error.error_msg(), " Postprocessing: Conversion failed!");
+        assert_eq!(error.to_string(),
+                   "yt-video id: unavailable\n   Reason:  Postprocessing: Conversion failed!\n   Failed during: post-processing (Merger)\n");
+        assert_eq!(YtdlpError::timeout(None).video_id(), None);
+    }
+}
@@ -0,0 +1,31 @@
+//! Reads back the files yt-dlp writes next to downloaded videos
+use std::path::Path;
+
+use crate::error::BlobResult;
+
+/// Reads a .description file written with --write-description, without the newline yt-dlp adds at the end
+pub fn read_description(path: &Path) -> BlobResult<String> {
+    let description = std::fs::read_to_string(path)?;
+    Ok(description.trim_end_matches(['\r', '\n']).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descriptions_are_read_without_the_last_newline() {
+        let path = std::env::temp_dir().join(format!("blob-dl-{}.description", std::process::id()));
+        std::fs::write(&path, "First line\n\nLinks:\nhttps://example.com\n").unwrap();
+
+        let description = read_description(&path);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(description.unwrap(), "First line\n\nLinks:\nhttps://example.com");
+    }
+
+    #[test]
+    fn missing_descriptions_are_errors() {
+        let path = std::env::temp_dir().join(format!("blob-dl-{}-missing.description", std::process::id()));
+        assert!(read_description(&path).is_err());
+    }
+}
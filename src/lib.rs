@@ -3,7 +3,107 @@ pub mod assembling;
 pub mod analyzer;
 pub mod dispatcher;
 mod run;
+mod checksum;
+mod timings;
+mod units;
 mod error;
+mod plugin;
+mod search;
+mod direct_urls;
+mod check;
+mod stats;
+mod simulate;
+mod state;
+mod only_new;
+mod update;
+mod lockfile;
+mod postprocess;
+mod secrets;
+mod speed_graph;
+pub mod inspect;
+mod pause;
+mod preset;
+mod dashboard;
+
+/// A yt-dlp command which always runs in the C locale
+///
+/// Don't remove the locale: blob-dl recognises errors by matching their messages (see youtube_error_message),
+/// and the ones yt-dlp passes on from the OS and ffmpeg ("Permission denied", "No space left on device", ...)
+/// are translated on machines with another locale, so they would stop matching
+pub(crate) fn ytdlp_command() -> std::process::Command {
+    let mut command = std::process::Command::new("yt-dlp");
+    command.env("LC_ALL", "C").env("LANG", "C");
+    command
+}
+
+/// Runs a yt-dlp command which only fetches information, capturing its stdout and stderr
+///
+/// yt-dlp is stopped if it doesn't print a line on stdout for `timeout`, None is returned then. The timeout restarts
+/// with every line, so that listing a big playlist can take longer
+pub(crate) fn ytdlp_output(command: &mut std::process::Command, timeout: std::time::Duration) -> std::io::Result<Option<std::process::Output>> {
+    use std::io::{BufRead, BufReader, Read};
+    use crossbeam_channel::RecvTimeoutError;
+
+    let mut child = command.stdout(std::process::Stdio::piped()).stderr(std::process::Stdio::piped()).spawn()?;
+
+    // Both are read on their own threads: waiting for stdout can time out, and a full stderr pipe would block yt-dlp
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let stderr_reader = std::thread::spawn(move || {
+        let mut buffer = vec![];
+        let _ = stderr.read_to_end(&mut buffer);
+        buffer
+    });
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut stdout = String::new();
+    loop {
+        match receiver.recv_timeout(timeout) {
+            Ok(line) => {
+                stdout.push_str(&line);
+                stdout.push('\n');
+            }
+            // yt-dlp is done
+            Err(RecvTimeoutError::Disconnected) => break,
+            Err(RecvTimeoutError::Timeout) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Ok(None);
+            }
+        }
+    }
+
+    let status = child.wait()?;
+    let stderr = stderr_reader.join().unwrap_or_default();
+    Ok(Some(std::process::Output { status, stdout: stdout.into_bytes(), stderr }))
+}
+
+/// The last "ERROR:" line yt-dlp wrote on `stderr`, which explains why it failed, with its secrets scrubbed
+pub(crate) fn last_ytdlp_error(stderr: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(stderr).lines().rev()
+        .find(|line| line.starts_with("ERROR:"))
+        .map(|line| secrets::scrub(line.trim_start_matches("ERROR:").trim()))
+}
+
+/// Turns a yt-dlp command which only fetches information and failed into an error, its last error line is shown first
+pub(crate) fn check_ytdlp_status(output: &std::process::Output) -> error::BlobResult<()> {
+    use colored::Colorize;
+
+    if output.status.success() {
+        return Ok(());
+    }
+    if let Some(error) = last_ytdlp_error(&output.stderr) {
+        eprintln!("{}: {}", "ERROR".red(), error);
+    }
+    Err(error::BlobdlError::YtdlpFailed(output.status.code()))
+}
 
 // Things blob-dl regularly tells the user
 pub mod ui_prompts {
@@ -27,15 +127,63 @@ pub mod ui_prompts {
 
     pub const YT_FORMAT_PROMPT_SINGLE_VIDEO: &str = "Choose a format to download the video in";
 
-    pub const CONVERT_FORMAT_PROMPT_VIDEO_PLAYLIST: &str = "Choose a format to recode all the videos to";
+    pub const THUMBNAIL_ONLY_PROMPT: &str = "Download thumbnail only";
+
+    pub const SHOW_FORMATS_TABLE_PROMPT: &str = "Show me every available format first";
+
+    pub const NO_MATCHING_FORMATS: &str = "None of the available formats can be downloaded as";
+
+    pub const EMPTY_FORMATS_PROMPT: &str = "What do you want to do?";
+
+    pub const BEST_QUALITY_FALLBACK_PROMPT: &str = "Let yt-dlp pick the best quality instead";
+
+    pub const EXACT_FORMAT_PROMPT: &str = "Exact format...";
+
+    pub const REMUX_FORMAT_PROMPT: &str = "Change container only (fast)";
+
+    pub const CONVERT_FORMAT_PROMPT_VIDEO_PLAYLIST: &str = "Convert/re-encode all the videos (slower, choose the format)";
+
+    pub const CONVERT_FORMAT_PROMPT_VIDEO_SINGLE_VIDEO: &str = "Convert/re-encode the video (slower, choose the format)";
+
+    pub const NATIVE_EXTRACTION_PROMPT: &str = "Keep youtube's audio stream (fastest, the format depends on the video)";
+
+    pub const M3U_WRITTEN: &str = "Playlist file saved to";
+
+    pub const ONLY_NEW_FIRST_RUN_PROMPT: &str = "This playlist wasn't downloaded with --only-new before, do you want to download all of its videos?";
+
+    pub const HIDDEN_VIDEOS_SKIPPED: &str = "private or deleted video(s) in the playlist will be skipped";
+
+    pub const FORMATS_UNREADABLE: &str = "The formats of this video couldn't be read, it won't restrict the playlist's formats: video";
+
+    pub const DUPLICATES_SKIPPED: &str = "Only the first copy of every duplicate will be downloaded, use --keep-duplicates to download all of them";
+
+    pub const EXTENSION_CONFLICTS_FOUND: &str = "Some videos are already in the output folder with a different extension:";
+
+    pub const EXTENSION_CONFLICTS_PROMPT: &str = "What do you want to do with them?";
 
-    pub const CONVERT_FORMAT_PROMPT_VIDEO_SINGLE_VIDEO: &str = "Choose a format to recode the video to";
+    pub const SKIP_CONFLICTS_PROMPT: &str = "Don't download these videos again";
 
-    pub const CONVERT_FORMAT_PROMPT_AUDIO: &str = "Choose an audio format to convert the audios to";
+    pub const KEEP_BOTH_CONFLICTS_PROMPT: &str = "Download them and keep both copies";
+
+    pub const REPLACE_CONFLICTS_PROMPT: &str = "Download them and delete the old copies";
+
+    pub const REPLACED_FILE: &str = "Deleted the old copy";
+
+    pub const CROP_SHORT_PROMPT: &str = "Do you want to crop the short to a vertical frame? (removes the bars around shorts uploaded as horizontal videos)";
+
+    pub const FREE_FORMATS_PROMPT: &str = "Prefer open/free codecs (VP9, Opus)?";
+
+    pub const CHAPTERS_PROMPT: &str = "Do you want to download only some of the video's chapters?";
+
+    pub const CHAPTER_SELECTION_PROMPT: &str = "Choose the chapters you want to download [space bar to select]";
+
+    pub const NO_CHAPTERS: &str = "This video isn't divided in chapters, the whole video will be downloaded";
+
+    pub const FFMPEG_CONVERT_PROMPT: &str = "Re-encode the audio with ffmpeg (choose the format)";
 
     pub const SEE_HELP_PAGE: &str = "Type blob-dl --help for a list of all the available options";
 
-    pub const USAGE_MSG: &str = "Usage: blob-dl [OPTIONS] [URL]";
+    pub const USAGE_MSG: &str = "Usage: blob-dl [OPTIONS] [URL]...";
 
     pub const ERROR_RETRY_PROMPT: &str = "The following videos weren't downloaded but retrying might help, choose which videos to re-download [space bar to select]";
 
@@ -43,6 +191,230 @@ pub mod ui_prompts {
 
     pub const DEBUG_REPORT_PROMPT: &str = "By default new errors are flagged as recoverable, if any unrecoverable errors are flagged incorrectly please report them to the github page";
 
+    pub const MULTIPLE_URLS_SUMMARY: &str = "Summary of the errors for each url:";
+
+    pub const ADVANCED_OPTIONS_PROMPT: &str = "Do you want to configure advanced options?";
+
+    pub const PLUGIN_PROMPT: &str = "Run a post-processing plugin?";
+
+    pub const PLUGIN_PATH_PROMPT: &str = "Path to the plugin (.so/.dylib/.dll):";
+
+    pub const SOURCE_ADDRESS_PROMPT: &str = "Bind to specific network interface? (advanced)";
+
+    pub const ATTENTION_NOTIFICATION: &str = "Some videos couldn't be downloaded, blob-dl needs your input";
+
+    pub const PROMPT_TIMEOUT_WAITING: &str = "Press enter to choose which videos to re-download, otherwise every recoverable error will be retried. Seconds left:";
+
+    pub const PROMPT_TIMEOUT_EXPIRED: &str = "Nobody answered, retrying every recoverable error";
+
+    pub const INTERRUPTED_MSG: &str = "The download was interrupted";
+
+    pub const PARTIAL_FILES_FOUND: &str = "These unfinished files were created during this session:";
+
+    pub const DELETE_PARTIAL_FILES_PROMPT: &str = "Do you want to delete them?";
+
+    pub const COMPATIBLE_CODEC_HINT: &str = "widely compatible";
+
+    pub const MODERN_CODEC_HINT: &str = "modern, smaller, may not play on older devices";
+
+    pub const PROXY_PROMPT: &str = "Some videos aren't available in your country, which proxy should be used to download them? (like socks5://127.0.0.1:1080, leave empty to skip them)";
+
+    pub const NEEDS_COOKIES_TAG: &str = "[needs cookies]";
+
+    pub const NEEDS_PROXY_TAG: &str = "[needs a proxy]";
+
+    pub const NAME_TOO_LONG_TAG: &str = "[name too long]";
+
+    pub const CLIENT_BLOCKED_TAG: &str = "[client blocked]";
+
+    pub const FORMAT_GONE_TAG: &str = "[format unavailable]";
+
+    pub const COOKIES_PROMPT: &str = "Some videos can only be downloaded by signed-in users, which browser are you signed in with? (its cookies will be used)";
+
+    pub const NO_COOKIES: &str = "Don't use cookies";
+
+    pub const SYSTEM_PATH_WARNING: &str = "This is a system directory, downloading here could scatter files where they don't belong";
+
+    pub const CLOUD_FOLDER_WARNING: &str = "This folder is synced by";
+
+    pub const NETWORK_FOLDER_WARNING: &str = "This folder is on a network filesystem";
+
+    pub const SLOW_STORAGE_ADVICE: &str = "Large downloads can be slow and keep syncing for a long time, consider downloading to a local folder and moving the files afterwards";
+
+    pub const RUNNING_AS_ROOT_WARNING: &str = "blob-dl is running as root, the downloaded files will belong to root";
+
+    pub const CURRENT_DIRECTORY_PROMPT: &str = "Current directory";
+
+    pub const DOWNLOADS_FOLDER_PROMPT: &str = "Home downloads folder";
+
+    pub const CUSTOM_PATH_PROMPT: &str = "Custom path...";
+
+    pub const WINDOWS_FILENAMES_PROMPT: &str = "Are you downloading to a Windows filesystem or share?";
+
+    pub const ASCII_NAMES_PROMPT: &str = "Use ASCII-safe file names? (useful for NAS shares which don't support emoji or CJK characters)";
+
+    pub const VIEW_COUNT_FILTER_PROMPT: &str = "Only download videos with a certain number of views?";
+
+    pub const MIN_VIEWS_PROMPT: &str = "Minimum views (leave empty for no minimum):";
+
+    pub const MAX_VIEWS_PROMPT: &str = "Maximum views (leave empty for no maximum):";
+
+    pub const IGNORE_ERRORS_PROMPT: &str = "Continue downloading if individual videos fail?";
+
+    pub const SHORT_TITLES_PROMPT: &str = "Shorten long titles in file names? (useful for older filesystems and SMB shares which reject long names)";
+
+    pub const SHORTER_NAMES_PROMPT: &str = "Some titles are too long for this filesystem, download those videos again with shortened titles? (\"Shorten long titles\" in the advanced options avoids this)";
+
+    pub const FALLBACK_CLIENT_PROMPT: &str = "Youtube seems to be blocking yt-dlp's player client for some videos, retry them with the android client? (--auto-fallback-client does it without asking)";
+
+    pub const STALE_FORMAT_FOUND: &str = "Youtube seems to have changed its format ids since the formats were fetched, the chosen format is gone for these videos:";
+
+    pub const REMAPPED_FORMAT_PROMPT: &str = "Retry them with the closest format available now? (--yes does it without asking)";
+
+    pub const NO_REMAPPED_FORMAT: &str = "No format close to the chosen one is available anymore, choose another one by running blob-dl again:";
+
+    pub const WRITE_DESCRIPTION_PROMPT: &str = "Save video description to file?";
+
+    pub const PLAYLIST_WRITE_DESCRIPTION_PROMPT: &str = "Save video descriptions to files? (one .description file per video)";
+
+    pub const ID3_TAGS_PROMPT: &str = "Add your own ID3 tags to the mp3 files?";
+
+    pub const ID3_TAG_INPUT_PROMPT: &str = "Tag as NAME=VALUE (leave empty when you are done)";
+
+    pub const ID3_TOOL_MISSING: &str = "Neither id3v2 nor eyeD3 was found, the custom ID3 tags weren't written";
+
+    pub const SUBTITLES_PROMPT: &str = "Download subtitles?";
+
+    pub const SUBTITLE_LANGUAGES_PROMPT: &str = "Subtitle languages, separated by commas (\"all\" for every language)";
+
+    pub const SUBTITLE_FORMAT_PROMPT: &str = "Which format should the subtitles be in?";
+
+    pub const KEEP_SUBTITLE_FORMAT_PROMPT: &str = "Keep the format youtube provides";
+
+    pub const VIDEO_ONLY_CONFIRM_PROMPT: &str = "Video-only files have no audio track, they will be silent. Is that what you want?";
+
+    pub const PREVIOUS_ANSWERS_PROMPT: &str = "You downloaded from this channel or playlist before";
+
+    pub const USE_PREVIOUS_ANSWERS_PROMPT: &str = "Use the previous settings";
+
+    pub const CHOOSE_AGAIN_PROMPT: &str = "Choose again";
+
+    pub const PREDICTED_FAILURES_FOUND: &str = "These videos will most likely fail to download:";
+
+    pub const EXCLUDE_PREDICTED_FAILURES_PROMPT: &str = "Leave them out of the download?";
+
+    pub const SIGN_IN_FIXES_PREDICTED_FAILURES: &str = "The ones which need signing in can be downloaded with your browser's cookies, blob-dl offers to use them when they fail";
+
+    pub const PREMIUM_ONLY_REASON: &str = "needs YouTube Music Premium";
+
+    pub const MEMBERS_ONLY_REASON: &str = "only available to the channel's members";
+
+    pub const NEEDS_AUTH_REASON: &str = "needs signing in";
+
+    pub const AGE_RESTRICTED_REASON: &str = "age-restricted, needs signing in";
+
+    pub const EMBED_INFO_JSON_PROMPT: &str = "Do you want to embed the videos' full metadata in the files? (MKV only)";
+
+    pub const INFO_JSON_CONTAINER_WARNING: &str = "The metadata can only be embedded in MKV files, it won't be embedded in";
+
+    pub const INFO_JSON_UNKNOWN_CONTAINER: &str = "the files youtube provides (usually mp4 or webm), remux them to mkv to keep it";
+
+    pub const FFMPEG_ARGS_PROMPT: &str = "Custom ffmpeg args?";
+
+    pub const FFMPEG_ARGS_WARNING: &str = "This is an advanced option: blob-dl doesn't check the arguments, invalid ones make every download fail";
+
+    pub const FFMPEG_ARGS_INPUT_PROMPT: &str = "ffmpeg arguments (like -af loudnorm), leave empty to cancel";
+
+    pub const NO_PART_FILES_PROMPT: &str = "Write directly to output file (no .part files)? Interrupted downloads will leave incomplete files with their final name";
+
+    pub const COMPAT_OPTIONS_PROMPT: &str = "Do you need youtube-dl compatibility options? (for scripts which rely on youtube-dl's behaviour)";
+
+    pub const COMPAT_OPTIONS_SELECTION_PROMPT: &str = "Choose the options you want [space bar to select]";
+
+    pub const UNKNOWN_COMPAT_OPTION: &str = "yt-dlp might not recognize this compat option:";
+
+    pub const ALTERNATE_PATH_PROMPT: &str = "Do you want to save the re-downloaded videos in a different folder?";
+
+    pub const ALTERNATE_PATH_SUMMARY: &str = "These files were saved in the alternate folder";
+
+    pub const FILESYSTEM_ERROR_HINT: &str = "Some files couldn't be written, downloading them again won't help until the output folder is fixed. Check that it exists, that you can write to it and that its disk has free space:";
+
+    pub const FILESYSTEM_ABORT: &str = "More than half of the playlist couldn't be written to the output folder, the download was stopped";
+
+    pub const INVALID_ENV_VARIABLE: &str = "The invalid value below comes from the environment variable";
+
+    pub const ENV_VARS_HELP: &str = "The options marked with [env: ...] can also be set with environment variables, options passed on the command line take precedence over them";
+
+    pub const CHECK_EXIT_CODES_HELP: &str = "Exit codes: 0 for videos, shorts and playlists, 1 if blob-dl failed (yt-dlp isn't installed, ...), 3 for mixes, 4 for channels, 5 for unsupported urls, 6 if --probe couldn't reach the url";
+
+    pub const PREMIUM_ONLY_SUMMARY: &str = "video(s) unavailable without YouTube Music Premium, pass --skip-premium-tracks to leave them out";
+
+    pub const VIDEO_ONLY_FILES: &str = "Downloaded without an audio track:";
+
+    pub const NO_AUDIO_TAG: &str = "(no audio)";
+
+    pub const PAUSED_MSG: &str = "Paused, the next video starts once this file is removed:";
+
+    pub const PAUSED_KEY_OR_FILE_MSG: &str = "Paused, press p or remove this file to continue:";
+
+    pub const PAUSED_KEY_MSG: &str = "Paused, press p to continue";
+
+    pub const PAUSE_REQUESTED_MSG: &str = "Pausing once this video is downloaded, press p again to keep going";
+
+    pub const PAUSE_CANCELLED_MSG: &str = "Not pausing anymore";
+
+    pub const RESUMED_MSG: &str = "Resuming";
+
+    pub const DASHBOARD_NOT_BUILT: &str = "This blob-dl was built without the dashboard (cargo install blob-dl --features dashboard), showing the normal output";
+
+    pub const DASHBOARD_NO_TERMINAL: &str = "The dashboard needs a terminal, showing the normal output";
+
+    pub const DASHBOARD_TOO_SMALL: &str = "The terminal is too small for the dashboard, showing the normal output";
+
+    pub const DASHBOARD_KEYS: &str = " p: pause after this video   q: quit";
+
+    pub const DASHBOARD_PAUSE_REQUESTED: &str = "pausing after this video";
+
+    pub const DASHBOARD_PAUSED: &str = "paused, press p to continue";
+
+    pub const DASHBOARD_WORKER_STARTING: &str = "starting";
+
+    pub const DASHBOARD_WORKER_DONE: &str = "done";
+
+    pub const POST_PROCESSING_HINT: &str = "Some videos were downloaded but ffmpeg couldn't process them, downloading them again won't help.\nMake sure ffmpeg is installed and up to date, or run blob-dl again with --keep-video to keep the downloaded streams and merge them yourself";
+
+    pub const KEPT_STREAMS: &str = "The downloaded streams were kept, you can merge them yourself:";
+
+    pub const YTDLP_CACHE_CLEARED: &str = "yt-dlp's cache was cleared";
+
+    pub const SEARCH_RESULT_PROMPT: &str = "Which video do you want to download? [Esc to cancel]";
+
+    pub const NO_SEARCH_RESULTS: &str = "The search didn't find any videos";
+
+    pub const RESUME_HINT: &str = "To download the rest of the playlist, resume next time with --start-at";
+
+    pub const COMMENTS_SLOW_WARNING: &str = "Fetching comments can take much longer than downloading the video itself, especially for popular videos. Cap them with --max-comments";
+
+    pub const COMMENTS_PROGRESS: &str = "Downloading comments:";
+
+    pub const COMMENTS_SAVED: &str = "Comments saved in:";
+
+    pub const GENTLE_MODE_NOTE: &str = "Gentle mode: downloads are limited to 2MB/s and blob-dl waits 5-15 seconds between videos, it's slower but youtube is less likely to block you";
+
+    pub const SIMULATION_SUMMARY: &str = "These videos would be downloaded from";
+
+    pub const TIMINGS_SUMMARY: &str = "Where the time went:";
+
+    pub const SLOWEST_VIDEOS_SUMMARY: &str = "Slowest videos (file | size | time | average speed):";
+
+    pub const AUTO_RETRY_SUMMARY: &str = "Summary of the automatic retry:";
+
+    pub const UP_TO_DATE: &str = "blob-dl is up to date";
+
+    pub const UPDATE_AVAILABLE: &str = "A new version of blob-dl is available:";
+
+    pub const UPDATE_INSTRUCTIONS: &str = "Update it with `cargo install blob-dl` or download the latest binaries from https://github.com/MicheleCioccarelli/blob-dl/releases";
+
     pub const SELECT_ALL: &str = "Select all\n";
     pub const SELECT_NOTHING: &str = "Don't re-download anything\n";
 }
@@ -71,6 +443,46 @@ mod youtube_error_message {
 
     pub const NONEXISTENT_VIDEO: &str = "Incomplete data received";
 
+    // These can be fixed by signing in, they are matched as substrings
+    pub const SIGN_IN_TO_CONFIRM_AGE: &str = "Sign in to confirm your age";
+
+    // Tracks of music playlists which need a YouTube Music Premium subscription, signing in without one doesn't help.
+    // Youtube words it in a few ways ("This video is only available to Music Premium members", ...)
+    pub const MUSIC_PREMIUM_ONLY: &str = "Music Premium members";
+
+    pub const MUSIC_PREMIUM_ONLY_PLAYABILITY: &str = "available with YouTube Music Premium";
+
+    // yt-dlp's error when the format id passed to -f doesn't exist (anymore), followed by "Use --list-formats ..."
+    pub const FORMAT_UNAVAILABLE: &str = "Requested format is not available";
+
+    // Videos blocked in the user's country, a proxy can fix it
+    pub const GEO_RESTRICTED: &str = "available in your country";
+
+    // The filesystem rejected the file's name (errno 36 and its Windows equivalent), a shorter title can fix it
+    pub const FILE_NAME_TOO_LONG: &str = "File name too long";
+
+    pub const WINDOWS_FILE_NAME_TOO_LONG: &str = "filename or extension is too long";
+
+    // The OS refused to write to the output folder, downloading again can't help until it's fixed
+    pub const PERMISSION_DENIED: &str = "Permission denied";
+
+    pub const READ_ONLY_FILESYSTEM: &str = "Read-only file system";
+
+    pub const NO_SPACE_LEFT: &str = "No space left on device";
+
+    // yt-dlp prints these when youtube blocks its player client, another client can work
+    pub const WEB_CLIENT_FORMATS_SKIPPED: &str = "Some web client https formats have been skipped";
+
+    pub const NSIG_EXTRACTION_FAILED: &str = "nsig extraction failed";
+
+    // Errors printed by post-processors contain one of these
+    pub const POSTPROCESSING: &str = "Postprocessing:";
+
+    pub const FFMPEG_EXITED: &str = "ffmpeg exited with code";
+
+    // Used by blob-dl when yt-dlp gets stuck, it counts as a network error
+    pub const YTDLP_STALLED: &str = " yt-dlp didn't print anything for 30 seconds and timed out";
+
     // All copyright error messages begin with this
     pub const VIDEO_UNAVAILABLE: &str = " Video unavailable";
 }
@@ -82,7 +494,7 @@ mod blobdl_error_message {
 
     pub const UNKNOWN_ISSUE_ERR: &str = "Congrats! You ran into an unknown issue, please file a report on blob-dl's github page :)";
 
-    pub const MISSING_ARGUMENT_ERR: &str = "You must provide 1 URL";
+    pub const MISSING_ARGUMENT_ERR: &str = "You must provide at least 1 URL";
 
     pub const JSON_SERIALIZATION_ERR: &str = "There was a problem serializing this video's format information";
 
@@ -95,4 +507,117 @@ mod blobdl_error_message {
     pub const URL_QUERY_COULD_NOT_BE_PARSED: &str = "This url's query could not be parsed, try using a regular youtube url";
 
     pub const URL_INDEX_PARSING_ERR: &str = "The video's index in the playlist couldn't be parsed, please report this issue to the github page";
+
+    pub const HTTP_ERR: &str = "Couldn't reach crates.io:";
+
+    pub const PLUGIN_LOADING_ERR: &str = "The post-processing plugin couldn't be loaded:";
+
+    pub const FORMAT_FETCH_TIMEOUT_ERR: &str = "yt-dlp took too long to fetch the available formats, check your connection or wait longer with --fetch-timeout";
+
+    pub const LIST_FETCH_TIMEOUT_ERR: &str = "yt-dlp took too long to list the videos, check your connection or wait longer with --fetch-timeout";
+
+    pub const URL_TRUNCATED_ERR: &str = "Your shell probably ate part of the url at an '&', wrap the url in quotes: blob-dl \"<url>\"";
+
+    pub const OUTPUT_DIRECTORY_LOCKED_ERR: &str = "Another blob-dl is downloading into the same folder, wait for it to finish or pass --force. Its pid is";
+
+    pub const INVALID_PLAYLIST_RANGE_ERR: &str = "--stop-at can't be smaller than --start-at";
+
+    pub const YTDLP_FAILED_ERR: &str = "yt-dlp failed with exit code";
+
+    pub const PLUGIN_FAILED_ERR: &str = "The post-processing plugin failed with exit code";
+
+    pub const ID3_TAGGING_FAILED_ERR: &str = "The ID3 tagging tool failed with exit code";
+
+    pub const MUSIC_NEEDS_FFMPEG_ERR: &str = "--music converts and tags the songs with ffmpeg, which wasn't found. Install it or run blob-dl without --music";
+
+    pub const DOWNLOAD_CANCELLED_ERR: &str = "The download was cancelled";
+
+    pub const UNKNOWN_PRESET_ERR: &str = "The built-in presets are music and video, others can be defined in presets.json in blob-dl's config directory. There is no preset called";
+
+    pub const DUPLICATE_PRESET_ERR: &str = "Only one --preset can be given for each url, and one before every url for the whole run. There are more for";
+
+    pub const INVALID_PRESET_FILE_ERR: &str = "presets.json in blob-dl's config directory couldn't be read:";
+
+    pub const INVALID_CONFIG_FILE_ERR: &str = "config.json in blob-dl's config directory couldn't be read:";
+
+    pub const INVALID_BATCH_FILE_ERR: &str = "Every line of a batch file has to be `url = <url>` or `preset = <name>`, this one isn't: line";
+
+    pub const SYSTEM_PATH_REFUSED_ERR: &str = "blob-dl won't write to a system directory or run as root without confirmation, pass --allow-system-paths if you are sure";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+
+    /// The variables a command sets for yt-dlp by name, None for the ones it removes
+    fn envs(command: &std::process::Command) -> Vec<(&OsStr, Option<&OsStr>)> {
+        let mut envs: Vec<_> = command.get_envs().collect();
+        envs.sort();
+        envs
+    }
+
+    #[test]
+    fn ytdlp_runs_in_the_c_locale() {
+        let command = ytdlp_command();
+
+        assert_eq!(command.get_program(), "yt-dlp");
+        assert_eq!(envs(&command), [(OsStr::new("LANG"), Some(OsStr::new("C"))), (OsStr::new("LC_ALL"), Some(OsStr::new("C")))]);
+    }
+
+    #[test]
+    fn downloads_run_in_the_c_locale() {
+        let config = assembling::youtube::config::DownloadConfig::new_video("https://youtu.be/a", assembling::youtube::VideoQualityAndFormatPreferences::BestQuality,
+                                                                            String::from("/downloads"), assembling::youtube::MediaSelection::FullVideo);
+        let (command, _) = config.build_command();
+
+        assert!(envs(&command).contains(&(OsStr::new("LC_ALL"), Some(OsStr::new("C")))));
+        assert!(envs(&command).contains(&(OsStr::new("LANG"), Some(OsStr::new("C")))));
+    }
+
+    #[test]
+    fn last_error_line_explains_the_failure() {
+        let stderr = b"WARNING: [youtube] falling back\nERROR: [youtube:search] first\nERROR: [youtube:tab] PLx: This playlist does not exist\n";
+        assert_eq!(last_ytdlp_error(stderr).as_deref(), Some("[youtube:tab] PLx: This playlist does not exist"));
+        assert_eq!(last_ytdlp_error(b"WARNING: only a warning\n"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failed_fetches_are_errors() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let output = |code: i32| std::process::Output {
+            status: std::process::ExitStatus::from_raw(code << 8),
+            stdout: vec![],
+            stderr: b"ERROR: [youtube] abc: Video unavailable\n".to_vec(),
+        };
+
+        assert!(check_ytdlp_status(&output(0)).is_ok());
+        assert!(matches!(check_ytdlp_status(&output(1)), Err(error::BlobdlError::YtdlpFailed(Some(1)))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn silent_fetches_time_out() {
+        let started = std::time::Instant::now();
+        let output = ytdlp_output(std::process::Command::new("sh").args(["-c", "echo '{}'; exec sleep 10"]), std::time::Duration::from_millis(200));
+
+        assert!(output.unwrap().is_none());
+        assert!(started.elapsed() < std::time::Duration::from_secs(5), "yt-dlp wasn't stopped");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn the_fetch_timeout_restarts_with_every_line() {
+        // Slower than the timeout overall, but never silent for that long
+        let script = "for i in 1 2 3 4 5; do echo \"{}\"; sleep 0.1; done; echo oops >&2";
+        let output = ytdlp_output(std::process::Command::new("sh").args(["-c", script]), std::time::Duration::from_millis(400))
+            .unwrap()
+            .expect("yt-dlp kept printing");
+
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8(output.stdout).unwrap().lines().count(), 5);
+        assert_eq!(output.stderr, b"oops\n");
+    }
 }
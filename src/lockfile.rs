@@ -0,0 +1,242 @@
+//! Keeps two blob-dl runs from downloading into the same directory at the same time, where they would fight
+//! over .part files and overwrite each other's records
+//!
+//! A lock file holding the pid of the run and when it started is written in every output directory,
+//! it is deleted when the run ends, even after CTRL+C or a panic.
+//! When the output directory is known from the command line it's locked before the wizard asks anything
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use colored::Colorize;
+
+use crate::assembling::youtube::config::DownloadConfig;
+use crate::error::{BlobdlError, BlobResult};
+
+/// The name of the lock file in every output directory
+const LOCK_FILE: &str = ".blob-dl.lock";
+
+/// How long a lock is trusted, after that it's taken for one left behind by a run whose pid was reused
+const MAX_LOCK_AGE_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// How many times taking a lock is tried when other runs keep replacing the lock file
+const LOCK_ATTEMPTS: usize = 3;
+
+/// A lock on an output directory, released when it's dropped
+#[derive(Debug)]
+pub(crate) struct OutputLock {
+    path: PathBuf,
+}
+
+impl Drop for OutputLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// The locks held by this run, released when they are dropped
+#[derive(Debug)]
+pub(crate) struct OutputLocks {
+    /// Whether the locks of other runs are ignored
+    force: bool,
+    /// The directories which were already handled, locked or not
+    directories: Vec<PathBuf>,
+    locks: Vec<OutputLock>,
+}
+
+impl OutputLocks {
+    /// No locks yet, `force` ignores the locks of other runs
+    pub(crate) fn new(force: bool) -> OutputLocks {
+        OutputLocks { force, directories: vec![], locks: vec![] }
+    }
+
+    /// Locks `directory` unless it already was, it's created if it doesn't exist
+    ///
+    /// Fails if another running blob-dl holds the lock, locks left behind by runs which are over are replaced.
+    /// With --force the directory is used without taking the lock: the other run still owns it.
+    /// Directories which can't be locked (read-only, ...) are only warned about, yt-dlp will report the real problem
+    pub(crate) fn lock(&mut self, directory: &Path) -> BlobResult<()> {
+        if self.directories.iter().any(|locked| locked == directory) {
+            return Ok(());
+        }
+
+        match lock(directory, self.force) {
+            Ok(Some(lock)) => self.locks.push(lock),
+            Ok(None) => {}
+            Err(BlobdlError::IoError(err)) => eprintln!("{}: {} ({})", "WARNING".yellow(), err, directory.join(LOCK_FILE).display()),
+            Err(err) => return Err(err),
+        }
+        self.directories.push(directory.to_path_buf());
+
+        Ok(())
+    }
+
+    /// Locks the output directory of every download
+    pub(crate) fn lock_all(&mut self, downloads: &[(std::process::Command, DownloadConfig)]) -> BlobResult<()> {
+        downloads.iter().try_for_each(|(_, config)| self.lock(Path::new(config.output_path())))
+    }
+}
+
+/// Writes the lock file in `directory`, which is created if it doesn't exist
+///
+/// None if another run holds the lock and `force` is true
+fn lock(directory: &Path, force: bool) -> BlobResult<Option<OutputLock>> {
+    std::fs::create_dir_all(directory)?;
+    let path = directory.join(LOCK_FILE);
+
+    for _ in 0..LOCK_ATTEMPTS {
+        // create_new fails if the file exists, so two runs can't both think they created it
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                writeln!(file, "{} {}", std::process::id(), now())?;
+                return Ok(Some(OutputLock { path }));
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            // Released in the meantime
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+
+        if let Some(pid) = held_by(&contents, now(), is_alive) {
+            return if pid == std::process::id() || force {
+                Ok(None)
+            } else {
+                Err(BlobdlError::OutputDirectoryLocked(pid))
+            };
+        }
+
+        remove_stale_lock(&path, &contents)?;
+    }
+
+    Err(std::io::Error::other("the lock file kept changing").into())
+}
+
+/// Removes the lock file at `path` if it still holds `stale_contents`
+///
+/// It's moved away first: if another run replaced the stale lock in the meantime, its lock is put back
+fn remove_stale_lock(path: &Path, stale_contents: &str) -> std::io::Result<()> {
+    let moved = path.with_extension(format!("stale-{}", std::process::id()));
+    match std::fs::rename(path, &moved) {
+        Ok(()) => {}
+        // Another run removed it first
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    }
+
+    if std::fs::read_to_string(&moved).map_or(true, |contents| contents != stale_contents) {
+        // Linking fails instead of replacing a lock taken since, that run then wins
+        let _ = std::fs::hard_link(&moved, path);
+    }
+    std::fs::remove_file(&moved)
+}
+
+/// The pid of the run which holds a lock with these contents, None if the lock is stale
+///
+/// A lock is stale when its process is over, when it's older than MAX_LOCK_AGE_SECS (the pid was likely reused)
+/// and when it can't be read
+fn held_by(contents: &str, now: u64, is_alive: impl Fn(u32) -> bool) -> Option<u32> {
+    let (pid, started) = parse_lock(contents)?;
+    (now.saturating_sub(started) <= MAX_LOCK_AGE_SECS && is_alive(pid)).then_some(pid)
+}
+
+/// The pid and start time in a lock file, which holds "<pid> <start time in seconds since the epoch>"
+fn parse_lock(contents: &str) -> Option<(u32, u64)> {
+    let mut fields = contents.split_whitespace();
+    Some((fields.next()?.parse().ok()?, fields.next()?.parse().ok()?))
+}
+
+/// Seconds since the unix epoch, 0 if the clock is before it
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0)
+}
+
+/// Whether a process with this pid is running
+#[cfg(unix)]
+fn is_alive(pid: u32) -> bool {
+    // Safety: signal 0 only checks whether the process exists, EPERM means it belongs to another user
+    unsafe {
+        libc::kill(pid as libc::pid_t, 0) == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+}
+
+/// Whether a process with this pid is running, if tasklist can't be run the lock is considered stale
+#[cfg(not(unix))]
+fn is_alive(pid: u32) -> bool {
+    std::process::Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).split_whitespace().any(|word| word == pid.to_string()))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    #[test]
+    fn live_recent_locks_are_held() {
+        assert_eq!(held_by(&format!("42 {}\n", NOW - 60), NOW, |_| true), Some(42));
+    }
+
+    #[test]
+    fn locks_of_runs_which_are_over_are_stale() {
+        assert_eq!(held_by(&format!("42 {}\n", NOW - 60), NOW, |_| false), None);
+    }
+
+    #[test]
+    fn old_locks_are_stale_even_if_the_pid_is_running() {
+        assert_eq!(held_by(&format!("42 {}\n", NOW - MAX_LOCK_AGE_SECS - 1), NOW, |_| true), None);
+    }
+
+    #[test]
+    fn unreadable_locks_are_stale() {
+        for contents in ["", "42", "abc 123", "42 abc"] {
+            assert_eq!(held_by(contents, NOW, |_| true), None, "{:?}", contents);
+        }
+    }
+
+    fn temp_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("blob-dl-lockfile-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        directory
+    }
+
+    #[test]
+    fn stale_locks_are_replaced_and_released() {
+        let directory = temp_directory("stale");
+        std::fs::create_dir_all(&directory).unwrap();
+        // Far too old to be trusted
+        std::fs::write(directory.join(LOCK_FILE), "1 0\n").unwrap();
+
+        let lock = lock(&directory, false).unwrap().unwrap();
+        let contents = std::fs::read_to_string(directory.join(LOCK_FILE)).unwrap();
+        assert_eq!(parse_lock(&contents).map(|(pid, _)| pid), Some(std::process::id()));
+
+        drop(lock);
+        assert!(!directory.join(LOCK_FILE).exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn forced_runs_leave_the_other_lock_alone() {
+        let directory = temp_directory("forced");
+        std::fs::create_dir_all(&directory).unwrap();
+        // init is always running
+        let other_run = format!("1 {}\n", now());
+        std::fs::write(directory.join(LOCK_FILE), &other_run).unwrap();
+
+        assert!(matches!(lock(&directory, false), Err(BlobdlError::OutputDirectoryLocked(1))));
+        assert!(lock(&directory, true).unwrap().is_none());
+        assert_eq!(std::fs::read_to_string(directory.join(LOCK_FILE)).unwrap(), other_run);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -2,26 +2,34 @@ use blob_dl::parser;
 use blob_dl::dispatcher::dispatch;
 use which::which;
 
+/// The exit code when blob-dl can't do what it was asked, 2 is left to clap's usage errors
+const FAILURE_EXIT_CODE: i32 = 1;
+
 fn main() {
     // Processed command line arguments live here
     let config = parser::parse_config();
 
-    // tested with yt-dlp 2023.07.06
-    if which("yt-dlp").is_ok() {
-        match config {
-            Ok(config) => {
-                // Ask for more input > Generate a command > Execute yt-dlp
-                if let Err(err) = dispatch(&config) {
-                    // Tell the user about the error
-                    err.report();
-                }
+    match config {
+        Ok(config) => {
+            // tested with yt-dlp 2023.07.06
+            let needs_ytdlp = config.subcommand().is_none_or(|subcommand| subcommand.needs_ytdlp());
+
+            if needs_ytdlp && which("yt-dlp").is_err() {
+                // ytdlp is not installed!
+                eprintln!("{}", blob_dl::ui_prompts::YTDLP_NOT_INSTALLED);
+                std::process::exit(FAILURE_EXIT_CODE);
             }
-            Err(err) => {
+
+            // Ask for more input > Generate a command > Execute yt-dlp
+            if let Err(err) = dispatch(&config) {
+                // Tell the user about the error
                 err.report();
+                std::process::exit(FAILURE_EXIT_CODE);
             }
         }
-    } else {
-        // ytdlp is not installed!
-        eprintln!("{}", blob_dl::ui_prompts::YTDLP_NOT_INSTALLED);
+        Err(err) => {
+            err.report();
+            std::process::exit(FAILURE_EXIT_CODE);
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,223 @@
+//! `blob-dl --only-new`: remembers the newest upload date seen for every playlist, so that the next run only downloads
+//! what was uploaded since, without keeping a yt-dlp archive file around
+//!
+//! Every run yt-dlp writes the upload date and id of the videos it finishes to a record file, which is merged
+//! into the state once the downloads are over
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::process::Command;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+
+use crate::assembling::youtube::config::DownloadConfig;
+use crate::state;
+
+/// Where the state of every playlist is kept, in blob-dl's state directory
+const STATE_FILE: &str = "only-new.json";
+
+/// What yt-dlp writes to the record file for every video once it's in its final place
+pub(crate) const RECORD_TEMPLATE: &str = "after_move:%(upload_date)s %(id)s";
+
+/// What the last runs of a playlist downloaded
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct PlaylistState {
+    /// YYYYMMDD, like yt-dlp's upload_date
+    pub(crate) newest_upload_date: String,
+    /// The videos uploaded on newest_upload_date which were already downloaded: --dateafter includes that day
+    pub(crate) seen_on_newest_date: Vec<String>,
+}
+
+/// The --only-new settings of a download
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct OnlyNew {
+    /// None on the first run, when the whole playlist is downloaded
+    pub(crate) previous: Option<PlaylistState>,
+    /// Where yt-dlp writes what it downloaded, see RECORD_TEMPLATE
+    pub(crate) record_file: PathBuf,
+}
+
+impl OnlyNew {
+    /// Loads what was downloaded from `url` in the previous runs
+    pub(crate) fn load(url: &str) -> OnlyNew {
+        OnlyNew { previous: load_states().remove(url), record_file: record_file(url) }
+    }
+}
+
+/// The yt-dlp arguments which skip the videos uploaded before the newest one the previous runs downloaded
+pub(crate) fn filter_args(state: &PlaylistState) -> Vec<String> {
+    vec![String::from("--dateafter"), state.newest_upload_date.clone()]
+}
+
+/// The --match-filters condition which skips the videos already downloaded from the newest day, --dateafter includes it
+///
+/// None if there are none
+pub(crate) fn seen_filter(state: &PlaylistState) -> Option<String> {
+    if state.seen_on_newest_date.is_empty() {
+        return None;
+    }
+
+    let filter: Vec<String> = state.seen_on_newest_date.iter().map(|id| format!("id!={}", id)).collect();
+    Some(filter.join(" & "))
+}
+
+/// Parses the lines yt-dlp wrote with RECORD_TEMPLATE into (upload date, id) pairs, videos without a date are left out
+pub(crate) fn parse_record(contents: &str) -> Vec<(String, String)> {
+    contents.lines()
+        .filter_map(|line| line.trim().split_once(' '))
+        .filter(|(date, id)| date.len() == 8 && date.chars().all(|c| c.is_ascii_digit()) && !id.is_empty())
+        .map(|(date, id)| (date.to_string(), id.to_string()))
+        .collect()
+}
+
+/// The state after downloading `downloaded` ((upload date, id) pairs), None if nothing is known about the playlist yet
+pub(crate) fn updated_state(previous: Option<&PlaylistState>, downloaded: &[(String, String)]) -> Option<PlaylistState> {
+    let newest_download = downloaded.iter().map(|(date, _)| date).max();
+
+    let newest_upload_date = match (previous, newest_download) {
+        (Some(previous), Some(newest)) => previous.newest_upload_date.clone().max(newest.clone()),
+        (Some(previous), None) => previous.newest_upload_date.clone(),
+        (None, Some(newest)) => newest.clone(),
+        (None, None) => return None,
+    };
+
+    // The videos seen before are only still relevant if no newer day was downloaded
+    let mut seen_on_newest_date: Vec<String> = previous
+        .filter(|previous| previous.newest_upload_date == newest_upload_date)
+        .map(|previous| previous.seen_on_newest_date.clone())
+        .unwrap_or_default();
+
+    for (date, id) in downloaded {
+        if *date == newest_upload_date && !seen_on_newest_date.contains(id) {
+            seen_on_newest_date.push(id.clone());
+        }
+    }
+
+    Some(PlaylistState { newest_upload_date, seen_on_newest_date })
+}
+
+/// Merges what every --only-new download recorded into the state, then deletes the record files
+///
+/// The downloads in `failed` (indexes into `downloads`) are left as they were: advancing the newest upload date past
+/// a video which failed would skip it in every later run.
+/// Failures are shown to the user, the next run downloads the videos again in the worst case
+pub(crate) fn record_runs(downloads: &[(Command, DownloadConfig)], failed: &[usize]) {
+    let mut states = load_states();
+    let mut changed = false;
+
+    for (i, (_, download_config)) in downloads.iter().enumerate() {
+        let record_file = match &download_config.only_new {
+            Some(only_new) => &only_new.record_file,
+            None => continue,
+        };
+        let downloaded = match std::fs::read_to_string(record_file) {
+            Ok(contents) => parse_record(&contents),
+            // Nothing was downloaded
+            Err(_) => continue,
+        };
+        let _ = std::fs::remove_file(record_file);
+
+        if failed.contains(&i) {
+            continue;
+        }
+
+        // The state on disk is newer than the one the download started with if it was already recorded once
+        if let Some(state) = updated_state(states.get(download_config.url()), &downloaded) {
+            states.insert(download_config.url().clone(), state);
+            changed = true;
+        }
+    }
+
+    if changed {
+        if let Err(err) = save_states(&states) {
+            eprintln!("{}: {} ({})", "ERROR".red(), err, STATE_FILE);
+        }
+    }
+}
+
+/// Deletes the record files without touching the state, for runs which were interrupted
+pub(crate) fn discard_records(downloads: &[(Command, DownloadConfig)]) {
+    for only_new in downloads.iter().filter_map(|(_, download_config)| download_config.only_new.as_ref()) {
+        let _ = std::fs::remove_file(&only_new.record_file);
+    }
+}
+
+/// Every playlist's state, keyed by url
+fn load_states() -> HashMap<String, PlaylistState> {
+    state::state_dir()
+        .and_then(|dir| std::fs::read_to_string(dir.join(STATE_FILE)).ok())
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+fn save_states(states: &HashMap<String, PlaylistState>) -> std::io::Result<()> {
+    let dir = state::state_dir().ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no state directory"))?;
+    std::fs::write(dir.join(STATE_FILE), serde_json::to_string_pretty(states)?)
+}
+
+/// A temporary file for the records of `url`, unique to this run
+fn record_file(url: &str) -> PathBuf {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    url.hash(&mut hasher);
+    std::env::temp_dir().join(format!("blob-dl-only-new-{}-{:x}.txt", std::process::id(), hasher.finish()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn downloaded(videos: &[(&str, &str)]) -> Vec<(String, String)> {
+        videos.iter().map(|(date, id)| (date.to_string(), id.to_string())).collect()
+    }
+
+    fn state(newest_upload_date: &str, seen_on_newest_date: &[&str]) -> PlaylistState {
+        PlaylistState {
+            newest_upload_date: newest_upload_date.to_string(),
+            seen_on_newest_date: seen_on_newest_date.iter().map(|id| id.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn records_without_a_date_are_left_out() {
+        let record = "20240102 abc\nNA def\n\n 20240103 ghi \n2024010 jkl\n20240104 \n";
+        assert_eq!(parse_record(record), downloaded(&[("20240102", "abc"), ("20240103", "ghi")]));
+    }
+
+    #[test]
+    fn nothing_known_and_nothing_downloaded_has_no_state() {
+        assert_eq!(updated_state(None, &[]), None);
+    }
+
+    #[test]
+    fn first_run_remembers_the_newest_day() {
+        let new = updated_state(None, &downloaded(&[("20240101", "a"), ("20240103", "b"), ("20240103", "c")]));
+        assert_eq!(new, Some(state("20240103", &["b", "c"])));
+    }
+
+    #[test]
+    fn videos_from_the_same_day_are_added_to_the_seen_ones() {
+        let previous = state("20240103", &["b"]);
+        let new = updated_state(Some(&previous), &downloaded(&[("20240103", "c"), ("20240103", "b")]));
+        assert_eq!(new, Some(state("20240103", &["b", "c"])));
+    }
+
+    #[test]
+    fn a_newer_day_forgets_the_seen_videos() {
+        let previous = state("20240103", &["b"]);
+        let new = updated_state(Some(&previous), &downloaded(&[("20240105", "d"), ("20240104", "e")]));
+        assert_eq!(new, Some(state("20240105", &["d"])));
+    }
+
+    #[test]
+    fn older_downloads_dont_move_the_date_back() {
+        let previous = state("20240103", &["b"]);
+        assert_eq!(updated_state(Some(&previous), &downloaded(&[("20240101", "z")])), Some(previous.clone()));
+        assert_eq!(updated_state(Some(&previous), &[]), Some(previous));
+    }
+
+    #[test]
+    fn seen_filter_skips_every_seen_video() {
+        assert_eq!(seen_filter(&state("20240103", &[])), None);
+        assert_eq!(seen_filter(&state("20240103", &["b", "c"])), Some(String::from("id!=b & id!=c")));
+    }
+}
@@ -1,20 +1,98 @@
-use clap::{Arg, Command, ArgMatches, ArgAction};
+use clap::{Arg, Command, ArgMatches, ArgAction, value_parser};
+use clap::builder::FalseyValueParser;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use colored::Colorize;
 
 use crate::ui_prompts::*;
+use crate::assembling::youtube::{ClientOverride, MediaSelection, SubtitleFormat, VideoQualityAndFormatPreferences, ViewCountFilter};
 use crate::error::{BlobdlError, BlobResult};
+use crate::preset::{Preset, PresetLibrary, RunSettings};
 
 pub fn parse_config() -> BlobResult<CliConfig> {
-    let matches = Command::new("blob-dl")
+    let command = cli();
+
+    let matches = command.clone().try_get_matches().unwrap_or_else(|err| {
+        if let Some(variable) = invalid_env_variable(&command, &err) {
+            eprintln!("{} {}", INVALID_ENV_VARIABLE, variable);
+        }
+        err.exit()
+    });
+
+    CliConfig::from(matches)
+}
+
+/// Every argument, subcommand and environment variable blob-dl accepts
+fn cli() -> Command {
+    Command::new("blob-dl")
         .version("1.0.1")
         .author("cioccarellimi@gmail.com")
         .about(SHORT_ABOUT)
         .long_about(LONG_ABOUT)
+        .after_help(ENV_VARS_HELP)
+        .args_conflicts_with_subcommands(true)
+        .subcommand(
+            Command::new("self-check")
+                .about("Check whether a newer version of blob-dl is available")
+        )
+        .subcommand(
+            Command::new("search")
+                .about("Search youtube and download one of the results")
+                .arg(Arg::new("QUERY")
+                    .help("What to search for")
+                    .required(true)
+                    .num_args(1..))
+        )
+        .subcommand(
+            Command::new("urls")
+                .about("Print the direct stream urls of videos instead of downloading them, one per line as <title>\t<url>")
+                .arg(Arg::new("format")
+                    .help("best, smallest or one of yt-dlp's format ids")
+                    .long("format")
+                    .short('f')
+                    .value_name("FORMAT")
+                    .default_value("best"))
+                .arg(Arg::new("quiet")
+                    .help("Only print the urls, without the titles")
+                    .long("quiet")
+                    .short('q')
+                    .action(ArgAction::SetTrue))
+                .arg(Arg::new("URL")
+                    .help("Link(s) to the youtube video(s)/playlist(s)")
+                    .required(true)
+                    .num_args(1..))
+        )
+        .subcommand(
+            Command::new("check")
+                .about("Tell what a url refers to (video, short, playlist, mix, channel or unsupported) without downloading anything")
+                .after_help(CHECK_EXIT_CODES_HELP)
+                .arg(Arg::new("probe")
+                    .help("Also ask yt-dlp whether the url is reachable, and for its title, number of entries and availability")
+                    .long("probe")
+                    .action(ArgAction::SetTrue))
+                .arg(Arg::new("json")
+                    .help("Print the result as JSON")
+                    .long("json")
+                    .action(ArgAction::SetTrue))
+                .arg(Arg::new("URL")
+                    .help("The url to check")
+                    .required(true))
+        )
+        .subcommand(
+            Command::new("stats")
+                .about("Show the totals of the previous runs: videos, sizes, speeds and the most common errors, month by month")
+        )
+        .subcommand(
+            Command::new("clear-ytdlp-cache")
+                .about("Delete yt-dlp's cache (downloaded player code, signatures, ...), useful for CI environments")
+        )
         .arg(
             Arg::new("verbose")
                 .short('v')
                 .long("verbose")
-                .help("Show all the output produced by yt-dlp")
-                .action(ArgAction::SetTrue),
+                .help("Show all the output produced by yt-dlp, use -vv to also see blob-dl's internal decisions and yt-dlp's debug output")
+                .action(ArgAction::Count),
         )
         .arg(
             Arg::new("quiet")
@@ -23,6 +101,42 @@ pub fn parse_config() -> BlobResult<CliConfig> {
                 .help("Silence all output except for the final error summary")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("yes")
+                .short('y')
+                .long("yes")
+                .help("Answer yes to the confirmations, like the one for video-only downloads")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("verbosity")
+                .help("How much output to show, -v, -vv and -q take precedence")
+                .long("verbosity")
+                .env("BLOB_DL_VERBOSITY")
+                .value_name("LEVEL")
+                .value_parser(["quiet", "default", "verbose", "debug"]),
+        )
+        .arg(
+            Arg::new("output-path")
+                .help("Save the downloads in this directory instead of asking where")
+                .long("output-path")
+                .env("BLOB_DL_OUTPUT_PATH")
+                .value_name("DIR"),
+        )
+        .arg(
+            Arg::new("media")
+                .help("Download this kind of file instead of asking")
+                .long("media")
+                .env("BLOB_DL_MEDIA")
+                .value_parser(["video", "audio", "video-only"]),
+        )
+        .arg(
+            Arg::new("quality")
+                .help("Download the best or the smallest format instead of asking")
+                .long("quality")
+                .env("BLOB_DL_QUALITY")
+                .value_parser(["best", "smallest"]),
+        )
         .arg(
             Arg::new("show-command")
                 .help("Print to the console the command generated by blob-dl")
@@ -30,63 +144,921 @@ pub fn parse_config() -> BlobResult<CliConfig> {
                 .short('s')
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("socket-timeout")
+                .env("BLOB_DL_SOCKET_TIMEOUT")
+                .help("Seconds yt-dlp waits for the network before giving up")
+                .long("socket-timeout")
+                .value_name("SECONDS")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            Arg::new("source-address")
+                .help("Bind yt-dlp to the network interface with this IP address, useful on hosts with multiple network interfaces")
+                .long("source-address")
+                .value_name("IP")
+                .value_parser(value_parser!(IpAddr)),
+        )
+        .arg(
+            Arg::new("notify")
+                .help("Send a desktop notification when blob-dl needs your input")
+                .long("notify")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("prompt-timeout")
+                .env("BLOB_DL_PROMPT_TIMEOUT")
+                .help("If nobody answers the re-download prompt within this many seconds, every recoverable error is retried")
+                .long("prompt-timeout")
+                .value_name("SECONDS")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            Arg::new("allow-system-paths")
+                .help("Don't ask for confirmation before writing to system directories or running as root")
+                .long("allow-system-paths")
+                .env("BLOB_DL_ALLOW_SYSTEM_PATHS")
+                .value_parser(FalseyValueParser::new())
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("ascii-names")
+                .help("Only use ASCII characters in file names, without spaces")
+                .long("ascii-names")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("auto-retry-all")
+                .help("Retry every recoverable error once without asking, useful for scripts where nobody can answer the prompt")
+                .long("auto-retry-all")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-cache-dir")
+                .help("Don't let yt-dlp read or write its cache, useful for CI environments")
+                .long("no-cache-dir")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("keep-video")
+                .help("Keep the separate video and audio streams after merging them, so they aren't lost if ffmpeg fails")
+                .long("keep-video")
+                .short('k')
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("start-at")
+                .help("Start downloading playlists from this video (counting from 1), useful to split huge playlists across runs")
+                .long("start-at")
+                .value_name("N")
+                .value_parser(value_parser!(u64).range(1..)),
+        )
+        .arg(
+            Arg::new("stop-at")
+                .help("Stop downloading playlists after this video (counting from 1)")
+                .long("stop-at")
+                .value_name("N")
+                .value_parser(value_parser!(u64).range(1..)),
+        )
+        .arg(
+            Arg::new("keep-duplicates")
+                .help("Download videos which appear more than once in a playlist every time, by default only the first copy is downloaded")
+                .long("keep-duplicates")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("gentle")
+                .help("Download slowly and wait between videos, so youtube is less likely to block your IP address")
+                .long("gentle")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("limit-rate")
+                .env("BLOB_DL_LIMIT_RATE")
+                .help("Maximum download speed, like 500K or 2M (overrides --gentle)")
+                .long("limit-rate")
+                .value_name("RATE"),
+        )
+        .arg(
+            Arg::new("retries")
+                .env("BLOB_DL_RETRIES")
+                .help("How many times yt-dlp retries a failed download (overrides --gentle)")
+                .long("retries")
+                .value_name("N")
+                .value_parser(value_parser!(u32)),
+        )
+        .arg(
+            Arg::new("show-formats-table")
+                .help("Print a table with every format available for a video before asking which one to download (single videos only)")
+                .long("show-formats-table")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("fetch-timeout")
+                .env("BLOB_DL_FETCH_TIMEOUT")
+                .help("Seconds to wait for yt-dlp while it fetches formats, playlists or search results before giving up, the wait restarts whenever it prints something")
+                .long("fetch-timeout")
+                .value_name("SECONDS")
+                .default_value("30")
+                .value_parser(value_parser!(u64).range(1..)),
+        )
+        .arg(
+            Arg::new("compat-options")
+                .help("youtube-dl behaviours to keep, passed on to yt-dlp's --compat-options (comma-separated)")
+                .long("compat-options")
+                .value_name("OPTIONS")
+                .value_delimiter(',')
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("write-checksums")
+                .help("Save the SHA-256 of every downloaded file next to it, check them with sha256sum -c")
+                .long("write-checksums")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("on-extension-conflict")
+                .help("What to do with files named like a video about to be downloaded but with another extension, instead of asking")
+                .long("on-extension-conflict")
+                .value_name("ACTION")
+                .value_parser(["skip", "keep", "replace"]),
+        )
+        .arg(
+            Arg::new("concurrent-playlist-downloads")
+                .env("BLOB_DL_CONCURRENT_PLAYLIST_DOWNLOADS")
+                .help("Split playlists between this many yt-dlp processes which download at the same time")
+                .long("concurrent-playlist-downloads")
+                .value_name("N")
+                .default_value("1")
+                .value_parser(value_parser!(u64).range(1..)),
+        )
+        .arg(
+            Arg::new("simulate")
+                .help("Complete the wizard, then list the videos yt-dlp would download (after every filter) without downloading them")
+                .long("simulate")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("write-thumbnail-only")
+                .help("Only download the thumbnail of every video, as a jpg")
+                .long("write-thumbnail-only")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("only-new")
+                .help("Only download the videos of a playlist uploaded since the last time it was downloaded with --only-new")
+                .long("only-new")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("music")
+                .help("Skip the wizard and download songs: best audio converted to FORMAT (default mp3), tagged, with cover art and a .m3u playlist")
+                .long("music")
+                .value_name("FORMAT")
+                .num_args(0..=1)
+                .default_missing_value("mp3")
+                .value_parser(["mp3", "opus"]),
+        )
+        .arg(
+            Arg::new("write-comments")
+                .help("Save the comments of every video in a .info.json file next to it, this can be slower than the download itself")
+                .long("write-comments")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("write-description")
+                .help("Save the description of every video in a .description file next to it")
+                .long("write-description")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("max-comments")
+                .help("Only fetch this many comments for every video")
+                .long("max-comments")
+                .value_name("N")
+                .requires("write-comments")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            Arg::new("min-views")
+                .help("Only download the videos of a playlist which have more views than this")
+                .long("min-views")
+                .value_name("N")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            Arg::new("max-views")
+                .help("Only download the videos of a playlist which have fewer views than this")
+                .long("max-views")
+                .value_name("N")
+                .value_parser(value_parser!(u64)),
+        )
+        .arg(
+            Arg::new("player-client")
+                .env("BLOB_DL_PLAYER_CLIENT")
+                .help("The youtube client yt-dlp gets videos with (android, ios, web, ...), some videos only fail with the default one")
+                .long("player-client")
+                .value_name("NAME"),
+        )
+        .arg(
+            Arg::new("user-agent")
+                .env("BLOB_DL_USER_AGENT")
+                .help("The user agent yt-dlp sends to youtube")
+                .long("user-agent")
+                .value_name("UA"),
+        )
+        .arg(
+            Arg::new("auto-fallback-client")
+                .help("Retry the videos youtube blocks for yt-dlp's player client with another client, without asking")
+                .long("auto-fallback-client")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("ignore-errors")
+                .help("Keep downloading the rest of a playlist when a video fails, without asking")
+                .long("ignore-errors")
+                .short('i')
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("print-paths")
+                .help("Print the path of every downloaded file on stdout, one per line. Everything else goes to stderr, so the paths can be piped")
+                .long("print-paths")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("pause-file")
+                .env("BLOB_DL_PAUSE_FILE")
+                .help("Wait before starting the next video while FILE exists, the video being downloaded is finished first. Pressing p in the terminal pauses and continues too")
+                .long("pause-file")
+                .value_name("FILE")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("no-stats")
+                .help("Don't record this run in the stats shown by `blob-dl stats`, they never leave this computer")
+                .long("no-stats")
+                .env("BLOB_DL_NO_STATS")
+                .value_parser(FalseyValueParser::new())
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("speed-graph")
+                .help("Show a graph of the download speed next to yt-dlp's progress")
+                .long("speed-graph")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("dashboard")
+                .help("Show the downloads full-screen, with a progress bar for every yt-dlp process. Needs blob-dl built with --features dashboard")
+                .long("dashboard")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("embed-info-json")
+                .help("Embed the video's full metadata (yt-dlp's info json) in the downloaded files, only works with MKV files")
+                .long("embed-info-json")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("exclude-unavailable")
+                .help("Leave out the videos of a playlist which youtube says can't be downloaded without signing in (members-only, age-restricted, ...)")
+                .long("exclude-unavailable")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("force")
+                .help("Download even if another blob-dl seems to be downloading into the same folder")
+                .long("force")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("sub-langs")
+                .help("Download subtitles in these languages, separated by commas (\"all\" for every language)")
+                .long("sub-langs")
+                .value_name("LANGS")
+                .value_delimiter(','),
+        )
+        .arg(
+            Arg::new("convert-subs")
+                .help("Convert the downloaded subtitles to this format")
+                .long("convert-subs")
+                .value_name("FORMAT")
+                .value_parser(["srt", "vtt", "ass", "lrc"]),
+        )
+        .arg(
+            Arg::new("skip-premium-tracks")
+                .help("Leave out the tracks of a playlist which need YouTube Music Premium, when youtube says which ones they are")
+                .long("skip-premium-tracks")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("error-file")
+                .help("Add the url of every video which fails to FILE, one per line, videos are taken out once downloaded (see --url-file)")
+                .long("error-file")
+                .env("BLOB_DL_ERROR_FILE")
+                .value_name("FILE")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("url-file")
+                .help("Also download the urls listed in FILE, one per line, like the --error-file of a previous run. Empty lines and lines starting with # are skipped. The videos listed after the first one are downloaded with the answers given for it")
+                .long("url-file")
+                .value_name("FILE")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("geo-bypass-country")
+                .env("BLOB_DL_GEO_BYPASS_COUNTRY")
+                .help("Pretend to be in another country (two-letter ISO 3166 code, like US), changes which videos and formats are available")
+                .long("geo-bypass-country")
+                .value_name("CODE"),
+        )
+        .arg(
+            Arg::new("index-counts-hidden")
+                .help("Count private and deleted videos in index prefixes like yt-dlp does, instead of numbering videos like youtube shows them")
+                .long("index-counts-hidden")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("url")
+                .help("A url to download, like the ones passed without --url. The --preset right after it only applies to it")
+                .long("url")
+                .value_name("URL")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("preset")
+                .help("Answer the wizard's first questions with a preset: music, video or one defined in presets.json. After a url it only applies to that url, before every url it applies to all of them")
+                .long("preset")
+                .value_name("NAME")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("batch-file")
+                .help("Also download the urls listed in FILE as `url = <url>` lines, each one can be followed by a `preset = <name>` line. A preset before the first url applies to all of them")
+                .long("batch-file")
+                .value_name("FILE")
+                .value_parser(value_parser!(PathBuf)),
+        )
         .arg(Arg::new("URL")
-            .help("Link to the youtube video/playlist that you want to download")
+            .help("Link(s) to the youtube video(s)/playlist(s) that you want to download, each one is configured separately")
+            .num_args(1..)
         )
-        .get_matches();
+}
 
-    CliConfig::from(matches)
+/// The kind of file --media (or a preset) asks for
+pub(crate) fn media_from_name(name: &str) -> Option<MediaSelection> {
+    match name {
+        "video" => Some(MediaSelection::FullVideo),
+        "audio" => Some(MediaSelection::AudioOnly),
+        "video-only" => Some(MediaSelection::VideoOnly),
+        _ => None,
+    }
+}
+
+/// The verbosity --verbosity (or a preset) asks for
+pub(crate) fn verbosity_from_name(name: &str) -> Option<Verbosity> {
+    match name {
+        "quiet" => Some(Verbosity::Quiet),
+        "default" => Some(Verbosity::Default),
+        "verbose" => Some(Verbosity::Verbose),
+        "debug" => Some(Verbosity::Debug),
+        _ => None,
+    }
+}
+
+/// The quality --quality (or a preset) asks for
+pub(crate) fn quality_from_name(name: &str) -> Option<VideoQualityAndFormatPreferences> {
+    match name {
+        "best" => Some(VideoQualityAndFormatPreferences::BestQuality),
+        "smallest" => Some(VideoQualityAndFormatPreferences::SmallestSize),
+        _ => None,
+    }
+}
+
+/// Urls along with the name of the preset each one was given, and the name of the one given for the whole run
+#[derive(Debug, Default, PartialEq)]
+struct UrlPresets {
+    run_preset: Option<String>,
+    urls: Vec<(String, Option<String>)>,
+}
+
+/// What a preset which doesn't follow a url applies to, for DuplicatePreset
+const WHOLE_RUN: &str = "the whole run";
+
+/// Pairs every --preset with the url right before it on the command line, a --preset before every url is the run's
+///
+/// `urls` (passed with --url or not) and `presets` come with their position among the arguments, urls sorted by it
+fn group_presets(urls: &[(usize, String)], presets: &[(usize, String)]) -> BlobResult<UrlPresets> {
+    let mut grouped = UrlPresets { run_preset: None, urls: urls.iter().map(|(_, url)| (url.clone(), None)).collect() };
+
+    for (preset_position, name) in presets {
+        let (slot, owner) = match urls.iter().rposition(|(url_position, _)| url_position < preset_position) {
+            Some(i) => (&mut grouped.urls[i].1, urls[i].1.as_str()),
+            None => (&mut grouped.run_preset, WHOLE_RUN),
+        };
+        if slot.replace(name.clone()).is_some() {
+            return Err(BlobdlError::DuplicatePreset(owner.to_string()));
+        }
+    }
+
+    Ok(grouped)
+}
+
+/// The urls listed in the contents of a --batch-file, every one with the preset on the line after it, if any
+///
+/// Empty lines and lines starting with # are skipped, a preset before the first url is the run's
+fn batch_file_entries(contents: &str) -> BlobResult<UrlPresets> {
+    let mut entries = UrlPresets::default();
+
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // Line numbers count from 1, like in text editors
+        let (key, value) = line.split_once('=')
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .filter(|(_, value)| !value.is_empty())
+            .ok_or(BlobdlError::InvalidBatchFile(i + 1))?;
+
+        match key {
+            "url" => entries.urls.push((value.to_string(), None)),
+            "preset" => {
+                let (slot, owner) = match entries.urls.last_mut() {
+                    Some((url, preset)) => (preset, url.clone()),
+                    None => (&mut entries.run_preset, String::from(WHOLE_RUN)),
+                };
+                if slot.replace(value.to_string()).is_some() {
+                    return Err(BlobdlError::DuplicatePreset(owner));
+                }
+            }
+            _ => return Err(BlobdlError::InvalidBatchFile(i + 1)),
+        }
+    }
+
+    Ok(entries)
+}
+
+/// The values of the argument `id` along with their position among the arguments
+fn values_with_positions(matches: &ArgMatches, id: &str) -> Vec<(usize, String)> {
+    match (matches.indices_of(id), matches.get_many::<String>(id)) {
+        (Some(positions), Some(values)) => positions.zip(values.cloned()).collect(),
+        _ => vec![],
+    }
+}
+
+/// The urls listed in the contents of a --url-file, without empty lines and # comments
+fn url_file_lines(contents: &str) -> Vec<String> {
+    contents.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+/// The environment variable the invalid value in `err` came from, None if it was passed on the command line
+fn invalid_env_variable(command: &Command, err: &clap::Error) -> Option<String> {
+    if err.kind() != clap::error::ErrorKind::InvalidValue {
+        return None;
+    }
+    let invalid_arg = err.get(clap::error::ContextKind::InvalidArg)?.to_string();
+
+    // A value on the command line would have replaced the one from the environment
+    let passed_args: Vec<String> = std::env::args().collect();
+
+    command.get_arguments()
+        .filter(|arg| arg.get_long().is_some_and(|long| invalid_arg.starts_with(&format!("--{} ", long))))
+        .filter(|arg| !passed_args.iter().any(|passed| arg.get_long().is_some_and(|long| passed.starts_with(&format!("--{}", long)))))
+        .find_map(|arg| arg.get_env().filter(|variable| std::env::var_os(variable).is_some()))
+        .map(|variable| variable.to_string_lossy().to_string())
+}
+
+/// Tasks blob-dl can do other than downloading
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlobSubcommand {
+    /// Look for a newer version of blob-dl
+    SelfCheck,
+    /// Delete yt-dlp's cache
+    ClearYtdlpCache,
+    /// Search youtube for this query and download one of the results
+    Search(String),
+    /// Print the direct stream urls of these videos/playlists in a format ("best", "smallest" or a format id),
+    /// without the titles if quiet is set
+    Urls { urls: Vec<String>, format: String, quiet: bool },
+    /// Tell what this url refers to, asking yt-dlp whether it's reachable if probe is set, as JSON if json is set
+    Check { url: String, probe: bool, json: bool },
+    /// Show the totals of the recorded runs
+    Stats,
+}
+
+impl BlobSubcommand {
+    /// Whether the subcommand runs yt-dlp
+    pub fn needs_ytdlp(&self) -> bool {
+        match self {
+            BlobSubcommand::SelfCheck => false,
+            BlobSubcommand::ClearYtdlpCache => true,
+            BlobSubcommand::Search(_) => true,
+            BlobSubcommand::Urls { .. } => true,
+            // Classifying the url doesn't need yt-dlp
+            BlobSubcommand::Check { probe, .. } => *probe,
+            BlobSubcommand::Stats => false,
+        }
+    }
 }
 
-/// The 3 possible verbosity options for this program
-#[derive(Debug)]
+/// The 4 possible verbosity options for this program
+///
+/// -q is Quiet, no flag is Default, -v is Verbose and -vv is Debug
+#[derive(Debug, Clone, PartialEq)]
 pub enum Verbosity {
+    Debug,
     Verbose,
     Default,
     Quiet,
 }
 
+impl Verbosity {
+    /// Explains one of blob-dl's internal decisions, only shown in Debug verbosity
+    pub(crate) fn debug(&self, message: &str) {
+        if *self == Verbosity::Debug {
+            eprintln!("{} {}", "[blob-dl debug]".purple(), message);
+        }
+    }
+}
+
+/// What to do when the output folder already has a video with a different extension (song.mp4 when downloading song.mp3)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExtensionConflictPolicy {
+    /// Don't download the video again
+    Skip,
+    /// Download it, both files are kept
+    KeepBoth,
+    /// Download it, the old file is deleted once the new one is ready
+    Replace,
+}
+
 /// Holds all the information that can be fetched as a command line argument
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CliConfig {
-    // Refs to this String are stored in other Config objects
-    url: String,
+    // Every url is downloaded with its own configuration, one after the other
+    urls: Vec<String>,
+    // The preset of every url in urls (its own or the run's), None if it has none
+    url_presets: Vec<Option<Preset>>,
+    // How many of the urls, at the end of the list, were read from --url-file
+    url_file_urls: usize,
+    // When this is Some blob-dl doesn't download anything, so urls can be empty
+    subcommand: Option<BlobSubcommand>,
     verbosity: Verbosity,
     // Whether to print to the console the final command which is the run by yt-dlp
     show_command: bool,
+    socket_timeout: Option<u64>,
+    source_address: Option<IpAddr>,
+    // Whether to send a desktop notification when user input is needed mid-run
+    notify: bool,
+    // How long to wait for the user to answer mid-run prompts
+    prompt_timeout: Option<Duration>,
+    allow_system_paths: bool,
+    ascii_names: bool,
+    // Whether to retry errors without asking the user
+    auto_retry_all: bool,
+    // The settings given by flags and by the config file, verbosity, notify and auto_retry_all are resolved from them
+    flag_settings: RunSettings,
+    file_settings: RunSettings,
+    no_cache: bool,
+    keep_video: bool,
+    // Which videos of a playlist to download, counting from 1
+    start_at: Option<usize>,
+    stop_at: Option<usize>,
+    keep_duplicates: bool,
+    // Whether to use conservative network settings
+    gentle: bool,
+    limit_rate: Option<String>,
+    retries: Option<u32>,
+    show_formats_table: bool,
+    // How long to wait for yt-dlp to fetch the available formats
+    fetch_timeout: Duration,
+    // Passed on to yt-dlp's --compat-options
+    compat_options: Vec<String>,
+    // Whether to write a .sha256 file next to every downloaded file
+    write_checksums: bool,
+    // When None the user is asked
+    on_extension_conflict: Option<ExtensionConflictPolicy>,
+    // How many yt-dlp processes download a playlist at the same time
+    concurrent_playlist_downloads: usize,
+    // Whether to only list what would be downloaded
+    simulate: bool,
+    // Whether to skip the videos and only keep their thumbnails
+    write_thumbnail_only: bool,
+    // Whether to skip the videos of a playlist which are older than the last run's
+    only_new: bool,
+    // The audio format of --music, None if the wizard is used
+    music: Option<String>,
+    // Whether to save the comments of every video
+    write_comments: bool,
+    // How many comments are fetched for every video, None for all of them
+    max_comments: Option<u64>,
+    // Whether to save the description of every video
+    write_description: bool,
+    // Whether index prefixes count the hidden videos of a playlist
+    index_counts_hidden: bool,
+    // The view counts the videos of a playlist have to be within, None if neither bound was passed
+    view_count_filter: Option<ViewCountFilter>,
+    // The player client and user agent yt-dlp uses, for fetching formats too
+    client_override: ClientOverride,
+    // Whether videos blocked for the player client are retried with another one without asking
+    auto_fallback_client: bool,
+    // Whether the path of every downloaded file is printed on stdout
+    print_paths: bool,
+    // Whether yt-dlp carries on after a video fails
+    ignore_errors: bool,
+    // Downloads wait between videos while this file exists
+    pause_file: Option<PathBuf>,
+    // The country yt-dlp pretends to be in, for fetching formats too
+    geo_bypass_country: Option<String>,
+    // Whether the confirmations are answered yes without asking
+    assume_yes: bool,
+    // Whether the run is left out of `blob-dl stats`
+    no_stats: bool,
+    // Whether a graph of the download speed is shown
+    speed_graph: bool,
+    // Whether the downloads are shown on the dashboard
+    dashboard: bool,
+    // Whether the info json is embedded in the downloaded files
+    embed_info_json: bool,
+    // Whether the videos of a playlist predicted to fail are left out without asking
+    exclude_unavailable: bool,
+    // Whether the lock files of other runs are ignored
+    force: bool,
+    // The languages subtitles are downloaded in, empty for none
+    sub_langs: Vec<String>,
+    // The format subtitles are converted to
+    convert_subs: Option<SubtitleFormat>,
+    // Whether the tracks which need YouTube Music Premium are left out
+    skip_premium_tracks: bool,
+    // Where the urls of the videos which fail are appended
+    error_file: Option<PathBuf>,
+    // Answers to the wizard's first questions, which are then skipped
+    output_path: Option<String>,
+    media: Option<MediaSelection>,
+    quality: Option<VideoQualityAndFormatPreferences>,
 }
 
 impl CliConfig {
-    /// Constructs a CliConfig object based on Clap's output
+    /// Constructs a CliConfig object based on Clap's output, with the built-in presets and the user's, and the user's config file
     pub fn from(matches: ArgMatches) -> BlobResult<CliConfig> {
+        CliConfig::with_presets(matches, &PresetLibrary::load()?, RunSettings::load()?)
+    }
+
+    /// Constructs a CliConfig object based on Clap's output, the presets the urls are given are looked up in `presets`.
+    /// The settings no flag or preset gives are taken from `file_settings`
+    pub(crate) fn with_presets(matches: ArgMatches, presets: &PresetLibrary, file_settings: RunSettings) -> BlobResult<CliConfig> {
 
-        let url = match matches.get_one::<String>("URL") {
-            Some(url) => url.clone(),
-            None => return Err(BlobdlError::MissingArgument),
+        let subcommand = match matches.subcommand() {
+            Some(("self-check", _)) => Some(BlobSubcommand::SelfCheck),
+            Some(("clear-ytdlp-cache", _)) => Some(BlobSubcommand::ClearYtdlpCache),
+            Some(("stats", _)) => Some(BlobSubcommand::Stats),
+            Some(("search", search_matches)) => {
+                // The query can be written without quotes
+                let words: Vec<String> = search_matches.get_many::<String>("QUERY").ok_or(BlobdlError::MissingArgument)?.cloned().collect();
+                Some(BlobSubcommand::Search(words.join(" ")))
+            }
+            Some(("urls", urls_matches)) => Some(BlobSubcommand::Urls {
+                urls: urls_matches.get_many::<String>("URL").ok_or(BlobdlError::MissingArgument)?.cloned().collect(),
+                // The argument has a default value, unwrap_or only keeps this from panicking
+                format: urls_matches.get_one::<String>("format").cloned().unwrap_or_else(|| String::from("best")),
+                quiet: urls_matches.get_flag("quiet"),
+            }),
+            Some(("check", check_matches)) => Some(BlobSubcommand::Check {
+                url: check_matches.get_one::<String>("URL").ok_or(BlobdlError::MissingArgument)?.clone(),
+                probe: check_matches.get_flag("probe"),
+                json: check_matches.get_flag("json"),
+            }),
+            _ => None,
         };
 
+        // Urls passed with and without --url are downloaded in the order they were passed in
+        let mut passed_urls = values_with_positions(&matches, "URL");
+        passed_urls.extend(values_with_positions(&matches, "url"));
+        passed_urls.sort_unstable_by_key(|(position, _)| *position);
+
+        let mut grouped = group_presets(&passed_urls, &values_with_positions(&matches, "preset"))?;
+        if let Some(batch_file) = matches.get_one::<PathBuf>("batch-file") {
+            let entries = batch_file_entries(&std::fs::read_to_string(batch_file)?)?;
+            // The preset passed on the command line wins
+            grouped.run_preset = grouped.run_preset.or(entries.run_preset);
+            grouped.urls.extend(entries.urls);
+        }
+        let mut url_file_urls = 0;
+        if let Some(url_file) = matches.get_one::<PathBuf>("url-file") {
+            let listed = url_file_lines(&std::fs::read_to_string(url_file)?);
+            url_file_urls = listed.len();
+            grouped.urls.extend(listed.into_iter().map(|url| (url, None)));
+        }
+        if grouped.urls.is_empty() && subcommand.is_none() {
+            return Err(BlobdlError::MissingArgument);
+        }
+
+        // Unknown presets are reported even if no url ends up using them
+        let run_preset = grouped.run_preset.as_deref().map(|name| presets.get(name)).transpose()?;
+        let mut urls = Vec::with_capacity(grouped.urls.len());
+        let mut url_presets = Vec::with_capacity(grouped.urls.len());
+        for (url, preset) in grouped.urls {
+            let preset = match preset {
+                Some(name) => Some(presets.get(&name)?),
+                None => run_preset,
+            };
+            urls.push(url);
+            url_presets.push(preset.cloned());
+        }
+
+        // None when no flag sets it, a preset or the config file can then
         let verbosity = {
             if matches.get_flag("quiet") {
-                Verbosity::Quiet
+                Some(Verbosity::Quiet)
+            }
+            else if matches.get_count("verbose") > 1 {
+                Some(Verbosity::Debug)
             }
-            else if matches.get_flag("verbose") {
-                Verbosity::Verbose
+            else if matches.get_count("verbose") == 1 {
+                Some(Verbosity::Verbose)
             }
             else {
-                Verbosity::Default
+                matches.get_one::<String>("verbosity").and_then(|verbosity| verbosity_from_name(verbosity))
             }
         };
+        let output_path = matches.get_one::<String>("output-path").cloned();
+        let error_file = matches.get_one::<PathBuf>("error-file").cloned();
+        let skip_premium_tracks = matches.get_flag("skip-premium-tracks");
+        let force = matches.get_flag("force");
+        let exclude_unavailable = matches.get_flag("exclude-unavailable");
+        let embed_info_json = matches.get_flag("embed-info-json");
+        let speed_graph = matches.get_flag("speed-graph");
+        let dashboard = matches.get_flag("dashboard");
+        let no_stats = matches.get_flag("no-stats");
+        let assume_yes = matches.get_flag("yes");
+        let sub_langs = matches.get_many::<String>("sub-langs")
+            .map(|languages| languages.map(|language| language.trim().to_string()).filter(|language| !language.is_empty()).collect())
+            .unwrap_or_default();
+        let convert_subs = matches.get_one::<String>("convert-subs").and_then(|format| SubtitleFormat::from_name(format));
+        // clap only accepts the names these understand
+        let media = matches.get_one::<String>("media").and_then(|media| media_from_name(media));
+        let quality = matches.get_one::<String>("quality").and_then(|quality| quality_from_name(quality));
         let show_command = matches.get_flag("show-command");
+        let socket_timeout = matches.get_one::<u64>("socket-timeout").copied();
+        let source_address = matches.get_one::<IpAddr>("source-address").copied();
+        let notify = matches.get_flag("notify").then_some(true);
+        let allow_system_paths = matches.get_flag("allow-system-paths");
+        let ascii_names = matches.get_flag("ascii-names");
+        let auto_retry_all = matches.get_flag("auto-retry-all").then_some(true);
+        let no_cache = matches.get_flag("no-cache-dir");
+        let keep_video = matches.get_flag("keep-video");
+        let start_at = matches.get_one::<u64>("start-at").map(|n| *n as usize);
+        let stop_at = matches.get_one::<u64>("stop-at").map(|n| *n as usize);
+        let keep_duplicates = matches.get_flag("keep-duplicates");
+        let gentle = matches.get_flag("gentle");
+        let limit_rate = matches.get_one::<String>("limit-rate").cloned();
+        let retries = matches.get_one::<u32>("retries").copied();
+        let show_formats_table = matches.get_flag("show-formats-table");
+        // The argument has a default value, unwrap_or only keeps this from panicking
+        let fetch_timeout = Duration::from_secs(matches.get_one::<u64>("fetch-timeout").copied().unwrap_or(30));
+        let write_checksums = matches.get_flag("write-checksums");
+        let simulate = matches.get_flag("simulate");
+        let write_thumbnail_only = matches.get_flag("write-thumbnail-only");
+        let only_new = matches.get_flag("only-new");
+        let music = matches.get_one::<String>("music").cloned();
+        let write_comments = matches.get_flag("write-comments");
+        let max_comments = matches.get_one::<u64>("max-comments").copied();
+        let write_description = matches.get_flag("write-description");
+        let index_counts_hidden = matches.get_flag("index-counts-hidden");
+        let client_override = ClientOverride {
+            player_client: matches.get_one::<String>("player-client").cloned(),
+            user_agent: matches.get_one::<String>("user-agent").cloned(),
+        };
+        let auto_fallback_client = matches.get_flag("auto-fallback-client");
+        let print_paths = matches.get_flag("print-paths");
+        let ignore_errors = matches.get_flag("ignore-errors");
+        let pause_file = matches.get_one::<PathBuf>("pause-file").cloned();
+        let geo_bypass_country = matches.get_one::<String>("geo-bypass-country").map(|country| country.to_uppercase());
+        let view_count_filter = match (matches.get_one::<u64>("min-views").copied(), matches.get_one::<u64>("max-views").copied()) {
+            (None, None) => None,
+            (min, max) => Some(ViewCountFilter { min, max }),
+        };
+        // The argument has a default value, unwrap_or only keeps this from panicking
+        let concurrent_playlist_downloads = matches.get_one::<u64>("concurrent-playlist-downloads").copied().unwrap_or(1) as usize;
+        let on_extension_conflict = matches.get_one::<String>("on-extension-conflict").map(|action| match action.as_str() {
+            "skip" => ExtensionConflictPolicy::Skip,
+            "replace" => ExtensionConflictPolicy::Replace,
+            _ => ExtensionConflictPolicy::KeepBoth,
+        });
+        let compat_options = matches.get_many::<String>("compat-options")
+            .map(|options| options.filter(|option| !option.is_empty()).cloned().collect())
+            .unwrap_or_default();
+
+        if let (Some(start), Some(stop)) = (start_at, stop_at) {
+            if stop < start {
+                return Err(BlobdlError::InvalidPlaylistRange);
+            }
+        }
+        let prompt_timeout = matches.get_one::<u64>("prompt-timeout").map(|seconds| Duration::from_secs(*seconds));
+        let flag_settings = RunSettings { verbosity, notify, auto_retry: auto_retry_all };
 
-        Ok(CliConfig {
-            url,
-            verbosity,
+        let mut config = CliConfig {
+            urls,
+            url_presets,
+            url_file_urls,
+            subcommand,
+            // Resolved below
+            verbosity: Verbosity::Default,
             show_command,
-        })
+            socket_timeout,
+            source_address,
+            notify: false,
+            prompt_timeout,
+            allow_system_paths,
+            ascii_names,
+            auto_retry_all: false,
+            flag_settings,
+            file_settings,
+            no_cache,
+            keep_video,
+            start_at,
+            stop_at,
+            keep_duplicates,
+            gentle,
+            limit_rate,
+            retries,
+            show_formats_table,
+            fetch_timeout,
+            compat_options,
+            write_checksums,
+            on_extension_conflict,
+            concurrent_playlist_downloads,
+            simulate,
+            write_thumbnail_only,
+            only_new,
+            music,
+            write_comments,
+            max_comments,
+            write_description,
+            index_counts_hidden,
+            view_count_filter,
+            client_override,
+            auto_fallback_client,
+            print_paths,
+            ignore_errors,
+            pause_file,
+            geo_bypass_country,
+            assume_yes,
+            no_stats,
+            speed_graph,
+            dashboard,
+            embed_info_json,
+            exclude_unavailable,
+            force,
+            sub_langs,
+            convert_subs,
+            skip_premium_tracks,
+            error_file,
+            output_path,
+            media,
+            quality,
+        };
+        // The downloads run with the run's preset, the one of a url only applies while it's configured
+        config.resolve_settings(run_preset);
+        Ok(config)
+    }
+
+    /// Sets verbosity, notify and auto_retry_all from the flags, `preset` and the config file, see preset::resolve_setting
+    fn resolve_settings(&mut self, preset: Option<&Preset>) {
+        let settings = RunSettings::resolve(&self.flag_settings, preset.map(|preset| &preset.settings), &self.file_settings);
+        self.verbosity = settings.verbosity.unwrap_or(Verbosity::Default);
+        self.notify = settings.notify.unwrap_or(false);
+        self.auto_retry_all = settings.auto_retry.unwrap_or(false);
     }
 
-    pub fn url(&self) -> &String {
-        &self.url
+    pub fn urls(&self) -> &Vec<String> {
+        &self.urls
+    }
+    /// The configuration the url at `index` in urls is downloaded with: its preset answers the questions no flag answers
+    pub fn for_url(&self, index: usize) -> CliConfig {
+        let mut config = self.clone();
+        if let Some(Some(preset)) = self.url_presets.get(index) {
+            config.media = config.media.or_else(|| preset.media.clone());
+            config.quality = config.quality.or_else(|| preset.quality.clone());
+            config.output_path = config.output_path.or_else(|| preset.output_path.clone());
+            config.resolve_settings(Some(preset));
+        }
+        config
+    }
+    /// Whether the url at `index` in urls was read from --url-file
+    pub fn from_url_file(&self, index: usize) -> bool {
+        index >= self.urls.len() - self.url_file_urls
+    }
+    pub fn subcommand(&self) -> Option<&BlobSubcommand> {
+        self.subcommand.as_ref()
     }
     pub fn verbosity(&self) -> &Verbosity {
         &self.verbosity
@@ -94,4 +1066,446 @@ impl CliConfig {
     pub fn show_command(&self) -> bool {
         self.show_command
     }
-}
\ No newline at end of file
+    pub fn socket_timeout(&self) -> Option<u64> {
+        self.socket_timeout
+    }
+    pub fn source_address(&self) -> Option<IpAddr> {
+        self.source_address
+    }
+    pub fn notify(&self) -> bool {
+        self.notify
+    }
+    pub fn prompt_timeout(&self) -> Option<Duration> {
+        self.prompt_timeout
+    }
+    pub fn allow_system_paths(&self) -> bool {
+        self.allow_system_paths
+    }
+    pub fn ascii_names(&self) -> bool {
+        self.ascii_names
+    }
+    pub fn auto_retry_all(&self) -> bool {
+        self.auto_retry_all
+    }
+    pub fn no_cache(&self) -> bool {
+        self.no_cache
+    }
+    pub fn keep_video(&self) -> bool {
+        self.keep_video
+    }
+    pub fn start_at(&self) -> Option<usize> {
+        self.start_at
+    }
+    pub fn stop_at(&self) -> Option<usize> {
+        self.stop_at
+    }
+    pub fn keep_duplicates(&self) -> bool {
+        self.keep_duplicates
+    }
+    pub fn gentle(&self) -> bool {
+        self.gentle
+    }
+    pub fn limit_rate(&self) -> Option<&String> {
+        self.limit_rate.as_ref()
+    }
+    pub fn retries(&self) -> Option<u32> {
+        self.retries
+    }
+    pub fn show_formats_table(&self) -> bool {
+        self.show_formats_table
+    }
+    pub fn fetch_timeout(&self) -> Duration {
+        self.fetch_timeout
+    }
+    pub fn compat_options(&self) -> &Vec<String> {
+        &self.compat_options
+    }
+    pub fn write_checksums(&self) -> bool {
+        self.write_checksums
+    }
+    pub fn on_extension_conflict(&self) -> Option<ExtensionConflictPolicy> {
+        self.on_extension_conflict
+    }
+    pub fn concurrent_playlist_downloads(&self) -> usize {
+        self.concurrent_playlist_downloads
+    }
+    pub fn simulate(&self) -> bool {
+        self.simulate
+    }
+    pub fn write_thumbnail_only(&self) -> bool {
+        self.write_thumbnail_only
+    }
+    pub fn only_new(&self) -> bool {
+        self.only_new
+    }
+    pub fn music(&self) -> Option<&String> {
+        self.music.as_ref()
+    }
+    pub fn write_comments(&self) -> bool {
+        self.write_comments
+    }
+    pub fn write_description(&self) -> bool {
+        self.write_description
+    }
+    pub fn max_comments(&self) -> Option<u64> {
+        self.max_comments
+    }
+    pub fn index_counts_hidden(&self) -> bool {
+        self.index_counts_hidden
+    }
+    pub(crate) fn view_count_filter(&self) -> Option<ViewCountFilter> {
+        self.view_count_filter
+    }
+    pub(crate) fn client_override(&self) -> &ClientOverride {
+        &self.client_override
+    }
+    pub fn auto_fallback_client(&self) -> bool {
+        self.auto_fallback_client
+    }
+    pub fn print_paths(&self) -> bool {
+        self.print_paths
+    }
+    pub fn ignore_errors(&self) -> bool {
+        self.ignore_errors
+    }
+    pub fn pause_file(&self) -> Option<&PathBuf> {
+        self.pause_file.as_ref()
+    }
+    pub fn geo_bypass_country(&self) -> Option<&String> {
+        self.geo_bypass_country.as_ref()
+    }
+    pub fn assume_yes(&self) -> bool {
+        self.assume_yes
+    }
+    pub fn no_stats(&self) -> bool {
+        self.no_stats
+    }
+    pub fn speed_graph(&self) -> bool {
+        self.speed_graph
+    }
+    pub fn dashboard(&self) -> bool {
+        self.dashboard
+    }
+    pub fn embed_info_json(&self) -> bool {
+        self.embed_info_json
+    }
+    pub fn exclude_unavailable(&self) -> bool {
+        self.exclude_unavailable
+    }
+    pub fn force(&self) -> bool {
+        self.force
+    }
+    pub fn sub_langs(&self) -> &[String] {
+        &self.sub_langs
+    }
+    pub(crate) fn convert_subs(&self) -> Option<SubtitleFormat> {
+        self.convert_subs
+    }
+    pub fn skip_premium_tracks(&self) -> bool {
+        self.skip_premium_tracks
+    }
+    pub fn error_file(&self) -> Option<&PathBuf> {
+        self.error_file.as_ref()
+    }
+    pub fn output_path(&self) -> Option<&String> {
+        self.output_path.as_ref()
+    }
+    pub(crate) fn media(&self) -> Option<&MediaSelection> {
+        self.media.as_ref()
+    }
+    pub(crate) fn quality(&self) -> Option<&VideoQualityAndFormatPreferences> {
+        self.quality.as_ref()
+    }
+}
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::RwLock;
+
+    /// clap reads the variables of `.env()` arguments while parsing: config_with_env holds this for writing while it
+    /// changes them, everything else which parses holds it for reading
+    static ENVIRONMENT: RwLock<()> = RwLock::new(());
+
+    /// What clap makes of these arguments, the environment is read too
+    fn matches(args: &[&str]) -> Result<ArgMatches, clap::Error> {
+        let _environment = ENVIRONMENT.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        cli().try_get_matches_from(std::iter::once("blob-dl").chain(args.iter().copied()))
+    }
+
+    /// The configuration blob-dl gets from these arguments with the built-in presets, the environment is read too
+    pub(crate) fn config(args: &[&str]) -> CliConfig {
+        try_config(args).unwrap()
+    }
+
+    fn try_config(args: &[&str]) -> BlobResult<CliConfig> {
+        CliConfig::with_presets(matches(args).unwrap(), &PresetLibrary::builtin(), RunSettings::default())
+    }
+
+    /// (url, preset name) pairs, for comparing with UrlPresets
+    fn urls_with(pairs: &[(&str, Option<&str>)]) -> Vec<(String, Option<String>)> {
+        pairs.iter().map(|(url, preset)| (url.to_string(), preset.map(String::from))).collect()
+    }
+
+    fn positioned(values: &[(usize, &str)]) -> Vec<(usize, String)> {
+        values.iter().map(|(position, value)| (*position, value.to_string())).collect()
+    }
+
+    /// Like config, with `variable` set to `value` (unset if None) while the arguments are parsed
+    ///
+    /// No other test parses meanwhile, and the environment is restored afterwards
+    fn config_with_env(variable: &str, value: Option<&str>, args: &[&str]) -> CliConfig {
+        let _environment = ENVIRONMENT.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let previous = std::env::var_os(variable);
+        match value {
+            Some(value) => std::env::set_var(variable, value),
+            None => std::env::remove_var(variable),
+        }
+        let matches = cli().try_get_matches_from(std::iter::once("blob-dl").chain(args.iter().copied()));
+        match previous {
+            Some(previous) => std::env::set_var(variable, previous),
+            None => std::env::remove_var(variable),
+        }
+
+        CliConfig::with_presets(matches.unwrap(), &PresetLibrary::builtin(), RunSettings::default()).unwrap()
+    }
+
+    #[test]
+    fn flags_take_precedence_over_the_environment() {
+        // (BLOB_DL_OUTPUT_PATH, arguments, output path)
+        let output_paths: [(Option<&str>, &[&str], Option<&str>); 4] = [
+            (None, &[], None),
+            (Some("/from/env"), &[], Some("/from/env")),
+            (None, &["--output-path", "/from/flag"], Some("/from/flag")),
+            (Some("/from/env"), &["--output-path", "/from/flag"], Some("/from/flag")),
+        ];
+        for (env, args, expected) in output_paths {
+            let args: Vec<&str> = args.iter().copied().chain(["https://youtu.be/abc"]).collect();
+            let config = config_with_env("BLOB_DL_OUTPUT_PATH", env, &args);
+            assert_eq!(config.output_path().map(String::as_str), expected, "{:?} {:?}", env, args);
+        }
+
+        // (BLOB_DL_ALLOW_SYSTEM_PATHS, arguments, allowed)
+        let allow_system_paths: [(Option<&str>, &[&str], bool); 7] = [
+            (None, &[], false),
+            (Some("1"), &[], true),
+            (Some("true"), &[], true),
+            (Some("0"), &[], false),
+            (Some("false"), &[], false),
+            (None, &["--allow-system-paths"], true),
+            (Some("0"), &["--allow-system-paths"], true),
+        ];
+        for (env, args, expected) in allow_system_paths {
+            let args: Vec<&str> = args.iter().copied().chain(["https://youtu.be/abc"]).collect();
+            let config = config_with_env("BLOB_DL_ALLOW_SYSTEM_PATHS", env, &args);
+            assert_eq!(config.allow_system_paths(), expected, "{:?} {:?}", env, args);
+        }
+    }
+
+    #[test]
+    fn fetch_timeout_is_at_least_a_second() {
+        assert_eq!(config(&["--fetch-timeout", "5", "https://youtu.be/abc"]).fetch_timeout(), Duration::from_secs(5));
+
+        // 0 would give up before yt-dlp even started
+        let zero = matches(&["--fetch-timeout", "0", "https://youtu.be/abc"]);
+        assert!(zero.is_err());
+    }
+
+    #[test]
+    fn verbosity_flags_choose_the_verbosity() {
+        let cases: [(&[&str], Verbosity); 6] = [
+            (&["-q"], Verbosity::Quiet),
+            (&[], Verbosity::Default),
+            (&["-v"], Verbosity::Verbose),
+            (&["-vv"], Verbosity::Debug),
+            (&["-v", "-v", "-v"], Verbosity::Debug),
+            // -q wins over -v
+            (&["-q", "-v"], Verbosity::Quiet),
+        ];
+        for (flags, verbosity) in cases {
+            let args: Vec<&str> = flags.iter().copied().chain(["https://youtu.be/a"]).collect();
+            assert_eq!(config(&args).verbosity(), &verbosity, "{:?}", flags);
+        }
+    }
+
+    #[test]
+    fn source_addresses_are_ipv4_or_ipv6() {
+        let source_address = |address: &str| config(&["--source-address", address, "https://youtu.be/a"]).source_address();
+        assert_eq!(source_address("192.168.1.20"), Some(IpAddr::from([192, 168, 1, 20])));
+        assert_eq!(source_address("::1"), Some(IpAddr::from(std::net::Ipv6Addr::LOCALHOST)));
+        assert_eq!(source_address("2001:db8::5"), Some("2001:db8::5".parse().unwrap()));
+        assert_eq!(config(&["https://youtu.be/a"]).source_address(), None);
+
+        for invalid in ["192.168.1.300", "192.168.1", "eth0", "2001:db8::g", "[::1]", "192.168.1.20:80"] {
+            let parsed = matches(&["--source-address", invalid, "https://youtu.be/a"]);
+            assert!(parsed.is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn presets_apply_to_the_url_before_them() {
+        // blob-dl --url a --preset music b --url c --preset video
+        let urls = positioned(&[(2, "a"), (5, "b"), (7, "c")]);
+        let grouped = group_presets(&urls, &positioned(&[(4, "music"), (9, "video")])).unwrap();
+        assert_eq!(grouped.run_preset, None);
+        assert_eq!(grouped.urls, urls_with(&[("a", Some("music")), ("b", None), ("c", Some("video"))]));
+
+        // blob-dl --preset music a b
+        let grouped = group_presets(&positioned(&[(3, "a"), (4, "b")]), &positioned(&[(2, "music")])).unwrap();
+        assert_eq!(grouped.run_preset.as_deref(), Some("music"));
+        assert_eq!(grouped.urls, urls_with(&[("a", None), ("b", None)]));
+
+        // Nothing to group
+        assert_eq!(group_presets(&[], &[]).unwrap(), UrlPresets::default());
+    }
+
+    #[test]
+    fn every_url_takes_a_single_preset() {
+        // blob-dl --url a --preset music --preset video
+        let twice = group_presets(&positioned(&[(2, "a")]), &positioned(&[(4, "music"), (6, "video")]));
+        assert!(matches!(twice, Err(BlobdlError::DuplicatePreset(url)) if url == "a"));
+
+        // blob-dl --preset music --preset video a
+        let twice = group_presets(&positioned(&[(6, "a")]), &positioned(&[(2, "music"), (4, "video")]));
+        assert!(matches!(twice, Err(BlobdlError::DuplicatePreset(owner)) if owner == WHOLE_RUN));
+    }
+
+    #[test]
+    fn grouped_arguments_give_every_url_its_own_answers() {
+        let config = config(&["--url", "https://youtu.be/a", "--preset", "music", "https://youtu.be/b", "--url", "https://youtu.be/c", "--preset", "video"]);
+        assert_eq!(config.urls(), &["https://youtu.be/a", "https://youtu.be/b", "https://youtu.be/c"]);
+
+        assert_eq!(config.for_url(0).media(), Some(&MediaSelection::AudioOnly));
+        assert_eq!(config.for_url(0).quality(), Some(&VideoQualityAndFormatPreferences::BestQuality));
+        // No preset, the wizard asks
+        assert_eq!(config.for_url(1).media(), None);
+        assert_eq!(config.for_url(2).media(), Some(&MediaSelection::FullVideo));
+        // Out of range, like the result of a search
+        assert_eq!(config.for_url(3).media(), None);
+    }
+
+    #[test]
+    fn flags_take_precedence_over_presets() {
+        let config = config(&["--media", "video-only", "--preset", "music", "https://youtu.be/a", "--url", "https://youtu.be/b", "--preset", "video"]);
+
+        for i in 0..2 {
+            assert_eq!(config.for_url(i).media(), Some(&MediaSelection::VideoOnly));
+            assert_eq!(config.for_url(i).quality(), Some(&VideoQualityAndFormatPreferences::BestQuality));
+        }
+        // The run's preset is replaced, not merged, by the url's
+        let config = self::config(&["--preset", "music", "--url", "https://youtu.be/a", "--url", "https://youtu.be/b", "--preset", "video"]);
+        assert_eq!(config.for_url(0).media(), Some(&MediaSelection::AudioOnly));
+        assert_eq!(config.for_url(1).media(), Some(&MediaSelection::FullVideo));
+    }
+
+    #[test]
+    fn run_settings_come_from_flags_then_presets_then_the_config_file() {
+        let presets = PresetLibrary::with_user_presets(r#"{
+            "overnight": { "verbosity": "quiet", "notify": true },
+            "quick": { "verbosity": "verbose" }
+        }"#).unwrap();
+        let file_settings = RunSettings { verbosity: Some(Verbosity::Debug), notify: None, auto_retry: Some(true) };
+        let config = |args: &[&str]| CliConfig::with_presets(matches(args).unwrap(), &presets, file_settings.clone()).unwrap();
+
+        let overnight = config(&["--preset", "overnight", "https://youtu.be/a"]);
+        assert_eq!(overnight.verbosity(), &Verbosity::Quiet);
+        assert!(overnight.notify());
+        assert!(overnight.auto_retry_all());
+
+        let explicit = config(&["-v", "--preset", "overnight", "https://youtu.be/a"]);
+        assert_eq!(explicit.verbosity(), &Verbosity::Verbose);
+        assert!(explicit.notify());
+
+        let unset = config(&["https://youtu.be/a"]);
+        assert_eq!(unset.verbosity(), &Verbosity::Debug);
+        assert!(!unset.notify());
+
+        // A url's preset applies while it's configured, the run keeps the run's
+        let mixed = config(&["--preset", "overnight", "--url", "https://youtu.be/a", "--url", "https://youtu.be/b", "--preset", "quick"]);
+        assert_eq!(mixed.verbosity(), &Verbosity::Quiet);
+        assert_eq!(mixed.for_url(0).verbosity(), &Verbosity::Quiet);
+        assert_eq!(mixed.for_url(1).verbosity(), &Verbosity::Verbose);
+        // The notify of the run's preset is replaced along with the rest, the config file doesn't have one
+        assert!(!mixed.for_url(1).notify());
+    }
+
+    #[test]
+    fn unknown_presets_are_errors() {
+        let unknown = try_config(&["--url", "https://youtu.be/a", "--preset", "archive"]);
+        assert!(matches!(unknown, Err(BlobdlError::UnknownPreset(name)) if name == "archive"));
+
+        // Even if every url has its own
+        let unknown = try_config(&["--preset", "archive", "--url", "https://youtu.be/a", "--preset", "music"]);
+        assert!(matches!(unknown, Err(BlobdlError::UnknownPreset(name)) if name == "archive"));
+    }
+
+    #[test]
+    fn batch_files_pair_urls_with_the_preset_after_them() {
+        let contents = "# overnight\npreset = video\n\nurl = https://youtube.com/playlist?list=PL1\npreset = music\n\n  url=https://youtu.be/watch?v=a  \n";
+        let entries = batch_file_entries(contents).unwrap();
+
+        assert_eq!(entries.run_preset.as_deref(), Some("video"));
+        // Only the first = separates the key
+        assert_eq!(entries.urls, urls_with(&[("https://youtube.com/playlist?list=PL1", Some("music")), ("https://youtu.be/watch?v=a", None)]));
+    }
+
+    #[test]
+    fn invalid_batch_file_lines_are_reported_with_their_number() {
+        let cases = [
+            ("url = a\npreset music\n", 2),
+            ("url = a\nformat = best\n", 2),
+            ("# no value\nurl =\n", 2),
+        ];
+        for (contents, line) in cases {
+            assert!(matches!(batch_file_entries(contents), Err(BlobdlError::InvalidBatchFile(number)) if number == line), "{:?}", contents);
+        }
+        assert!(matches!(batch_file_entries("url = a\npreset = music\npreset = video"), Err(BlobdlError::DuplicatePreset(url)) if url == "a"));
+    }
+
+    #[test]
+    fn batch_files_come_after_the_arguments_and_before_url_files() {
+        let batch_file = std::env::temp_dir().join(format!("blob-dl-batch-file-{}.txt", std::process::id()));
+        std::fs::write(&batch_file, "preset = video\nurl = https://youtu.be/b\nurl = https://youtu.be/c\npreset = music\n").unwrap();
+        let url_file = std::env::temp_dir().join(format!("blob-dl-batch-url-file-{}.txt", std::process::id()));
+        std::fs::write(&url_file, "https://youtu.be/d\n").unwrap();
+
+        let config = config(&["--batch-file", batch_file.to_str().unwrap(), "--url-file", url_file.to_str().unwrap(), "https://youtu.be/a"]);
+        let _ = std::fs::remove_file(batch_file);
+        let _ = std::fs::remove_file(url_file);
+
+        assert_eq!(config.urls(), &["https://youtu.be/a", "https://youtu.be/b", "https://youtu.be/c", "https://youtu.be/d"]);
+        // The batch file's run preset applies to every url without one
+        let media: Vec<_> = (0..4).map(|i| config.for_url(i).media().cloned()).collect();
+        assert_eq!(media, [Some(MediaSelection::FullVideo), Some(MediaSelection::FullVideo), Some(MediaSelection::AudioOnly), Some(MediaSelection::FullVideo)]);
+        assert!(config.from_url_file(3));
+        assert!(!config.from_url_file(2));
+    }
+
+    #[test]
+    fn url_files_skip_blank_lines_and_comments() {
+        let contents = "https://youtu.be/a\n\n  # failed on monday\n https://youtu.be/b \r\n";
+        assert_eq!(url_file_lines(contents), ["https://youtu.be/a", "https://youtu.be/b"]);
+    }
+
+    #[test]
+    fn url_files_are_downloaded_after_the_arguments() {
+        let url_file = std::env::temp_dir().join(format!("blob-dl-url-file-{}.txt", std::process::id()));
+        std::fs::write(&url_file, "https://youtu.be/b\n").unwrap();
+
+        let config = config(&["--url-file", url_file.to_str().unwrap(), "https://youtu.be/a"]);
+        assert_eq!(config.urls(), &["https://youtu.be/a", "https://youtu.be/b"]);
+        assert!(!config.from_url_file(0));
+        assert!(config.from_url_file(1));
+
+        let _ = std::fs::remove_file(url_file);
+    }
+
+    #[test]
+    fn max_comments_needs_write_comments() {
+        let config = config(&["--write-comments", "--max-comments", "500", "https://youtu.be/a"]);
+        assert!(config.write_comments());
+        assert_eq!(config.max_comments(), Some(500));
+
+        assert!(matches(&["--max-comments", "500", "https://youtu.be/a"]).is_err());
+    }
+}
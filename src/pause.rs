@@ -0,0 +1,383 @@
+//! Pausing a run between two videos, with `--pause-file` or by pressing `p`
+//!
+//! The video being downloaded is always finished first: yt-dlp is held as soon as it announces the next one
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use colored::Colorize;
+
+use crate::ui_prompts::*;
+
+/// How often the pause file is checked while paused
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Where a run is in pausing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PauseState {
+    Running,
+    /// `p` was pressed, the run pauses when the next video starts
+    Requested,
+    Paused,
+}
+
+/// What moves a run from one PauseState to another
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PauseEvent {
+    /// `p` was pressed
+    Key,
+    /// yt-dlp is about to start a video
+    NextVideo { pause_file_exists: bool },
+    /// The pause file was removed while paused
+    PauseFileRemoved,
+}
+
+impl PauseState {
+    pub(crate) fn next(self, event: PauseEvent) -> PauseState {
+        match (self, event) {
+            (PauseState::Running, PauseEvent::Key) => PauseState::Requested,
+            // Pressing p again changes your mind, or continues
+            (PauseState::Requested | PauseState::Paused, PauseEvent::Key) => PauseState::Running,
+            (PauseState::Requested, PauseEvent::NextVideo { .. }) => PauseState::Paused,
+            (PauseState::Running, PauseEvent::NextVideo { pause_file_exists: true }) => PauseState::Paused,
+            (PauseState::Paused, PauseEvent::PauseFileRemoved) => PauseState::Running,
+            (state, _) => state,
+        }
+    }
+}
+
+/// The pause state of a run, shared by the threads running yt-dlp and the one reading the keyboard
+pub(crate) struct PauseControl {
+    state: Mutex<PauseState>,
+    /// Whether a thread is already holding yt-dlp, the others wait for it
+    holding: AtomicBool,
+    /// Whether `p` is being read, otherwise the messages don't mention it
+    reading_keys: AtomicBool,
+    /// Whether the messages are left out, the dashboard shows the pause instead
+    quiet: AtomicBool,
+    /// With --pause-file, no new video is started while this file exists
+    pause_file: Option<PathBuf>,
+    /// CTRL+C ends the pause
+    interrupted: Arc<AtomicBool>,
+    /// The running yt-dlp processes, they are suspended while paused
+    child_pids: Arc<Mutex<HashSet<u32>>>,
+}
+
+impl PauseControl {
+    pub(crate) fn new(pause_file: Option<PathBuf>, interrupted: Arc<AtomicBool>, child_pids: Arc<Mutex<HashSet<u32>>>) -> PauseControl {
+        PauseControl {
+            state: Mutex::new(PauseState::Running),
+            holding: AtomicBool::new(false),
+            reading_keys: AtomicBool::new(false),
+            quiet: AtomicBool::new(false),
+            pause_file,
+            interrupted,
+            child_pids,
+        }
+    }
+
+    pub(crate) fn state(&self) -> PauseState {
+        *self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// What pressing `p` does, for the dashboard which reads the keyboard itself
+    #[cfg(feature = "dashboard")]
+    pub(crate) fn press_key(&self) {
+        self.handle(PauseEvent::Key);
+    }
+
+    #[cfg(feature = "dashboard")]
+    pub(crate) fn set_quiet(&self, quiet: bool) {
+        self.quiet.store(quiet, Ordering::SeqCst);
+    }
+
+    /// Whether the run is paused, CTRL+C ends the pause
+    pub(crate) fn is_paused(&self) -> bool {
+        self.state() == PauseState::Paused && !self.interrupted.load(Ordering::SeqCst)
+    }
+
+    fn pause_file_exists(&self) -> bool {
+        self.pause_file.as_ref().is_some_and(|pause_file| pause_file.exists())
+    }
+
+    /// Moves to the state `event` leads to and tells the user what changed
+    fn handle(&self, event: PauseEvent) -> PauseState {
+        let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let previous = *state;
+        *state = previous.next(event);
+        if self.quiet.load(Ordering::SeqCst) {
+            return *state;
+        }
+
+        match (previous, *state) {
+            (PauseState::Running, PauseState::Requested) => eprintln!("\n{}", PAUSE_REQUESTED_MSG.bold().cyan()),
+            (PauseState::Requested, PauseState::Running) => eprintln!("\n{}", PAUSE_CANCELLED_MSG.bold().cyan()),
+            (PauseState::Paused, PauseState::Running) => eprintln!("{}", RESUMED_MSG.bold().cyan()),
+            _ => {}
+        }
+
+        *state
+    }
+
+    /// Called when yt-dlp announces a video, before it starts downloading it: returns whether the run is now paused
+    ///
+    /// Call hold next if it is. The two are apart so that the announcement can be passed on in between,
+    /// whoever reads it then already sees the run paused
+    pub(crate) fn at_next_video(&self) -> bool {
+        let pause_file_exists = self.pause_file_exists();
+        self.handle(PauseEvent::NextVideo { pause_file_exists }) == PauseState::Paused
+    }
+
+    /// Waits while paused or until CTRL+C is pressed, meanwhile every running yt-dlp process is suspended
+    ///
+    /// Processes which can't be suspended carry on, the pause then happens before the next process starts.
+    /// When several threads get here only the first one suspends the processes, the others wait for it
+    pub(crate) fn hold(&self) {
+        if self.holding.swap(true, Ordering::SeqCst) {
+            self.wait_until_resumed();
+            return;
+        }
+
+        let pids: Vec<u32> = self.child_pids.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).iter().copied().collect();
+        let suspended: Vec<u32> = pids.into_iter().filter(|pid| suspend(*pid)).collect();
+
+        let reading_keys = self.reading_keys.load(Ordering::SeqCst);
+        match &self.pause_file {
+            _ if self.quiet.load(Ordering::SeqCst) => {}
+            Some(pause_file) if reading_keys => eprintln!("{} {}", PAUSED_KEY_OR_FILE_MSG.bold().cyan(), pause_file.display()),
+            Some(pause_file) => eprintln!("{} {}", PAUSED_MSG.bold().cyan(), pause_file.display()),
+            None => eprintln!("{}", PAUSED_KEY_MSG.bold().cyan()),
+        }
+
+        // Only removing the file continues, a pause file which appears while paused by p is left for the next video
+        let mut pause_file_existed = self.pause_file_exists();
+        while self.is_paused() {
+            thread::sleep(PAUSE_POLL_INTERVAL);
+            let pause_file_exists = self.pause_file_exists();
+            if pause_file_existed && !pause_file_exists {
+                self.handle(PauseEvent::PauseFileRemoved);
+            }
+            pause_file_existed = pause_file_exists;
+        }
+
+        // A suspended yt-dlp only handles the SIGTERM sent by CTRL+C once it's resumed
+        for pid in suspended {
+            resume(pid);
+        }
+        self.holding.store(false, Ordering::SeqCst);
+    }
+
+    /// Waits before starting a yt-dlp process if the run is paused
+    pub(crate) fn before_next_video(&self) {
+        if self.at_next_video() {
+            self.hold();
+        }
+    }
+
+    /// Waits while another thread holds yt-dlp, without suspending anything
+    pub(crate) fn wait_until_resumed(&self) {
+        while self.is_paused() {
+            thread::sleep(PAUSE_POLL_INTERVAL);
+        }
+    }
+}
+
+/// Reads `p` from the terminal while yt-dlp runs, until it's dropped
+///
+/// The terminal is switched out of line mode so that `p` arrives without enter, only echo and line buffering are
+/// turned off so CTRL+C still works. Nothing is read when stdin isn't a terminal, or on Windows
+pub(crate) struct KeyReader {
+    stop: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+    #[cfg(unix)]
+    original_termios: Option<libc::termios>,
+}
+
+/// How often the key reader checks whether it has to stop
+#[cfg(unix)]
+const KEY_POLL_INTERVAL_MS: libc::c_int = 100;
+
+impl KeyReader {
+    #[cfg(unix)]
+    pub(crate) fn start(control: &Arc<PauseControl>) -> KeyReader {
+        let stop = Arc::new(AtomicBool::new(false));
+        let original_termios = raw_stdin();
+        if original_termios.is_none() {
+            return KeyReader { stop, thread: None, original_termios };
+        }
+
+        control.reading_keys.store(true, Ordering::SeqCst);
+        let thread = {
+            let stop = Arc::clone(&stop);
+            let control = Arc::clone(control);
+            thread::spawn(move || {
+                while !stop.load(Ordering::SeqCst) {
+                    let mut stdin = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+                    // Safety: the pointer is to a single pollfd which outlives the call
+                    if unsafe { libc::poll(&mut stdin, 1, KEY_POLL_INTERVAL_MS) } <= 0 {
+                        continue;
+                    }
+
+                    let mut key = 0u8;
+                    // Safety: one byte is read into a one byte buffer
+                    if unsafe { libc::read(libc::STDIN_FILENO, (&mut key as *mut u8).cast(), 1) } <= 0 {
+                        // stdin was closed
+                        break;
+                    }
+                    if key.eq_ignore_ascii_case(&b'p') {
+                        control.handle(PauseEvent::Key);
+                    }
+                }
+                control.reading_keys.store(false, Ordering::SeqCst);
+            })
+        };
+
+        KeyReader { stop, thread: Some(thread), original_termios }
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn start(_control: &Arc<PauseControl>) -> KeyReader {
+        KeyReader { stop: Arc::new(AtomicBool::new(false)), thread: None }
+    }
+}
+
+impl Drop for KeyReader {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+
+        #[cfg(unix)]
+        if let Some(original_termios) = &self.original_termios {
+            // Safety: the termios was filled in by tcgetattr
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original_termios);
+            }
+        }
+    }
+}
+
+/// Turns off echo and line buffering on the terminal, returns how it was set up before. None if stdin isn't a terminal
+#[cfg(unix)]
+fn raw_stdin() -> Option<libc::termios> {
+    // Safety: isatty only looks at the file descriptor
+    if unsafe { libc::isatty(libc::STDIN_FILENO) } != 1 {
+        return None;
+    }
+
+    // Safety: termios is plain data, tcgetattr fills it in
+    let mut original: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+        return None;
+    }
+
+    let mut raw = original;
+    raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+    // Safety: raw is a copy of a termios filled in by tcgetattr
+    (unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } == 0).then_some(original)
+}
+
+/// Stops yt-dlp with SIGSTOP, returns whether it worked
+#[cfg(unix)]
+fn suspend(pid: u32) -> bool {
+    // Safety: kill doesn't touch any memory, at worst the pid doesn't exist anymore
+    unsafe { libc::kill(pid as libc::pid_t, libc::SIGSTOP) == 0 }
+}
+
+#[cfg(unix)]
+fn resume(pid: u32) {
+    // Safety: see suspend
+    unsafe {
+        libc::kill(pid as libc::pid_t, libc::SIGCONT);
+    }
+}
+
+/// Windows has no signal to suspend a process with, downloads only pause between yt-dlp processes
+#[cfg(not(unix))]
+fn suspend(_pid: u32) -> bool {
+    false
+}
+
+#[cfg(not(unix))]
+fn resume(_pid: u32) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_events(events: &[PauseEvent]) -> Vec<PauseState> {
+        events.iter().scan(PauseState::Running, |state, event| {
+            *state = state.next(*event);
+            Some(*state)
+        }).collect()
+    }
+
+    const NEXT_VIDEO: PauseEvent = PauseEvent::NextVideo { pause_file_exists: false };
+    const NEXT_VIDEO_WITH_FILE: PauseEvent = PauseEvent::NextVideo { pause_file_exists: true };
+
+    #[test]
+    fn key_pauses_at_the_next_video_only() {
+        assert_eq!(run_events(&[PauseEvent::Key, NEXT_VIDEO, PauseEvent::Key, NEXT_VIDEO]),
+                   [PauseState::Requested, PauseState::Paused, PauseState::Running, PauseState::Running]);
+    }
+
+    #[test]
+    fn second_key_cancels_a_request() {
+        assert_eq!(run_events(&[PauseEvent::Key, PauseEvent::Key, NEXT_VIDEO]),
+                   [PauseState::Requested, PauseState::Running, PauseState::Running]);
+    }
+
+    #[test]
+    fn pause_file_pauses_until_it_is_removed() {
+        assert_eq!(run_events(&[NEXT_VIDEO, NEXT_VIDEO_WITH_FILE, NEXT_VIDEO_WITH_FILE, PauseEvent::PauseFileRemoved, NEXT_VIDEO]),
+                   [PauseState::Running, PauseState::Paused, PauseState::Paused, PauseState::Running, PauseState::Running]);
+    }
+
+    #[test]
+    fn key_continues_a_pause_file_pause() {
+        assert_eq!(run_events(&[NEXT_VIDEO_WITH_FILE, PauseEvent::Key]), [PauseState::Paused, PauseState::Running]);
+    }
+
+    #[test]
+    fn removing_the_pause_file_only_matters_while_paused() {
+        assert_eq!(run_events(&[PauseEvent::PauseFileRemoved, PauseEvent::Key, PauseEvent::PauseFileRemoved]),
+                   [PauseState::Running, PauseState::Requested, PauseState::Requested]);
+    }
+
+    #[test]
+    fn control_pauses_at_the_next_video_after_a_key() {
+        let control = PauseControl::new(None, Arc::new(AtomicBool::new(false)), Arc::new(Mutex::new(HashSet::new())));
+
+        assert!(!control.at_next_video());
+        control.handle(PauseEvent::Key);
+        assert!(!control.is_paused());
+        assert!(control.at_next_video());
+        assert!(control.is_paused());
+    }
+
+    #[test]
+    fn control_pauses_while_the_pause_file_exists() {
+        let pause_file = std::env::temp_dir().join(format!("blob-dl-pause-{}", std::process::id()));
+        let control = PauseControl::new(Some(pause_file.clone()), Arc::new(AtomicBool::new(false)), Arc::new(Mutex::new(HashSet::new())));
+
+        assert!(!control.at_next_video());
+        std::fs::write(&pause_file, "").unwrap();
+        let paused = control.at_next_video();
+        std::fs::remove_file(&pause_file).unwrap();
+
+        assert!(paused);
+    }
+
+    #[test]
+    fn interrupting_ends_the_wait() {
+        let control = PauseControl::new(None, Arc::new(AtomicBool::new(true)), Arc::new(Mutex::new(HashSet::new())));
+        control.handle(PauseEvent::Key);
+        assert!(control.at_next_video());
+
+        // Returns right away instead of waiting for a key
+        control.hold();
+    }
+}
@@ -0,0 +1,164 @@
+//! Post-processing plugins: shared libraries (.so/.dylib/.dll) which are run on every downloaded file
+//!
+//! ## ABI
+//! A plugin must export this function, which is called once for every file blob-dl downloads:
+//! ```c
+//! int blob_dl_post_process(const char *downloaded_file, const char *url);
+//! ```
+//! - `downloaded_file` is the path to the final file (after merging and conversions)
+//! - `url` is the url the user passed to blob-dl
+//!
+//! Both strings are UTF-8 and null-terminated, they are only valid for the duration of the call.
+//! The function has to return 0 on success, any other value is reported to the user as an error.
+//!
+//! An example plugin is in examples/plugin.rs, build it with `cargo build --example plugin`
+use std::ffi::{c_char, c_int, CString};
+use std::path::Path;
+use colored::Colorize;
+
+use crate::assembling::youtube::config::DownloadConfig;
+use crate::error::{BlobdlError, BlobResult};
+
+/// Name of the function every plugin has to export
+const PLUGIN_SYMBOL: &[u8] = b"blob_dl_post_process";
+
+/// The signature of PLUGIN_SYMBOL
+type PostProcessFn = unsafe extern "C" fn(*const c_char, *const c_char) -> c_int;
+
+/// Something which can work on a file after it has been downloaded
+pub(crate) trait PluginInterface {
+    fn post_process(&self, downloaded_file: &Path, config: &DownloadConfig) -> BlobResult<()>;
+}
+
+/// Adapts a function with the signature of PLUGIN_SYMBOL to PluginInterface
+struct ExternPlugin {
+    post_process: PostProcessFn,
+}
+
+impl PluginInterface for ExternPlugin {
+    fn post_process(&self, downloaded_file: &Path, config: &DownloadConfig) -> BlobResult<()> {
+        // Interior null bytes can't be passed to C
+        let file = CString::new(downloaded_file.display().to_string()).map_err(|_| BlobdlError::Utf8Error)?;
+        let url = CString::new(config.url().as_str()).map_err(|_| BlobdlError::Utf8Error)?;
+
+        // Safety: both strings outlive the call, as the ABI section requires
+        match unsafe { (self.post_process)(file.as_ptr(), url.as_ptr()) } {
+            0 => Ok(()),
+            code => Err(BlobdlError::PluginFailed(code)),
+        }
+    }
+}
+
+/// A plugin loaded from a shared library
+pub(crate) struct SharedLibraryPlugin {
+    plugin: ExternPlugin,
+    // post_process points into the library, so it has to stay loaded as long as the plugin exists
+    _library: libloading::Library,
+}
+
+impl SharedLibraryPlugin {
+    /// Loads the library and finds PLUGIN_SYMBOL in it, so a library which isn't a plugin is reported before any file is processed
+    pub(crate) fn load(path: &Path) -> BlobResult<SharedLibraryPlugin> {
+        // Safety: the user chose to run this library, its initialization routines are trusted
+        let library = unsafe { libloading::Library::new(path) }?;
+        // Safety: the symbol's signature is documented in this module's ABI section
+        let post_process = *unsafe { library.get::<PostProcessFn>(PLUGIN_SYMBOL) }?;
+
+        Ok(SharedLibraryPlugin { plugin: ExternPlugin { post_process }, _library: library })
+    }
+}
+
+impl PluginInterface for SharedLibraryPlugin {
+    fn post_process(&self, downloaded_file: &Path, config: &DownloadConfig) -> BlobResult<()> {
+        self.plugin.post_process(downloaded_file, config)
+    }
+}
+
+/// Runs the plugin chosen by the user (if any) on every downloaded file
+///
+/// Failures are shown to the user but they don't stop the other files from being processed
+pub(crate) fn post_process_all(downloaded_files: &[String], config: &DownloadConfig) {
+    let plugin_path = match config.plugin() {
+        Some(path) => path,
+        None => return,
+    };
+
+    if downloaded_files.is_empty() {
+        return;
+    }
+
+    match SharedLibraryPlugin::load(plugin_path) {
+        Ok(plugin) => post_process_with(&plugin, downloaded_files, config),
+        Err(err) => eprintln!("{}: {}", "ERROR".red(), err),
+    }
+}
+
+/// Runs `plugin` on every downloaded file, one failure doesn't stop the others
+fn post_process_with(plugin: &impl PluginInterface, downloaded_files: &[String], config: &DownloadConfig) {
+    for file in downloaded_files {
+        if let Err(err) = plugin.post_process(Path::new(file), config) {
+            eprintln!("{}: {} ({})", "ERROR".red(), err, file);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::ffi::CStr;
+    use std::path::PathBuf;
+    use crate::assembling::youtube::{MediaSelection, VideoQualityAndFormatPreferences};
+
+    fn download_config() -> DownloadConfig {
+        DownloadConfig::new_video("https://youtu.be/a", VideoQualityAndFormatPreferences::BestQuality,
+                                  String::from("/downloads"), MediaSelection::FullVideo)
+    }
+
+    /// Stands in for a plugin's exported function: accepts webm files downloaded from https://youtu.be/a
+    unsafe extern "C" fn accepts_webm(downloaded_file: *const c_char, url: *const c_char) -> c_int {
+        let (file, url) = unsafe { (CStr::from_ptr(downloaded_file), CStr::from_ptr(url)) };
+        match (file.to_str(), url.to_str()) {
+            (Ok(file), Ok("https://youtu.be/a")) if file.ends_with(".webm") => 0,
+            _ => 3,
+        }
+    }
+
+    #[test]
+    fn extern_functions_receive_the_file_and_the_url() {
+        let plugin = ExternPlugin { post_process: accepts_webm };
+
+        assert!(plugin.post_process(Path::new("/downloads/a.webm"), &download_config()).is_ok());
+        assert!(matches!(plugin.post_process(Path::new("/downloads/a.mp4"), &download_config()), Err(BlobdlError::PluginFailed(3))));
+        // Never reaches the plugin
+        assert!(matches!(plugin.post_process(Path::new("/downloads/a\0.webm"), &download_config()), Err(BlobdlError::Utf8Error)));
+    }
+
+    #[test]
+    fn libraries_which_cant_be_loaded_are_errors() {
+        let missing = std::env::temp_dir().join("blob-dl-no-such-plugin.so");
+        assert!(matches!(SharedLibraryPlugin::load(&missing), Err(BlobdlError::PluginLoadingError(_))));
+    }
+
+    /// Remembers every file it is given and fails on the first one
+    #[derive(Default)]
+    struct RecordingPlugin {
+        processed: RefCell<Vec<PathBuf>>,
+    }
+
+    impl PluginInterface for RecordingPlugin {
+        fn post_process(&self, downloaded_file: &Path, _config: &DownloadConfig) -> BlobResult<()> {
+            let mut processed = self.processed.borrow_mut();
+            processed.push(downloaded_file.to_path_buf());
+            if processed.len() == 1 { Err(BlobdlError::PluginFailed(1)) } else { Ok(()) }
+        }
+    }
+
+    #[test]
+    fn a_failure_doesnt_stop_the_other_files() {
+        let plugin = RecordingPlugin::default();
+        post_process_with(&plugin, &[String::from("a.webm"), String::from("b.webm")], &download_config());
+
+        assert_eq!(plugin.processed.into_inner(), [PathBuf::from("a.webm"), PathBuf::from("b.webm")]);
+    }
+}
@@ -0,0 +1,66 @@
+//! Post-processing blob-dl runs itself with external tools, once yt-dlp is done with a file
+use std::path::Path;
+use std::process::{Command, Stdio};
+use colored::Colorize;
+use which::which;
+
+use crate::assembling::youtube::config::DownloadConfig;
+use crate::error::{BlobdlError, BlobResult};
+use crate::ui_prompts::ID3_TOOL_MISSING;
+
+/// The tools which can write custom ID3 tags, the first one installed is used
+const ID3_TOOLS: [&str; 2] = ["id3v2", "eyeD3"];
+
+/// The first of ID3_TOOLS which is installed
+pub(crate) fn id3_tool() -> Option<&'static str> {
+    ID3_TOOLS.into_iter().find(|tool| which(tool).is_ok())
+}
+
+/// Writes `tags` to the mp3 `file` as user-defined (TXXX) frames, `tool` is one of ID3_TOOLS
+///
+/// Every tag is a (name, value) pair, names can't contain ':'
+pub(crate) fn apply_id3_tags(file: &Path, tags: &[(String, String)], tool: &str) -> BlobResult<()> {
+    let mut command = Command::new(tool);
+
+    for (name, value) in tags {
+        let frame = format!("{}:{}", name, value);
+        match tool {
+            "eyeD3" => command.arg("--user-text-frame").arg(frame),
+            _ => command.arg("--TXXX").arg(frame),
+        };
+    }
+
+    let status = command.arg(file).stdout(Stdio::null()).status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(BlobdlError::Id3TaggingFailed(status.code()))
+    }
+}
+
+/// Writes the custom ID3 tags chosen by the user (if any) to every downloaded mp3 file
+///
+/// Failures are shown to the user but they don't stop the other files from being tagged
+pub(crate) fn tag_all(downloaded_files: &[String], config: &DownloadConfig) {
+    if config.custom_id3_tags.is_empty() || downloaded_files.is_empty() {
+        return;
+    }
+
+    let tool = match id3_tool() {
+        Some(tool) => tool,
+        None => {
+            eprintln!("{}: {}", "WARNING".yellow(), ID3_TOOL_MISSING);
+            return;
+        }
+    };
+
+    let mp3_files = downloaded_files.iter()
+        .map(Path::new)
+        .filter(|file| file.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("mp3")));
+
+    for file in mp3_files {
+        if let Err(err) = apply_id3_tags(file, &config.custom_id3_tags, tool) {
+            eprintln!("{}: {} ({})", "ERROR".red(), err, file.display());
+        }
+    }
+}
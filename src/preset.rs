@@ -0,0 +1,227 @@
+//! Presets: named answers to the wizard's first questions, so that every url of a run can be downloaded differently
+//!
+//! `music` and `video` are always available, more can be defined in PRESETS_FILE in blob-dl's config directory:
+//! `{ "podcasts": { "media": "audio", "quality": "smallest", "output_path": "/home/me/Podcasts" } }`.
+//! A preset defined there with the name of a built-in one replaces it
+//!
+//! A preset can also carry RunSettings (`"verbosity": "quiet", "notify": true, "auto_retry": true`), which SETTINGS_FILE
+//! gives every run. A flag wins over the preset, which wins over SETTINGS_FILE, see resolve_setting
+use std::collections::HashMap;
+use serde::Deserialize;
+
+use crate::assembling::youtube::{MediaSelection, VideoQualityAndFormatPreferences};
+use crate::error::{BlobdlError, BlobResult};
+use crate::parser;
+use crate::state;
+
+/// Where the user's presets are kept, in blob-dl's config directory
+const PRESETS_FILE: &str = "presets.json";
+
+/// Where the settings of every run are kept, in blob-dl's config directory
+const SETTINGS_FILE: &str = "config.json";
+
+/// The value of a setting which can come from several places: an explicit flag wins over the preset, which wins over
+/// SETTINGS_FILE. None if none of them has it
+pub(crate) fn resolve_setting<T>(flag: Option<T>, preset: Option<T>, config_file: Option<T>) -> Option<T> {
+    flag.or(preset).or(config_file)
+}
+
+/// How blob-dl behaves while it runs, as opposed to what it downloads. None is a setting left to the next source
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(try_from = "SettingsFields")]
+pub struct RunSettings {
+    pub(crate) verbosity: Option<parser::Verbosity>,
+    pub(crate) notify: Option<bool>,
+    pub(crate) auto_retry: Option<bool>,
+}
+
+/// RunSettings as they are written in SETTINGS_FILE and PRESETS_FILE, with the values --verbosity accepts
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SettingsFields {
+    verbosity: Option<String>,
+    notify: Option<bool>,
+    auto_retry: Option<bool>,
+}
+
+impl TryFrom<SettingsFields> for RunSettings {
+    type Error = String;
+
+    fn try_from(fields: SettingsFields) -> Result<RunSettings, String> {
+        let verbosity = fields.verbosity
+            .map(|verbosity| parser::verbosity_from_name(&verbosity).ok_or(format!("unknown verbosity \"{}\", use quiet, default, verbose or debug", verbosity)))
+            .transpose()?;
+
+        Ok(RunSettings { verbosity, notify: fields.notify, auto_retry: fields.auto_retry })
+    }
+}
+
+impl RunSettings {
+    /// The settings in the user's SETTINGS_FILE, none if there isn't one
+    pub fn load() -> BlobResult<RunSettings> {
+        match state::config_dir().map(|dir| std::fs::read_to_string(dir.join(SETTINGS_FILE))) {
+            Some(Ok(contents)) => serde_json::from_str(&contents).map_err(BlobdlError::InvalidConfigFile),
+            Some(Err(err)) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(RunSettings::default()),
+        }
+    }
+
+    /// Every setting taken from `flags`, `preset` or `config_file`, according to resolve_setting
+    pub(crate) fn resolve(flags: &RunSettings, preset: Option<&RunSettings>, config_file: &RunSettings) -> RunSettings {
+        let preset = preset.cloned().unwrap_or_default();
+        RunSettings {
+            verbosity: resolve_setting(flags.verbosity.clone(), preset.verbosity, config_file.verbosity.clone()),
+            notify: resolve_setting(flags.notify, preset.notify, config_file.notify),
+            auto_retry: resolve_setting(flags.auto_retry, preset.auto_retry, config_file.auto_retry),
+        }
+    }
+}
+
+/// Answers a preset gives to the wizard, the questions it leaves out are asked as usual
+///
+/// Flags passed on the command line (like --media) take precedence over a preset
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(try_from = "PresetFields")]
+pub struct Preset {
+    pub(crate) media: Option<MediaSelection>,
+    pub(crate) quality: Option<VideoQualityAndFormatPreferences>,
+    pub(crate) output_path: Option<String>,
+    pub(crate) settings: RunSettings,
+}
+
+/// A preset as it is written in PRESETS_FILE, with the values --media and --quality accept
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PresetFields {
+    media: Option<String>,
+    quality: Option<String>,
+    output_path: Option<String>,
+    verbosity: Option<String>,
+    notify: Option<bool>,
+    auto_retry: Option<bool>,
+}
+
+impl TryFrom<PresetFields> for Preset {
+    type Error = String;
+
+    fn try_from(fields: PresetFields) -> Result<Preset, String> {
+        let media = fields.media
+            .map(|media| parser::media_from_name(&media).ok_or(format!("unknown media \"{}\", use video, audio or video-only", media)))
+            .transpose()?;
+        let quality = fields.quality
+            .map(|quality| parser::quality_from_name(&quality).ok_or(format!("unknown quality \"{}\", use best or smallest", quality)))
+            .transpose()?;
+
+        let settings = RunSettings::try_from(SettingsFields { verbosity: fields.verbosity, notify: fields.notify, auto_retry: fields.auto_retry })?;
+
+        Ok(Preset { media, quality, output_path: fields.output_path, settings })
+    }
+}
+
+/// Every preset a run can use, by name
+#[derive(Debug)]
+pub struct PresetLibrary {
+    presets: HashMap<String, Preset>,
+}
+
+impl PresetLibrary {
+    /// The presets which are always available
+    pub(crate) fn builtin() -> PresetLibrary {
+        let presets = HashMap::from([
+            (String::from("music"), Preset {
+                media: Some(MediaSelection::AudioOnly),
+                quality: Some(VideoQualityAndFormatPreferences::BestQuality),
+                ..Preset::default()
+            }),
+            (String::from("video"), Preset {
+                media: Some(MediaSelection::FullVideo),
+                quality: Some(VideoQualityAndFormatPreferences::BestQuality),
+                ..Preset::default()
+            }),
+        ]);
+
+        PresetLibrary { presets }
+    }
+
+    /// The built-in presets along with the ones defined in the user's PRESETS_FILE, if there is one
+    pub fn load() -> BlobResult<PresetLibrary> {
+        let contents = match state::config_dir().map(|dir| std::fs::read_to_string(dir.join(PRESETS_FILE))) {
+            Some(Ok(contents)) => contents,
+            Some(Err(err)) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => return Ok(PresetLibrary::builtin()),
+        };
+
+        PresetLibrary::with_user_presets(&contents)
+    }
+
+    /// The built-in presets along with the ones in `json`, the contents of a PRESETS_FILE
+    pub(crate) fn with_user_presets(json: &str) -> BlobResult<PresetLibrary> {
+        let user_presets: HashMap<String, Preset> = serde_json::from_str(json).map_err(BlobdlError::InvalidPresetFile)?;
+
+        let mut library = PresetLibrary::builtin();
+        library.presets.extend(user_presets);
+        Ok(library)
+    }
+
+    pub(crate) fn get(&self, name: &str) -> BlobResult<&Preset> {
+        self.presets.get(name).ok_or_else(|| BlobdlError::UnknownPreset(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_presets_are_added_to_the_builtin_ones() {
+        let library = PresetLibrary::with_user_presets(r#"{
+            "podcasts": { "media": "audio", "quality": "smallest", "output_path": "/podcasts", "verbosity": "quiet", "notify": true },
+            "music": { "media": "video-only" }
+        }"#).unwrap();
+
+        assert_eq!(library.get("podcasts").unwrap(), &Preset {
+            media: Some(MediaSelection::AudioOnly),
+            quality: Some(VideoQualityAndFormatPreferences::SmallestSize),
+            output_path: Some(String::from("/podcasts")),
+            settings: RunSettings { verbosity: Some(parser::Verbosity::Quiet), notify: Some(true), auto_retry: None },
+        });
+        // Replaced, the fields it leaves out aren't taken from the built-in preset
+        assert_eq!(library.get("music").unwrap(), &Preset { media: Some(MediaSelection::VideoOnly), ..Preset::default() });
+        assert_eq!(library.get("video").unwrap().media, Some(MediaSelection::FullVideo));
+        assert!(matches!(library.get("archive"), Err(BlobdlError::UnknownPreset(name)) if name == "archive"));
+    }
+
+    #[test]
+    fn presets_only_accept_the_values_of_the_flags() {
+        for json in [r#"{ "a": { "media": "mp3" } }"#, r#"{ "a": { "quality": "1080p" } }"#, r#"{ "a": { "format": "best" } }"#,
+                     r#"{ "a": { "verbosity": "loud" } }"#, r#"{ "a": { "notify": "yes" } }"#] {
+            assert!(matches!(PresetLibrary::with_user_presets(json), Err(BlobdlError::InvalidPresetFile(_))), "{}", json);
+        }
+    }
+
+    #[test]
+    fn flags_win_over_presets_which_win_over_the_config_file() {
+        let cases = [
+            (Some(1), Some(2), Some(3), Some(1)),
+            (Some(1), None, Some(3), Some(1)),
+            (None, Some(2), Some(3), Some(2)),
+            (None, None, Some(3), Some(3)),
+            (None, None, None, None),
+        ];
+        for (flag, preset, config_file, expected) in cases {
+            assert_eq!(resolve_setting(flag, preset, config_file), expected, "{:?} {:?} {:?}", flag, preset, config_file);
+        }
+    }
+
+    #[test]
+    fn every_run_setting_is_resolved_on_its_own() {
+        let flags = RunSettings { verbosity: Some(parser::Verbosity::Debug), ..RunSettings::default() };
+        let preset = RunSettings { verbosity: Some(parser::Verbosity::Quiet), notify: Some(true), ..RunSettings::default() };
+        let config_file: RunSettings = serde_json::from_str(r#"{ "verbosity": "verbose", "notify": false, "auto_retry": true }"#).unwrap();
+
+        assert_eq!(RunSettings::resolve(&flags, Some(&preset), &config_file),
+                   RunSettings { verbosity: Some(parser::Verbosity::Debug), notify: Some(true), auto_retry: Some(true) });
+        assert_eq!(RunSettings::resolve(&RunSettings::default(), None, &config_file), config_file);
+        assert!(serde_json::from_str::<RunSettings>(r#"{ "media": "audio" }"#).is_err());
+    }
+}
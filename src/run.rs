@@ -1,211 +1,2890 @@
-use std::process::{Command, Stdio};
-use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::io::{BufRead, BufReader, Read, Write};
 use dialoguer::{theme::ColorfulTheme, MultiSelect};
 use dialoguer::console::Term;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use std::path::{Path, PathBuf};
 use colored::Colorize;
+use crossbeam_channel::{select, Receiver, Sender};
 
 use crate::youtube_error_message::*;
 use crate::ui_prompts::*;
 use crate::parser;
-use crate::error::YtdlpError;
+use crate::error::{BlobResult, YtdlpError, YtdlpErrorKind, UNKNOWN_VIDEO_ID};
+use crate::assembling::youtube;
 use crate::assembling::youtube::config;
+use crate::plugin;
+use crate::postprocess;
+use crate::checksum;
+use crate::secrets;
+use crate::speed_graph::{self, SpeedGraph, SPEED_GRAPH_WIDTH};
+use crate::pause::{KeyReader, PauseControl};
+use crate::dashboard::{self, Dashboard, EventStream, RunEvent};
+use crate::only_new;
+use crate::stats;
+use crate::analyzer;
+use crate::assembling::youtube::music;
+use crate::timings::{Phase, Timings};
+use crate::units::Bytes;
+use crate::assembling::youtube::format_timestamp;
 
-/// Executes the yt-dlp command and analyzes its output.
+/// Executes the yt-dlp commands one after the other and analyzes their output.
 ///
 /// It filters what to show to the user according to verbosity options
 ///
 /// It records which videos fail to download and the reason: if trying again can fix the issue the user can choose to retry
-pub fn run_and_observe(command: &mut Command, download_config: &config::DownloadConfig, verbosity: &parser::Verbosity) {
-    // Run the command and record any errors
-    if let Some(errors) = run_command(command, verbosity) {
-        // Some videos could not be downloaded, ask the user which ones they want to try to re-download
-        let user_selection = ask_for_redownload(&errors);
+///
+/// Downloads and retries are recorded in `timings`, which is shown at the end in Verbose mode.
+/// An error is only returned if yt-dlp can't be started
+pub fn run_and_observe(mut downloads: Vec<(Command, config::DownloadConfig)>, cli_config: &parser::CliConfig, timings: &Timings) -> BlobResult<()> {
+    let verbosity = cli_config.verbosity();
 
-        // The list of commands that have to be re-run in case of errors
-        let mut to_be_downloaded = Vec::new();
+    // Lets CTRL+C stop yt-dlp cleanly
+    let shutdown = Shutdown::install(cli_config.pause_file().cloned());
 
-        // Selection 0 and 1 are hard-coded (select all | select nothing)
-        if !user_selection.is_empty() {
-            if user_selection[0] == 0 {
-                // The user wants to re-download all the videos
-                for video_to_re_download in &errors {
-                    // Re-download every video while keeping the current command configuration (quality, naming preference, ...)
-                    to_be_downloaded.push(download_config.build_command_for_video(video_to_re_download.video_id()));
-                }
-            } else if user_selection[0] == 1 {
-                // The user doesn't want to re-download anything
-            } else {
-                // Only re-download the selected videos
-                for i in user_selection {
-                    // Skip 0 and 1 because they are hard-coded options (select all or nothing)
-                    if i == 0 || i == 1 {
-                        continue;
-                    }
-                    // There is a 1:1 correspondence between the number in user_selection and
-                    // the index of the video it refers to in errors
-                    to_be_downloaded.push(download_config.build_command_for_video(errors[i - 2].video_id().as_str()));
-                }
+    let (errors, mut video_timings) = download_all(&mut downloads, cli_config, timings, &shutdown)?;
+
+    if shutdown.is_interrupted() {
+        // The videos after the interrupted one were never looked at, they mustn't be skipped next time
+        only_new::discard_records(&downloads);
+        clean_up_partial_files(&downloads, &shutdown);
+        return Ok(());
+    }
+
+    // Everything after the first download of every url is a retry
+    let errors = timings.time(Phase::Retry, || retry_with_fixes(errors, &downloads, &mut video_timings, cli_config, &shutdown));
+
+    if shutdown.is_interrupted() {
+        only_new::discard_records(&downloads);
+        clean_up_partial_files(&downloads, &shutdown);
+        return Ok(());
+    }
+
+    // Recorded before the retries the user is asked about, the urls which still have failures keep their old state
+    let failed: Vec<usize> = errors.iter().map(|(download_index, _)| *download_index).collect();
+    only_new::record_runs(&downloads, &failed);
+
+    report_results(&downloads, &errors, &video_timings, verbosity);
+
+    // Recorded before the retries the user is asked about, waiting for an answer isn't downloading
+    if !cli_config.no_stats() {
+        record_stats(&downloads, &errors, &video_timings, timings);
+    }
+
+    timings.time(Phase::Retry, || retry_failed_downloads(&errors, &downloads, cli_config, &shutdown));
+
+    // The processes started by the wizard were counted as they ran
+    timings.count_processes(shutdown.spawned());
+
+    if matches!(verbosity, parser::Verbosity::Verbose | parser::Verbosity::Debug) {
+        timings.print();
+    }
+
+    Ok(())
+}
+
+/// Appends what the run downloaded to blob-dl's stats (see `blob-dl stats`)
+///
+/// `errors` are the ones left after the automatic retries and `video_timings` includes the videos those fixed
+fn record_stats(downloads: &[(Command, config::DownloadConfig)], errors: &[(usize, YtdlpError)],
+                video_timings: &[VideoTiming], timings: &Timings)
+{
+    let bytes = video_timings.iter().filter_map(|timing| timing.bytes).sum();
+    let duration = timings.total(Phase::Download) + timings.total(Phase::Retry);
+
+    let modes: Vec<String> = downloads.iter().map(|(_, download_config)| download_config.stats_mode()).collect();
+    let mode = match modes.split_first() {
+        Some((first, rest)) if rest.iter().all(|mode| mode == first) => first.clone(),
+        Some(_) => String::from("mixed"),
+        None => return,
+    };
+
+    let lut = init_error_msg_lut();
+    let failed = failed_videos(errors);
+    let mut error_categories = HashMap::new();
+    for error in &failed {
+        *error_categories.entry(error_category(error, &lut).to_string()).or_insert(0) += 1;
+    }
+
+    stats::record_run(&stats::RunRecord::new(video_timings.len(), failed.len(), bytes, duration, mode, error_categories));
+}
+
+/// The first error of every video which failed, yt-dlp can report more than one for the same video
+/// (a failed download then its post-processing, ...). Errors which don't say which video failed are all kept
+fn failed_videos(errors: &[(usize, YtdlpError)]) -> Vec<&YtdlpError> {
+    let mut seen = HashSet::new();
+    errors.iter()
+        .map(|(_, error)| error)
+        .filter(|error| error.video_id().is_none_or(|video_id| seen.insert(video_id)))
+        .collect()
+}
+
+/// Errors paired with the index of the download they come from
+type DownloadErrors = Vec<(usize, YtdlpError)>;
+
+/// Runs the first download of every url, post-processing what each one downloaded
+///
+/// Returns the errors, paired with the index of the download they come from, and how long every video took.
+/// It stops at the first download which is interrupted
+fn download_all(downloads: &mut [(Command, config::DownloadConfig)], cli_config: &parser::CliConfig, timings: &Timings,
+                shutdown: &Shutdown) -> BlobResult<(DownloadErrors, Vec<VideoTiming>)>
+{
+    let verbosity = cli_config.verbosity();
+
+    let mut errors = Vec::new();
+    let mut video_timings = Vec::new();
+
+    let show_dashboard = cli_config.dashboard() && match dashboard::unavailable_reason() {
+        Some(reason) => {
+            eprintln!("{}: {}", "WARNING".yellow(), reason);
+            false
+        }
+        None => true,
+    };
+
+    for (i, (command, download_config)) in downloads.iter_mut().enumerate() {
+        verbosity.debug(&format!("Starting the download of {}", download_config));
+        // Run the command and record any errors, p pauses it meanwhile. The dashboard reads the keyboard itself
+        let view = show_dashboard.then(|| Dashboard::start(&shutdown.events, &shutdown.pause, shutdown.interrupter()));
+        let keys = view.is_none().then(|| KeyReader::start(&shutdown.pause));
+        let output = timings.time(Phase::Download, || run_download(command, download_config, cli_config, shutdown));
+        drop(keys);
+        drop(view);
+        let output = output?;
+        errors.extend(output.errors.into_iter().map(|error| (i, error)));
+        video_timings.extend(output.timings);
+
+        finish_downloads(&output.downloaded_files, download_config, cli_config);
+        remove_replaced_files(&output.downloaded_files, download_config);
+        if download_config.write_comments && !output.info_json_files.is_empty() && *verbosity != parser::Verbosity::Quiet {
+            eprintln!("\n{}", COMMENTS_SAVED.bold().cyan());
+            for file in &output.info_json_files {
+                eprintln!("   {}", file);
             }
         }
-        for mut com in to_be_downloaded {
-            run_command(&mut com, verbosity);
+        if download_config.music && download_config.download_target == analyzer::DownloadOption::YtPlaylist {
+            match music::write_m3u(&output.downloaded_files) {
+                Ok(Some(path)) => eprintln!("{} {}", M3U_WRITTEN.bold().cyan(), path.display()),
+                Ok(None) => {}
+                Err(err) => eprintln!("{}: {} (.m3u)", "ERROR".red(), err),
+            }
+        }
+
+        if let Some(next_start) = next_playlist_start(download_config, output.playlist_progress, shutdown.is_interrupted()) {
+            eprintln!("{} {}", RESUME_HINT.bold().cyan(), next_start);
+        }
+
+        // The other urls are saved in the same place
+        if shutdown.is_interrupted() || output.filesystem_abort {
+            break;
         }
-    } else {
-        #[cfg(debug_assertions)]
-        println!("The command ran without any errors!! :)");
     }
+
+    Ok((errors, video_timings))
 }
 
-/// Returns whether it makes sense to try downloading the video again
-fn is_recoverable(error: &YtdlpError, table: &HashMap<&'static str, bool>) -> bool {
-    if error.error_msg().contains(VIDEO_UNAVAILABLE) {
-        return false;
+/// What is done with the files a yt-dlp process downloaded, in the first run and in the retries alike:
+/// the plugin, the tags, --print-paths, the list of video-only files and --write-checksums
+fn finish_downloads(downloaded_files: &[String], download_config: &config::DownloadConfig, cli_config: &parser::CliConfig) {
+    plugin::post_process_all(downloaded_files, download_config);
+    postprocess::tag_all(downloaded_files, download_config);
+    print_paths(downloaded_files, cli_config);
+    list_video_only_files(downloaded_files, download_config, cli_config.verbosity());
+    if cli_config.write_checksums() {
+        checksum::write_all(downloaded_files);
     }
-    if let Some(result) = table.get(error.error_msg().as_str()) {
-        if *result == false {
-            // The error is documented and unrecoverable
-            false
+}
+
+/// Points out the files of a video-only download, they have no sound track and are often mistaken for normal videos
+fn list_video_only_files(downloaded_files: &[String], download_config: &config::DownloadConfig, verbosity: &parser::Verbosity) {
+    if download_config.is_video_only() && !downloaded_files.is_empty() && *verbosity != parser::Verbosity::Quiet {
+        eprintln!("\n{}", VIDEO_ONLY_FILES.bold().cyan());
+        for file in downloaded_files {
+            eprintln!("   {} {}", file, NO_AUDIO_TAG.yellow());
+        }
+    }
+}
+
+/// Where a video can be downloaded from, given its id
+const VIDEO_URL_PREFIX: &str = "https://www.youtube.com/watch?v=";
+
+/// The yt-dlp processes of a playlist split with --concurrent-playlist-downloads share its --error-file
+static ERROR_FILE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Adds the url of the video which failed to --error-file as soon as the error is parsed
+///
+/// Errors which don't say which video failed aren't recorded, the url of their whole download would download it all again
+fn record_failed_url(error_file: Option<&Path>, error: &YtdlpError) {
+    if let (Some(error_file), Some(video_id)) = (error_file, error.video_id()) {
+        let _guard = ERROR_FILE_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Err(err) = append_to_error_file(error_file, &format!("{}{}", VIDEO_URL_PREFIX, video_id)) {
+            eprintln!("{}: {} ({})", "ERROR".red(), err, error_file.display());
+        }
+    }
+}
+
+/// Takes a video which was downloaded out of --error-file, a retry (or a later run reading the file with --url-file) fixed it
+fn forget_failed_url(error_file: Option<&Path>, video_id: Option<&str>) {
+    if let (Some(error_file), Some(video_id)) = (error_file, video_id) {
+        let _guard = ERROR_FILE_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Err(err) = remove_from_error_file(error_file, &format!("{}{}", VIDEO_URL_PREFIX, video_id)) {
+            eprintln!("{}: {} ({})", "ERROR".red(), err, error_file.display());
+        }
+    }
+}
+
+/// The lines of the file at `path`, empty if it doesn't exist
+fn read_error_file(path: &Path) -> BlobResult<String> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => Ok(contents),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Appends `url` on its own line to the file at `path`, which is created if it doesn't exist
+///
+/// A url which is already listed isn't added again, so the file can be passed back to --url-file as it is
+fn append_to_error_file(path: &Path, url: &str) -> BlobResult<()> {
+    if read_error_file(path)?.lines().any(|line| line.trim() == url) {
+        return Ok(());
+    }
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", url)?;
+    Ok(())
+}
+
+/// Removes the lines of the file at `path` which are `url`, the file is only rewritten if it lists it
+fn remove_from_error_file(path: &Path, url: &str) -> BlobResult<()> {
+    let contents = read_error_file(path)?;
+    if !contents.lines().any(|line| line.trim() == url) {
+        return Ok(());
+    }
+    let kept: String = contents.lines().filter(|line| line.trim() != url).map(|line| format!("{}\n", line)).collect();
+    std::fs::write(path, kept)?;
+    Ok(())
+}
+
+/// With --print-paths, prints the final path of every downloaded file on stdout, one per line
+///
+/// Everything else blob-dl shows goes to stderr, so stdout can be piped to other programs
+fn print_paths(downloaded_files: &[String], cli_config: &parser::CliConfig) {
+    if cli_config.print_paths() {
+        for file in downloaded_files {
+            println!("{}", file);
+        }
+    }
+}
+
+/// Shows what happened to every url, the slowest videos and the videos which couldn't be post-processed
+fn report_results(downloads: &[(Command, config::DownloadConfig)], errors: &[(usize, YtdlpError)], video_timings: &[VideoTiming],
+                  verbosity: &parser::Verbosity)
+{
+    if downloads.len() > 1 {
+        print_summary(downloads, errors);
+    }
+
+    if video_timings.len() > 1 && *verbosity != parser::Verbosity::Quiet {
+        print_slowest(video_timings);
+    }
+
+    report_post_processing_failures(errors, downloads);
+
+    report_filesystem_errors(errors, downloads);
+
+    report_premium_only(errors, downloads);
+}
+
+/// Counts the tracks of every download which need YouTube Music Premium, they aren't listed one by one with the other errors
+fn report_premium_only(errors: &[(usize, YtdlpError)], downloads: &[(Command, config::DownloadConfig)]) {
+    for (i, (_, download_config)) in downloads.iter().enumerate() {
+        let count = errors.iter().filter(|(download_index, error)| *download_index == i && error.is_premium_only()).count();
+        if count == 0 {
+            continue;
+        }
+
+        if downloads.len() > 1 {
+            eprintln!("\n{} {} ({})", count, PREMIUM_ONLY_SUMMARY.yellow(), download_config.url());
         } else {
-            // The error is documented and recoverable
-            true
+            eprintln!("\n{} {}", count, PREMIUM_ONLY_SUMMARY.yellow());
         }
-    } else {
-        // By default undocumented errors are flagged as recoverable
-        true
     }
 }
 
-/// A list of all the documented youtube error messages and whether they are recoverable.
-fn init_error_msg_lut() -> HashMap<&'static str, bool> {
-    HashMap::from([
-        (PRIVATE_VIDEO,          false),
-        (NONEXISTENT_PLAYLIST,   false),
-        (HOMEPAGE_REDIRECT,      false),
-        (VIOLENT_VIDEO,          false),
-        (REMOVED_VIDEO,          false),
-        (YTDLP_GAVE_UP,          false),
-        (VIDEO_NOT_FOUND,        false),
-        (NETWORK_FAIL,           true),
-        (NO_API_PAGE,            false),
-        (ENCODER_STREAM_ERROR,   false),
-        (NONEXISTENT_VIDEO,   false),
-    ])
+/// Points at the output folders the OS refused to write to, along with what it said
+fn report_filesystem_errors(errors: &[(usize, YtdlpError)], downloads: &[(Command, config::DownloadConfig)]) {
+    let mut folders: Vec<(&String, &str)> = errors.iter()
+        .filter_map(|(i, error)| Some((downloads[*i].1.output_path(), error.filesystem_error()?)))
+        .collect();
+    folders.sort_unstable();
+    folders.dedup();
+
+    if !folders.is_empty() {
+        eprintln!("\n{}", FILESYSTEM_ERROR_HINT.yellow());
+        for (folder, reason) in folders {
+            eprintln!("   {} ({})", folder, reason);
+        }
+    }
 }
 
-/// Runs the command and displays the output to the console.
+/// Retries the videos which need the browser's cookies, a proxy, shorter file names or another player client right away,
+/// after asking for them
 ///
-/// If yt-dlp runs into any errors, they are returned in a vector of Ytdlp errors (parsed Strings)
-fn run_command(command: &mut Command, verbosity: &parser::Verbosity) -> Option<Vec<YtdlpError>> {
-    // Run the command and capture its output
-    let mut youtube_dl = command.stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .expect("Failed to start yt-dlp process");
+/// With --auto-fallback-client the player client is switched without asking
+///
+/// Returns the errors which are still there, the videos the retries fixed are added to `video_timings`
+fn retry_with_fixes(mut errors: Vec<(usize, YtdlpError)>, downloads: &[(Command, config::DownloadConfig)],
+                    video_timings: &mut Vec<VideoTiming>, cli_config: &parser::CliConfig, shutdown: &Shutdown) -> Vec<(usize, YtdlpError)>
+{
+    let verbosity = cli_config.verbosity();
+    let lut = init_error_msg_lut();
 
-    let stdout = BufReader::new(youtube_dl.stdout.take().unwrap());
-    let stderr = BufReader::new(youtube_dl.stderr.take().unwrap());
+    // Videos youtube blocked for yt-dlp's player client can be retried right away with another client
+    if errors.iter().any(|(_, error)| classify_error(error, &lut, verbosity) == ErrorRecoverability::RecoverableWithOtherClient) {
+        let ask = |term: &Term| if cli_config.auto_fallback_client() {
+            Ok(Some(youtube::FALLBACK_PLAYER_CLIENT.to_string()))
+        } else {
+            prompt_fallback_client(term)
+        };
+        let (still_failing, fixed) = retry_with_setting(errors, downloads, ErrorRecoverability::RecoverableWithOtherClient, ask,
+                                                        |config, client| config.client.player_client = Some(client.to_string()), cli_config, shutdown);
+        errors = still_failing;
+        video_timings.extend(fixed);
 
-    // All the errors produced by yt-dlp
-    let mut errors: Vec<YtdlpError> = vec![];
+        if shutdown.is_interrupted() {
+            return errors;
+        }
+    }
 
-    match verbosity {
-        parser::Verbosity::Quiet   => {
-            // This has to be run or the command does nothing
-            for line in stdout.lines().chain(stderr.lines()) {
-                let line = line.unwrap();
+    // Videos which require signing in can be retried right away with the browser's cookies
+    if errors.iter().any(|(_, error)| classify_error(error, &lut, verbosity) == ErrorRecoverability::RecoverableWithAuth) {
+        let (still_failing, fixed) = retry_with_setting(errors, downloads, ErrorRecoverability::RecoverableWithAuth, prompt_browser_selection,
+                                                        |config, browser| config.cookies_from_browser = Some(secrets::Secret::new(browser)), cli_config, shutdown);
+        errors = still_failing;
+        video_timings.extend(fixed);
 
-                // Keep track of errors without displaying anything
-                if line.contains("ERROR:") {
-                    errors.push(YtdlpError::from_error_output(&line));
-                }
+        if shutdown.is_interrupted() {
+            return errors;
+        }
+    }
+
+    // A stale format id makes every video fail, the format can be remapped to the current ids
+    if errors.iter().any(|(_, error)| classify_error(error, &lut, verbosity) == ErrorRecoverability::RecoverableWithRemappedFormat) {
+        let (still_failing, fixed) = retry_with_remapped_formats(errors, downloads, cli_config, shutdown);
+        errors = still_failing;
+        video_timings.extend(fixed);
+
+        if shutdown.is_interrupted() {
+            return errors;
+        }
+    }
+
+    // Geo-restricted videos can be retried right away through a proxy
+    if errors.iter().any(|(_, error)| classify_error(error, &lut, verbosity) == ErrorRecoverability::RecoverableWithProxy) {
+        let (still_failing, fixed) = retry_with_setting(errors, downloads, ErrorRecoverability::RecoverableWithProxy, prompt_proxy,
+                                                        |config, proxy| config.proxy = Some(secrets::Secret::new(proxy)), cli_config, shutdown);
+        errors = still_failing;
+        video_timings.extend(fixed);
+
+        if shutdown.is_interrupted() {
+            return errors;
+        }
+    }
+
+    // Names the filesystem rejected can be retried right away with shortened titles, unless they already were
+    let (already_shortened, mut errors): (Vec<_>, Vec<_>) = errors.into_iter()
+        .partition(|(i, error)| classify_error(error, &lut, verbosity) == ErrorRecoverability::RecoverableWithShorterNames
+            && !shortening_titles_helps(&downloads[*i].1));
+    if errors.iter().any(|(_, error)| classify_error(error, &lut, verbosity) == ErrorRecoverability::RecoverableWithShorterNames) {
+        let (still_failing, fixed) = retry_with_setting(errors, downloads, ErrorRecoverability::RecoverableWithShorterNames, prompt_shorter_names,
+                                                        |config, bytes| config.max_title_bytes = Some(*bytes), cli_config, shutdown);
+        errors = still_failing;
+        video_timings.extend(fixed);
+    }
+    errors.extend(already_shortened);
+
+    errors
+}
+
+/// Whether titles are longer in file names than the retry would shorten them to, --max-title-bytes can already be lower
+fn shortening_titles_helps(download_config: &config::DownloadConfig) -> bool {
+    download_config.max_title_bytes.is_none_or(|bytes| bytes > config::DEFAULT_MAX_TITLE_BYTES)
+}
+
+/// Retries the videos which failed: all of them with --auto-retry-all, otherwise the ones the user picks
+fn retry_failed_downloads(errors: &[(usize, YtdlpError)], downloads: &[(Command, config::DownloadConfig)],
+                          cli_config: &parser::CliConfig, shutdown: &Shutdown)
+{
+    let verbosity = cli_config.verbosity();
+
+    if !errors.is_empty() && cli_config.auto_retry_all() {
+        // Nobody is there to answer the prompt
+        retry_all_recoverable(errors, downloads, cli_config, shutdown);
+
+        if shutdown.is_interrupted() {
+            clean_up_partial_files(downloads, shutdown);
+        }
+    } else if !errors.is_empty() {
+        // Some videos could not be downloaded, ask the user which ones they want to try to re-download
+        let (user_selection, alternate_path) = match ask_for_redownload(errors, downloads, cli_config) {
+            Ok(answer) => answer,
+            Err(err) => {
+                eprintln!("{}: {}", "ERROR".red(), err);
+                return;
             }
-        },
+        };
 
-        parser::Verbosity::Default => {
-            for line in stdout.lines().chain(stderr.lines()) {
-                let line = line.unwrap();
+        // The videos that have to be downloaded again, along with the index of the download they belong to
+        let mut to_be_downloaded = Vec::new();
 
-                // Only show download/error lines
-                if line.contains("[download]") {
-                    println!("{}", line);
-                } else if line.contains("ERROR:") {
-                    errors.push(YtdlpError::from_error_output(&line));
-                    // Color error messages red
-                    println!("{}", line.red());
-                }
+        for error_index in user_selection {
+            let (download_index, error) = &errors[error_index];
+            // There is nothing to retry if yt-dlp didn't say which video failed
+            if let Some(video_id) = error.video_id() {
+                verbosity.debug(&format!("Scheduling a retry for {}", video_id));
+                to_be_downloaded.push((*download_index, video_id));
             }
-        },
+        }
+        // Files which were saved in alternate_path instead of their usual directory
+        let mut relocated_files = Vec::new();
 
-        parser::Verbosity::Verbose => {
-            // Print to the console everything that yt-dlp is doing
-            for line in stdout.lines().chain(stderr.lines()) {
-                let line = line.unwrap();
+        // Re-download every video while keeping the current command configuration (quality, naming preference, ...)
+        for (i, output) in run_retries(&to_be_downloaded, |i| &downloads[i].1, alternate_path.as_deref(), verbosity, shutdown) {
+            finish_downloads(&output.downloaded_files, &downloads[i].1, cli_config);
 
-                if line.contains("ERROR:") {
-                    errors.push(YtdlpError::from_error_output(&line));
-                    // Color error messages red
-                    println!("{}", line.red());
-                } else {
-                    println!("{}", line);
-                }
+            if alternate_path.is_some() {
+                relocated_files.extend(output.downloaded_files);
             }
         }
-    }
 
-    if errors.is_empty() {
-        None
+        if shutdown.is_interrupted() {
+            clean_up_partial_files(downloads, shutdown);
+            return;
+        }
+
+        if let Some(path) = alternate_path {
+            eprintln!("\n{} {}", ALTERNATE_PATH_SUMMARY.bold().cyan(), path);
+            for file in relocated_files {
+                eprintln!("   {}", file);
+            }
+        }
     } else {
-        Some(errors)
+        #[cfg(debug_assertions)]
+        eprintln!("The command ran without any errors!! :)");
     }
 }
 
-/// Shows the user which videos could not be downloaded and returns which have to be re-downloaded based on what the user wants
+/// Re-downloads `retries` (pairs of download index and video id), every video with the configuration `config_of` gives for its download
 ///
-/// Returns a Vec containing which errors the user wants to re-download
-fn ask_for_redownload(errors: &Vec<YtdlpError>) -> Vec<usize> {
-    let term = Term::buffered_stderr();
+/// The videos of the same download share their configuration, so they are retried by a single yt-dlp process which reads
+/// their ids from a batch file, instead of paying yt-dlp's startup for every video. A download with a single video to retry,
+/// or whose batch file can't be written, gets a command per video
+///
+/// Returns the output of every process with the index of its download, it stops early if blob-dl is interrupted
+/// or if yt-dlp can't be started
+fn run_retries<'a>(retries: &[(usize, &str)], config_of: impl Fn(usize) -> &'a config::DownloadConfig, output_path: Option<&str>,
+                   verbosity: &parser::Verbosity, shutdown: &Shutdown) -> Vec<(usize, RunOutput)>
+{
+    let mut outputs = vec![];
+    for mut retry in handle_errors(retries, &config_of, output_path) {
+        if let Some(batch_file) = &retry.batch_file {
+            verbosity.debug(&format!("Retrying the videos listed in {} with a single yt-dlp process", batch_file.display()));
+        }
 
-    // Initialize a lut, which contains all documented errors and whether they can be recovered from
+        let download_config = config_of(retry.download_index);
+        let keys = KeyReader::start(&shutdown.pause);
+        let output = run_command(&mut retry.command, verbosity, download_config.speed_graph, download_config.error_file.as_deref(), shutdown);
+        drop(keys);
+
+        match output {
+            Ok(output) => outputs.push((retry.download_index, output)),
+            // The other retries would fail the same way
+            Err(err) => {
+                eprintln!("{}: {}", "ERROR".red(), err);
+                break;
+            }
+        }
+
+        if shutdown.is_interrupted() {
+            break;
+        }
+    }
+
+    outputs
+}
+
+/// A yt-dlp command which downloads again some of the videos of a download
+#[derive(Debug)]
+struct RetryCommand {
+    download_index: usize,
+    command: Command,
+    /// The file the command reads the videos' ids from, it's deleted with the command whether it ran or not
+    batch_file: Option<PathBuf>,
+}
+
+impl Drop for RetryCommand {
+    fn drop(&mut self) {
+        if let Some(batch_file) = &self.batch_file {
+            let _ = std::fs::remove_file(batch_file);
+        }
+    }
+}
+
+/// Turns the videos to retry (pairs of download index and video id) into the commands which download them again,
+/// in the order their downloads were started in
+fn handle_errors<'a>(retries: &[(usize, &str)], config_of: impl Fn(usize) -> &'a config::DownloadConfig, output_path: Option<&str>)
+                     -> Vec<RetryCommand>
+{
+    let mut grouped: Vec<(usize, Vec<&str>)> = vec![];
+    for (download_index, video_id) in retries {
+        match grouped.iter_mut().find(|(i, _)| i == download_index) {
+            Some((_, ids)) => ids.push(video_id),
+            None => grouped.push((*download_index, vec![video_id])),
+        }
+    }
+
+    let mut commands = vec![];
+    for (download_index, ids) in grouped {
+        let download_config = config_of(download_index);
+
+        let batch_file = if ids.len() > 1 { write_batch_file(download_index, &ids) } else { None };
+
+        match batch_file {
+            Some(batch_file) => commands.push(RetryCommand {
+                download_index,
+                command: download_config.build_command_for_videos(&batch_file, output_path),
+                batch_file: Some(batch_file),
+            }),
+            None => commands.extend(ids.iter().map(|id| RetryCommand {
+                download_index,
+                command: download_config.build_command_for_video(id, output_path),
+                batch_file: None,
+            })),
+        }
+    }
+
+    commands
+}
+
+/// Writes the ids of the videos of a download which are retried together in a temporary file, one per line
+///
+/// Returns None if the file can't be written
+fn write_batch_file(download_index: usize, video_ids: &[&str]) -> Option<PathBuf> {
+    let path = std::env::temp_dir().join(format!("blob-dl-retry-{}-{}.txt", std::process::id(), download_index));
+    std::fs::write(&path, video_ids.join("\n")).ok()?;
+    Some(path)
+}
+
+/// Runs the yt-dlp command of a download, with --concurrent-playlist-downloads playlists are split
+/// into ranges which are downloaded by separate yt-dlp processes at the same time
+///
+/// The output of every process is merged, their lines are shown as they arrive
+fn run_download(command: &mut Command, download_config: &config::DownloadConfig, cli_config: &parser::CliConfig, shutdown: &Shutdown)
+                -> BlobResult<RunOutput> {
+    let verbosity = cli_config.verbosity();
+
+    let chunks = match download_config.playlist_chunks(cli_config.concurrent_playlist_downloads()) {
+        Some(chunks) if chunks.len() > 1 => chunks,
+        _ => return run_command(command, verbosity, download_config.speed_graph, download_config.error_file.as_deref(), shutdown),
+    };
+
+    verbosity.debug(&format!("Splitting the playlist between {} yt-dlp processes: {:?}", chunks.len(), chunks));
+
+    // CTRL+C reaches every yt-dlp process on its own, they share blob-dl's process group
+    let outputs: Vec<BlobResult<RunOutput>> = thread::scope(|scope| {
+        let workers: Vec<_> = chunks.iter()
+            .map(|(first, last)| {
+                let mut chunk_command = download_config.build_command_for_range(*first, *last);
+                scope.spawn(move || run_command(&mut chunk_command, verbosity, false, download_config.error_file.as_deref(), shutdown))
+            })
+            .collect();
+
+        // A worker which panicked lost its errors, blob-dl can't report the download correctly without them
+        workers.into_iter()
+            .map(|worker| worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+            .collect()
+    });
+
+    let mut merged = RunOutput { errors: vec![], downloaded_files: vec![], playlist_progress: None, timings: vec![], info_json_files: vec![],
+                                 filesystem_abort: false };
+    for output in outputs {
+        let output = output?;
+        merged.errors.extend(output.errors);
+        merged.downloaded_files.extend(output.downloaded_files);
+        merged.timings.extend(output.timings);
+        merged.info_json_files.extend(output.info_json_files);
+        merged.filesystem_abort |= output.filesystem_abort;
+    }
+    // Progress is tracked per process, so there is no single video to resume from
+
+    Ok(merged)
+}
+
+/// Re-downloads every video which failed because of a recoverable error once, without asking the user
+///
+/// Unrecoverable errors are listed and skipped, at the end a summary of the retry is shown
+fn retry_all_recoverable(errors: &[(usize, YtdlpError)], downloads: &[(Command, config::DownloadConfig)],
+                         cli_config: &parser::CliConfig, shutdown: &Shutdown)
+{
+    let verbosity = cli_config.verbosity();
     let lut = init_error_msg_lut();
 
-    // The possible choices which will be presented to the user (all recoverable errors)
-    let mut user_options = Vec::new();
+    let (recoverable, unrecoverable): (Vec<_>, Vec<_>) = errors.iter()
+        .partition(|(_, error)| classify_error(error, &lut, verbosity) == ErrorRecoverability::RecoverableWithRetry);
 
-    let mut unrecoverable_errors = Vec::new();
+    // Premium-only tracks were already counted by report_results
+    let listed: Vec<_> = unrecoverable.iter().filter(|(_, error)| !error.is_premium_only()).collect();
+    if !listed.is_empty() {
+        eprintln!("{}", UNRECOVERABLE_ERROR_PROMPT.bold().cyan());
+        for (_, error) in listed {
+            eprintln!("   {}", error);
+        }
+    }
 
-    // Default options
-    user_options.push(String::from(SELECT_ALL));
-    user_options.push(String::from(SELECT_NOTHING));
+    // A video can fail with more than one recoverable error, it's only retried once
+    let mut seen = HashSet::new();
+    let retries: Vec<(usize, &str)> = recoverable.iter()
+        .filter_map(|(i, error)| error.video_id().map(|video_id| (*i, video_id)))
+        .filter(|(_, video_id)| seen.insert(*video_id))
+        .inspect(|(_, video_id)| verbosity.debug(&format!("Automatically retrying {}", video_id)))
+        .collect();
 
-    for error in errors {
-        if is_recoverable(error, &lut) {
-            // It makes sense to try a re-download
-            user_options.push(error.to_string())
-        } else {
-            // Don't bother asking to re-download the error
-            unrecoverable_errors.push(error);
+    let mut retry_errors = vec![];
+    for (i, output) in run_retries(&retries, |i| &downloads[i].1, None, verbosity, shutdown) {
+        finish_downloads(&output.downloaded_files, &downloads[i].1, cli_config);
+        retry_errors.extend(output.errors.into_iter().map(|error| (i, error)));
+    }
+
+    if shutdown.is_interrupted() {
+        return;
+    }
+
+    let summary = AutoRetrySummary::new(errors, retries.len(), &retry_errors);
+    eprintln!("\n{}", AUTO_RETRY_SUMMARY.bold().cyan());
+    eprintln!("   Originally failed: {}", summary.originally_failed);
+    eprintln!("   Retried: {}", summary.retried);
+    eprintln!("   Still failing: {}", summary.still_failing);
+}
+
+/// What an automatic retry achieved, counted in videos: yt-dlp can report more than one error for the same video
+#[derive(Debug, PartialEq)]
+struct AutoRetrySummary {
+    originally_failed: usize,
+    retried: usize,
+    still_failing: usize,
+}
+
+impl AutoRetrySummary {
+    /// `retried` videos out of the ones which failed with `errors` were downloaded again, and failed with `retry_errors`
+    fn new(errors: &[(usize, YtdlpError)], retried: usize, retry_errors: &[(usize, YtdlpError)]) -> AutoRetrySummary {
+        let originally_failed = failed_videos(errors).len();
+        // The videos which weren't retried are still failing
+        let still_failing = originally_failed.saturating_sub(retried) + failed_videos(retry_errors).len();
+
+        AutoRetrySummary { originally_failed, retried, still_failing }
+    }
+}
+
+/// Tells the user where the streams of videos which couldn't be post-processed are,
+/// or suggests keeping them next time if they were deleted
+fn report_post_processing_failures(errors: &[(usize, YtdlpError)], downloads: &[(Command, config::DownloadConfig)]) {
+    let report = PostProcessingReport::new(errors, |i| downloads[i].1.keep_video);
+
+    for (video_id, streams) in report.kept_streams {
+        eprintln!("{} {}", video_id, KEPT_STREAMS.yellow());
+        for stream in streams {
+            eprintln!("   {}", stream);
         }
     }
 
-    if !unrecoverable_errors.is_empty() {
-        println!("{}", UNRECOVERABLE_ERROR_PROMPT.bold().cyan());
-        for error in unrecoverable_errors {
-            println!("   {}", error);
+    if report.suggest_keep_video {
+        eprintln!("{}", POST_PROCESSING_HINT.yellow());
+    }
+}
+
+/// What the user is told about the videos which couldn't be post-processed
+#[derive(Debug, PartialEq)]
+struct PostProcessingReport<'a> {
+    /// The video id and the streams yt-dlp kept, for every video downloaded with --keep-video
+    kept_streams: Vec<(&'a str, &'a [String])>,
+    /// Whether the streams of a video were deleted, --keep-video would have kept them
+    suggest_keep_video: bool,
+}
+
+impl<'a> PostProcessingReport<'a> {
+    /// `keeps_video` tells whether the download at an index was run with --keep-video
+    fn new(errors: &'a [(usize, YtdlpError)], keeps_video: impl Fn(usize) -> bool) -> PostProcessingReport<'a> {
+        let mut report = PostProcessingReport { kept_streams: vec![], suggest_keep_video: false };
+
+        for (i, error) in errors {
+            if let YtdlpErrorKind::PostProcessing { streams, .. } = error.kind() {
+                if keeps_video(*i) && !streams.is_empty() {
+                    report.kept_streams.push((error.video_id().unwrap_or(UNKNOWN_VIDEO_ID), streams));
+                } else {
+                    report.suggest_keep_video = true;
+                }
+            }
+        }
+        report
+    }
+}
+
+/// Deletes the old copies the user chose to replace (see yt_playlist::handle_extension_conflicts),
+/// but only the ones whose new copy was actually downloaded
+fn remove_replaced_files(downloaded_files: &[String], download_config: &config::DownloadConfig) {
+    for old_file in &download_config.replaced_files {
+        let replaced = downloaded_files.iter()
+            .map(Path::new)
+            .any(|new_file| new_file.file_stem() == old_file.file_stem() && new_file.parent() == old_file.parent() && new_file != old_file);
+
+        if replaced {
+            match std::fs::remove_file(old_file) {
+                Ok(()) => eprintln!("{}: {}", REPLACED_FILE, old_file.display()),
+                Err(err) => eprintln!("{}: {} ({})", "ERROR".red(), err, old_file.display()),
+            }
         }
     }
+}
 
-    if user_options.len() > 2 {
-        // If user_options has only 2 elements there aren't any videos to re-download
-        let user_selection = MultiSelect::with_theme(&ColorfulTheme::default())
-            .with_prompt(ERROR_RETRY_PROMPT)
-            .items(&user_options[..])
-            .interact_on(&term).unwrap();
+/// How many of the slowest videos are listed at the end
+const SLOWEST_COUNT: usize = 5;
+
+/// How long a video took to download, from the moment yt-dlp started working on it to the end of post-processing
+#[derive(Debug, Clone, PartialEq)]
+struct VideoTiming {
+    /// The final file's name, without the directory
+    title: String,
+    /// The final file's size, None if it couldn't be read
+    bytes: Option<Bytes>,
+    duration: Duration,
+}
+
+impl VideoTiming {
+    /// Describes the video `file`, which yt-dlp started working on at `started`
+    fn finished(file: &str, started: Instant) -> VideoTiming {
+        let path = Path::new(file);
+        VideoTiming {
+            title: path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_else(|| file.to_string()),
+            bytes: std::fs::metadata(path).ok().map(|metadata| Bytes(metadata.len())),
+            duration: started.elapsed(),
+        }
+    }
 
-        println!("{}", DEBUG_REPORT_PROMPT);
-        return user_selection
+    /// Average speed in bytes per second
+    fn speed(&self) -> Option<f64> {
+        let seconds = self.duration.as_secs_f64();
+        self.bytes.filter(|_| seconds > 0.0).map(|bytes| u64::from(bytes) as f64 / seconds)
     }
+}
 
-    // The user didn't choose any options so an empty Vec is returned
-    Vec::new()
+/// Returns the `count` videos which took the longest, slowest first
+fn slowest(timings: &[VideoTiming], count: usize) -> Vec<&VideoTiming> {
+    let mut sorted: Vec<&VideoTiming> = timings.iter().collect();
+    sorted.sort_by_key(|timing| std::cmp::Reverse(timing.duration));
+    sorted.truncate(count);
+    sorted
+}
+
+/// Lists the videos which took the longest to download, with their size and average speed
+fn print_slowest(timings: &[VideoTiming]) {
+    eprintln!("\n{}", SLOWEST_VIDEOS_SUMMARY.bold().cyan());
+
+    for timing in slowest(timings, SLOWEST_COUNT) {
+        let size = timing.bytes.map(|bytes| bytes.to_string()).unwrap_or_else(|| String::from("?"));
+        let speed = timing.speed().map(|speed| format!("{:.2}MB/s", speed / 1_000_000.0)).unwrap_or_else(|| String::from("?"));
+        eprintln!("   {} | {} | {} | {}", timing.title, size, format_timestamp(timing.duration.as_secs_f64()), speed);
+    }
+}
+
+/// Tells the user how many errors each url ran into
+fn print_summary(downloads: &[(Command, config::DownloadConfig)], errors: &[(usize, YtdlpError)]) {
+    eprintln!("\n{}", MULTIPLE_URLS_SUMMARY.bold().cyan());
+
+    for (i, (_, download_config)) in downloads.iter().enumerate() {
+        let error_count = errors.iter().filter(|(download_index, _)| *download_index == i).count();
+        if download_config.is_video_only() {
+            eprintln!("   {} {}: {} error(s)", download_config.url(), NO_AUDIO_TAG.yellow(), error_count);
+        } else {
+            eprintln!("   {}: {} error(s)", download_config.url(), error_count);
+        }
+    }
+}
+
+/// Whether the video can only be downloaded by a signed-in user
+fn needs_authentication(error: &YtdlpError) -> bool {
+    error.error_msg().contains(SIGN_IN_TO_CONFIRM_AGE)
+}
+
+/// Re-downloads the videos whose errors are classified as `recoverability`, with a setting asked to the user
+/// (like a browser to take cookies from) which `apply` adds to a copy of their configuration
+///
+/// Returns the errors which are still there (the other ones and the ones the retry didn't fix) and how long the videos it fixed took
+fn retry_with_setting<T: std::fmt::Display>(errors: Vec<(usize, YtdlpError)>, downloads: &[(Command, config::DownloadConfig)],
+                                            recoverability: ErrorRecoverability,
+                                            ask: impl FnOnce(&Term) -> BlobResult<Option<T>>,
+                                            apply: impl Fn(&mut config::DownloadConfig, &T),
+                                            cli_config: &parser::CliConfig, shutdown: &Shutdown) -> (Vec<(usize, YtdlpError)>, Vec<VideoTiming>)
+{
+    let verbosity = cli_config.verbosity();
+    let lut = init_error_msg_lut();
+    let (to_retry, mut other_errors): (Vec<_>, Vec<_>) = errors.into_iter()
+        .partition(|(_, error)| classify_error(error, &lut, verbosity) == recoverability);
+
+    let term = Term::buffered_stderr();
+    let setting = match ask(&term) {
+        Ok(Some(setting)) => setting,
+        // The user doesn't want to use the setting, the errors are left as they are
+        _ => {
+            other_errors.extend(to_retry);
+            return (other_errors, vec![]);
+        }
+    };
+
+    // Keep every other preference the user chose
+    let retry_configs: Vec<config::DownloadConfig> = downloads.iter()
+        .map(|(_, download_config)| {
+            let mut retry_config = download_config.clone();
+            apply(&mut retry_config, &setting);
+            retry_config
+        })
+        .collect();
+
+    let retries: Vec<(usize, &str)> = to_retry.iter()
+        .filter_map(|(i, error)| error.video_id().map(|video_id| (*i, video_id)))
+        .inspect(|(_, video_id)| verbosity.debug(&format!("Retrying {} with {}", video_id, setting)))
+        .collect();
+
+    let mut fixed = vec![];
+    for (i, output) in run_retries(&retries, |i| &retry_configs[i], None, verbosity, shutdown) {
+        finish_downloads(&output.downloaded_files, &retry_configs[i], cli_config);
+        fixed.extend(output.timings);
+        other_errors.extend(output.errors.into_iter().map(|error| (i, error)));
+    }
+
+    (other_errors, fixed)
+}
+
+/// Re-downloads the videos which failed because youtube changed its format ids after the wizard fetched them,
+/// with the closest current equivalent of the chosen format (see youtube::refresh_stale_format)
+///
+/// Only downloads with at least STALE_FORMAT_MIN_FAILURES such failures are remapped, the formats of their first
+/// failing video are fetched again. Returns the errors which are still there and how long the videos it fixed took
+fn retry_with_remapped_formats(errors: Vec<(usize, YtdlpError)>, downloads: &[(Command, config::DownloadConfig)],
+                               cli_config: &parser::CliConfig, shutdown: &Shutdown) -> (Vec<(usize, YtdlpError)>, Vec<VideoTiming>)
+{
+    let verbosity = cli_config.verbosity();
+    let lut = init_error_msg_lut();
+    let term = Term::buffered_stderr();
+
+    let mut retry_configs: Vec<config::DownloadConfig> = downloads.iter().map(|(_, download_config)| download_config.clone()).collect();
+    let mut remapped = vec![false; downloads.len()];
+
+    for (i, (_, download_config)) in downloads.iter().enumerate() {
+        let stale: Vec<&str> = errors.iter()
+            .filter(|(index, error)| *index == i && classify_error(error, &lut, verbosity) == ErrorRecoverability::RecoverableWithRemappedFormat)
+            .filter_map(|(_, error)| error.video_id())
+            .collect();
+        // A single video missing the format is more likely a problem with that video
+        if stale.len() < STALE_FORMAT_MIN_FAILURES {
+            continue;
+        }
+
+        eprintln!("\n{} {}", STALE_FORMAT_FOUND.yellow(), download_config.url());
+        eprintln!("   {}", stale.join(", "));
+
+        let format = match youtube::refresh_stale_format(download_config, stale[0], cli_config) {
+            Ok(Some(format)) => format,
+            Ok(None) => {
+                eprintln!("{} {}", NO_REMAPPED_FORMAT, download_config.url());
+                continue;
+            }
+            Err(err) => {
+                eprintln!("{}: {}", "ERROR".red(), err);
+                continue;
+            }
+        };
+
+        if let Some(chosen) = &download_config.exact_format {
+            eprintln!("   Chosen:  {}", chosen);
+        }
+        eprintln!("   Closest: {}", format);
+
+        if cli_config.assume_yes() || prompt_remapped_format(&term).unwrap_or(false) {
+            verbosity.debug(&format!("Remapping the format of {} to {}", download_config.url(), format.format_id()));
+            retry_configs[i].remap_exact_format(format);
+            remapped[i] = true;
+        }
+    }
+
+    let (to_retry, mut other_errors): (Vec<_>, Vec<_>) = errors.into_iter()
+        .partition(|(i, error)| remapped[*i] && classify_error(error, &lut, verbosity) == ErrorRecoverability::RecoverableWithRemappedFormat);
+
+    let retries: Vec<(usize, &str)> = to_retry.iter()
+        .filter_map(|(i, error)| error.video_id().map(|video_id| (*i, video_id)))
+        .inspect(|(_, video_id)| verbosity.debug(&format!("Retrying {} with the remapped format", video_id)))
+        .collect();
+
+    let mut fixed = vec![];
+    for (i, output) in run_retries(&retries, |i| &retry_configs[i], None, verbosity, shutdown) {
+        finish_downloads(&output.downloaded_files, &retry_configs[i], cli_config);
+        fixed.extend(output.timings);
+        other_errors.extend(output.errors.into_iter().map(|error| (i, error)));
+    }
+
+    (other_errors, fixed)
+}
+
+/// Asks whether the videos whose format went stale should be downloaded again with the closest current format
+fn prompt_remapped_format(term: &Term) -> BlobResult<bool> {
+    let user_selection = dialoguer::Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(REMAPPED_FORMAT_PROMPT)
+        .default(0)
+        .items(&["Yes", "No"])
+        .interact_on(term)?;
+
+    Ok(user_selection == 0)
+}
+
+/// Asks for a proxy yt-dlp should use to download geo-restricted videos
+///
+/// Returns None if the user doesn't want to use a proxy
+fn prompt_proxy(term: &Term) -> BlobResult<Option<String>> {
+    let proxy: String = dialoguer::Input::with_theme(&ColorfulTheme::default())
+        .with_prompt(PROXY_PROMPT)
+        .allow_empty(true)
+        .interact_text_on(term)?;
+
+    let proxy = proxy.trim();
+    Ok(if proxy.is_empty() { None } else { Some(proxy.to_string()) })
+}
+
+/// Asks whether the videos youtube blocked for yt-dlp's player client should be retried with FALLBACK_PLAYER_CLIENT
+///
+/// Returns the client to retry with, None if the user doesn't want to switch
+fn prompt_fallback_client(term: &Term) -> BlobResult<Option<String>> {
+    let user_selection = dialoguer::Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(FALLBACK_CLIENT_PROMPT)
+        .default(0)
+        .items(&["Yes", "No"])
+        .interact_on(term)?;
+
+    Ok((user_selection == 0).then(|| youtube::FALLBACK_PLAYER_CLIENT.to_string()))
+}
+
+/// Asks whether the videos whose names were too long should be downloaded again with shortened titles
+///
+/// Returns how many bytes titles are shortened to, None if the user doesn't want them shortened
+fn prompt_shorter_names(term: &Term) -> BlobResult<Option<usize>> {
+    let user_selection = dialoguer::Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(SHORTER_NAMES_PROMPT)
+        .default(0)
+        .items(&["Yes", "No"])
+        .interact_on(term)?;
+
+    Ok((user_selection == 0).then_some(config::DEFAULT_MAX_TITLE_BYTES))
+}
+
+/// Asks which browser yt-dlp should extract cookies from
+///
+/// Returns None if the user doesn't want to use cookies
+fn prompt_browser_selection(term: &Term) -> BlobResult<Option<String>> {
+    let browsers = ["chrome", "firefox", "edge", "safari"];
+
+    let mut options: Vec<&str> = browsers.to_vec();
+    options.push(NO_COOKIES);
+
+    let user_selection = dialoguer::Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(COOKIES_PROMPT)
+        .default(0)
+        .items(&options)
+        .interact_on(term)?;
+
+    Ok(browsers.get(user_selection).map(|browser| browser.to_string()))
+}
+
+/// Whether it makes sense to try downloading a video again, and what could make the retry work
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ErrorRecoverability {
+    Unrecoverable,
+    /// Trying again as it is could work (network hiccups, ...)
+    RecoverableWithRetry,
+    /// The video needs a signed-in user, the browser's cookies can help
+    RecoverableWithAuth,
+    /// The video isn't available in the user's country, a proxy can help
+    RecoverableWithProxy,
+    /// The filesystem rejected the file's name, a shorter title can help
+    RecoverableWithShorterNames,
+    /// Youtube blocked yt-dlp's player client, another client can help
+    RecoverableWithOtherClient,
+    /// Youtube changed its format ids since the formats were fetched, the closest current format can help
+    RecoverableWithRemappedFormat,
+}
+
+/// The kind of error `error` is, as `blob-dl stats` groups them
+///
+/// Recoverable errors are grouped by what classify_error says can fix them, unrecoverable ones by why they failed
+fn error_category(error: &YtdlpError, table: &HashMap<&'static str, ErrorRecoverability>) -> &'static str {
+    match classify_error(error, table, &parser::Verbosity::Quiet) {
+        ErrorRecoverability::RecoverableWithRetry => "other",
+        ErrorRecoverability::RecoverableWithAuth => "needs sign-in",
+        ErrorRecoverability::RecoverableWithProxy => "geo-restricted",
+        ErrorRecoverability::RecoverableWithShorterNames => "file name too long",
+        ErrorRecoverability::RecoverableWithOtherClient => "blocked client",
+        ErrorRecoverability::RecoverableWithRemappedFormat => "format unavailable",
+        ErrorRecoverability::Unrecoverable => if let YtdlpErrorKind::PostProcessing { .. } = error.kind() {
+            "post-processing"
+        } else if error.is_premium_only() {
+            "premium only"
+        } else if error.filesystem_error().is_some() {
+            "filesystem"
+        } else {
+            "unavailable"
+        },
+    }
+}
+
+/// Decides whether it makes sense to try downloading the video again and how
+fn classify_error(error: &YtdlpError, table: &HashMap<&'static str, ErrorRecoverability>, verbosity: &parser::Verbosity) -> ErrorRecoverability {
+    if let YtdlpErrorKind::PostProcessing { .. } = error.kind() {
+        // The video was already downloaded, downloading it again would fail the same way
+        verbosity.debug(&format!("{} failed during post-processing, flagged as unrecoverable", error.video_id().unwrap_or(UNKNOWN_VIDEO_ID)));
+        return ErrorRecoverability::Unrecoverable;
+    }
+    if error.is_premium_only() {
+        verbosity.debug(&format!("{} needs YouTube Music Premium, flagged as unrecoverable", error.video_id().unwrap_or(UNKNOWN_VIDEO_ID)));
+        return ErrorRecoverability::Unrecoverable;
+    }
+    if needs_authentication(error) {
+        verbosity.debug(&format!("{} needs a signed-in user, flagged as recoverable with cookies", error.video_id().unwrap_or(UNKNOWN_VIDEO_ID)));
+        return ErrorRecoverability::RecoverableWithAuth;
+    }
+    if error.is_name_too_long() {
+        verbosity.debug(&format!("{}'s file name is too long, flagged as recoverable with a shorter title", error.video_id().unwrap_or(UNKNOWN_VIDEO_ID)));
+        return ErrorRecoverability::RecoverableWithShorterNames;
+    }
+    if error.error_msg().contains(FORMAT_UNAVAILABLE) {
+        verbosity.debug(&format!("{}'s format isn't available, flagged as recoverable with a remapped format", error.video_id().unwrap_or(UNKNOWN_VIDEO_ID)));
+        return ErrorRecoverability::RecoverableWithRemappedFormat;
+    }
+    // Geo-restriction messages also begin with VIDEO_UNAVAILABLE, so they are checked first
+    if error.error_msg().contains(GEO_RESTRICTED) {
+        verbosity.debug(&format!("{} is geo-restricted, flagged as recoverable with a proxy", error.video_id().unwrap_or(UNKNOWN_VIDEO_ID)));
+        return ErrorRecoverability::RecoverableWithProxy;
+    }
+    if let Some(reason) = error.filesystem_error() {
+        verbosity.debug(&format!("{} couldn't be written ({}), flagged as unrecoverable", error.video_id().unwrap_or(UNKNOWN_VIDEO_ID), reason));
+        return ErrorRecoverability::Unrecoverable;
+    }
+    if error.error_msg().contains(VIDEO_UNAVAILABLE) {
+        verbosity.debug(&format!("{} is unavailable, flagged as unrecoverable", error.video_id().unwrap_or(UNKNOWN_VIDEO_ID)));
+        return ErrorRecoverability::Unrecoverable;
+    }
+    if let Some(result) = table.get(error.error_msg()) {
+        verbosity.debug(&format!("{} failed with a documented error: {:?}", error.video_id().unwrap_or(UNKNOWN_VIDEO_ID), result));
+        return *result;
+    }
+    // The client warnings are about the whole video, so they only explain errors which nothing else explains
+    if error.client_blocked() {
+        verbosity.debug(&format!("{} was blocked for yt-dlp's player client, flagged as recoverable with another client", error.video_id().unwrap_or(UNKNOWN_VIDEO_ID)));
+        ErrorRecoverability::RecoverableWithOtherClient
+    } else {
+        // By default undocumented errors are flagged as recoverable
+        verbosity.debug(&format!("{} failed with an undocumented error, flagged as recoverable", error.video_id().unwrap_or(UNKNOWN_VIDEO_ID)));
+        ErrorRecoverability::RecoverableWithRetry
+    }
+}
+
+/// A list of all the documented youtube error messages and whether they are recoverable.
+fn init_error_msg_lut() -> HashMap<&'static str, ErrorRecoverability> {
+    use ErrorRecoverability::*;
+
+    HashMap::from([
+        (PRIVATE_VIDEO,          Unrecoverable),
+        (NONEXISTENT_PLAYLIST,   Unrecoverable),
+        (HOMEPAGE_REDIRECT,      Unrecoverable),
+        (VIOLENT_VIDEO,          Unrecoverable),
+        (REMOVED_VIDEO,          Unrecoverable),
+        (YTDLP_GAVE_UP,          Unrecoverable),
+        (VIDEO_NOT_FOUND,        Unrecoverable),
+        (NETWORK_FAIL,           RecoverableWithRetry),
+        (NO_API_PAGE,            Unrecoverable),
+        (ENCODER_STREAM_ERROR,   Unrecoverable),
+        (NONEXISTENT_VIDEO,      Unrecoverable),
+    ])
+}
+
+/// How many videos of a download have to fail with FORMAT_UNAVAILABLE for its format id to be considered stale
+const STALE_FORMAT_MIN_FAILURES: usize = 2;
+
+/// yt-dlp is considered stuck if it doesn't print anything for this long
+///
+/// When it announces a wait (--sleep-interval, --retry-sleep, ...) the wait is added to it, see announced_sleep
+const STALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What the threads reading yt-dlp's output send to run_command
+enum ReaderMessage {
+    Line(String),
+    /// yt-dlp wrote something which isn't a whole line yet, like a progress bar update ending in \r
+    Activity,
+}
+
+/// The result of waiting for yt-dlp's next line
+enum NextLine {
+    Line(String),
+    /// yt-dlp closed both stdout and stderr
+    Finished,
+    /// yt-dlp didn't write anything for too long
+    Stalled,
+}
+
+/// Reads stdout and stderr on two threads, their lines are sent through the same channel
+///
+/// The channel disconnects when both streams are closed. yt-dlp is held on the stdout thread when it announces
+/// a video while the run is paused: the lines can be read a while after yt-dlp printed them, by then it would be
+/// downloading the video already
+fn spawn_readers(stdout: impl Read + Send + 'static, stderr: impl Read + Send + 'static, pause: &Arc<PauseControl>) -> Receiver<ReaderMessage> {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+
+    // yt-dlp announces videos on stdout
+    let streams = [(Box::new(stdout) as Box<dyn Read + Send>, Some(Arc::clone(pause))), (Box::new(stderr), None)];
+    for (stream, pause) in streams {
+        let sender = sender.clone();
+        thread::spawn(move || {
+            let reader = BufReader::new(ActivityReader { inner: stream, sender: sender.clone() });
+            for line in reader.lines().map_while(Result::ok) {
+                let hold = line.starts_with(NEW_VIDEO_LINE) && pause.as_ref().is_some_and(|pause| pause.at_next_video());
+                if sender.send(ReaderMessage::Line(line)).is_err() {
+                    break;
+                }
+                if let Some(pause) = pause.as_ref().filter(|_| hold) {
+                    pause.hold();
+                }
+            }
+        });
+    }
+
+    receiver
+}
+
+/// Waits for the next line yt-dlp prints, giving up if nothing at all is written within `stall_timeout`
+fn next_line(receiver: &Receiver<ReaderMessage>, stall_timeout: Option<Duration>) -> NextLine {
+    loop {
+        let message = match stall_timeout {
+            Some(timeout) => select! {
+                recv(receiver) -> message => message,
+                default(timeout) => return NextLine::Stalled,
+            },
+            None => receiver.recv(),
+        };
+
+        match message {
+            Ok(ReaderMessage::Line(line)) => return NextLine::Line(line),
+            // Not a whole line yet, but yt-dlp isn't stuck
+            Ok(ReaderMessage::Activity) => continue,
+            Err(_) => return NextLine::Finished,
+        }
+    }
+}
+
+/// Wraps one of yt-dlp's output streams, every time something is read an Activity message is sent
+struct ActivityReader<R> {
+    inner: R,
+    sender: Sender<ReaderMessage>,
+}
+
+impl<R: Read> Read for ActivityReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if read > 0 {
+            let _ = self.sender.send(ReaderMessage::Activity);
+        }
+        Ok(read)
+    }
+}
+
+/// What happened while a yt-dlp command was running
+struct RunOutput {
+    /// The errors yt-dlp ran into
+    errors: Vec<YtdlpError>,
+    /// The paths of the files yt-dlp finished downloading
+    downloaded_files: Vec<String>,
+    /// For playlists, the last item yt-dlp started working on and how many items it had to download: (item, total)
+    playlist_progress: Option<(usize, usize)>,
+    /// How long every video in downloaded_files took
+    timings: Vec<VideoTiming>,
+    /// The .info.json files yt-dlp wrote, they hold the comments with --write-comments
+    info_json_files: Vec<String>,
+    /// Whether yt-dlp was stopped early because most of the playlist couldn't be written
+    filesystem_abort: bool,
+}
+
+/// Runs the command and displays the output to the console.
+///
+/// Returns the errors yt-dlp ran into (parsed Strings), the paths of the files it finished downloading and how far it got in a playlist
+///
+/// With `speed_graph` the command has to print every progress update on its own line (--newline).
+/// With an `error_file` the urls of the videos which fail are added to it as they fail, and taken out once downloaded.
+/// An error is returned if yt-dlp can't be started
+fn run_command(command: &mut Command, verbosity: &parser::Verbosity, speed_graph: bool, error_file: Option<&Path>,
+               shutdown: &Shutdown) -> BlobResult<RunOutput> {
+    let process = spawn_download(command, shutdown)?;
+    Ok(collect_output(process, verbosity, speed_graph, error_file, shutdown, STALL_TIMEOUT))
+}
+
+/// A running yt-dlp process and the lines it prints
+struct DownloadProcess {
+    child: Child,
+    receiver: Receiver<ReaderMessage>,
+}
+
+/// Starts the command, capturing its output
+fn spawn_download(command: &mut Command, shutdown: &Shutdown) -> BlobResult<DownloadProcess> {
+    shutdown.pause.before_next_video();
+
+    let mut child = command.stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // The CTRL+C handler needs to know who to stop
+    shutdown.register(child.id());
+    shutdown.spawned.fetch_add(1, Ordering::SeqCst);
+
+    // stdout and stderr are read on their own threads, so that a silent yt-dlp can't block blob-dl
+    let receiver = spawn_readers(child.stdout.take().unwrap(), child.stderr.take().unwrap(), &shutdown.pause);
+
+    Ok(DownloadProcess { child, receiver })
+}
+
+/// Shows yt-dlp's output according to `verbosity` until the process exits, recording what it downloaded and the errors it ran into
+///
+/// yt-dlp is stopped if it doesn't print anything for `stall_timeout`, see STALL_TIMEOUT
+fn collect_output(process: DownloadProcess, verbosity: &parser::Verbosity, speed_graph: bool, error_file: Option<&Path>,
+                  shutdown: &Shutdown, stall_timeout: Duration) -> RunOutput {
+    let DownloadProcess { child: mut youtube_dl, receiver } = process;
+
+    // The events say which process they come from
+    let worker = youtube_dl.id();
+    let events = &shutdown.events;
+    // The dashboard shows the output instead
+    let verbosity = if events.is_listened() { &parser::Verbosity::Quiet } else { verbosity };
+
+    // All the errors produced by yt-dlp
+    let mut errors: Vec<YtdlpError> = vec![];
+
+    // The final file of every video which was downloaded successfully
+    let mut downloaded_files = vec![];
+    // yt-dlp can mention more than one destination for the same video (merging, conversions, ...), the last one is the final file
+    let mut current_file: Option<String> = None;
+
+    // yt-dlp often prints the same warning for every video, in Default verbosity it's shown once
+    let mut warnings = WarningAggregator::default();
+
+    // Post-processing errors don't say which video or post-processor they come from, so yt-dlp's progress is tracked
+    let mut current_video_id: Option<String> = None;
+    let mut current_stage: Option<String> = None;
+    // The files downloaded for the current video, before any post-processor runs
+    let mut current_streams: Vec<String> = vec![];
+    let mut playlist_progress = None;
+    // When yt-dlp started working on the current video
+    let mut video_started = Instant::now();
+    let mut timings = vec![];
+
+    // Whether yt-dlp was stopped because most of the playlist couldn't be written
+    let mut filesystem_abort = false;
+
+    // Whether yt-dlp warned that youtube is blocking its player client for the current video
+    let mut client_blocked = false;
+
+    // Whether the last line came from a post-processor: ffmpeg can run silently for a long time after it
+    let mut post_processing = false;
+    // How long yt-dlp said it would wait after the last line, it doesn't print anything while it sleeps
+    let mut sleeping = Duration::ZERO;
+
+    let mut info_json_files = vec![];
+    // In Default verbosity the progress of comment fetching (and of downloads, with the speed graph) is shown
+    // on a single line, which is overwritten
+    let mut showing_progress_line = false;
+
+    // Sampled once per SPEED_GRAPH_INTERVAL, however often yt-dlp reports its progress
+    let mut speed_graph = speed_graph.then(|| SpeedGraph::new(SPEED_GRAPH_WIDTH));
+    let mut last_speed_sample: Option<Instant> = None;
+
+    // This has to be run or the command does nothing
+    loop {
+        let timeout = if post_processing { None } else { Some(stall_timeout + sleeping) };
+
+        let line = match next_line(&receiver, timeout) {
+            NextLine::Line(line) => line,
+            NextLine::Finished => break,
+            // yt-dlp was suspended by the thread of another process which is holding it
+            NextLine::Stalled if shutdown.pause.is_paused() => continue,
+            NextLine::Stalled => {
+                verbosity.debug(&format!("yt-dlp didn't print anything for {} seconds, stopping it", (stall_timeout + sleeping).as_secs()));
+                let _ = youtube_dl.kill();
+                let error = YtdlpError::timeout(current_video_id.as_deref());
+                record_failed_url(error_file, &error);
+                errors.push(error);
+                events.send(RunEvent::Failed { worker });
+                // Whatever it was working on isn't finished
+                current_file = None;
+                break;
+            }
+        };
+        post_processing = parse_post_processor(&line).is_some();
+        sleeping = announced_sleep(&line).unwrap_or(Duration::ZERO);
+
+        let line_kind = classify_output_line(&line);
+        let is_error = matches!(line_kind, OutputLine::Error(_));
+        let is_warning = line_kind == OutputLine::Warning;
+        client_blocked |= YtdlpError::is_blocked_client_output(&line);
+
+        // Progress updates move the dashboard's bars, the other lines go to its log
+        if events.is_listened() {
+            match parse_download_percent(&line) {
+                Some(percent) => events.send(RunEvent::Progress { worker, percent, speed: parse_download_speed(&line) }),
+                None => events.send(RunEvent::Line { worker, line: secrets::scrub(&line) }),
+            }
+        }
+
+        if let OutputLine::Error(error_line) = &line_kind {
+            events.send(RunEvent::Failed { worker });
+            verbosity.debug(&format!("Classified as an error: {}", secrets::scrub(error_line)));
+            if YtdlpError::is_post_processing_output(error_line) {
+                let error = YtdlpError::from_post_processing_output(&secrets::scrub(error_line), current_video_id.as_deref(), current_stage.as_deref(), &current_streams);
+                record_failed_url(error_file, &error);
+                errors.push(error);
+            } else {
+                let mut error = YtdlpError::from_error_output(&secrets::scrub(error_line));
+                if error.is_name_too_long() || error.filesystem_error().is_some() {
+                    error = error.or_video_id(current_video_id.as_deref());
+                }
+                if client_blocked {
+                    error = error.with_blocked_client();
+                }
+                record_failed_url(error_file, &error);
+                errors.push(error);
+
+                // The rest of the playlist would most likely fail the same way
+                if let Some(reason) = too_many_filesystem_errors(&errors, playlist_progress) {
+                    eprintln!("{}: {} ({})", "ERROR".red(), FILESYSTEM_ABORT, reason);
+                    let _ = youtube_dl.kill();
+                    filesystem_abort = true;
+                    current_file = None;
+                    break;
+                }
+            }
+            // The video failed, so whatever was written to disk isn't a finished file
+            current_file = None;
+        } else if line.starts_with(NEW_VIDEO_LINE) {
+            // The previous video is done
+            if let Some(file) = current_file.take() {
+                timings.push(VideoTiming::finished(&file, video_started));
+                events.send(RunEvent::Finished { worker, file: file.clone() });
+                downloaded_files.push(file);
+                forget_failed_url(error_file, current_video_id.as_deref());
+            }
+            // The stdout reader holds yt-dlp before the next video while paused, that time isn't the next video's
+            shutdown.pause.wait_until_resumed();
+            video_started = Instant::now();
+        } else if let Some(destination) = parse_destination(&line) {
+            current_file = Some(destination.to_string());
+        } else if let Some((item, total)) = parse_playlist_progress(&line) {
+            playlist_progress = Some((item, total));
+            events.send(RunEvent::Item { worker, item, total });
+        } else if let Some(info_json) = line.strip_prefix(INFO_JSON_LINE) {
+            info_json_files.push(info_json.to_string());
+        }
+
+        if let Some(video_id) = parse_video_id(&line) {
+            if current_video_id.as_deref() != Some(video_id) {
+                current_streams.clear();
+                client_blocked = false;
+                events.send(RunEvent::Video { worker, video_id: video_id.to_string() });
+            }
+            current_video_id = Some(video_id.to_string());
+            current_stage = None;
+        } else if let Some(stream) = line.strip_prefix("[download] Destination: ") {
+            current_streams.push(stream.to_string());
+        } else if let Some(stage) = parse_post_processor(&line) {
+            current_stage = Some(stage.to_string());
+        }
+
+        match verbosity {
+            // Keep track of errors without displaying anything
+            parser::Verbosity::Quiet => {}
+
+            parser::Verbosity::Default => {
+                if let Some(progress) = parse_comment_progress(&line) {
+                    // There can be thousands of these lines for a single video
+                    eprint!("\r{} {}", COMMENTS_PROGRESS, progress);
+                    let _ = std::io::stderr().flush();
+                    showing_progress_line = true;
+                    continue;
+                }
+                if let Some(graph) = speed_graph.as_mut().filter(|_| is_progress_update(&line)) {
+                    if last_speed_sample.is_none_or(|sampled| sampled.elapsed() >= SPEED_GRAPH_INTERVAL) {
+                        if let Some(speed) = parse_download_speed(&line) {
+                            graph.push(speed);
+                            last_speed_sample = Some(Instant::now());
+                        }
+                    }
+                    // The graph goes first, so that it doesn't move when the progress changes length
+                    let (_, columns) = Term::stderr().size();
+                    eprint!("{}", speed_graph::progress_line(&graph.render(), line.trim_start_matches("[download]").trim(), usize::from(columns)));
+                    let _ = std::io::stderr().flush();
+                    showing_progress_line = true;
+                    continue;
+                }
+                if showing_progress_line {
+                    // Whatever comes next goes on its own line
+                    eprintln!();
+                    showing_progress_line = false;
+                }
+
+                // Only show download/error lines and new warnings
+                if is_error {
+                    // Color error messages red
+                    eprintln!("{}", secrets::scrub(&line).red());
+                } else if is_warning {
+                    let line = secrets::scrub(&line);
+                    if warnings.is_new(&line) {
+                        eprintln!("{}", line.yellow());
+                    }
+                } else if line.contains("[download]") {
+                    eprintln!("{}", line);
+                }
+            }
+
+            // Print to the console everything that yt-dlp is doing, -v prints the whole command line
+            parser::Verbosity::Verbose | parser::Verbosity::Debug => {
+                if is_error {
+                    // Color error messages red
+                    eprintln!("{}", secrets::scrub(&line).red());
+                } else {
+                    eprintln!("{}", secrets::scrub(&line));
+                }
+            }
+        }
+    }
+
+    if shutdown.is_interrupted() {
+        // Give yt-dlp some time to finish writing, then stop it for good
+        let deadline = Instant::now() + SHUTDOWN_GRACE_PERIOD;
+        while let Ok(None) = youtube_dl.try_wait() {
+            if Instant::now() > deadline {
+                let _ = youtube_dl.kill();
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        // Whatever it was working on isn't finished
+        if let Some(file) = current_file.take() {
+            shutdown.unfinished_files.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push(PathBuf::from(file));
+        }
+    }
+    let _ = youtube_dl.wait();
+    shutdown.unregister(worker);
+
+    if let Some(file) = current_file {
+        timings.push(VideoTiming::finished(&file, video_started));
+        events.send(RunEvent::Finished { worker, file: file.clone() });
+        downloaded_files.push(file);
+        forget_failed_url(error_file, current_video_id.as_deref());
+    }
+
+    warnings.print_summary();
+    events.send(RunEvent::Exited { worker });
+
+    RunOutput { errors, downloaded_files, playlist_progress, timings, info_json_files, filesystem_abort }
+}
+
+/// The filesystem error more than half of the playlist failed with, `playlist_progress` is (item, total)
+fn too_many_filesystem_errors(errors: &[YtdlpError], playlist_progress: Option<(usize, usize)>) -> Option<&'static str> {
+    let (_, total) = playlist_progress?;
+    let reason = errors.last()?.filesystem_error()?;
+
+    let same_reason = errors.iter().filter(|error| error.filesystem_error() == Some(reason)).count();
+    (same_reason * 2 > total).then_some(reason)
+}
+
+/// Keeps track of which warnings were already shown and how many times they were repeated
+#[derive(Default)]
+struct WarningAggregator {
+    /// Every distinct warning (see warning_key) with the number of times it was repeated after its first occurrence
+    repetitions: HashMap<String, usize>,
+    /// The first occurrence of each distinct warning, in order of appearance
+    first_occurrences: Vec<(String, String)>,
+}
+
+impl WarningAggregator {
+    /// Records a warning line, returns whether it's the first time this warning shows up
+    fn is_new(&mut self, line: &str) -> bool {
+        let key = warning_key(line);
+
+        if let Some(count) = self.repetitions.get_mut(&key) {
+            *count += 1;
+            false
+        } else {
+            self.repetitions.insert(key.clone(), 0);
+            self.first_occurrences.push((key, line.to_string()));
+            true
+        }
+    }
+
+    /// Tells the user how many warnings were hidden
+    fn print_summary(&self) {
+        for (line, count) in self.repeated() {
+            eprintln!("{}\n   ...and {} more identical warnings", line.yellow(), count);
+        }
+    }
+
+    /// The first occurrence of every warning which was repeated, with the number of hidden repetitions
+    fn repeated(&self) -> Vec<(&str, usize)> {
+        self.first_occurrences.iter()
+            .map(|(key, line)| (line.as_str(), self.repetitions[key]))
+            .filter(|(_, count)| *count > 0)
+            .collect()
+    }
+}
+
+/// Strips the parts of a warning which change every time it's repeated (video ids and timestamps)
+fn warning_key(line: &str) -> String {
+    line.split_whitespace()
+        .filter(|word| !is_video_id(word) && !is_timestamp(word))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Youtube video ids are 11 characters long, in yt-dlp's output they are followed by ':'
+fn is_video_id(word: &str) -> bool {
+    match word.strip_suffix(':') {
+        Some(id) => id.len() == 11 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+        None => false,
+    }
+}
+
+/// Times, dates and bare numbers such as 00:03:12, 2023-08-20 or 1.5
+fn is_timestamp(word: &str) -> bool {
+    word.chars().any(|c| c.is_ascii_digit())
+        && word.chars().all(|c| c.is_ascii_digit() || ":-./[]".contains(c))
+}
+
+/// What a line of yt-dlp's output reports, see classify_output_line
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum OutputLine {
+    /// A video (or the whole run) failed. The line from "ERROR:" on, without color codes
+    Error(String),
+    /// Something went wrong but yt-dlp carries on, like a fragment it downloads again
+    Warning,
+    Other,
+}
+
+/// Classifies a line of yt-dlp's output by where it reports errors and warnings, not by what it contains:
+/// video titles can contain "ERROR:" anywhere, like in `[download] Destination: ERROR: the movie.mp4`
+///
+/// - Errors start with `ERROR:`, possibly colored, or with an extractor's tag, like `[generic] ERROR: ...`
+/// - Warnings start with `WARNING:` the same way. Retries of a fragment or of a whole download,
+///   like `[download] Got error: HTTP Error 503. Retrying fragment 3 (1/10)...`, are warnings too
+///
+/// Only what comes after the last carriage return counts, the rest was overwritten on the terminal
+fn classify_output_line(line: &str) -> OutputLine {
+    let line = strip_color_codes(line.rsplit('\r').next().unwrap_or_default());
+    let line = line.trim_start();
+
+    // An extractor's or downloader's tag, like [generic] or [download]
+    let message = match line.strip_prefix('[').and_then(|rest| rest.split_once("] ")) {
+        Some((tag, message)) if !tag.is_empty() && !tag.contains(char::is_whitespace) => message.trim_start(),
+        _ => line,
+    };
+
+    if message.starts_with("ERROR:") {
+        OutputLine::Error(message.to_string())
+    } else if message.starts_with("WARNING:") || message.starts_with("Got error:") {
+        OutputLine::Warning
+    } else {
+        OutputLine::Other
+    }
+}
+
+/// Removes the ANSI escape sequences yt-dlp colors its output with, like `\x1b[0;31m`
+fn strip_color_codes(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // The sequence ends with its first letter: [0;31m
+            chars.find(|c| c.is_ascii_alphabetic());
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
+
+/// yt-dlp prints this line every time it starts working on a new youtube video
+const NEW_VIDEO_LINE: &str = "[youtube] Extracting URL:";
+
+/// yt-dlp prints this line, followed by the path, when it writes a .info.json file
+const INFO_JSON_LINE: &str = "[info] Writing video metadata as JSON to: ";
+
+/// If the line is about a youtube video, like `[youtube] dQw4w9WgXcQ: Downloading webpage`, returns the video's id
+fn parse_video_id(line: &str) -> Option<&str> {
+    let word = line.strip_prefix("[youtube] ")?.split_whitespace().next()?;
+    if is_video_id(word) {
+        word.strip_suffix(':')
+    } else {
+        None
+    }
+}
+
+/// How long yt-dlp is going to wait, if the line announces it
+///
+/// Like `[download] Sleeping 12.34 seconds ...`, printed before the waits of --sleep-interval and --retry-sleep
+fn announced_sleep(line: &str) -> Option<Duration> {
+    let (_, rest) = line.split_once("Sleeping ")?;
+    let (seconds, _) = rest.split_once(" seconds")?;
+    seconds.parse::<f64>().ok().filter(|seconds| seconds.is_finite() && *seconds >= 0.0).map(Duration::from_secs_f64)
+}
+
+/// If the line comes from a post-processor, like `[Merger] Merging formats into ...`, returns its name
+fn parse_post_processor(line: &str) -> Option<&str> {
+    // Everything else in brackets is an extractor or the downloader
+    const POST_PROCESSORS: &[&str] = &["Merger", "ExtractAudio", "VideoConvertor", "VideoRemuxer", "FixupM3u8", "FixupM4a",
+                                       "FixupStretched", "FixupDuplicateMoov", "FixupTimestamp", "EmbedThumbnail", "EmbedSubtitle",
+                                       "Metadata", "SponsorBlock", "ModifyChapters", "SplitChapters", "ThumbnailsConvertor"];
+
+    let tag = line.strip_prefix('[')?.split_once(']')?.0;
+    POST_PROCESSORS.contains(&tag).then_some(tag)
+}
+
+/// If the line is like `[download] Downloading item 3 of 50`, returns (3, 50)
+///
+/// Items are counted from the first one yt-dlp was asked to download, not from the beginning of the playlist
+fn parse_playlist_progress(line: &str) -> Option<(usize, usize)> {
+    // Older versions of yt-dlp say "video" instead of "item"
+    let progress = line.strip_prefix("[download] Downloading item ")
+        .or_else(|| line.strip_prefix("[download] Downloading video "))?;
+
+    let (item, total) = progress.split_once(" of ")?;
+    Some((item.trim().parse().ok()?, total.trim().parse().ok()?))
+}
+
+/// Computes the --start-at value which continues a playlist download from where this run stopped
+///
+/// Only playlists downloaded with --start-at or --stop-at get a suggestion, None is also returned if there's nothing left to download
+fn next_playlist_start(download_config: &config::DownloadConfig, progress: Option<(usize, usize)>, interrupted: bool) -> Option<usize> {
+    let (start, stop) = download_config.playlist_range()?;
+    let (item, total) = progress?;
+
+    // Failed items count as done: they are offered for re-download, the next run shouldn't repeat them.
+    // An interrupted run didn't finish the item it was working on
+    let finished = if interrupted { item.saturating_sub(1) } else { item };
+
+    if finished < total {
+        // Skipped videos (like duplicates) aren't counted by yt-dlp
+        return Some(download_config.nth_selected_position(finished + 1));
+    }
+
+    match stop {
+        // The whole range was downloaded, the next chunk begins right after it (unless the playlist ended before --stop-at)
+        Some(stop) if total == download_config.selected_count(start, stop) => Some(stop + 1),
+        _ => None,
+    }
+}
+
+/// How often the speed graph gets a new sample
+const SPEED_GRAPH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether the line is one of yt-dlp's progress updates, like `[download]  12.3% of 50.00MiB at  2.34MiB/s ETA 00:20`
+fn is_progress_update(line: &str) -> bool {
+    line.strip_prefix("[download]").is_some_and(|progress| progress.contains("% of"))
+}
+
+/// How much of the file a progress update says is downloaded, in percent
+fn parse_download_percent(line: &str) -> Option<f64> {
+    if !is_progress_update(line) {
+        return None;
+    }
+    line.strip_prefix("[download]")?.trim_start().split_once('%')?.0.parse().ok()
+}
+
+/// The speed of a progress update in bytes per second, None when yt-dlp doesn't know it ("Unknown B/s")
+fn parse_download_speed(line: &str) -> Option<f64> {
+    let speed = line.split(" at ").nth(1)?.split_whitespace().next()?.strip_suffix("/s")?;
+    let (number, unit) = speed.split_at(speed.find(|c: char| c.is_ascii_alphabetic())?);
+
+    let multiplier = match unit {
+        "B" => 1.0,
+        "KiB" => 1024.0,
+        "MiB" => 1024.0 * 1024.0,
+        "GiB" => 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    Some(number.parse::<f64>().ok()? * multiplier)
+}
+
+/// If the line is about fetching comments, returns how many were fetched out of about how many there are (like "120/~3400")
+fn parse_comment_progress(line: &str) -> Option<&str> {
+    if !line.contains("Downloading comment") {
+        return None;
+    }
+    line.trim_end().strip_suffix(')')?.rsplit_once('(').map(|(_, progress)| progress)
+}
+
+/// If the line tells where yt-dlp is writing a file, returns the file's path
+fn parse_destination(line: &str) -> Option<&str> {
+    if let Some(merged) = line.strip_prefix("[Merger] Merging formats into \"") {
+        // The path is wrapped in quotes
+        return merged.strip_suffix('"');
+    }
+    if let Some(already_downloaded) = line.strip_prefix("[download] ") {
+        if let Some(path) = already_downloaded.strip_suffix(" has already been downloaded") {
+            return Some(path);
+        }
+    }
+    // Used by [download], [ExtractAudio], [VideoConvertor], [VideoRemuxer], ...
+    line.split_once("Destination: ").map(|(_, path)| path)
+}
+
+/// Why a recoverable error happened, the retry prompt groups errors by category
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ErrorCategory {
+    Network,
+    FormatUnavailable,
+    Fragment,
+    Unknown,
+}
+
+impl ErrorCategory {
+    fn of(error: &YtdlpError) -> ErrorCategory {
+        const NETWORK: &[&str] = &["urlopen error", "name resolution", "timed out", "Connection reset", "Connection refused",
+                                   "Remote end closed connection", "HTTP Error 5"];
+        const FORMAT_UNAVAILABLE: &[&str] = &["Requested format is not available", "format is not available"];
+        const FRAGMENT: &[&str] = &["fragment", "Did not get any data blocks"];
+
+        let msg = error.error_msg();
+        if NETWORK.iter().any(|marker| msg.contains(marker)) {
+            ErrorCategory::Network
+        } else if FORMAT_UNAVAILABLE.iter().any(|marker| msg.contains(marker)) {
+            ErrorCategory::FormatUnavailable
+        } else if FRAGMENT.iter().any(|marker| msg.to_lowercase().contains(&marker.to_lowercase())) {
+            ErrorCategory::Fragment
+        } else {
+            ErrorCategory::Unknown
+        }
+    }
+
+    /// Short tag shown before every error of the category
+    fn tag(&self) -> &'static str {
+        match self {
+            ErrorCategory::Network => "[network]",
+            ErrorCategory::FormatUnavailable => "[format unavailable]",
+            ErrorCategory::Fragment => "[fragments]",
+            ErrorCategory::Unknown => "[unknown]",
+        }
+    }
+}
+
+/// What an entry of the retry prompt stands for
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RetryOption {
+    All,
+    Nothing,
+    Category(ErrorCategory),
+    /// Index of the error in the list given to ask_for_redownload
+    Error(usize),
+}
+
+/// The entries of the retry prompt: select all, select nothing, then every error after the header of its category
+///
+/// `categories` is the category of every recoverable error, by index, sorted by category
+fn retry_options(categories: &[(usize, ErrorCategory)]) -> Vec<RetryOption> {
+    let mut options = vec![RetryOption::All, RetryOption::Nothing];
+
+    let mut current_category = None;
+    for (error_index, category) in categories {
+        if current_category != Some(*category) {
+            options.push(RetryOption::Category(*category));
+            current_category = Some(*category);
+        }
+        options.push(RetryOption::Error(*error_index));
+    }
+    options
+}
+
+/// Turns what the user picked in the retry prompt into the indexes of the errors to retry, sorted and without duplicates
+///
+/// `options` are the entries of the prompt and `categories` the category of every recoverable error, by index
+fn resolve_retry_selection(selection: &[usize], options: &[RetryOption], categories: &[(usize, ErrorCategory)]) -> Vec<usize> {
+    let picked: Vec<RetryOption> = selection.iter().map(|i| options[*i]).collect();
+
+    if picked.contains(&RetryOption::Nothing) {
+        return vec![];
+    }
+
+    let mut to_retry: Vec<usize> = if picked.contains(&RetryOption::All) {
+        categories.iter().map(|(error_index, _)| *error_index).collect()
+    } else {
+        picked.iter().flat_map(|option| match option {
+            RetryOption::Error(error_index) => vec![*error_index],
+            RetryOption::Category(category) => categories.iter()
+                .filter(|(_, c)| c == category)
+                .map(|(error_index, _)| *error_index)
+                .collect(),
+            RetryOption::All | RetryOption::Nothing => vec![],
+        }).collect()
+    };
+
+    to_retry.sort_unstable();
+    to_retry.dedup();
+    to_retry
+}
+
+/// Shows the user which videos could not be downloaded and returns which have to be re-downloaded based on what the user wants
+///
+/// Recoverable errors are grouped by category, every group can be selected at once.
+/// When there is more than one url every error is labelled with the url it comes from
+///
+/// Returns the indexes (in `errors`) of the errors the user wants to re-download,
+/// along with the directory they should be saved in if the user doesn't want to use the original one
+fn ask_for_redownload(errors: &[(usize, YtdlpError)], downloads: &[(Command, config::DownloadConfig)], cli_config: &parser::CliConfig)
+                      -> BlobResult<(Vec<usize>, Option<String>)>
+{
+    let term = Term::buffered_stderr();
+
+    // Initialize a lut, which contains all documented errors and whether they can be recovered from
+    let lut = init_error_msg_lut();
+
+    let mut unrecoverable_errors = Vec::new();
+    // Every recoverable error along with its category
+    let mut categories = Vec::new();
+
+    for (error_index, (i, error)) in errors.iter().enumerate() {
+        match classify_error(error, &lut, cli_config.verbosity()) {
+            // It makes sense to try a re-download
+            ErrorRecoverability::RecoverableWithRetry => categories.push((error_index, ErrorCategory::of(error))),
+            // Premium-only tracks were already counted by report_results
+            ErrorRecoverability::Unrecoverable if error.is_premium_only() => {}
+            // Don't bother asking to re-download the error
+            ErrorRecoverability::Unrecoverable => unrecoverable_errors.push(error_label(error, &downloads[*i].1, downloads.len())),
+            // The user was already asked for cookies, a proxy or shorter names and didn't want them, retrying without would fail again
+            ErrorRecoverability::RecoverableWithAuth => unrecoverable_errors.push(format!("{} {}", NEEDS_COOKIES_TAG, error_label(error, &downloads[*i].1, downloads.len()))),
+            ErrorRecoverability::RecoverableWithProxy => unrecoverable_errors.push(format!("{} {}", NEEDS_PROXY_TAG, error_label(error, &downloads[*i].1, downloads.len()))),
+            ErrorRecoverability::RecoverableWithShorterNames => unrecoverable_errors.push(format!("{} {}", NAME_TOO_LONG_TAG, error_label(error, &downloads[*i].1, downloads.len()))),
+            ErrorRecoverability::RecoverableWithOtherClient => unrecoverable_errors.push(format!("{} {}", CLIENT_BLOCKED_TAG, error_label(error, &downloads[*i].1, downloads.len()))),
+            ErrorRecoverability::RecoverableWithRemappedFormat => unrecoverable_errors.push(format!("{} {}", FORMAT_GONE_TAG, error_label(error, &downloads[*i].1, downloads.len()))),
+        }
+    }
+    // The sort is stable, so errors keep their order inside each group
+    categories.sort_by_key(|(_, category)| *category);
+
+    // The possible choices which will be presented to the user and what each one stands for
+    let retry_options = retry_options(&categories);
+    let user_options: Vec<String> = retry_options.iter()
+        .map(|option| match option {
+            RetryOption::All => String::from(SELECT_ALL),
+            RetryOption::Nothing => String::from(SELECT_NOTHING),
+            // The group's header also selects the whole group
+            RetryOption::Category(category) => {
+                let count = categories.iter().filter(|(_, c)| c == category).count();
+                format!("{} all {} error(s) of this kind\n", category.tag().bold(), count)
+            }
+            RetryOption::Error(error_index) => {
+                let (i, error) = &errors[*error_index];
+                format!("   {} {}", ErrorCategory::of(error).tag(), error_label(error, &downloads[*i].1, downloads.len()))
+            }
+        })
+        .collect();
+
+    if !unrecoverable_errors.is_empty() {
+        eprintln!("{}", UNRECOVERABLE_ERROR_PROMPT.bold().cyan());
+        for error in unrecoverable_errors {
+            eprintln!("   {}", error);
+        }
+    }
+
+    if !categories.is_empty() {
+        // There are videos worth re-downloading
+        request_attention(cli_config.notify());
+
+        // Without a terminal nobody can answer, like when the timeout expires
+        let nobody_answered = !term.is_term() || cli_config.prompt_timeout().is_some_and(|timeout| !wait_for_user(timeout));
+        if nobody_answered {
+            // Retry every recoverable error once
+            eprintln!("{}", PROMPT_TIMEOUT_EXPIRED);
+            return Ok((resolve_retry_selection(&[0], &retry_options, &categories), None));
+        }
+
+        let user_selection = MultiSelect::with_theme(&ColorfulTheme::default())
+            .with_prompt(ERROR_RETRY_PROMPT)
+            .items(&user_options[..])
+            .interact_on(&term)?;
+
+        let to_retry = resolve_retry_selection(&user_selection, &retry_options, &categories);
+
+        // Only ask about the directory if something is going to be re-downloaded
+        let alternate_path = if to_retry.is_empty() {
+            None
+        } else {
+            prompt_alternate_output_path(&term).unwrap_or(None)
+        };
+
+        eprintln!("{}", DEBUG_REPORT_PROMPT);
+        return Ok((to_retry, alternate_path))
+    }
+
+    // The user didn't choose any options so an empty Vec is returned
+    Ok((Vec::new(), None))
+}
+
+/// Describes an error, mentioning the url it comes from when there is more than one
+fn error_label(error: &YtdlpError, download_config: &config::DownloadConfig, url_count: usize) -> String {
+    if url_count > 1 {
+        format!("{} {}\n   {}", "url:", download_config.url(), error)
+    } else {
+        error.to_string()
+    }
+}
+
+/// Asks whether retried videos should be saved in a different directory, useful when the original one is full or gone
+///
+/// Returns None if the user wants to keep the original directory
+fn prompt_alternate_output_path(term: &Term) -> BlobResult<Option<String>> {
+    let answer = dialoguer::Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(ALTERNATE_PATH_PROMPT)
+        .default(1)
+        .items(&["Yes", "No"])
+        .interact_on(term)?;
+
+    if answer != 0 {
+        return Ok(None);
+    }
+
+    let path: String = dialoguer::Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Output path:")
+        .interact_text_on(term)?;
+
+    Ok(Some(path))
+}
+
+/// Rings the terminal bell and, if the user asked for it, sends a desktop notification
+///
+/// Used before asking something in the middle of a run, which could be hours after it started
+fn request_attention(notify: bool) {
+    eprint!("\x07");
+
+    if notify {
+        send_desktop_notification(ATTENTION_NOTIFICATION);
+    }
+}
+
+/// Uses the platform's notification tool, nothing happens if it isn't available
+fn send_desktop_notification(message: &str) {
+    #[cfg(target_os = "macos")]
+    let mut command = {
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(format!("display notification \"{}\" with title \"blob-dl\"", message));
+        command
+    };
+
+    #[cfg(not(target_os = "macos"))]
+    let mut command = {
+        let mut command = Command::new("notify-send");
+        command.arg("blob-dl").arg(message);
+        command
+    };
+
+    // A missing notification tool isn't worth bothering the user
+    let _ = command.stdout(Stdio::null()).stderr(Stdio::null()).status();
+}
+
+/// Waits for the user to press enter, returns false if they didn't within `timeout` or if stdin was closed
+fn wait_for_user(timeout: Duration) -> bool {
+    eprintln!("{} {}", PROMPT_TIMEOUT_WAITING.bold(), timeout.as_secs());
+
+    #[cfg(unix)]
+    {
+        use std::os::fd::AsRawFd;
+        let stdin = std::io::stdin();
+        line_written_within(stdin.as_raw_fd(), &mut stdin.lock(), timeout)
+    }
+    #[cfg(not(unix))]
+    line_written_within(|line| std::io::stdin().read_line(line), timeout)
+}
+
+/// Whether a line is written to `input` within `timeout`, an empty read means the input was closed and isn't an answer.
+/// `fd` is polled, it has to be the file descriptor `input` reads from
+///
+/// stdin is polled instead of read from another thread, which would be left blocked on it when nobody answers
+/// and then take the answer to the next prompt
+#[cfg(unix)]
+fn line_written_within(fd: std::os::fd::RawFd, input: &mut impl BufRead, timeout: Duration) -> bool {
+    let mut poll_fd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+    let timeout_ms = libc::c_int::try_from(timeout.as_millis()).unwrap_or(libc::c_int::MAX);
+
+    // Safety: the pointer is to a single pollfd which outlives the call
+    let ready = unsafe { libc::poll(&mut poll_fd, 1, timeout_ms) };
+
+    let mut line = String::new();
+    // A terminal only becomes readable once enter is pressed, so this doesn't block
+    ready > 0 && input.read_line(&mut line).is_ok_and(|read| read > 0)
+}
+
+/// Whether `read_line` reads a line within `timeout`, an empty read means the input was closed and isn't an answer
+///
+/// stdin can't be polled here, so it's read from another thread. If nobody answers that thread is detached on purpose,
+/// it stays blocked on stdin until blob-dl exits: the prompts after this one are skipped or answered automatically
+#[cfg(not(unix))]
+fn line_written_within(read_line: impl FnOnce(&mut String) -> std::io::Result<usize> + Send + 'static, timeout: Duration) -> bool {
+    let (sender, receiver) = crossbeam_channel::bounded(1);
+
+    thread::spawn(move || {
+        let mut line = String::new();
+        let _ = sender.send(read_line(&mut line).is_ok_and(|read| read > 0));
+    });
+
+    receiver.recv_timeout(timeout).unwrap_or(false)
+}
+
+/// How long yt-dlp has to exit after CTRL+C is pressed
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Cooperative shutdown signaling between the CTRL+C handler and the code running yt-dlp
+struct Shutdown {
+    interrupted: Arc<AtomicBool>,
+    /// The pids of the running yt-dlp processes, more than one when a playlist is split between processes
+    child_pids: Arc<Mutex<HashSet<u32>>>,
+    /// When blob-dl started downloading, older .part files weren't created by this session
+    started_at: SystemTime,
+    /// How many yt-dlp processes were started, shown with the run's timings
+    spawned: Arc<AtomicUsize>,
+    /// Whether a new video can start, with --pause-file or the p key
+    pause: Arc<PauseControl>,
+    /// What yt-dlp was writing when it was stopped, with --no-part these files have their final name but are cut short
+    unfinished_files: Mutex<Vec<PathBuf>>,
+    /// What the yt-dlp processes are doing, for --dashboard
+    events: Arc<EventStream>,
+}
+
+impl Shutdown {
+    /// Sets up the CTRL+C handler, which asks yt-dlp to terminate
+    fn install(pause_file: Option<PathBuf>) -> Shutdown {
+        let shutdown = Shutdown::new(pause_file);
+
+        // This only fails if a handler was already set, in which case CTRL+C keeps its default behaviour
+        let _ = ctrlc::set_handler(shutdown.interrupter());
+
+        shutdown
+    }
+
+    /// What CTRL+C does: yt-dlp is asked to terminate and the run stops
+    fn interrupter(&self) -> impl Fn() + Send + 'static {
+        let interrupted = Arc::clone(&self.interrupted);
+        let child_pids = Arc::clone(&self.child_pids);
+
+        move || {
+            interrupted.store(true, Ordering::SeqCst);
+            for pid in live_pids(&child_pids) {
+                terminate(pid);
+            }
+        }
+    }
+
+    /// Nothing is interrupted yet, CTRL+C isn't handled
+    fn new(pause_file: Option<PathBuf>) -> Shutdown {
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let child_pids = Arc::new(Mutex::new(HashSet::new()));
+        Shutdown {
+            pause: Arc::new(PauseControl::new(pause_file, Arc::clone(&interrupted), Arc::clone(&child_pids))),
+            interrupted,
+            child_pids,
+            started_at: SystemTime::now(),
+            spawned: Arc::new(AtomicUsize::new(0)),
+            unfinished_files: Mutex::new(vec![]),
+            events: Arc::default(),
+        }
+    }
+
+    fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::SeqCst)
+    }
+
+    fn spawned(&self) -> usize {
+        self.spawned.load(Ordering::SeqCst)
+    }
+
+    /// Remembers a yt-dlp process which was just started, until unregister is called
+    fn register(&self, pid: u32) {
+        self.child_pids.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).insert(pid);
+    }
+
+    /// Forgets a yt-dlp process which exited
+    fn unregister(&self, pid: u32) {
+        self.child_pids.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&pid);
+    }
+}
+
+/// A copy of the pids of the running yt-dlp processes, a thread which panicked while holding the lock doesn't hide them
+fn live_pids(child_pids: &Mutex<HashSet<u32>>) -> Vec<u32> {
+    child_pids.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).iter().copied().collect()
+}
+
+/// Sends SIGTERM to yt-dlp so it can exit on its own terms
+#[cfg(unix)]
+fn terminate(pid: u32) {
+    // Safety: kill doesn't touch any memory, at worst the pid doesn't exist anymore
+    unsafe {
+        libc::kill(pid as libc::pid_t, libc::SIGTERM);
+    }
+}
+
+/// On Windows CTRL+C already reaches every process attached to the console, yt-dlp included
+#[cfg(not(unix))]
+fn terminate(_pid: u32) {}
+
+/// Looks for the .part files left behind by the interrupted downloads and asks whether to delete them
+fn clean_up_partial_files(downloads: &[(Command, config::DownloadConfig)], shutdown: &Shutdown) {
+    eprintln!("\n{}", INTERRUPTED_MSG.bold().cyan());
+
+    let mut partial_files = vec![];
+    for (_, download_config) in downloads {
+        find_partial_files(Path::new(download_config.output_path()), shutdown.started_at, 2, &mut partial_files);
+    }
+    let no_part_files = downloads.iter().any(|(_, download_config)| download_config.no_part_files);
+    partial_files.extend(cut_short_files(no_part_files, &shutdown.unfinished_files.lock().unwrap_or_else(|poisoned| poisoned.into_inner())));
+
+    if partial_files.is_empty() {
+        return;
+    }
+
+    eprintln!("{}", PARTIAL_FILES_FOUND.bold());
+    for file in &partial_files {
+        eprintln!("   {}", file.display());
+    }
+
+    let term = Term::buffered_stderr();
+    let delete = dialoguer::Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(DELETE_PARTIAL_FILES_PROMPT)
+        .default(0)
+        .items(&["Yes", "No"])
+        .interact_on(&term);
+
+    if let Ok(0) = delete {
+        for file in partial_files {
+            if let Err(err) = std::fs::remove_file(&file) {
+                eprintln!("{}: {} ({})", "ERROR".red(), err, file.display());
+            }
+        }
+    }
+}
+
+/// The files yt-dlp was writing when it was stopped which are still on disk
+///
+/// Without .part files, these can't be told apart from finished ones; otherwise the .part files already cover them
+fn cut_short_files(no_part_files: bool, unfinished_files: &[PathBuf]) -> Vec<PathBuf> {
+    if !no_part_files {
+        return vec![];
+    }
+    unfinished_files.iter().filter(|file| file.exists()).cloned().collect()
+}
+
+/// Collects the .part files in `dir` (and its subdirectories, up to `depth`) modified after `since`
+///
+/// Playlists are downloaded in a subdirectory named after them, so a depth of 2 is enough
+fn find_partial_files(dir: &Path, since: SystemTime, depth: usize, found: &mut Vec<PathBuf>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            if depth > 1 {
+                find_partial_files(&path, since, depth - 1, found);
+            }
+        } else if path.extension().is_some_and(|extension| extension == "part") {
+            let is_recent = entry.metadata()
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| modified >= since);
+
+            if is_recent {
+                found.push(path);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::BlobdlError;
+
+    #[test]
+    fn output_lines_are_classified_by_position() {
+        let error = |message: &str| OutputLine::Error(message.to_string());
+
+        assert_eq!(classify_output_line("ERROR: [youtube] abc: Video unavailable"), error("ERROR: [youtube] abc: Video unavailable"));
+        assert_eq!(classify_output_line("\x1b[0;31mERROR:\x1b[0m Private video"), error("ERROR: Private video"));
+        assert_eq!(classify_output_line("[generic] ERROR: Unsupported URL"), error("ERROR: Unsupported URL"));
+        // What was overwritten on the terminal doesn't count
+        assert_eq!(classify_output_line("[download]  50.0% of 1MiB\rERROR: unable to download"), error("ERROR: unable to download"));
+
+        assert_eq!(classify_output_line("WARNING: [youtube] Falling back to generic n function search"), OutputLine::Warning);
+        assert_eq!(classify_output_line("[download] Got error: HTTP Error 503. Retrying fragment 3 (1/10)..."), OutputLine::Warning);
+
+        // Titles can contain anything
+        assert_eq!(classify_output_line("[download] Destination: ERROR: the movie.mp4"), OutputLine::Other);
+        assert_eq!(classify_output_line("[download] Destination: WARNING: signs.mp4"), OutputLine::Other);
+        assert_eq!(classify_output_line("[some tag] ERROR: not a tag"), OutputLine::Other);
+    }
+
+    #[test]
+    fn download_speeds_are_read_from_progress_updates() {
+        let line = "[download]  12.3% of   50.00MiB at    2.50MiB/s ETA 00:20";
+        assert!(is_progress_update(line));
+        assert_eq!(parse_download_speed(line), Some(2.5 * 1024.0 * 1024.0));
+
+        assert_eq!(parse_download_speed("[download]   1.0% of ~ 10.00GiB at  512.00KiB/s ETA 05:33 (frag 2/300)"), Some(512.0 * 1024.0));
+        assert_eq!(parse_download_speed("[download]   0.0% of   50.00MiB at  Unknown B/s ETA Unknown"), None);
+        assert_eq!(parse_download_speed("[download]  12.3% of   50.00MiB at    2.50TiB/s ETA 00:00"), None);
+
+        assert!(!is_progress_update("[download] Destination: video.webm"));
+    }
+
+    #[test]
+    fn download_percentages_are_read_from_progress_updates() {
+        assert_eq!(parse_download_percent("[download]  12.3% of   50.00MiB at    2.50MiB/s ETA 00:20"), Some(12.3));
+        assert_eq!(parse_download_percent("[download] 100% of   50.00MiB in 00:00:21 at 2.38MiB/s"), Some(100.0));
+        assert_eq!(parse_download_percent("[download] Destination: 50% off.webm"), None);
+        assert_eq!(parse_download_percent("[youtube] 12.3% of the formats"), None);
+    }
+
+    #[test]
+    fn retried_files_get_checksums_too() {
+        let file = std::env::temp_dir().join(format!("blob-dl-finish-{}.webm", std::process::id()));
+        std::fs::write(&file, "video").unwrap();
+        let checksum = PathBuf::from(format!("{}.sha256", file.display()));
+        let download_config = config::DownloadConfig::new_video("https://youtu.be/a", youtube::VideoQualityAndFormatPreferences::BestQuality,
+                                                                String::from("/downloads"), youtube::MediaSelection::FullVideo);
+        let cli_config = parser::tests::config(&["--write-checksums", "--quiet", "https://youtu.be/a"]);
+
+        finish_downloads(&[file.display().to_string()], &download_config, &cli_config);
+        let written = checksum.exists();
+        let _ = std::fs::remove_file(&file);
+        let _ = std::fs::remove_file(&checksum);
+
+        assert!(written);
+    }
+
+    /// Runs `check` with the reading end of a new pipe, as a file descriptor and a reader, and with its writing end
+    #[cfg(unix)]
+    fn with_pipe(check: impl FnOnce(std::os::fd::RawFd, &mut BufReader<std::fs::File>, std::fs::File)) {
+        use std::os::fd::FromRawFd;
+        let mut fds = [0; 2];
+        // Safety: pipe writes two file descriptors in the array, they are owned by the Files below
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let (read_end, write_end) = unsafe { (std::fs::File::from_raw_fd(fds[0]), std::fs::File::from_raw_fd(fds[1])) };
+
+        check(fds[0], &mut BufReader::new(read_end), write_end);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn answers_are_lines_written_before_the_timeout() {
+        with_pipe(|fd, input, mut write_end| {
+            write_end.write_all(b"\n").unwrap();
+            assert!(line_written_within(fd, input, Duration::from_secs(5)));
+        });
+
+        // Written later, from another thread
+        with_pipe(|fd, input, mut write_end| {
+            let writer = thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                write_end.write_all(b"\n").unwrap();
+            });
+            assert!(line_written_within(fd, input, Duration::from_secs(5)));
+            writer.join().unwrap();
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn nobody_answers_when_the_timeout_expires_or_the_input_is_closed() {
+        with_pipe(|fd, input, _write_end| {
+            let started = Instant::now();
+            assert!(!line_written_within(fd, input, Duration::from_millis(50)));
+            assert!(started.elapsed() >= Duration::from_millis(50));
+        });
+
+        with_pipe(|fd, input, write_end| {
+            drop(write_end);
+            assert!(!line_written_within(fd, input, Duration::from_secs(5)));
+        });
+    }
+
+    /// What blob-dl makes of a yt-dlp run which printed `transcript`
+    #[cfg(unix)]
+    fn run_transcript(transcript: &str) -> RunOutput {
+        let file = std::env::temp_dir().join(format!("blob-dl-transcript-{}-{:?}.txt", std::process::id(), thread::current().id()));
+        std::fs::write(&file, transcript).unwrap();
+
+        let output = run_command(Command::new("cat").arg(&file), &parser::Verbosity::Quiet, false, None, &Shutdown::new(None)).unwrap();
+        let _ = std::fs::remove_file(&file);
+        output
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn silent_downloads_are_stopped_after_the_stall_timeout() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo '[youtube] Extracting URL: https://www.youtube.com/watch?v=dQw4w9WgXcQ'; exec sleep 10"]);
+        let shutdown = Shutdown::new(None);
+        let started = Instant::now();
+
+        let process = spawn_download(&mut command, &shutdown).unwrap();
+        let output = collect_output(process, &parser::Verbosity::Quiet, false, None, &shutdown, Duration::from_millis(200));
+
+        assert!(started.elapsed() < Duration::from_secs(5), "yt-dlp wasn't stopped");
+        assert_eq!(output.errors.len(), 1);
+        assert!(matches!(output.errors[0].kind(), YtdlpErrorKind::Timeout));
+        assert!(output.downloaded_files.is_empty());
+    }
+
+    /// yt-dlp downloading both streams of a video, then failing to merge them
+    const MERGER_FAILURE: &str = "\
+[youtube] Extracting URL: https://www.youtube.com/watch?v=dQw4w9WgXcQ
+[youtube] dQw4w9WgXcQ: Downloading webpage
+[youtube] dQw4w9WgXcQ: Downloading tv client config
+[info] dQw4w9WgXcQ: Downloading 1 format(s): 137+140
+[download] Destination: /downloads/Video.f137.mp4
+[download] 100% of   10.00MiB in 00:00:02 at 4.50MiB/s
+[download] Destination: /downloads/Video.f140.m4a
+[download] 100% of    3.00MiB in 00:00:01 at 2.80MiB/s
+[Merger] Merging formats into \"/downloads/Video.mp4\"
+ERROR: Postprocessing: ffmpeg exited with code 1
+";
+
+    #[cfg(unix)]
+    #[test]
+    fn merger_failures_are_post_processing_errors() {
+        let output = run_transcript(MERGER_FAILURE);
+
+        assert_eq!(output.errors.len(), 1);
+        let error = &output.errors[0];
+        assert_eq!(error.video_id(), Some("dQw4w9WgXcQ"));
+        assert_eq!(error.kind(), &YtdlpErrorKind::PostProcessing {
+            stage: Some(String::from("Merger")),
+            streams: vec![String::from("/downloads/Video.f137.mp4"), String::from("/downloads/Video.f140.m4a")],
+        });
+        // Downloading the video again wouldn't help
+        assert_eq!(classify_error(error, &init_error_msg_lut(), &parser::Verbosity::Quiet), ErrorRecoverability::Unrecoverable);
+        assert_eq!(error_category(error, &init_error_msg_lut()), "post-processing");
+        // The merged file was never written
+        assert!(output.downloaded_files.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn kept_streams_are_listed_and_deleted_ones_suggest_keep_video() {
+        let errors: Vec<(usize, YtdlpError)> = run_transcript(MERGER_FAILURE).errors.into_iter().map(|error| (0, error)).collect();
+
+        let without = PostProcessingReport::new(&errors, |_| false);
+        assert_eq!(without, PostProcessingReport { kept_streams: vec![], suggest_keep_video: true });
+
+        let with = PostProcessingReport::new(&errors, |_| true);
+        let streams = [String::from("/downloads/Video.f137.mp4"), String::from("/downloads/Video.f140.m4a")];
+        assert_eq!(with, PostProcessingReport { kept_streams: vec![("dQw4w9WgXcQ", &streams[..])], suggest_keep_video: false });
+
+        // Nothing to say about download errors
+        let download_error = [(0, YtdlpError::from_error_output("ERROR: [youtube] dQw4w9WgXcQ: Video unavailable"))];
+        assert_eq!(PostProcessingReport::new(&download_error, |_| false), PostProcessingReport { kept_streams: vec![], suggest_keep_video: false });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn download_errors_are_not_post_processing_errors() {
+        let output = run_transcript("\
+[youtube] Extracting URL: https://www.youtube.com/watch?v=dQw4w9WgXcQ
+[youtube] dQw4w9WgXcQ: Downloading webpage
+ERROR: [youtube] dQw4w9WgXcQ: Video unavailable. This video has been removed by the uploader
+");
+        assert_eq!(output.errors.len(), 1);
+        assert_eq!(output.errors[0].kind(), &YtdlpErrorKind::Download);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn finished_files_are_collected_with_the_playlist_progress() {
+        let output = run_transcript("\
+[download] Downloading item 1 of 3
+[youtube] Extracting URL: https://www.youtube.com/watch?v=aaaaaaaaaaa
+[download] Destination: /downloads/1_First.f137.mp4
+[download] Destination: /downloads/1_First.f140.m4a
+[Merger] Merging formats into \"/downloads/1_First.mp4\"
+[download] Downloading item 2 of 3
+[youtube] Extracting URL: https://www.youtube.com/watch?v=bbbbbbbbbbb
+ERROR: [youtube] bbbbbbbbbbb: Video unavailable. This video has been removed by the uploader
+[download] Downloading item 3 of 3
+[youtube] Extracting URL: https://www.youtube.com/watch?v=ccccccccccc
+[download] /downloads/3_Third.mp4 has already been downloaded
+");
+
+        // The merged file replaces its streams, the failed video has none
+        assert_eq!(output.downloaded_files, ["/downloads/1_First.mp4", "/downloads/3_Third.mp4"]);
+        assert_eq!(output.timings.len(), 2);
+        assert_eq!(output.playlist_progress, Some((3, 3)));
+        assert_eq!(output.errors.len(), 1);
+        assert_eq!(output.errors[0].video_id(), Some("bbbbbbbbbbb"));
+        assert!(!output.filesystem_abort);
+    }
+
+    #[test]
+    fn filesystem_errors_are_not_retried() {
+        let cases = [
+            ("ERROR: unable to open for writing: [Errno 13] Permission denied: '/downloads/video.f137.mp4.part'", PERMISSION_DENIED),
+            ("ERROR: unable to open for writing: [Errno 30] Read-only file system: '/downloads/video.f137.mp4.part'", READ_ONLY_FILESYSTEM),
+            ("ERROR: unable to write data: [Errno 28] No space left on device", NO_SPACE_LEFT),
+        ];
+
+        for (line, reason) in cases {
+            assert_eq!(YtdlpError::from_error_output(line).filesystem_error(), Some(reason), "{}", line);
+            assert_eq!(classify(line, false), ErrorRecoverability::Unrecoverable, "{}", line);
+        }
+        assert_eq!(YtdlpError::from_error_output("ERROR: [youtube] dQw4w9WgXcQ: Video unavailable").filesystem_error(), None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn playlists_stop_when_most_videos_cant_be_written() {
+        let output = run_transcript("\
+[download] Downloading item 1 of 3
+[youtube] Extracting URL: https://www.youtube.com/watch?v=aaaaaaaaaaa
+[youtube] aaaaaaaaaaa: Downloading webpage
+ERROR: unable to write data: [Errno 28] No space left on device
+[download] Downloading item 2 of 3
+[youtube] Extracting URL: https://www.youtube.com/watch?v=bbbbbbbbbbb
+[youtube] bbbbbbbbbbb: Downloading webpage
+ERROR: unable to write data: [Errno 28] No space left on device
+[download] Downloading item 3 of 3
+[youtube] Extracting URL: https://www.youtube.com/watch?v=ccccccccccc
+[youtube] ccccccccccc: Downloading webpage
+ERROR: unable to write data: [Errno 28] No space left on device
+");
+
+        // 2 of 3 is already more than half, the third video isn't looked at
+        assert!(output.filesystem_abort);
+        assert_eq!(output.errors.len(), 2);
+        assert_eq!(output.errors[1].video_id(), Some("bbbbbbbbbbb"));
+    }
+
+    #[test]
+    fn only_the_same_filesystem_error_counts_towards_stopping() {
+        let no_space = || YtdlpError::from_error_output("ERROR: unable to write data: [Errno 28] No space left on device");
+        let denied = || YtdlpError::from_error_output("ERROR: unable to open for writing: [Errno 13] Permission denied: 'a.part'");
+
+        assert_eq!(too_many_filesystem_errors(&[no_space(), no_space(), no_space()], Some((3, 5))), Some(NO_SPACE_LEFT));
+        assert_eq!(too_many_filesystem_errors(&[no_space(), denied(), no_space()], Some((3, 5))), None);
+        // Half isn't more than half
+        assert_eq!(too_many_filesystem_errors(&[no_space(), no_space()], Some((2, 4))), None);
+        // Single videos have no playlist to stop
+        assert_eq!(too_many_filesystem_errors(&[no_space()], None), None);
+    }
+
+    #[test]
+    fn commands_which_cant_start_are_errors() {
+        let shutdown = Shutdown::new(None);
+        let mut command = Command::new("blob-dl-no-such-program");
+
+        assert!(matches!(spawn_download(&mut command, &shutdown), Err(BlobdlError::IoError(_))));
+        assert!(run_command(&mut command, &parser::Verbosity::Quiet, false, None, &shutdown).is_err());
+        // Nothing for CTRL+C to stop
+        assert_eq!(shutdown.spawned(), 0);
+    }
+
+    #[test]
+    fn retries_are_grouped_by_download_in_the_order_they_started() {
+        let download_config = config::DownloadConfig::new_playlist("https://www.youtube.com/playlist?list=PL", String::from("/downloads"), true,
+                                                                   youtube::VideoQualityAndFormatPreferences::BestQuality,
+                                                                   youtube::MediaSelection::FullVideo);
+        let retries = handle_errors(&[(3, "aaaaaaaaaaa"), (1, "bbbbbbbbbbb"), (3, "ccccccccccc")], |_| &download_config, Some("/elsewhere"));
+        let args = |retry: &RetryCommand| retry.command.get_args().map(|arg| arg.to_string_lossy().into_owned()).collect::<Vec<_>>();
+
+        assert_eq!(retries.iter().map(|retry| retry.download_index).collect::<Vec<_>>(), [3, 1]);
+        let batch = args(&retries[0]);
+        assert!(batch.windows(2).any(|pair| pair[0] == "-a" && pair[1] == retries[0].batch_file.as_ref().unwrap().to_string_lossy()), "{:?}", batch);
+        let single = args(&retries[1]);
+        assert_eq!(single.last().map(String::as_str), Some("bbbbbbbbbbb"));
+        // Both are saved in the other folder
+        for args in [&batch, &single] {
+            assert!(args.iter().any(|arg| arg.starts_with("/elsewhere")) && !args.iter().any(|arg| arg.starts_with("/downloads")), "{:?}", args);
+        }
+        assert!(handle_errors(&[], |_| &download_config, None).is_empty());
+    }
+
+    /// The categories of five recoverable errors, sorted like in ask_for_redownload
+    fn retry_categories() -> Vec<(usize, ErrorCategory)> {
+        let messages = [
+            "ERROR: [youtube] aaaaaaaaaaa: Unable to download webpage: <urlopen error [Errno -3] Temporary failure in name resolution>",
+            "ERROR: [youtube] bbbbbbbbbbb: Requested format is not available",
+            "ERROR: [youtube] ccccccccccc: Something nobody has seen before",
+            "ERROR: [youtube] ddddddddddd: HTTP Error 503: Service Unavailable",
+            "ERROR: [youtube] eeeeeeeeeee: Did not get any data blocks",
+        ];
+        let mut categories: Vec<(usize, ErrorCategory)> = messages.iter()
+            .enumerate()
+            .map(|(i, message)| (i, ErrorCategory::of(&YtdlpError::from_error_output(message))))
+            .collect();
+        categories.sort_by_key(|(_, category)| *category);
+        categories
+    }
+
+    #[test]
+    fn retry_options_put_a_header_before_every_group() {
+        use RetryOption::*;
+        assert_eq!(retry_options(&retry_categories()), [
+            All, Nothing,
+            Category(ErrorCategory::Network), Error(0), Error(3),
+            Category(ErrorCategory::FormatUnavailable), Error(1),
+            Category(ErrorCategory::Fragment), Error(4),
+            Category(ErrorCategory::Unknown), Error(2),
+        ]);
+        assert_eq!(retry_options(&[]), [All, Nothing]);
+    }
+
+    #[test]
+    fn retry_selections_resolve_to_error_indexes() {
+        let categories = retry_categories();
+        let options = retry_options(&categories);
+        let resolve = |selection: &[usize]| resolve_retry_selection(selection, &options, &categories);
+
+        assert_eq!(resolve(&[0]), [0, 1, 2, 3, 4]);
+        assert_eq!(resolve(&[1]), [] as [usize; 0]);
+        assert_eq!(resolve(&[]), [] as [usize; 0]);
+        // Nothing wins over everything else
+        assert_eq!(resolve(&[0, 1]), [] as [usize; 0]);
+        // Quick picks select their whole group
+        assert_eq!(resolve(&[2]), [0, 3]);
+        assert_eq!(resolve(&[2, 7]), [0, 3, 4]);
+        // Mixed with single errors, which can be in the picked group too
+        assert_eq!(resolve(&[2, 3, 6, 10]), [0, 1, 2, 3]);
+        assert_eq!(resolve(&[4, 8]), [3, 4]);
+    }
+
+    #[test]
+    fn playlists_continue_after_the_last_finished_video() {
+        let mut download_config = config::DownloadConfig::new_playlist("https://www.youtube.com/playlist?list=PL", String::from("/downloads"), true,
+                                                                       youtube::VideoQualityAndFormatPreferences::BestQuality, youtube::MediaSelection::FullVideo);
+        // Without --start-at or --stop-at there's no suggestion
+        assert_eq!(next_playlist_start(&download_config, Some((5, 30)), false), None);
+
+        download_config.playlist_start = Some(11);
+        download_config.playlist_stop = Some(30);
+        // yt-dlp doesn't count the skipped videos: 18 of the 20 are downloaded, the 6th is at position 18
+        download_config.excluded_positions = vec![13, 14];
+        for hidden_positions in [vec![], vec![12, 20]] {
+            // Hidden videos are downloaded (and fail), so they are counted
+            download_config.hidden_positions = hidden_positions;
+
+            assert_eq!(next_playlist_start(&download_config, Some((5, 18)), false), Some(18));
+            // The 5th video wasn't finished
+            assert_eq!(next_playlist_start(&download_config, Some((5, 18)), true), Some(17));
+            assert_eq!(next_playlist_start(&download_config, Some((1, 18)), true), Some(11));
+            // The whole range is done, the next one starts after it
+            assert_eq!(next_playlist_start(&download_config, Some((18, 18)), false), Some(31));
+            // The playlist ended before --stop-at
+            assert_eq!(next_playlist_start(&download_config, Some((9, 9)), false), None);
+            assert_eq!(next_playlist_start(&download_config, None, false), None);
+        }
+    }
+
+    #[test]
+    fn auto_retry_summaries_count_videos_not_errors() {
+        let download_error = || (0, YtdlpError::from_error_output("ERROR: [youtube] dQw4w9WgXcQ: HTTP Error 503: Service Unavailable"));
+        let errors = [
+            download_error(),
+            (0, YtdlpError::from_error_output("ERROR: [youtube] dQw4w9WgXcQ: Unable to download video data: HTTP Error 503")),
+            (0, YtdlpError::from_error_output("ERROR: [youtube] 9bZkp7q19f0: Private video")),
+        ];
+
+        // The first video was retried and downloaded, the second one is private
+        assert_eq!(AutoRetrySummary::new(&errors, 1, &[]), AutoRetrySummary { originally_failed: 2, retried: 1, still_failing: 1 });
+        // The retry failed again, twice
+        let retry_errors = [download_error(), download_error()];
+        assert_eq!(AutoRetrySummary::new(&errors, 1, &retry_errors), AutoRetrySummary { originally_failed: 2, retried: 1, still_failing: 2 });
+    }
+
+    #[test]
+    fn warning_keys_leave_out_ids_and_numbers() {
+        let cases = [
+            ("WARNING: [youtube] dQw4w9WgXcQ: nsig extraction failed: You may experience throttling for some formats",
+             "WARNING: [youtube] nsig extraction failed: You may experience throttling for some formats"),
+            ("WARNING: [youtube] 9bZkp7q19f0: nsig extraction failed: You may experience throttling for some formats",
+             "WARNING: [youtube] nsig extraction failed: You may experience throttling for some formats"),
+            ("WARNING: [generic] Falling back on generic information extractor",
+             "WARNING: [generic] Falling back on generic information extractor"),
+            ("WARNING: [youtube] a-b_c-d_e-f: Some formats are possibly damaged. They will be deprioritized",
+             "WARNING: [youtube] Some formats are possibly damaged. They will be deprioritized"),
+            ("WARNING: fragment 12 not found, skipping at 00:03:12 on 2023-08-20",
+             "WARNING: fragment not found, skipping at on"),
+            ("WARNING: [download] Got error: HTTP Error 503. Retrying (1/10)...",
+             "WARNING: [download] Got error: HTTP Error Retrying (1/10)..."),
+            // Only 11 characters followed by ':' are ids
+            ("WARNING: [youtube] shortid: Unable to download webpage",
+             "WARNING: [youtube] shortid: Unable to download webpage"),
+        ];
+        for (line, key) in cases {
+            assert_eq!(warning_key(line), key, "{}", line);
+        }
+    }
+
+    #[test]
+    fn repeated_warnings_are_counted_once_shown() {
+        let mut warnings = WarningAggregator::default();
+        let nsig = |id: &str| format!("WARNING: [youtube] {}: nsig extraction failed: You may experience throttling for some formats", id);
+
+        assert!(warnings.is_new(&nsig("dQw4w9WgXcQ")));
+        assert!(warnings.is_new("WARNING: [generic] Falling back on generic information extractor"));
+        for id in ["9bZkp7q19f0", "kJQP7kiw5Fk", "JGwWNGJdvx8"] {
+            assert!(!warnings.is_new(&nsig(id)));
+        }
+
+        // Warnings which were shown once aren't in the summary
+        assert_eq!(warnings.repeated(), vec![(nsig("dQw4w9WgXcQ").as_str(), 3)]);
+    }
+
+    #[test]
+    fn errors_name_their_url_when_there_are_several() {
+        let download_config = config::DownloadConfig::new_video("https://youtu.be/a", youtube::VideoQualityAndFormatPreferences::BestQuality,
+                                                                String::from("/downloads"), youtube::MediaSelection::FullVideo);
+        let error = YtdlpError::from_error_output("ERROR: [youtube] a: Private video");
+
+        assert_eq!(error_label(&error, &download_config, 1), error.to_string());
+        assert_eq!(error_label(&error, &download_config, 2), format!("url: https://youtu.be/a\n   {}", error));
+    }
+
+    #[test]
+    fn titles_are_only_shortened_once() {
+        let mut download_config = config::DownloadConfig::new_video("https://youtu.be/a", youtube::VideoQualityAndFormatPreferences::BestQuality,
+                                                                    String::from("/downloads"), youtube::MediaSelection::FullVideo);
+        assert!(shortening_titles_helps(&download_config));
+
+        download_config.max_title_bytes = Some(config::DEFAULT_MAX_TITLE_BYTES + 20);
+        assert!(shortening_titles_helps(&download_config));
+
+        // Already shortened by an earlier retry, or by --max-title-bytes
+        download_config.max_title_bytes = Some(config::DEFAULT_MAX_TITLE_BYTES);
+        assert!(!shortening_titles_helps(&download_config));
+        download_config.max_title_bytes = Some(100);
+        assert!(!shortening_titles_helps(&download_config));
+    }
+
+    #[test]
+    fn batch_files_are_deleted_with_their_commands() {
+        let download_config = config::DownloadConfig::new_playlist("https://www.youtube.com/playlist?list=PL", String::from("/downloads"), true,
+                                                                   youtube::VideoQualityAndFormatPreferences::BestQuality,
+                                                                   youtube::MediaSelection::FullVideo);
+        // The second download has a single video, it doesn't need a batch file
+        let retries = handle_errors(&[(7, "aaaaaaaaaaa"), (7, "bbbbbbbbbbb"), (8, "ccccccccccc")], |_| &download_config, None);
+        assert_eq!(retries.len(), 2);
+
+        let batch_file = retries[0].batch_file.clone().unwrap();
+        assert_eq!(std::fs::read_to_string(&batch_file).unwrap(), "aaaaaaaaaaa\nbbbbbbbbbbb");
+        assert_eq!(retries[1].batch_file, None);
+
+        // Like the commands which are left when blob-dl is interrupted
+        drop(retries);
+        assert!(!batch_file.exists());
+    }
+
+    #[test]
+    fn error_files_list_every_failed_video_once_until_it_is_downloaded() {
+        let error_file = std::env::temp_dir().join(format!("blob-dl-error-file-{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&error_file);
+        let failed = |video_id: &str| YtdlpError::from_error_output(&format!("ERROR: [youtube] {}: Video unavailable", video_id));
+
+        // Created by the first failure
+        record_failed_url(Some(&error_file), &failed("aaaaaaaaaaa"));
+        record_failed_url(Some(&error_file), &failed("bbbbbbbbbbb"));
+        record_failed_url(Some(&error_file), &failed("aaaaaaaaaaa"));
+        // Without an id there is no url which wouldn't download everything again
+        record_failed_url(Some(&error_file), &YtdlpError::from_error_output("ERROR: Unable to download webpage"));
+        assert_eq!(std::fs::read_to_string(&error_file).unwrap(),
+                   "https://www.youtube.com/watch?v=aaaaaaaaaaa\nhttps://www.youtube.com/watch?v=bbbbbbbbbbb\n");
+
+        forget_failed_url(Some(&error_file), Some("aaaaaaaaaaa"));
+        assert_eq!(std::fs::read_to_string(&error_file).unwrap(), "https://www.youtube.com/watch?v=bbbbbbbbbbb\n");
+
+        let _ = std::fs::remove_file(error_file);
+    }
+
+    fn classify(line: &str, client_blocked: bool) -> ErrorRecoverability {
+        let mut error = YtdlpError::from_error_output(line);
+        if client_blocked {
+            error = error.with_blocked_client();
+        }
+        classify_error(&error, &init_error_msg_lut(), &parser::Verbosity::Quiet)
+    }
+
+    #[test]
+    fn documented_errors_follow_the_lookup_table() {
+        let cases = [
+            (NETWORK_FAIL, ErrorRecoverability::RecoverableWithRetry),
+            (YTDLP_GAVE_UP, ErrorRecoverability::Unrecoverable),
+            (PRIVATE_VIDEO, ErrorRecoverability::Unrecoverable),
+        ];
+
+        for (message, expected) in cases {
+            assert_eq!(classify(&format!("ERROR: [youtube] dQw4w9WgXcQ:{}", message), false), expected, "{}", message);
+        }
+    }
+
+    #[test]
+    fn client_warnings_dont_hide_specific_errors() {
+        let cases = [
+            ("ERROR: [youtube] dQw4w9WgXcQ: The uploader has not made this video available in your country", ErrorRecoverability::RecoverableWithProxy),
+            ("ERROR: [youtube] dQw4w9WgXcQ: Video unavailable. This video has been removed by the uploader", ErrorRecoverability::Unrecoverable),
+            ("ERROR: [youtube] dQw4w9WgXcQ: Private video. Sign in if you've been granted access to this video", ErrorRecoverability::Unrecoverable),
+            ("ERROR: [youtube] dQw4w9WgXcQ: Sign in to confirm your age. This video may be inappropriate for some users.", ErrorRecoverability::RecoverableWithAuth),
+            ("ERROR: unable to open for writing: [Errno 13] Permission denied: 'video.mp4'", ErrorRecoverability::Unrecoverable),
+            ("ERROR: unable to open for writing: [Errno 36] File name too long: 'video.mp4'", ErrorRecoverability::RecoverableWithShorterNames),
+            ("ERROR: [youtube] dQw4w9WgXcQ: Requested format is not available. Use --list-formats for a list of available formats", ErrorRecoverability::RecoverableWithRemappedFormat),
+        ];
+
+        for (line, expected) in cases {
+            assert_eq!(classify(line, false), expected, "{}", line);
+            assert_eq!(classify(line, true), expected, "{} (client blocked)", line);
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn client_warnings_suggest_retrying_with_another_client() {
+        let transcript = "[youtube] Extracting URL: https://www.youtube.com/watch?v=dQw4w9WgXcQ\n\
+                          WARNING: [youtube] dQw4w9WgXcQ: Some web client https formats have been skipped as they are missing a url. YouTube is forcing SABR streaming for this client.\n\
+                          ERROR: [youtube] dQw4w9WgXcQ: unable to download video data: HTTP Error 403: Forbidden\n";
+
+        let errors = run_transcript(transcript).errors;
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].client_blocked());
+        assert_eq!(classify_error(&errors[0], &init_error_msg_lut(), &parser::Verbosity::Quiet), ErrorRecoverability::RecoverableWithOtherClient);
+
+        assert!(YtdlpError::is_blocked_client_output("WARNING: [youtube] dQw4w9WgXcQ: nsig extraction failed: You may experience throttling for some formats"));
+        assert!(!YtdlpError::is_blocked_client_output("WARNING: [youtube] dQw4w9WgXcQ: Falling back to generic n function search"));
+    }
+
+    #[test]
+    fn unexplained_errors_of_blocked_clients_are_retried_with_another_client() {
+        let line = "ERROR: [youtube] dQw4w9WgXcQ: unable to download video data: HTTP Error 403: Forbidden";
+
+        assert_eq!(classify(line, true), ErrorRecoverability::RecoverableWithOtherClient);
+        assert_eq!(classify(line, false), ErrorRecoverability::RecoverableWithRetry);
+    }
+
+    #[test]
+    fn slowest_videos_come_first_and_are_limited_to_count() {
+        let timing = |title: &str, seconds: u64| VideoTiming { title: title.to_string(), bytes: None, duration: Duration::from_secs(seconds) };
+        let timings = [timing("a", 10), timing("b", 30), timing("c", 20), timing("d", 30)];
+        let titles = |count| slowest(&timings, count).iter().map(|timing| timing.title.as_str()).collect::<Vec<_>>();
+
+        // Ties keep the order the videos were downloaded in
+        assert_eq!(titles(3), ["b", "d", "c"]);
+        assert_eq!(titles(10), ["b", "d", "c", "a"]);
+        assert!(titles(0).is_empty());
+        assert!(slowest(&[], SLOWEST_COUNT).is_empty());
+    }
+
+    #[test]
+    fn speeds_need_a_size_and_some_time() {
+        let timing = |bytes: Option<u64>, millis: u64| VideoTiming { title: String::new(), bytes: bytes.map(Bytes), duration: Duration::from_millis(millis) };
+
+        assert_eq!(timing(Some(10_000_000), 2000).speed(), Some(5_000_000.0));
+        assert_eq!(timing(None, 2000).speed(), None);
+        assert_eq!(timing(Some(10_000_000), 0).speed(), None);
+
+        let missing = VideoTiming::finished("/nonexistent/blob-dl/Some video.mp4", Instant::now());
+        assert_eq!(missing.title, "Some video.mp4");
+        assert_eq!(missing.bytes, None);
+    }
+
+    #[test]
+    fn announced_sleeps_extend_the_stall_timeout() {
+        assert_eq!(announced_sleep("[download] Sleeping 12.50 seconds ..."), Some(Duration::from_millis(12_500)));
+        assert_eq!(announced_sleep("[youtube] abc: Sleeping 120.00 seconds ..."), Some(Duration::from_secs(120)));
+        assert_eq!(announced_sleep("[download] Sleeping forever seconds ..."), None);
+        assert_eq!(announced_sleep("[download]  42.0% of 10.00MiB at 1.00MiB/s ETA 00:05"), None);
+    }
+
+    #[test]
+    fn post_processing_errors_are_unrecoverable() {
+        let line = "ERROR: Postprocessing: Error selecting an encoder for stream 0:1";
+        let error = YtdlpError::from_post_processing_output(line, Some("dQw4w9WgXcQ"), Some("Merger"), &[]);
+
+        assert_eq!(classify_error(&error, &init_error_msg_lut(), &parser::Verbosity::Quiet), ErrorRecoverability::Unrecoverable);
+    }
+
+    #[test]
+    fn stats_categories_follow_the_classification() {
+        let cases = [
+            ("ERROR: [youtube] dQw4w9WgXcQ: The uploader has not made this video available in your country", "geo-restricted"),
+            ("ERROR: [youtube] dQw4w9WgXcQ: Private video. Sign in if you've been granted access to this video", "unavailable"),
+            ("ERROR: [youtube] dQw4w9WgXcQ: Sign in to confirm your age. This video may be inappropriate for some users.", "needs sign-in"),
+            ("ERROR: unable to open for writing: [Errno 13] Permission denied: 'video.mp4'", "filesystem"),
+            ("ERROR: unable to open for writing: [Errno 36] File name too long: 'video.mp4'", "file name too long"),
+            ("ERROR: [youtube] dQw4w9WgXcQ: unable to download video data: HTTP Error 403: Forbidden", "other"),
+        ];
+
+        for (line, expected) in cases {
+            assert_eq!(error_category(&YtdlpError::from_error_output(line), &init_error_msg_lut()), expected, "{}", line);
+        }
+        let blocked = YtdlpError::from_error_output("ERROR: [youtube] dQw4w9WgXcQ: unable to download video data: HTTP Error 403: Forbidden");
+        assert_eq!(error_category(&blocked.with_blocked_client(), &init_error_msg_lut()), "blocked client");
+    }
+
+    #[test]
+    fn videos_with_more_than_one_error_fail_once() {
+        let errors = [
+            (0, YtdlpError::from_error_output("ERROR: [youtube] aaaaaaaaaaa: Video unavailable")),
+            (0, YtdlpError::from_post_processing_output("ERROR: Postprocessing: Conversion failed!", Some("aaaaaaaaaaa"), None, &[])),
+            (1, YtdlpError::from_error_output("ERROR: [youtube] bbbbbbbbbbb: Video unavailable")),
+            // Nothing tells whether these are the same video
+            (1, YtdlpError::from_error_output("ERROR: Unable to download webpage")),
+            (1, YtdlpError::from_error_output("ERROR: Unable to download webpage")),
+        ];
+
+        let failed = failed_videos(&errors);
+        assert_eq!(failed.len(), 4);
+        assert_eq!(failed[0].video_id(), Some("aaaaaaaaaaa"));
+        assert_eq!(error_category(failed[0], &init_error_msg_lut()), "unavailable");
+    }
+
+    #[test]
+    fn files_cut_short_are_only_offered_without_part_files() {
+        let file = std::env::temp_dir().join(format!("blob-dl-cut-short-{}.webm", std::process::id()));
+        std::fs::write(&file, "half a video").unwrap();
+        let gone = std::env::temp_dir().join(format!("blob-dl-cut-short-{}-gone.webm", std::process::id()));
+        let unfinished = [file.clone(), gone];
+
+        let with_part_files = cut_short_files(false, &unfinished);
+        let without_part_files = cut_short_files(true, &unfinished);
+        let _ = std::fs::remove_file(&file);
+        assert!(with_part_files.is_empty());
+        assert_eq!(without_part_files, [file]);
+    }
+
+    #[test]
+    fn comment_progress_is_read_from_the_fetching_lines() {
+        assert_eq!(parse_comment_progress("[youtube] Downloading comment API JSON page 1 (0/~3400)"), Some("0/~3400"));
+        assert_eq!(parse_comment_progress("[youtube]        Downloading comment replies API JSON page 2 (120/~3400)  "), Some("120/~3400"));
+        // Without a count there is nothing to show
+        assert_eq!(parse_comment_progress("[youtube] Downloading comment section API JSON"), None);
+        assert_eq!(parse_comment_progress("[download] Destination: Downloading comments (live).webm"), None);
+        assert_eq!(parse_comment_progress("[youtube] abc: Downloading webpage (1/2)"), None);
+    }
 }
@@ -0,0 +1,89 @@
+//! Finds youtube videos without leaving the terminal, using yt-dlp's ytsearch
+use colored::Colorize;
+use dialoguer::console::Term;
+use dialoguer::{theme::ColorfulTheme, Select};
+use serde::Deserialize;
+
+use crate::assembling::youtube::format_timestamp;
+use crate::error::{BlobdlError, BlobResult};
+use crate::parser;
+use crate::ui_prompts::*;
+
+/// How many videos are shown to the user
+const RESULT_COUNT: u32 = 10;
+
+/// A video found by ytsearch, parsed from one line of yt-dlp's output
+#[derive(Deserialize, Debug)]
+struct SearchResult {
+    title: String,
+    // Missing for some results, like live streams
+    uploader: Option<String>,
+    // In seconds, missing for live streams
+    duration: Option<f64>,
+    url: String,
+}
+
+impl std::fmt::Display for SearchResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let duration = match self.duration {
+            Some(seconds) => format_timestamp(seconds),
+            None => String::from("live"),
+        };
+
+        write!(f, "{} [{}] - {}", self.title, duration, self.uploader.as_deref().unwrap_or("unknown uploader"))
+    }
+}
+
+/// `blob-dl search <QUERY>`: shows the first results of a youtube search and lets the user pick one
+///
+/// Returns the url of the video the user picked, or None if there were no results or the user didn't pick anything
+pub(crate) fn search(query: &str, cli_config: &parser::CliConfig) -> BlobResult<Option<String>> {
+    let results = fetch_results(query, cli_config)?;
+
+    if results.is_empty() {
+        eprintln!("{}", NO_SEARCH_RESULTS);
+        return Ok(None);
+    }
+
+    let term = Term::buffered_stderr();
+    let user_selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(SEARCH_RESULT_PROMPT)
+        .default(0)
+        .items(&results)
+        .interact_on_opt(&term)?;
+
+    Ok(user_selection.map(|i| results[i].url.clone()))
+}
+
+/// Runs <yt-dlp "ytsearchN:query" --flat-playlist -j> and parses every result
+///
+/// --flat-playlist keeps yt-dlp from fetching the formats of every result, which would take a long time.
+/// yt-dlp is given --fetch-timeout to answer
+fn fetch_results(query: &str, cli_config: &parser::CliConfig) -> BlobResult<Vec<SearchResult>> {
+    let mut sp = spinoff::Spinner::new_with_stream(spinoff::spinners::Dots10, "Searching...", spinoff::Color::Cyan, spinoff::Streams::Stderr);
+
+    let mut command = crate::ytdlp_command();
+    command.arg(format!("ytsearch{}:{}", RESULT_COUNT, query));
+    command.arg("--flat-playlist");
+    command.arg("-j");
+
+    let output = match crate::ytdlp_output(&mut command, cli_config.fetch_timeout())? {
+        Some(output) => output,
+        None => {
+            sp.fail("yt-dlp took too long to answer");
+            return Err(BlobdlError::ListFetchTimeout);
+        }
+    };
+    if !output.status.success() {
+        sp.fail("yt-dlp couldn't search youtube");
+        crate::check_ytdlp_status(&output)?;
+    }
+
+    sp.success("Search completed".bold().to_string().as_str());
+
+    // Every line is a JSON object describing one video, lines which can't be parsed are skipped
+    Ok(std::str::from_utf8(&output.stdout)?
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
@@ -0,0 +1,133 @@
+//! `blob-dl --simulate`: asks yt-dlp which videos it would download, after every filter is applied, without downloading them
+use std::process::{Command, Stdio};
+use colored::Colorize;
+
+use crate::assembling::youtube::config::DownloadConfig;
+use crate::error::{BlobdlError, BlobResult};
+use crate::secrets;
+use crate::ui_prompts::SIMULATION_SUMMARY;
+use crate::units::Bytes;
+
+/// What yt-dlp prints for every video, the title goes last because it can contain tabs
+const PRINT_TEMPLATE: &str = "%(playlist_index)s\t%(id)s\t%(filesize_approx)s\t%(title)s";
+
+/// A video yt-dlp would download
+#[derive(Debug, Clone, PartialEq)]
+struct SimulatedItem {
+    /// Position in the playlist, None for single videos
+    index: Option<usize>,
+    id: String,
+    /// yt-dlp's estimate of the file's size
+    size: Option<Bytes>,
+    title: String,
+}
+
+/// Runs every command in simulation mode and shows a table of the videos which would be downloaded
+pub(crate) fn simulate(downloads: Vec<(Command, DownloadConfig)>) -> BlobResult<()> {
+    for (mut command, download_config) in downloads {
+        command.arg("--skip-download").arg("--print").arg(PRINT_TEMPLATE);
+
+        let output = command.stdout(Stdio::piped()).stderr(Stdio::piped()).output()?;
+
+        // yt-dlp's errors (private videos, ...) are still shown
+        for line in String::from_utf8_lossy(&output.stderr).lines() {
+            eprintln!("{}", secrets::scrub(line));
+        }
+        if !output.status.success() && output.stdout.is_empty() {
+            return Err(BlobdlError::YtdlpFailed(output.status.code()));
+        }
+
+        let items: Vec<SimulatedItem> = std::str::from_utf8(&output.stdout)?
+            .lines()
+            .filter_map(parse_print_line)
+            .collect();
+
+        println!("\n{} {}", SIMULATION_SUMMARY.bold().cyan(), secrets::scrub(download_config.url()));
+        for row in simulation_table(&items) {
+            println!("{}", row);
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses a line printed with PRINT_TEMPLATE, yt-dlp prints "NA" for missing fields
+fn parse_print_line(line: &str) -> Option<SimulatedItem> {
+    let mut fields = line.splitn(4, '\t');
+
+    let index = fields.next()?.trim().parse().ok();
+    let id = fields.next()?.trim().to_string();
+    // filesize_approx is a float when yt-dlp computes it from the bitrate
+    let size = fields.next()?.trim().parse::<f64>().ok().map(|size| Bytes(size as u64));
+    let title = fields.next()?.to_string();
+
+    if id.is_empty() || id == "NA" {
+        return None;
+    }
+
+    Some(SimulatedItem { index, id, size, title })
+}
+
+/// Lays out the videos as a table followed by the totals, videos whose size is unknown are counted separately
+fn simulation_table(items: &[SimulatedItem]) -> Vec<String> {
+    let mut rows = vec![format!("{:>5}  {:<11}  {:>10}  {}", "#", "ID", "SIZE", "TITLE")];
+
+    for item in items {
+        let index = item.index.map(|index| index.to_string()).unwrap_or_else(|| String::from("-"));
+        let size = item.size.map(|size| size.to_string()).unwrap_or_else(|| String::from("?"));
+        rows.push(format!("{:>5}  {:<11}  {:>10}  {}", index, item.id, size, item.title));
+    }
+
+    let total: Bytes = items.iter().filter_map(|item| item.size).sum();
+    let unknown = items.iter().filter(|item| item.size.is_none()).count();
+
+    let mut totals = format!("{} video(s), about {}", items.len(), total);
+    if unknown > 0 {
+        totals.push_str(&format!(" ({} of unknown size)", unknown));
+    }
+    rows.push(totals);
+
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn print_lines_are_split_at_the_first_three_tabs() {
+        assert_eq!(parse_print_line("3\tdQw4w9WgXcQ\t2048.5\tA title\twith a tab"), Some(SimulatedItem {
+            index: Some(3),
+            id: String::from("dQw4w9WgXcQ"),
+            size: Some(Bytes(2048)),
+            title: String::from("A title\twith a tab"),
+        }));
+        // Single videos have no index, and yt-dlp doesn't always know the size
+        assert_eq!(parse_print_line("NA\tdQw4w9WgXcQ\tNA\tA title"), Some(SimulatedItem {
+            index: None,
+            id: String::from("dQw4w9WgXcQ"),
+            size: None,
+            title: String::from("A title"),
+        }));
+        assert_eq!(parse_print_line("1\tNA\t100\tNo id"), None);
+        assert_eq!(parse_print_line("[youtube] Extracting URL: https://youtu.be/dQw4w9WgXcQ"), None);
+    }
+
+    #[test]
+    fn totals_count_the_videos_of_unknown_size() {
+        let items = [
+            SimulatedItem { index: Some(1), id: String::from("aaaaaaaaaaa"), size: Some(Bytes(1024)), title: String::from("First") },
+            SimulatedItem { index: Some(2), id: String::from("bbbbbbbbbbb"), size: None, title: String::from("Second") },
+            SimulatedItem { index: Some(3), id: String::from("ccccccccccc"), size: Some(Bytes(2048)), title: String::from("Third") },
+        ];
+
+        assert_eq!(simulation_table(&items), [
+            "    #  ID                 SIZE  TITLE",
+            "    1  aaaaaaaaaaa     1.0 KiB  First",
+            "    2  bbbbbbbbbbb           ?  Second",
+            "    3  ccccccccccc     2.0 KiB  Third",
+            "3 video(s), about 3.0 KiB (1 of unknown size)",
+        ]);
+        assert_eq!(simulation_table(&[]), ["    #  ID                 SIZE  TITLE", "0 video(s), about 0 B"]);
+    }
+}
@@ -0,0 +1,91 @@
+//! `blob-dl --speed-graph`: a sparkline of the download speed, shown next to yt-dlp's progress
+use std::collections::VecDeque;
+
+/// How many samples the graph shows, one column each
+pub(crate) const SPEED_GRAPH_WIDTH: usize = 20;
+
+/// The bars of the sparkline, from the slowest to the fastest speed
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// The last `width` download speeds, the oldest first
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SpeedGraph {
+    history: VecDeque<f64>,
+    width: usize,
+}
+
+impl SpeedGraph {
+    pub(crate) fn new(width: usize) -> SpeedGraph {
+        SpeedGraph { history: VecDeque::with_capacity(width), width }
+    }
+
+    /// Adds a speed in bytes per second, the oldest one is dropped once the graph is full
+    pub(crate) fn push(&mut self, speed: f64) {
+        if self.history.len() == self.width {
+            self.history.pop_front();
+        }
+        self.history.push_back(speed.max(0.0));
+    }
+
+    /// The sparkline, scaled to the fastest speed in the history. Empty columns are spaces, so it's always `width` wide
+    pub(crate) fn render(&self) -> String {
+        let fastest = self.history.iter().cloned().fold(0.0, f64::max);
+
+        let bars = self.history.iter().map(|speed| {
+            if fastest <= 0.0 {
+                return BARS[0];
+            }
+            let level = (speed / fastest * (BARS.len() - 1) as f64).round() as usize;
+            BARS[level.min(BARS.len() - 1)]
+        });
+
+        std::iter::repeat_n(' ', self.width.saturating_sub(self.history.len()))
+            .chain(bars)
+            .collect()
+    }
+}
+
+/// The graph followed by yt-dlp's progress, redrawn over the previous update
+///
+/// It's cut to the terminal's `columns`: `\r` only goes back to the start of the last row, a line which wrapped
+/// would leave a copy behind at every update. What a longer previous update left on the row is cleared
+pub(crate) fn progress_line(graph: &str, progress: &str, columns: usize) -> String {
+    // Writing in the last column makes some terminals wrap
+    let line: String = format!("{} {}", graph, progress).chars().take(columns.saturating_sub(1)).collect();
+    format!("\r{}\x1b[K", line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn graph_is_always_full_width() {
+        let mut graph = SpeedGraph::new(4);
+        assert_eq!(graph.render(), "    ");
+
+        graph.push(0.0);
+        assert_eq!(graph.render(), "   ▁");
+
+        graph.push(100.0);
+        graph.push(50.0);
+        assert_eq!(graph.render(), " ▁█▅");
+    }
+
+    #[test]
+    fn oldest_speeds_are_dropped() {
+        let mut graph = SpeedGraph::new(2);
+        for speed in [800.0, 100.0, 200.0] {
+            graph.push(speed);
+        }
+        // 800 is gone, so 200 is the fastest
+        assert_eq!(graph.render(), "▅█");
+    }
+
+    #[test]
+    fn progress_line_fits_the_terminal() {
+        assert_eq!(progress_line("▁█", "12.3% of 50.00MiB", 80), "\r▁█ 12.3% of 50.00MiB\x1b[K");
+        assert_eq!(progress_line("▁█", "12.3% of 50.00MiB", 9), "\r▁█ 12.3%\x1b[K");
+        assert_eq!(progress_line("▁█", "12.3%", 0), "\r\x1b[K");
+    }
+}
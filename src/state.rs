@@ -0,0 +1,23 @@
+//! Files blob-dl keeps between runs
+use std::path::PathBuf;
+
+/// The directory where blob-dl stores its state, it is created if it doesn't exist
+///
+/// Returns None if the platform doesn't have a suitable directory or it can't be created
+pub(crate) fn state_dir() -> Option<PathBuf> {
+    // state_dir is only defined on Linux
+    let dir = dirs::state_dir()
+        .or_else(dirs::data_local_dir)?
+        .join("blob-dl");
+
+    std::fs::create_dir_all(&dir).ok()?;
+
+    Some(dir)
+}
+
+/// The directory where the user keeps blob-dl's settings (like presets.json), it isn't created
+///
+/// Returns None if the platform doesn't have one
+pub(crate) fn config_dir() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("blob-dl"))
+}
@@ -0,0 +1,285 @@
+//! `blob-dl stats`: totals of the previous runs, kept on this computer only
+//!
+//! Every run which isn't interrupted appends a line to STATS_FILE, `--no-stats` (or BLOB_DL_NO_STATS) turns it off.
+//! Nothing is ever sent anywhere
+use std::collections::{BTreeMap, HashMap};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+
+use crate::error::BlobResult;
+use crate::state;
+use crate::units::Bytes;
+
+/// Where the runs are kept, one JSON object per line, in blob-dl's state directory
+const STATS_FILE: &str = "stats.jsonl";
+
+/// How many error categories `blob-dl stats` lists
+const TOP_ERROR_CATEGORIES: usize = 5;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// What a run downloaded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct RunRecord {
+    /// YYYY-MM-DD, in UTC
+    date: String,
+    attempted: usize,
+    succeeded: usize,
+    failed: usize,
+    /// The size of the downloaded files
+    bytes: Bytes,
+    /// How long the downloads and the automatic retries took
+    duration_secs: f64,
+    /// What was downloaded, like "playlist (audio-only)", or "mixed" when the run had urls of different kinds
+    mode: String,
+    /// How many videos failed for every kind of error (see run::error_category)
+    #[serde(default)]
+    error_categories: HashMap<String, usize>,
+}
+
+impl RunRecord {
+    /// Describes a run which just finished, videos which failed and then succeeded in a retry count as succeeded
+    pub(crate) fn new(succeeded: usize, failed: usize, bytes: Bytes, duration: Duration, mode: String,
+                      error_categories: HashMap<String, usize>) -> RunRecord {
+        RunRecord {
+            date: date(now()),
+            attempted: succeeded + failed,
+            succeeded,
+            failed,
+            bytes,
+            duration_secs: duration.as_secs_f64(),
+            mode,
+            error_categories,
+        }
+    }
+
+    /// YYYY-MM
+    fn month(&self) -> &str {
+        self.date.get(..7).unwrap_or(&self.date)
+    }
+}
+
+/// Appends `record` to the stats file
+///
+/// Failures are shown to the user, the run is then missing from `blob-dl stats`
+pub(crate) fn record_run(record: &RunRecord) {
+    if let Err(err) = append_record(record) {
+        eprintln!("{}: {} ({})", "ERROR".red(), err, STATS_FILE);
+    }
+}
+
+fn append_record(record: &RunRecord) -> std::io::Result<()> {
+    let dir = state::state_dir().ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no state directory"))?;
+    let mut file = OpenOptions::new().create(true).append(true).open(dir.join(STATS_FILE))?;
+    writeln!(file, "{}", serde_json::to_string(record)?)
+}
+
+/// Every recorded run, the oldest first
+///
+/// Lines which can't be read (cut short by a crash, written by another version of blob-dl, ...) are left out
+fn load_records() -> Vec<RunRecord> {
+    state::state_dir()
+        .and_then(|dir| std::fs::read_to_string(dir.join(STATS_FILE)).ok())
+        .map(|contents| parse_records(&contents))
+        .unwrap_or_default()
+}
+
+/// The runs in the lines of a stats file, leaving out the ones which can't be read
+fn parse_records(contents: &str) -> Vec<RunRecord> {
+    contents.lines().filter_map(|line| serde_json::from_str(line).ok()).collect()
+}
+
+/// The totals of a group of runs
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Totals {
+    runs: usize,
+    attempted: usize,
+    succeeded: usize,
+    failed: usize,
+    bytes: Bytes,
+    duration_secs: f64,
+}
+
+impl Totals {
+    fn add(&mut self, record: &RunRecord) {
+        self.runs += 1;
+        self.attempted += record.attempted;
+        self.succeeded += record.succeeded;
+        self.failed += record.failed;
+        self.bytes += record.bytes;
+        self.duration_secs += record.duration_secs;
+    }
+
+    /// Bytes per second, None if nothing was downloaded
+    fn average_speed(&self) -> Option<Bytes> {
+        (self.duration_secs > 0.0 && self.bytes.0 > 0).then(|| Bytes((self.bytes.0 as f64 / self.duration_secs) as u64))
+    }
+}
+
+/// What `blob-dl stats` shows about a group of runs
+#[derive(Debug, PartialEq)]
+struct Summary<'a> {
+    overall: Totals,
+    /// Keyed by YYYY-MM, so the oldest month comes first
+    months: BTreeMap<&'a str, Totals>,
+    /// How many runs had each mode, the most common first
+    modes: Vec<(&'a str, usize)>,
+    /// How many videos failed for each kind of error, the most common first
+    error_categories: Vec<(&'a str, usize)>,
+}
+
+fn summarize(records: &[RunRecord]) -> Summary<'_> {
+    let mut overall = Totals::default();
+    let mut months: BTreeMap<&str, Totals> = BTreeMap::new();
+    let mut modes: HashMap<&str, usize> = HashMap::new();
+    let mut error_categories: HashMap<&str, usize> = HashMap::new();
+
+    for record in records {
+        overall.add(record);
+        months.entry(record.month()).or_default().add(record);
+        *modes.entry(&record.mode).or_default() += 1;
+        for (category, count) in &record.error_categories {
+            *error_categories.entry(category).or_default() += count;
+        }
+    }
+
+    Summary { overall, months, modes: sorted_by_count(modes), error_categories: sorted_by_count(error_categories) }
+}
+
+/// Prints the totals of every recorded run on stdout: overall, month by month and the most common errors
+pub(crate) fn print_stats() -> BlobResult<()> {
+    let records = load_records();
+    let (first, last) = match (records.first(), records.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => {
+            println!("No runs recorded yet");
+            return Ok(());
+        }
+    };
+    let Summary { overall, months, modes, error_categories } = summarize(&records);
+
+    println!("Runs: {} (from {} to {})", overall.runs, first.date, last.date);
+    println!("Videos: {} attempted, {} downloaded, {} failed", overall.attempted, overall.succeeded, overall.failed);
+    println!("Downloaded: {} in {}", overall.bytes, format_duration(overall.duration_secs));
+    if let Some(speed) = overall.average_speed() {
+        println!("Average speed: {}/s", speed);
+    }
+
+    println!("\nPer month:");
+    for (month, totals) in &months {
+        let speed = totals.average_speed().map(|speed| format!("{}/s", speed)).unwrap_or_else(|| String::from("-"));
+        println!("   {}   {:>4} runs   {:>5} videos   {:>5} failed   {:>10}   {:>12}",
+                 month, totals.runs, totals.succeeded, totals.failed, totals.bytes, speed);
+    }
+
+    println!("\nModes:");
+    for (mode, runs) in modes {
+        println!("   {:>4} runs   {}", runs, mode);
+    }
+
+    if !error_categories.is_empty() {
+        println!("\nMost common errors:");
+        for (category, count) in error_categories.into_iter().take(TOP_ERROR_CATEGORIES) {
+            println!("   {:>5}   {}", count, category);
+        }
+    }
+
+    Ok(())
+}
+
+/// The most frequent first, ties in alphabetical order
+fn sorted_by_count(counts: HashMap<&str, usize>) -> Vec<(&str, usize)> {
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|(a_name, a_count), (b_name, b_count)| b_count.cmp(a_count).then(a_name.cmp(b_name)));
+    counts
+}
+
+/// Like 3h 04m 05s
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+
+    if hours > 0 {
+        format!("{}h {:02}m {:02}s", hours, minutes, seconds)
+    } else {
+        format!("{}m {:02}s", minutes, seconds)
+    }
+}
+
+/// The UTC date of `unix_seconds` as YYYY-MM-DD
+fn date(unix_seconds: u64) -> String {
+    let (year, month, day) = civil_from_days((unix_seconds / SECONDS_PER_DAY) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// The year, month and day of the `days`-th day after 1970-01-01, in the proleptic gregorian calendar
+///
+/// Howard Hinnant's algorithm: the year is shifted to start in March, so that the leap day is the last one
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+/// Seconds since the unix epoch, 0 if the clock is before it
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATS: &str = r#"{"date":"2026-09-30","attempted":10,"succeeded":8,"failed":2,"bytes":800000000,"duration_secs":400.0,"mode":"playlist (audio-only)","error_categories":{"unavailable":2}}
+{"date":"2026-10-01","attempted":1,"succeeded":1,"failed":0,"bytes":200000000,"duration_secs":100.0,"mode":"video"}
+{"date":"2026-10-02","attempted":5,"succeeded":2,"failed":3,"bytes":0,"duration_secs":0.0,"mode":"playlist (audio-only)","error_categories":{"private":1,"unavailable":2}}
+{"date":"2026-10-03","attempted":4,"#;
+
+    #[test]
+    fn runs_are_summed_overall_and_per_month() {
+        let records = parse_records(STATS);
+        // The last line was cut short
+        assert_eq!(records.len(), 3);
+
+        let summary = summarize(&records);
+        assert_eq!(summary.overall, Totals { runs: 3, attempted: 16, succeeded: 11, failed: 5, bytes: Bytes(1_000_000_000), duration_secs: 500.0 });
+        assert_eq!(summary.overall.average_speed(), Some(Bytes(2_000_000)));
+
+        let months: Vec<_> = summary.months.iter().map(|(month, totals)| (*month, totals.runs, totals.succeeded)).collect();
+        assert_eq!(months, [("2026-09", 1, 8), ("2026-10", 2, 3)]);
+        assert_eq!(summary.months["2026-10"].average_speed(), Some(Bytes(2_000_000)));
+
+        assert_eq!(summary.modes, [("playlist (audio-only)", 2), ("video", 1)]);
+        assert_eq!(summary.error_categories, [("unavailable", 4), ("private", 1)]);
+    }
+
+    #[test]
+    fn runs_which_downloaded_nothing_have_no_speed() {
+        let records = parse_records(STATS);
+        assert_eq!(summarize(&records[2..]).overall.average_speed(), None);
+        assert!(summarize(&[]).months.is_empty());
+    }
+
+    #[test]
+    fn dates_are_utc_days() {
+        assert_eq!(date(0), "1970-01-01");
+        // The leap day and the day after it
+        assert_eq!(date(1_709_164_800), "2024-02-29");
+        assert_eq!(date(1_709_251_200), "2024-03-01");
+        assert_eq!(date(1_709_251_199), "2024-02-29");
+        assert_eq!(format_duration(3845.0), "1h 04m 05s");
+        assert_eq!(format_duration(59.6), "1m 00s");
+    }
+}
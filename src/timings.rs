@@ -0,0 +1,159 @@
+//! Where the time of a run goes: fetching formats, answering the wizard, downloading and retrying
+//!
+//! A Timings is created at the start of a run and passed to everything which spawns yt-dlp or waits for the user,
+//! it is shown at the end in Verbose mode
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use colored::Colorize;
+
+use crate::ui_prompts::TIMINGS_SUMMARY;
+
+/// A part of a run which is timed on its own
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    /// Waiting for yt-dlp to list formats, playlist entries, ...
+    FormatFetching,
+    /// Waiting for the user to answer the wizard's questions
+    Wizard,
+    /// The first download of every url
+    Download,
+    /// Re-downloading the videos which failed
+    Retry,
+}
+
+impl Phase {
+    fn name(&self) -> &'static str {
+        match self {
+            Phase::FormatFetching => "Fetching formats",
+            Phase::Wizard => "Answering the wizard",
+            Phase::Download => "Downloading",
+            Phase::Retry => "Retrying",
+        }
+    }
+}
+
+/// The wall time of every phase, in the order they were recorded, and how many processes were spawned
+///
+/// Interior mutability lets it be shared without threading &mut everywhere, also with the threads running yt-dlp
+#[derive(Debug)]
+pub struct Timings {
+    started: Instant,
+    phases: Mutex<Vec<(Phase, Duration)>>,
+    processes: AtomicUsize,
+}
+
+impl Timings {
+    pub(crate) fn new() -> Timings {
+        Timings { started: Instant::now(), phases: Mutex::new(vec![]), processes: AtomicUsize::new(0) }
+    }
+
+    pub(crate) fn record(&self, phase: Phase, duration: Duration) {
+        self.phases.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push((phase, duration));
+    }
+
+    /// Runs `f` and records how long it took as `phase`
+    pub(crate) fn time<T>(&self, phase: Phase, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = f();
+        self.record(phase, started.elapsed());
+        result
+    }
+
+    /// Counts a process blob-dl started (yt-dlp, ffmpeg, ...)
+    pub(crate) fn count_process(&self) {
+        self.count_processes(1);
+    }
+
+    pub(crate) fn count_processes(&self, count: usize) {
+        self.processes.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// The time spent in `phase`, summing every time it was recorded
+    pub(crate) fn total(&self, phase: Phase) -> Duration {
+        self.phases().iter()
+            .filter(|(recorded, _)| *recorded == phase)
+            .map(|(_, duration)| *duration)
+            .sum()
+    }
+
+    /// How many processes were counted so far
+    fn processes(&self) -> usize {
+        self.processes.load(Ordering::Relaxed)
+    }
+
+    /// A copy of the phases recorded so far
+    fn phases(&self) -> Vec<(Phase, Duration)> {
+        self.phases.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    /// The total of every phase which was recorded, in the order they first happened
+    fn totals(&self) -> Vec<(Phase, Duration)> {
+        let mut totals: Vec<(Phase, Duration)> = vec![];
+        for (phase, duration) in self.phases() {
+            match totals.iter_mut().find(|(recorded, _)| *recorded == phase) {
+                Some((_, total)) => *total += duration,
+                None => totals.push((phase, duration)),
+            }
+        }
+        totals
+    }
+
+    /// Lists the time spent in every phase, in the order they first happened, and the whole run's time
+    pub(crate) fn print(&self) {
+        eprintln!("\n{}", TIMINGS_SUMMARY.bold().cyan());
+
+        for (phase, total) in self.totals() {
+            eprintln!("   {}: {:.1}s", phase.name(), total.as_secs_f64());
+        }
+
+        eprintln!("   Total: {:.1}s, {} process(es) spawned", self.started.elapsed().as_secs_f64(), self.processes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phases_add_up_in_the_order_they_first_happened() {
+        let timings = Timings::new();
+        timings.record(Phase::Wizard, Duration::from_secs(3));
+        timings.record(Phase::FormatFetching, Duration::from_secs(2));
+        timings.record(Phase::Wizard, Duration::from_secs(4));
+        timings.record(Phase::Download, Duration::from_secs(60));
+
+        assert_eq!(timings.total(Phase::Wizard), Duration::from_secs(7));
+        assert_eq!(timings.total(Phase::Retry), Duration::ZERO);
+        assert_eq!(timings.totals(), [
+            (Phase::Wizard, Duration::from_secs(7)),
+            (Phase::FormatFetching, Duration::from_secs(2)),
+            (Phase::Download, Duration::from_secs(60)),
+        ]);
+    }
+
+    #[test]
+    fn timed_closures_are_recorded_and_return_their_value() {
+        let timings = Timings::new();
+
+        assert_eq!(timings.time(Phase::Retry, || 42), 42);
+        assert_eq!(timings.totals().len(), 1);
+        assert_eq!(timings.totals()[0].0, Phase::Retry);
+    }
+
+    #[test]
+    fn processes_are_counted_from_every_thread() {
+        let timings = Timings::new();
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    timings.count_process();
+                    timings.count_processes(2);
+                });
+            }
+        });
+
+        assert_eq!(timings.processes(), 12);
+    }
+}
@@ -0,0 +1,112 @@
+//! Types which make the unit of a number explicit
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Sub};
+use serde::{Deserialize, Serialize};
+
+/// A size in bytes
+///
+/// It's shown in binary units like yt-dlp's output, with their IEC names: 1024 bytes are 1.0 KiB, 1024 KiB are 1.0 MiB.
+/// The unit is the biggest one which keeps the rounded number at 1.0 or above
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Bytes(pub u64);
+
+impl fmt::Display for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+        if self.0 < 1024 {
+            // pad() keeps width and alignment working, like in "{:<10}"
+            return f.pad(&format!("{} B", self.0));
+        }
+
+        let mut size = self.0 as f64 / 1024.0;
+        let mut unit = 0;
+        // Checked after rounding to one decimal, so that 1048575 bytes are 1.0 MiB rather than 1024.0 KiB
+        while size >= 1023.95 && unit < UNITS.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+
+        f.pad(&format!("{:.1} {}", size, UNITS[unit]))
+    }
+}
+
+impl From<u64> for Bytes {
+    fn from(bytes: u64) -> Self {
+        Bytes(bytes)
+    }
+}
+
+impl From<Bytes> for u64 {
+    fn from(bytes: Bytes) -> Self {
+        bytes.0
+    }
+}
+
+impl Add for Bytes {
+    type Output = Bytes;
+
+    fn add(self, other: Bytes) -> Bytes {
+        Bytes(self.0 + other.0)
+    }
+}
+
+impl AddAssign for Bytes {
+    fn add_assign(&mut self, other: Bytes) {
+        self.0 += other.0;
+    }
+}
+
+/// Sizes can't be negative, so subtracting a bigger size gives 0
+impl Sub for Bytes {
+    type Output = Bytes;
+
+    fn sub(self, other: Bytes) -> Bytes {
+        Bytes(self.0.saturating_sub(other.0))
+    }
+}
+
+impl Sum for Bytes {
+    fn sum<I: Iterator<Item = Bytes>>(iter: I) -> Bytes {
+        iter.fold(Bytes(0), Add::add)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_use_binary_units() {
+        let cases = [
+            (0, "0 B"),
+            (1023, "1023 B"),
+            (1024, "1.0 KiB"),
+            (1536, "1.5 KiB"),
+            (1_048_575, "1.0 MiB"),
+            (1_048_576, "1.0 MiB"),
+            (1_000_000, "976.6 KiB"),
+            (5 * 1024 * 1024 * 1024, "5.0 GiB"),
+            (3 * 1024_u64.pow(4), "3.0 TiB"),
+            // There is no bigger unit
+            (2048 * 1024_u64.pow(4), "2048.0 TiB"),
+        ];
+
+        for (bytes, shown) in cases {
+            assert_eq!(Bytes(bytes).to_string(), shown, "{}", bytes);
+        }
+    }
+
+    #[test]
+    fn sizes_can_be_aligned() {
+        assert_eq!(format!("{:>8}|{:<8}|", Bytes(12), Bytes(2048)), "    12 B|2.0 KiB |");
+    }
+
+    #[test]
+    fn sizes_never_go_below_zero() {
+        assert_eq!(Bytes(10) - Bytes(20), Bytes(0));
+        assert_eq!([Bytes(1), Bytes(2), Bytes(3)].into_iter().sum::<Bytes>(), Bytes(6));
+    }
+}
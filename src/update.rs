@@ -0,0 +1,197 @@
+//! Checks whether a newer version of blob-dl was published on crates.io
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use colored::Colorize;
+use serde::Deserialize;
+
+use crate::error::BlobResult;
+use crate::state;
+use crate::ui_prompts::*;
+
+const CRATES_IO_URL: &str = "https://crates.io/api/v1/crates/blob-dl";
+
+/// The version of blob-dl which is running
+const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Setting this environment variable disables the automatic check (useful for packagers)
+const DISABLE_ENV_VAR: &str = "BLOB_DL_NO_UPDATE_CHECK";
+
+/// The automatic check runs at most once in this time span
+const CHECK_INTERVAL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// The network is never allowed to slow blob-dl down more than this
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Where the time of the last automatic check is stored
+const LAST_CHECK_FILE: &str = "last_update_check";
+
+/// The part of crates.io's response blob-dl cares about
+#[derive(Deserialize)]
+struct CratesIoResponse {
+    #[serde(rename = "crate")]
+    krate: CrateInfo,
+}
+
+#[derive(Deserialize)]
+struct CrateInfo {
+    max_stable_version: String,
+}
+
+/// `blob-dl self-check`: tells the user whether they are running the latest version
+pub(crate) fn self_check() -> BlobResult<()> {
+    let latest = parse_latest_version(&fetch_release_response()?)?;
+
+    if is_newer(&latest, CURRENT_VERSION) {
+        println!("{} {} -> {}", UPDATE_AVAILABLE.bold(), CURRENT_VERSION, latest);
+        println!("{}", UPDATE_INSTRUCTIONS);
+    } else {
+        println!("{} ({})", UP_TO_DATE, CURRENT_VERSION);
+    }
+
+    Ok(())
+}
+
+/// Checks for updates on a separate thread, unless it was done in the last week or it is disabled
+///
+/// The newest version is sent through the returned channel, only if it's newer than the current one
+pub(crate) fn spawn_background_check() -> Option<mpsc::Receiver<String>> {
+    if std::env::var_os(DISABLE_ENV_VAR).is_some() {
+        return None;
+    }
+
+    let last_check_file = state::state_dir()?.join(LAST_CHECK_FILE);
+    if !claim_check(&last_check_file, SystemTime::now()) {
+        return None;
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        if let Ok(Some(latest)) = fetch_release_response().and_then(|body| newer_version(&body, CURRENT_VERSION)) {
+            let _ = sender.send(latest);
+        }
+    });
+
+    Some(receiver)
+}
+
+/// Whether the automatic check is due at `now` according to the time of the last one, stored in `last_check_file`.
+/// If it is, `now` is stored as the time of the last check
+fn claim_check(last_check_file: &Path, now: SystemTime) -> bool {
+    let last_check = std::fs::read_to_string(last_check_file).ok()
+        .and_then(|contents| contents.trim().parse::<u64>().ok())
+        .map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds));
+
+    if !should_check(last_check, now) {
+        return false;
+    }
+
+    // Even if the check fails it shouldn't be retried on every run
+    if let Ok(since_epoch) = now.duration_since(UNIX_EPOCH) {
+        let _ = std::fs::write(last_check_file, since_epoch.as_secs().to_string());
+    }
+    true
+}
+
+/// Prints a one-line notice if the background check found a newer version, it never waits for the check to finish
+pub(crate) fn print_notice(receiver: Option<mpsc::Receiver<String>>) {
+    if let Some(Ok(latest)) = receiver.map(|receiver| receiver.try_recv()) {
+        eprintln!("\n{} {} -> {} (run blob-dl self-check for details)", UPDATE_AVAILABLE, CURRENT_VERSION, latest);
+    }
+}
+
+/// Asks crates.io for the information about blob-dl, the latest version is read with parse_latest_version
+fn fetch_release_response() -> BlobResult<String> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(REQUEST_TIMEOUT))
+        .build()
+        .into();
+
+    let body = agent.get(CRATES_IO_URL)
+        // crates.io rejects requests without a user agent
+        .header("User-Agent", concat!("blob-dl/", env!("CARGO_PKG_VERSION"), " (https://github.com/MicheleCioccarelli/blob-dl)"))
+        .call()?
+        .body_mut()
+        .read_to_string()?;
+
+    Ok(body)
+}
+
+/// The latest version in crates.io's response, only if it's newer than `current`
+fn newer_version(response: &str, current: &str) -> BlobResult<Option<String>> {
+    let latest = parse_latest_version(response)?;
+    Ok(is_newer(&latest, current).then_some(latest))
+}
+
+/// Extracts the latest stable version from crates.io's JSON response
+fn parse_latest_version(json: &str) -> BlobResult<String> {
+    let response: CratesIoResponse = serde_json::from_str(json)?;
+    Ok(response.krate.max_stable_version)
+}
+
+/// Whether enough time has passed since the last automatic check
+fn should_check(last_check: Option<SystemTime>, now: SystemTime) -> bool {
+    match last_check {
+        // A timestamp in the future means the clock changed, check again
+        Some(last_check) => now.duration_since(last_check).map_or(true, |elapsed| elapsed >= CHECK_INTERVAL),
+        None => true,
+    }
+}
+
+/// Compares two MAJOR.MINOR.PATCH versions, anything which can't be parsed is considered older
+fn is_newer(candidate: &str, current: &str) -> bool {
+    let parse = |version: &str| -> Vec<u64> {
+        version.split('.').map(|part| part.parse().unwrap_or(0)).collect()
+    };
+
+    parse(candidate) > parse(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An excerpt of crates.io's response
+    const RESPONSE: &str = r#"{"crate": {"id": "blob-dl", "max_version": "1.3.0-beta.1", "max_stable_version": "1.2.0", "downloads": 4170},
+                               "versions": null}"#;
+
+    #[test]
+    fn the_latest_stable_version_is_read_from_the_response() {
+        assert_eq!(parse_latest_version(RESPONSE).unwrap(), "1.2.0");
+        assert!(parse_latest_version(r#"{"errors": [{"detail": "Not Found"}]}"#).is_err());
+        assert!(parse_latest_version("<html>").is_err());
+
+        assert_eq!(newer_version(RESPONSE, "1.0.1").unwrap().as_deref(), Some("1.2.0"));
+        assert_eq!(newer_version(RESPONSE, "1.2.0").unwrap(), None);
+        assert_eq!(newer_version(RESPONSE, "1.10.0").unwrap(), None);
+    }
+
+    #[test]
+    fn checks_are_at_least_a_week_apart() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        assert!(should_check(None, now));
+        assert!(!should_check(Some(now - Duration::from_secs(60)), now));
+        assert!(!should_check(Some(now - CHECK_INTERVAL + Duration::from_secs(1)), now));
+        assert!(should_check(Some(now - CHECK_INTERVAL), now));
+        // The clock went back
+        assert!(should_check(Some(now + Duration::from_secs(60)), now));
+    }
+
+    #[test]
+    fn claimed_checks_are_remembered() {
+        let last_check_file = std::env::temp_dir().join(format!("blob-dl-last-update-check-{}", std::process::id()));
+        let _ = std::fs::remove_file(&last_check_file);
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        let claims = [
+            claim_check(&last_check_file, now),
+            claim_check(&last_check_file, now + Duration::from_secs(60 * 60)),
+            claim_check(&last_check_file, now + CHECK_INTERVAL),
+        ];
+        let _ = std::fs::remove_file(&last_check_file);
+
+        assert_eq!(claims, [true, false, true]);
+    }
+}